@@ -0,0 +1,94 @@
+//! Timing instrumentation for `--bench`, and the summary `--stats` prints
+
+use std::time::{Duration, Instant};
+
+/// Counters and timers collected while rendering
+pub struct Bench {
+    start: Instant,
+    /// Markdown bytes rendered
+    pub bytes: usize,
+    /// Parser events handled
+    pub events: usize,
+    /// Time spent removing ANSI escapes from the input
+    pub strip: Duration,
+    /// Time spent parsing and styling, not counting printing
+    pub render: Duration,
+    /// Time spent writing rendered output
+    pub print: Duration,
+}
+
+impl Bench {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            bytes: 0,
+            events: 0,
+            strip: Duration::ZERO,
+            render: Duration::ZERO,
+            print: Duration::ZERO,
+        }
+    }
+
+    /// Summary of throughput and where the time went
+    pub fn report(&self) -> String {
+        let total = self.start.elapsed();
+        let seconds = total.as_secs_f64().max(f64::EPSILON);
+        let share = |part: Duration| format!("{:>9.3}s {:>5.1}%", part.as_secs_f64(), part.as_secs_f64() / seconds * 100.0);
+        let other = total.saturating_sub(self.strip + self.render + self.print);
+        format!(
+            "bench: {} bytes, {} events in {:.3}s\n  throughput  {:.2} MB/s, {:.0} events/s\n  strip ANSI  {}\n  parse+style {}\n  print       {}\n  other       {}\n",
+            self.bytes,
+            self.events,
+            seconds,
+            self.bytes as f64 / seconds / 1_000_000.0,
+            self.events as f64 / seconds,
+            share(self.strip),
+            share(self.render),
+            share(self.print),
+            share(other)
+        )
+    }
+}
+
+/// Arrival times and size of the output, for the `--stats` line
+pub struct Stats {
+    start: Instant,
+    /// When the first input arrived, after the start
+    first: Option<Duration>,
+    /// Markdown bytes rendered
+    pub bytes: usize,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self { start: Instant::now(), first: None, bytes: 0 }
+    }
+
+    /// Note that input has arrived
+    pub fn arrived(&mut self) {
+        if self.first.is_none() {
+            self.first = Some(self.start.elapsed());
+        }
+    }
+
+    /// Time to the first byte, total time, bytes, and throughput once output started
+    /// flowing, so a slow start doesn't hide a fast backend
+    pub fn summary(&self) -> String {
+        let total = self.start.elapsed();
+        let first = self.first.unwrap_or(total);
+        let flowing = total.saturating_sub(first).as_secs_f64();
+        let rate = if flowing > 0.001 { self.bytes as f64 / flowing } else { self.bytes as f64 / total.as_secs_f64().max(f64::EPSILON) };
+        format!("first byte {:.2}s · {:.2}s total · {} · {}/s", first.as_secs_f64(), total.as_secs_f64(), size(self.bytes as f64), size(rate))
+    }
+}
+
+/// Bytes as `512 B`, `18.2 KB`, or `1.4 MB`
+fn size(bytes: f64) -> String {
+    if bytes < 1000.0 {
+        format!("{:.0} B", bytes)
+    } else if bytes < 1_000_000.0 {
+        format!("{:.1} KB", bytes / 1000.0)
+    } else {
+        format!("{:.1} MB", bytes / 1_000_000.0)
+    }
+}
@@ -0,0 +1,59 @@
+//! Response caching for LLM queries
+
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// On-disk cache of LLM responses keyed by provider and prompt
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Option<Duration>,
+}
+
+impl ResponseCache {
+    /// Open the cache in ~/.cache/livemd/responses (or the platform equivalent)
+    pub fn open(ttl_secs: Option<u64>) -> Option<Self> {
+        let dir = dirs::cache_dir()?.join("livemd").join("responses");
+        Some(Self {
+            dir,
+            ttl: ttl_secs.map(Duration::from_secs),
+        })
+    }
+
+    /// Build a stable cache key from the provider command and the full prompt
+    pub fn key(provider: &str, prompt: &str) -> String {
+        // FNV-1a, so keys stay stable across Rust versions
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in provider.bytes().chain([0u8]).chain(prompt.bytes()) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        format!("{:016x}", hash)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.md", key))
+    }
+
+    /// Look up a cached response, ignoring entries older than the TTL
+    pub fn get(&self, key: &str) -> Option<String> {
+        let path = self.path(key);
+        if let Some(ttl) = self.ttl {
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+            if age > ttl {
+                let _ = std::fs::remove_file(&path);
+                return None;
+            }
+        }
+        std::fs::read_to_string(path).ok()
+    }
+
+    /// Store a response
+    pub fn put(&self, key: &str, response: &str) {
+        if let Err(e) = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(self.path(key), response))
+        {
+            eprintln!("Warning: Failed to write response cache: {}", e);
+        }
+    }
+}
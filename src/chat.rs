@@ -0,0 +1,379 @@
+//! Interactive chat with the configured LLM (`livemd chat`)
+
+use crate::config::{ConfigFile, LlmCmdConfig};
+use crate::error::Result;
+use crate::history::{self, Session};
+use crate::readline::LineEditor;
+use crate::runner;
+use crate::streamer::{estimate_tokens, MinimalStreamer};
+use std::path::Path;
+use std::time::Instant;
+
+/// Who wrote a message in the conversation
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    User,
+    Assistant,
+}
+
+impl Role {
+    fn label(self) -> &'static str {
+        match self {
+            Role::User => "User",
+            Role::Assistant => "Assistant",
+        }
+    }
+
+    /// The section heading for the role's messages in an exported chat
+    fn heading(self) -> &'static str {
+        match self {
+            Role::User => "You",
+            Role::Assistant => "Assistant",
+        }
+    }
+}
+
+/// The messages so far, oldest first
+#[derive(Default)]
+struct Conversation {
+    turns: Vec<(Role, String)>,
+    /// The first message still sent as context; those before it were dropped or summarized
+    first: usize,
+    /// Summary standing in for the messages before `first`
+    summary: Option<String>,
+}
+
+impl Conversation {
+    /// The prompt for the next reply. LLM commands take a single prompt, so earlier messages
+    /// are written into it ahead of the new one.
+    fn prompt(&self, message: &str) -> String {
+        self.prompt_from(self.first, message)
+    }
+
+    /// The prompt for the next reply with the messages from `first` on as context
+    fn prompt_from(&self, first: usize, message: &str) -> String {
+        let turns = &self.turns[first.min(self.turns.len())..];
+        if turns.is_empty() && self.summary.is_none() {
+            return message.to_string();
+        }
+        let mut prompt = String::from("Continue this conversation by replying to the last message.\n\n");
+        if let Some(summary) = &self.summary {
+            prompt.push_str(&format!("Summary of the conversation before this: {}\n\n", summary.trim()));
+        }
+        for (role, text) in turns {
+            prompt.push_str(&format!("{}: {}\n\n", role.label(), text.trim()));
+        }
+        prompt.push_str(&format!("{}: {}", Role::User.label(), message));
+        prompt
+    }
+
+    /// Forget everything, including what was summarized
+    fn clear(&mut self) {
+        *self = Conversation::default();
+    }
+
+    /// Take back the last message and its reply, returning the message so it can be sent again
+    fn take_last(&mut self) -> Option<String> {
+        match self.turns.as_slice() {
+            [.., (Role::User, _), (Role::Assistant, _)] => {
+                self.turns.pop();
+                let message = self.turns.pop().map(|(_, message)| message);
+                self.first = self.first.min(self.turns.len());
+                message
+            }
+            _ => None,
+        }
+    }
+
+    /// The conversation as Markdown, a `## You` or `## Assistant` section per message
+    fn to_markdown(&self, title: &str) -> String {
+        let mut markdown = format!("{}\n", title);
+        for (role, text) in &self.turns {
+            markdown.push_str(&format!("\n## {}\n\n{}\n", role.heading(), text.trim()));
+        }
+        markdown
+    }
+
+    /// The last reply, as the Markdown the LLM wrote
+    fn last_reply(&self) -> Option<&str> {
+        self.turns.iter().rev().find(|(role, _)| *role == Role::Assistant).map(|(_, text)| text.as_str())
+    }
+}
+
+/// Tokens of context sent with each message when `context-budget` isn't set
+const DEFAULT_CONTEXT_BUDGET: usize = 16_000;
+
+/// Asks for a summary of the oldest messages when they're summarized to save context
+const SUMMARY_PROMPT: &str = "Summarize this conversation in a short paragraph for whoever continues it. \
+Keep facts, decisions, names, and code identifiers that later messages may refer to.\n\n";
+
+const HELP: &str = "Commands:
+  /save FILE      write the last reply to FILE
+  /export FILE    write the whole conversation to FILE as Markdown
+  /clear          forget the conversation so far
+  /model NAME     answer with the llm-cmd preset NAME (or the command NAME); /model lists them
+  /model NAME MSG send MSG to the preset NAME alone, then go back
+  /system TEXT    put TEXT ahead of the conversation as instructions; /system alone removes them
+  /persona NAME   answer as a persona from the config, with its instructions and model
+  /retry          ask again for a new reply to the last message
+  /quit           leave (as does Ctrl-D)
+Start a message with // to send one beginning with /.
+End a line with \\ or press Alt-Enter to write several lines, then send them with an empty line.";
+
+/// What to do after a slash command
+enum Next {
+    /// Wait for the next message
+    Read,
+    /// Send this message to the LLM
+    Send(String),
+    Quit,
+}
+
+/// A chat session: the conversation and the LLM command answering it
+struct Chat<'a> {
+    conversation: Conversation,
+    config: Option<&'a ConfigFile>,
+    /// The preset chosen with `/model`, shown in the prompt
+    model: Option<String>,
+    /// The preset and command to go back to after a one-off `/model NAME MESSAGE`
+    restore: Option<(Option<String>, String)>,
+    /// Heading for the conversation when it's saved or exported, with when it started
+    title: String,
+    /// Where the conversation is saved after each reply, unless `chat-history` is off
+    session: Option<Session>,
+}
+
+impl<'a> Chat<'a> {
+    fn new(config: Option<&'a ConfigFile>) -> Self {
+        let mut chat = Chat { conversation: Conversation::default(), config, model: None, restore: None, title: String::new(), session: None };
+        chat.start_session();
+        chat
+    }
+
+    /// Start saving to a new file, for a new conversation
+    fn start_session(&mut self) {
+        self.title = history::title();
+        let enabled = self.config.and_then(|c| c.chat_history).unwrap_or(true);
+        self.session = if enabled { Session::start() } else { None };
+    }
+
+    /// Add a message and its reply, and save the conversation
+    fn push(&mut self, message: String, reply: String) {
+        self.conversation.turns.push((Role::User, message));
+        self.conversation.turns.push((Role::Assistant, reply));
+        if let Some(session) = &self.session {
+            session.save(&self.conversation.to_markdown(&self.title));
+        }
+    }
+
+    /// Preset names from the config, sorted
+    fn presets(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = match self.config.and_then(|c| c.llm_cmd.as_ref()) {
+            Some(LlmCmdConfig::Multiple(presets)) => presets.keys().map(String::as_str).collect(),
+            _ => Vec::new(),
+        };
+        names.sort_unstable();
+        names
+    }
+
+    /// The command for a preset
+    fn resolve(&self, name: &str) -> Option<String> {
+        match self.config.and_then(|c| c.llm_cmd.as_ref()) {
+            Some(LlmCmdConfig::Multiple(presets)) => presets.get(name).cloned(),
+            _ => None,
+        }
+    }
+
+    fn switch(&mut self, streamer: &mut MinimalStreamer, model: Option<String>, llm_cmd: String) {
+        let pricing = self.config.and_then(|c| c.resolve_pricing(&llm_cmd));
+        streamer.set_llm_cmd(llm_cmd, pricing);
+        self.model = model;
+    }
+
+    /// Go back to the model in use before a one-off `/model NAME MESSAGE`
+    fn restore(&mut self, streamer: &mut MinimalStreamer) {
+        if let Some((model, llm_cmd)) = self.restore.take() {
+            self.switch(streamer, model, llm_cmd);
+        }
+    }
+
+    /// Keep the prompt for `message` within the `context-budget` by dropping the oldest
+    /// exchanges from the context, or with `context-overflow = "summarize"`, replacing them
+    /// with a summary. Saved and exported chats keep every message.
+    fn fit_context(&mut self, message: &str, streamer: &mut MinimalStreamer) {
+        let budget = self.config.and_then(|c| c.context_budget).unwrap_or(DEFAULT_CONTEXT_BUDGET);
+        let conversation = &self.conversation;
+        let mut first = conversation.first;
+        while first < conversation.turns.len() && estimate_tokens(&conversation.prompt_from(first, message)) > budget {
+            // A message and its reply go together
+            first += 2;
+        }
+        let first = first.min(conversation.turns.len());
+        let count = first - conversation.first;
+        if count == 0 {
+            return;
+        }
+
+        let summarize = self.config.and_then(|c| c.context_overflow.as_deref()) == Some("summarize");
+        if summarize {
+            let mut transcript = conversation.summary.as_ref().map_or(String::new(), |summary| format!("{}\n\n", summary.trim()));
+            for (role, text) in &conversation.turns[conversation.first..first] {
+                transcript.push_str(&format!("{}: {}\n\n", role.label(), text.trim()));
+            }
+            let prompt = format!("{}{}", SUMMARY_PROMPT, transcript);
+            match streamer.complete(&prompt) {
+                Ok(summary) if !summary.trim().is_empty() => {
+                    eprintln!("Summarized the {} oldest messages to keep the context under ~{} tokens", count, budget);
+                    self.conversation.summary = Some(summary);
+                    self.conversation.first = first;
+                    return;
+                }
+                Ok(_) => eprintln!("Warning: the summary came back empty"),
+                Err(e) => eprintln!("Warning: couldn't summarize the conversation: {}", e),
+            }
+        }
+        eprintln!("Dropped the {} oldest messages from the context to keep it under ~{} tokens", count, budget);
+        self.conversation.first = first;
+    }
+
+    fn prompt(&self) -> String {
+        match &self.model {
+            Some(model) => format!("\n{}> ", model),
+            None => "\n> ".to_string(),
+        }
+    }
+
+    /// Carry out a slash command, given without its slash, before anything is sent to the LLM
+    fn command(&mut self, line: &str, streamer: &mut MinimalStreamer) -> Result<Next> {
+        let (name, argument) = line.split_once(char::is_whitespace).map_or((line, ""), |(name, argument)| (name, argument.trim()));
+        let conversation = &mut self.conversation;
+        match name {
+            "quit" | "exit" | "q" => return Ok(Next::Quit),
+            "help" | "?" => eprintln!("{}", HELP),
+            "clear" => {
+                conversation.clear();
+                self.start_session();
+                eprintln!("Cleared the conversation");
+            }
+            "export" if argument.is_empty() => eprintln!("Usage: /export FILE"),
+            "export" => match std::fs::write(argument, conversation.to_markdown(&self.title)) {
+                Ok(()) => eprintln!("Wrote the conversation to {}", argument),
+                Err(e) => eprintln!("Couldn't export to {}: {}", argument, e),
+            },
+            "system" if argument.is_empty() => {
+                streamer.set_system(None);
+                eprintln!("Removed the instructions");
+            }
+            "system" => {
+                streamer.set_system(Some(argument.to_string()));
+                eprintln!("Instructions set for the rest of the conversation");
+            }
+            "persona" if argument.is_empty() => {
+                let mut names: Vec<&str> = self.config.and_then(|c| c.personas.as_ref()).map(|p| p.keys().map(String::as_str).collect()).unwrap_or_default();
+                names.sort_unstable();
+                if names.is_empty() {
+                    eprintln!("The config has no personas");
+                } else {
+                    eprintln!("Personas: {}", names.join(", "));
+                }
+            }
+            "persona" => match self.config.map(|c| c.persona(argument)) {
+                Some(Ok(persona)) => {
+                    let persona = persona.clone();
+                    streamer.set_system(Some(persona.prompt));
+                    eprintln!("Answering as {}", argument);
+                    if let Some(model) = persona.model {
+                        return self.command(&format!("model {}", model), streamer);
+                    }
+                }
+                Some(Err(e)) => eprintln!("{}", e),
+                None => eprintln!("The config has no personas"),
+            },
+            "retry" => match conversation.take_last() {
+                Some(message) => return Ok(Next::Send(message)),
+                None => eprintln!("There's no reply to retry yet"),
+            },
+            "save" if argument.is_empty() => eprintln!("Usage: /save FILE"),
+            "save" => match conversation.last_reply() {
+                // A bad path shouldn't end the chat
+                Some(reply) => match std::fs::write(argument, format!("{}\n", reply.trim_end())) {
+                    Ok(()) => eprintln!("Saved the last reply to {}", argument),
+                    Err(e) => eprintln!("Couldn't save to {}: {}", argument, e),
+                },
+                None => eprintln!("There's no reply to save yet"),
+            },
+            "model" if argument.is_empty() => {
+                let presets = self.presets();
+                if presets.is_empty() {
+                    eprintln!("Answering with `{}`; /model COMMAND runs another", streamer.llm_cmd().unwrap_or("nothing"));
+                } else {
+                    let current = self.model.as_deref().map_or(String::new(), |model| format!(" (using {})", model));
+                    eprintln!("Presets: {}{}", presets.join(", "), current);
+                }
+            }
+            "model" if self.presets().is_empty() => {
+                // Without presets, the argument is the command to run, shown in the prompt by
+                // its program name
+                let program = argument.split_whitespace().next().unwrap_or(argument);
+                let label = Path::new(program).file_name().and_then(|name| name.to_str()).unwrap_or(program).to_string();
+                eprintln!("Answering with `{}`", argument);
+                self.restore = None;
+                self.switch(streamer, Some(label), argument.to_string());
+            }
+            "model" => {
+                // A message after the preset goes to it alone
+                let (model, message) = argument.split_once(char::is_whitespace).map_or((argument, ""), |(model, message)| (model, message.trim()));
+                let Some(llm_cmd) = self.resolve(model) else {
+                    eprintln!("No llm-cmd preset named \"{}\" (there are {})", model, self.presets().join(", "));
+                    return Ok(Next::Read);
+                };
+                if message.is_empty() {
+                    eprintln!("Answering with `{}`", llm_cmd);
+                    self.restore = None;
+                    self.switch(streamer, Some(model.to_string()), llm_cmd);
+                    return Ok(Next::Read);
+                }
+                self.restore = streamer.llm_cmd().map(|previous| (self.model.clone(), previous.to_string()));
+                self.switch(streamer, Some(model.to_string()), llm_cmd);
+                return Ok(Next::Send(message.to_string()));
+            }
+            _ => eprintln!("Unknown command /{} (/help lists them)", name),
+        }
+        Ok(Next::Read)
+    }
+}
+
+/// Read messages from the terminal and stream each reply, until end of input
+pub async fn run(streamer: &mut MinimalStreamer, config: Option<&ConfigFile>) -> Result<()> {
+    eprintln!("Chat with the configured LLM. Type /help for commands, or press Ctrl-D to leave.");
+    let (_, output) = runner::terminal()?;
+    // `chat-history = false` keeps what's typed out of the history file too
+    let mut input = LineEditor::new(output, config.and_then(|c| c.chat_history).unwrap_or(true));
+    let mut chat = Chat::new(config);
+    while let Some(message) = input.read(&chat.prompt())? {
+        let message = message.trim().to_string();
+        let message = match message.strip_prefix('/') {
+            Some(line) if !line.starts_with('/') => match chat.command(line, streamer)? {
+                Next::Read => continue,
+                Next::Send(message) => message,
+                Next::Quit => break,
+            },
+            Some(line) => line.to_string(),
+            None => message,
+        };
+        if message.is_empty() {
+            continue;
+        }
+        chat.fit_context(&message, streamer);
+        let started = Instant::now();
+        streamer.restart_stats();
+        let reply = streamer.stream_query(&chat.conversation.prompt(&message)).await;
+        if reply.is_ok() {
+            streamer.print_stats();
+        }
+        streamer.notify_finished("The reply", started.elapsed());
+        chat.restore(streamer);
+        chat.push(message, reply?);
+    }
+    Ok(())
+}
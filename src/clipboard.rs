@@ -0,0 +1,49 @@
+//! Clipboard access through the terminal (OSC 52), which also works over SSH
+
+use std::io::Write;
+
+/// Ask the terminal to put `text` on the system clipboard. Terminals that don't support
+/// OSC 52 (or have it disabled) silently ignore the request.
+pub fn copy(text: &str) -> std::io::Result<()> {
+    let mut sequence = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    if std::env::var_os("TMUX").is_some() {
+        // tmux only forwards escape sequences wrapped in a DCS passthrough
+        sequence = format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"));
+    }
+    // Prefer the terminal itself, so the request isn't lost when stdout is piped
+    match std::fs::OpenOptions::new().write(true).open(tty_path()) {
+        Ok(mut tty) => tty.write_all(sequence.as_bytes()),
+        Err(_) => {
+            let mut stdout = std::io::stdout();
+            stdout.write_all(sequence.as_bytes())?;
+            stdout.flush()
+        }
+    }
+}
+
+#[cfg(unix)]
+fn tty_path() -> &'static str {
+    "/dev/tty"
+}
+
+#[cfg(windows)]
+fn tty_path() -> &'static str {
+    "CONOUT$"
+}
+
+/// Standard base64 with padding
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,64 @@
+//! Editor color themes (TextMate `.tmTheme`, as used by bat and Sublime Text) for code blocks
+
+use crate::error::{LivemdError, Result};
+use std::path::PathBuf;
+
+/// Code block colors taken from a tmTheme's global settings. livemd has no syntax
+/// highlighter, so only the background and default foreground are used.
+#[derive(Debug, Clone, Default)]
+pub struct CodeTheme {
+    /// Editor background, as `#RRGGBB`
+    pub background: Option<String>,
+    /// Default text color, as `#RRGGBB`
+    pub foreground: Option<String>,
+}
+
+impl CodeTheme {
+    /// Load a theme from a `.tmTheme` path, or by name from bat's themes directory
+    pub fn load(name_or_path: &str) -> Result<Self> {
+        let path = Self::resolve(name_or_path)
+            .ok_or_else(|| LivemdError::Theme(format!("code theme not found: {} (bat's built-in themes can't be read; use a .tmTheme file)", name_or_path)))?;
+        let content = std::fs::read_to_string(&path)?;
+        let theme = Self::parse(&content);
+        if theme.background.is_none() && theme.foreground.is_none() {
+            return Err(LivemdError::Theme(format!("no background or foreground color found in {:?}", path)));
+        }
+        Ok(theme)
+    }
+
+    /// Find a theme file: an existing path, or `<name>.tmTheme` in bat's config directory
+    fn resolve(name_or_path: &str) -> Option<PathBuf> {
+        let path = match name_or_path.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir()?.join(rest),
+            None => PathBuf::from(name_or_path),
+        };
+        if path.is_file() {
+            return Some(path);
+        }
+        let bat_dir = std::env::var_os("BAT_CONFIG_DIR")
+            .map(PathBuf::from)
+            .or_else(|| dirs::config_dir().map(|d| d.join("bat")))?;
+        let themed = bat_dir.join("themes").join(format!("{}.tmTheme", name_or_path));
+        themed.is_file().then_some(themed)
+    }
+
+    /// Read the global colors from tmTheme plist XML. The first settings dict without a
+    /// `scope` holds them, and it comes before any scoped rules.
+    fn parse(content: &str) -> Self {
+        let global = content.split("<key>scope</key>").next().unwrap_or(content);
+        Self {
+            background: plist_color(global, "background"),
+            foreground: plist_color(global, "foreground"),
+        }
+    }
+}
+
+/// The `#RRGGBB` value of `<key>name</key><string>#...</string>`, dropping any alpha channel
+fn plist_color(xml: &str, name: &str) -> Option<String> {
+    let key = format!("<key>{}</key>", name);
+    let rest = &xml[xml.find(&key)? + key.len()..];
+    let value = rest.trim_start().strip_prefix("<string>")?;
+    let value = value[..value.find("</string>")?].trim();
+    let hex = value.strip_prefix('#')?;
+    (hex.len() >= 6 && hex[..6].chars().all(|c| c.is_ascii_hexdigit())).then(|| format!("#{}", &hex[..6]))
+}
@@ -0,0 +1,163 @@
+//! Subcommands for managing livemd itself, rather than streaming content
+
+use crate::config::{ConfigFile, StreamerConfig};
+use crate::error::{LivemdError, Result};
+use crate::streamer::MinimalStreamer;
+use crate::theme::Theme;
+
+/// Sample document rendered by `themes preview`, covering every themed element
+const PREVIEW_DOC: &str = include_str!("assets/preview.md");
+
+/// Starter config written by `config init`, with every setting commented out
+const STARTER_CONFIG: &str = include_str!("assets/config.toml");
+
+/// Print the built-in themes and any custom themes, marking the active one
+pub fn list_themes(config: &StreamerConfig) {
+    let active_file = config.theme_file.as_deref().and_then(|p| p.file_stem()).and_then(|s| s.to_str());
+    let marker = |active: bool| if active { "*" } else { " " };
+
+    println!("Built-in themes:");
+    for name in Theme::BUILTIN_NAMES {
+        let active = active_file.is_none() && config.theme_name == name;
+        println!("  {} {}", marker(active), name);
+    }
+
+    let custom = Theme::custom_names();
+    if let (Some(dir), false) = (Theme::themes_dir(), custom.is_empty()) {
+        println!("\nCustom themes ({}):", dir.display());
+        for name in custom {
+            let active = active_file == Some(name.as_str());
+            println!("  {} {}", marker(active), name);
+        }
+    }
+}
+
+/// Render the sample document with a built-in theme, a custom theme name, or a theme file path
+pub async fn preview_theme(name: &str, mut config: StreamerConfig) -> Result<()> {
+    if Theme::builtin(name).is_some() {
+        config.theme_name = name.to_string();
+        config.theme_file = None;
+    } else {
+        let path = Theme::find_file(name).ok_or_else(|| LivemdError::Theme(format!("Unknown theme: {}", name)))?;
+        // Surface errors in the theme itself rather than falling back to a built-in
+        Theme::from_file(&path)?;
+        config.theme_file = Some(path);
+    }
+    MinimalStreamer::new(config).stream_text(PREVIEW_DOC).await
+}
+
+/// Print a theme as JSON, as a starting point for a custom theme. Custom themes are printed
+/// with any `"extends"` chain resolved.
+pub fn export_theme(name: &str) -> Result<()> {
+    let theme = match Theme::builtin(name) {
+        Some(theme) => theme,
+        None => {
+            let path = Theme::find_file(name).ok_or_else(|| LivemdError::Theme(format!("Unknown theme: {}", name)))?;
+            Theme::from_file(&path)?
+        }
+    };
+    let json = serde_json::to_string_pretty(&theme).map_err(|e| LivemdError::Theme(e.to_string()))?;
+    println!("{}", json);
+    Ok(())
+}
+
+/// Write a commented starter config.toml to the config directory
+pub fn init_config(force: bool) -> Result<()> {
+    let dir = crate::config::config_dir().ok_or_else(|| LivemdError::Config("can't find a config directory".to_string()))?;
+    let path = dir.join("config.toml");
+    let existing = [path.clone(), dir.join("config.json")].into_iter().find(|p| p.exists());
+    if let (Some(existing), false) = (existing, force) {
+        return Err(LivemdError::Config(format!("{} already exists (use --force to replace it)", existing.display())));
+    }
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(&path, STARTER_CONFIG)?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Check a config file, or the global and project config files, and print any problems with
+/// their line numbers. Returns whether there were none.
+pub fn validate_config(file: Option<&std::path::Path>) -> Result<bool> {
+    let paths: Vec<_> = match file {
+        Some(file) => vec![file.to_path_buf()],
+        None => [Some(ConfigFile::global_path()), ConfigFile::project_path()].into_iter().flatten().filter(|p| p.is_file()).collect(),
+    };
+    if paths.is_empty() {
+        println!("No config files found (looked for {})", ConfigFile::global_path().display());
+        return Ok(true);
+    }
+    let mut count = 0;
+    for path in &paths {
+        let problems = ConfigFile::check(path).map_err(|e| LivemdError::Config(format!("can't read {}: {}", path.display(), e)))?;
+        if problems.is_empty() {
+            println!("{}: ok", path.display());
+        }
+        for problem in &problems {
+            println!("{}", problem.located(path));
+        }
+        count += problems.len();
+    }
+    Ok(count == 0)
+}
+
+/// Print the settings in effect, after config files, profile, environment, flags, and
+/// detection, as TOML in config file keys. `sources` lists where settings came from.
+pub fn show_config(config: &StreamerConfig, sources: &[String], code_theme: Option<&str>) {
+    // TOML strings are JSON strings
+    let quote = |s: &str| serde_json::to_string(s).unwrap_or_default();
+    println!("# Settings in effect, from {}", sources.join(", "));
+    let mut unset = Vec::new();
+    let mut optional = |key: &str, value: Option<String>| match value {
+        Some(value) => println!("{} = {}", key, value),
+        None => unset.push(key.to_string()),
+    };
+    println!("theme = {}", quote(&config.theme_name));
+    optional("theme-file", config.theme_file.as_ref().map(|p| quote(&p.to_string_lossy())));
+    println!("shell = {}", quote(&config.shell));
+    optional("llm-cmd", config.llm_cmd.as_deref().map(quote));
+    println!("inject-md-instruction = {}", config.inject_md_instruction);
+    println!("cache = {}", config.cache);
+    optional("cache-ttl", config.cache_ttl.map(|ttl| ttl.to_string()));
+    println!("usage-footer = {}", config.usage_footer);
+    optional("notify", config.notify.map(|how| quote(&format!("{:?}", how).to_lowercase())));
+    println!("notify-after = {}", config.notify_after.as_secs());
+    println!("speed = {}", config.speed);
+    println!("chunk-size = {}", config.chunk_size);
+    println!("granularity = {}", quote(&format!("{:?}", config.granularity).to_lowercase()));
+    println!("live = {}", config.live);
+    optional("width", config.width.map(|w| w.to_string()));
+    println!("center = {}", config.center);
+    println!("margin = {}", config.margin);
+    println!("pager = {}", config.pager);
+    println!("table-style = {}", quote(&format!("{:?}", config.table_style).to_lowercase()));
+    println!("stream-tables = {}", config.stream_tables);
+    println!("strip-boxes = {}", config.strip_boxes);
+    println!("color = {}", quote(if config.color { "always" } else { "never" }));
+    let depth = match config.color_depth {
+        crate::terminal::ColorDepth::TrueColor => "truecolor",
+        crate::terminal::ColorDepth::Ansi256 => "256",
+        crate::terminal::ColorDepth::Ansi16 => "16",
+    };
+    println!("color-depth = {}", quote(depth));
+    optional("code-theme", code_theme.filter(|_| config.code_theme.is_some()).map(quote));
+    println!("code-frame = {}", config.code_frame);
+    println!("pretty-json = {}", config.pretty_json);
+    println!("hyperlinks = {}", config.hyperlinks);
+    println!("link-style = {}", quote(&format!("{:?}", config.link_style).to_lowercase()));
+    match config.images {
+        Some(protocol) => println!("images = true  # {:?} protocol", protocol),
+        None => println!("images = false"),
+    }
+    println!("images-remote = {}", config.images_remote);
+    optional("image-width", config.image_width.map(|w| w.to_string()));
+    println!("frontmatter = {}", quote(&format!("{:?}", config.frontmatter).to_lowercase()));
+    if let Some(pricing) = config.pricing {
+        println!("# pricing for this command: ${} prompt, ${} completion per million tokens", pricing.prompt, pricing.completion);
+    }
+    if let Some(system) = &config.system {
+        println!("# sent ahead of each query: {}", quote(system));
+    }
+    if !unset.is_empty() {
+        println!("# not set: {}", unset.join(", "));
+    }
+}
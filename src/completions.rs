@@ -0,0 +1,388 @@
+//! Shell completion scripts for `livemd completions SHELL`, generated from clap's description
+//! of the command line. Theme names and LLM presets change with the user's config, so the scripts
+//! ask `livemd __complete KIND` for them as they complete.
+
+use crate::config::{ConfigFile, FrontmatterStyle, Granularity, LinkStyle, LlmCmdConfig, OutputFormat};
+use crate::csv;
+use crate::table::TableStyle;
+use crate::terminal::ColorMode;
+use crate::theme::Theme;
+use clap::{Arg, ArgAction, Command, ValueHint};
+
+/// Shells there are completion scripts for
+pub const SHELLS: [&str; 4] = ["bash", "zsh", "fish", "powershell"];
+
+/// What a flag's value or a positional argument completes to
+#[derive(Clone, Copy)]
+enum Values {
+    /// Nothing to offer; the flag takes free text or a number
+    Free,
+    Files,
+    Dirs,
+    Choices(&'static [&'static str]),
+    /// Read from `livemd __complete KIND` when completing
+    Dynamic(&'static str),
+}
+
+/// A flag: its names, help, and what its value completes to (None if it takes no value)
+struct Flag {
+    long: Option<String>,
+    short: Option<char>,
+    help: String,
+    values: Option<Values>,
+    repeated: bool,
+}
+
+/// A command or subcommand, with the words that lead to it from `livemd`
+struct Node {
+    path: Vec<String>,
+    about: String,
+    /// Flags of its own; global flags apply everywhere and are kept on the root
+    flags: Vec<Flag>,
+    positional: Option<Values>,
+    subcommands: Vec<Node>,
+}
+
+/// Values for flags that take one of a fixed set of names
+fn choices(long: &str) -> Option<&'static [&'static str]> {
+    Some(match long {
+        "output" => &OutputFormat::NAMES,
+        "color" => &ColorMode::NAMES,
+        "table-style" => &TableStyle::NAMES,
+        "link-style" => &LinkStyle::NAMES,
+        "frontmatter" => &FrontmatterStyle::NAMES,
+        "granularity" => &Granularity::NAMES,
+        "format" => &csv::FORMATS,
+        "shell" => &["sh", "bash", "zsh", "cmd", "powershell", "pwsh"],
+        _ => return None,
+    })
+}
+
+fn values(arg: &Arg) -> Values {
+    match (arg.get_long(), arg.get_id().as_str()) {
+        (Some("theme"), _) | (None, "name") => Values::Dynamic("themes"),
+        (Some("llm-cmd"), _) => Values::Dynamic("presets"),
+        (Some("persona"), _) => Values::Dynamic("personas"),
+        (None, "shell") => Values::Choices(&SHELLS),
+        (None, "id") => Values::Dynamic("chats"),
+        (long, _) => match (long.and_then(choices), arg.get_value_hint()) {
+            (Some(choices), _) => Values::Choices(choices),
+            (None, ValueHint::FilePath | ValueHint::AnyPath) => Values::Files,
+            (None, ValueHint::DirPath) => Values::Dirs,
+            _ => Values::Free,
+        },
+    }
+}
+
+fn node(command: &Command, path: Vec<String>) -> Node {
+    let mut flags: Vec<Flag> = command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set() && (path.is_empty() || !arg.is_global_set()))
+        .map(|arg| Flag {
+            long: arg.get_long().map(str::to_string),
+            short: arg.get_short(),
+            help: arg.get_help().map(|help| help.to_string()).unwrap_or_default(),
+            // A value that must follow `=`, as in `--notify=bell`, isn't completed as the next word
+            values: (arg.get_action().takes_values() && !arg.is_require_equals_set()).then(|| values(arg)),
+            repeated: matches!(arg.get_action(), ArgAction::Count | ArgAction::Append),
+        })
+        .collect();
+    if path.is_empty() {
+        // clap adds these when the command is built
+        flags.push(Flag { long: Some("help".to_string()), short: Some('h'), help: "Print help".to_string(), values: None, repeated: false });
+        flags.push(Flag { long: Some("version".to_string()), short: Some('V'), help: "Print version".to_string(), values: None, repeated: false });
+    }
+    // The root's positional is the free-form query
+    let positional = command.get_positionals().next().filter(|_| !path.is_empty()).map(values);
+    let subcommands = command
+        .get_subcommands()
+        .filter(|sub| !sub.is_hide_set())
+        .map(|sub| {
+            let mut path = path.clone();
+            path.push(sub.get_name().to_string());
+            node(sub, path)
+        })
+        .collect();
+    Node { path, about: command.get_about().map(|about| about.to_string()).unwrap_or_default(), flags, positional, subcommands }
+}
+
+/// Every node in the tree, parents first
+fn walk(node: &Node) -> Vec<&Node> {
+    let mut nodes = vec![node];
+    for sub in &node.subcommands {
+        nodes.extend(walk(sub));
+    }
+    nodes
+}
+
+/// The flag's names as typed: `-f`, `--file`
+fn names(flag: &Flag) -> Vec<String> {
+    flag.short.map(|short| format!("-{}", short)).into_iter().chain(flag.long.as_ref().map(|long| format!("--{}", long))).collect()
+}
+
+/// The completion script for a shell, or None for a shell there isn't one for
+pub fn script(shell: &str, command: &Command) -> Option<String> {
+    let root = node(command, Vec::new());
+    match shell {
+        "bash" => Some(bash(&root)),
+        "zsh" => Some(zsh(&root)),
+        "fish" => Some(fish(&root)),
+        "powershell" => Some(powershell(&root)),
+        _ => None,
+    }
+}
+
+/// Values printed by `livemd __complete KIND`, one per line
+pub fn dynamic_values(kind: &str) -> Vec<String> {
+    match kind {
+        "themes" => {
+            let mut names: Vec<String> = Theme::BUILTIN_NAMES.iter().map(|name| name.to_string()).collect();
+            names.push("auto".to_string());
+            names.extend(Theme::custom_names());
+            names
+        }
+        "presets" => {
+            let config = ConfigFile::load(None).ok().flatten();
+            let mut presets: Vec<String> = match config.and_then(|config| config.llm_cmd) {
+                Some(LlmCmdConfig::Multiple(map)) => map.into_keys().collect(),
+                _ => Vec::new(),
+            };
+            presets.sort();
+            presets
+        }
+        "chats" => {
+            let mut ids = crate::history::ids();
+            ids.push("last".to_string());
+            ids
+        }
+        "personas" => {
+            let config = ConfigFile::load(None).ok().flatten();
+            let mut personas: Vec<String> = config.and_then(|config| config.personas).map(|map| map.into_keys().collect()).unwrap_or_default();
+            personas.sort();
+            personas
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn bash_values(values: Values) -> String {
+    match values {
+        Values::Free => "return".to_string(),
+        Values::Files => "compopt -o filenames 2>/dev/null; COMPREPLY=($(compgen -f -- \"$cur\"))".to_string(),
+        Values::Dirs => "compopt -o filenames 2>/dev/null; COMPREPLY=($(compgen -d -- \"$cur\"))".to_string(),
+        Values::Choices(choices) => format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", choices.join(" ")),
+        Values::Dynamic(kind) => format!("COMPREPLY=($(compgen -W \"$(livemd __complete {} 2>/dev/null)\" -- \"$cur\"))", kind),
+    }
+}
+
+fn bash(root: &Node) -> String {
+    let nodes = walk(root);
+    let mut out = String::from("# bash completion for livemd\n_livemd() {\n    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n");
+    out.push_str("    local path=\"\" word i\n    for ((i = 1; i < COMP_CWORD; i++)); do\n        word=\"${COMP_WORDS[i]}\"\n        case \"$path:$word\" in\n");
+    for node in &nodes[1..] {
+        let (last, parents) = node.path.split_last().expect("subcommands have a name");
+        out.push_str(&format!("            \"{}:{}\") path=\"{}\" ;;\n", parents.join(" "), last, node.path.join(" ")));
+    }
+    out.push_str("        esac\n    done\n\n    case \"$prev\" in\n");
+    for node in &nodes {
+        for flag in node.flags.iter().filter(|flag| flag.values.is_some()) {
+            let values = match flag.values {
+                Some(Values::Free) | None => String::new(),
+                Some(values) => format!("{}; ", bash_values(values)),
+            };
+            out.push_str(&format!("        {}) {}return ;;\n", names(flag).join("|"), values));
+        }
+    }
+    let global: Vec<String> = root.flags.iter().flat_map(names).collect();
+    out.push_str(&format!("    esac\n\n    local flags=\"{}\"\n    case \"$path\" in\n", global.join(" ")));
+    for node in &nodes {
+        let flags: Vec<String> = std::iter::once("$flags".to_string()).chain(node.flags.iter().filter(|_| !node.path.is_empty()).flat_map(names)).collect();
+        let words = match (&node.positional, node.subcommands.is_empty()) {
+            (_, false) => format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", node.subcommands.iter().map(|sub| sub.path.last().unwrap().as_str()).collect::<Vec<_>>().join(" ")),
+            (Some(values), true) => bash_values(*values),
+            (None, true) => "return".to_string(),
+        };
+        out.push_str(&format!(
+            "        \"{}\")\n            if [[ $cur == -* ]]; then\n                COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n            else\n                {}\n            fi ;;\n",
+            node.path.join(" "),
+            flags.join(" "),
+            words
+        ));
+    }
+    out.push_str("    esac\n}\ncomplete -F _livemd livemd\n");
+    out
+}
+
+/// Text for a zsh `_arguments` spec, where brackets and colons are special
+fn zsh_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "'\\''").replace('[', "\\[").replace(']', "\\]").replace(':', "\\:")
+}
+
+fn zsh_values(values: Values) -> String {
+    match values {
+        Values::Free => " ".to_string(),
+        Values::Files => "_files".to_string(),
+        Values::Dirs => "_files -/".to_string(),
+        Values::Choices(choices) => format!("({})", choices.join(" ")),
+        Values::Dynamic(kind) => format!("_livemd_dynamic {}", kind),
+    }
+}
+
+fn zsh_flag_specs(flag: &Flag) -> Vec<String> {
+    names(flag)
+        .into_iter()
+        .map(|name| {
+            let value = flag.values.map(|values| format!(":value:{}", zsh_values(values))).unwrap_or_default();
+            format!("'{}{}[{}]{}'", if flag.repeated { "*" } else { "" }, name, zsh_escape(&flag.help), value)
+        })
+        .collect()
+}
+
+fn zsh(root: &Node) -> String {
+    let mut out = String::from("#compdef livemd\n\n_livemd_dynamic() {\n    local -a values\n    values=(${(f)\"$(livemd __complete $1 2>/dev/null)\"})\n    _describe $1 values\n}\n");
+    let global: Vec<String> = root.flags.iter().flat_map(zsh_flag_specs).collect();
+    out.push_str(&format!("\n_livemd_flags=(\n    {}\n)\n", global.join("\n    ")));
+    for node in walk(root) {
+        let name = std::iter::once("_livemd").chain(node.path.iter().map(String::as_str)).collect::<Vec<_>>().join("_").replace('-', "_");
+        let mut specs = vec!["$_livemd_flags".to_string()];
+        if !node.path.is_empty() {
+            specs.extend(node.flags.iter().flat_map(zsh_flag_specs));
+        }
+        out.push_str(&format!("\n{}() {{\n", name));
+        if node.subcommands.is_empty() {
+            if let Some(values) = node.positional {
+                specs.push(format!("'*:argument:{}'", zsh_values(values)));
+            }
+            out.push_str(&format!("    _arguments -s \\\n        {}\n}}\n", specs.join(" \\\n        ")));
+            continue;
+        }
+        specs.push("'1: :->command'".to_string());
+        specs.push("'*:: :->args'".to_string());
+        out.push_str("    local context state state_descr line\n    typeset -A opt_args\n");
+        out.push_str(&format!("    _arguments -s -C \\\n        {}\n", specs.join(" \\\n        ")));
+        out.push_str("    case $state in\n        command)\n            local -a commands=(\n");
+        for sub in &node.subcommands {
+            out.push_str(&format!("                '{}:{}'\n", sub.path.last().unwrap(), zsh_escape(&sub.about)));
+        }
+        out.push_str("            )\n            _describe command commands ;;\n        args)\n            case $line[1] in\n");
+        for sub in &node.subcommands {
+            out.push_str(&format!("                {}) {}_{} ;;\n", sub.path.last().unwrap(), name, sub.path.last().unwrap().replace('-', "_")));
+        }
+        out.push_str("            esac ;;\n    esac\n}\n");
+    }
+    out.push_str("\n_livemd \"$@\"\n");
+    out
+}
+
+/// Text in a single-quoted fish string
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn fish_values(values: Values) -> String {
+    match values {
+        Values::Free => "-x".to_string(),
+        Values::Files => "-r -F".to_string(),
+        Values::Dirs => "-x -a '(__fish_complete_directories)'".to_string(),
+        Values::Choices(choices) => format!("-x -a '{}'", choices.join(" ")),
+        Values::Dynamic(kind) => format!("-x -a '(livemd __complete {} 2>/dev/null)'", kind),
+    }
+}
+
+fn fish(root: &Node) -> String {
+    let mut out = String::from("# fish completion for livemd\ncomplete -c livemd -f\n");
+    for node in walk(root) {
+        // The condition for this node's words: its own name seen, none of its subcommands yet
+        let condition = match node.path.last() {
+            None => "__fish_use_subcommand".to_string(),
+            Some(name) => {
+                let subs: Vec<&str> = node.subcommands.iter().map(|sub| sub.path.last().unwrap().as_str()).collect();
+                if subs.is_empty() {
+                    format!("__fish_seen_subcommand_from {}", name)
+                } else {
+                    format!("__fish_seen_subcommand_from {}; and not __fish_seen_subcommand_from {}", name, subs.join(" "))
+                }
+            }
+        };
+        for sub in &node.subcommands {
+            out.push_str(&format!("complete -c livemd -n '{}' -a {} -d '{}'\n", condition, sub.path.last().unwrap(), fish_escape(&sub.about)));
+        }
+        for flag in &node.flags {
+            let mut line = String::from("complete -c livemd");
+            if let Some(name) = node.path.last() {
+                line.push_str(&format!(" -n '__fish_seen_subcommand_from {}'", name));
+            }
+            if let Some(short) = flag.short {
+                line.push_str(&format!(" -s {}", short));
+            }
+            if let Some(long) = &flag.long {
+                line.push_str(&format!(" -l {}", long));
+            }
+            line.push_str(&format!(" -d '{}'", fish_escape(&flag.help)));
+            if let Some(values) = flag.values {
+                line.push(' ');
+                line.push_str(&fish_values(values));
+            }
+            out.push_str(&line);
+            out.push('\n');
+        }
+        // A positional's values are offered as arguments; -r and -x only mean something for flags
+        let positional = match node.positional {
+            Some(Values::Files) => Some("-F".to_string()),
+            Some(Values::Free) | None => None,
+            Some(values) => Some(fish_values(values).replace("-x ", "")),
+        };
+        if let (Some(positional), Some(name)) = (positional, node.path.last()) {
+            out.push_str(&format!("complete -c livemd -n '__fish_seen_subcommand_from {}' {}\n", name, positional));
+        }
+    }
+    out
+}
+
+/// Words in a PowerShell array: `'a','b'`
+fn ps_list(words: impl IntoIterator<Item = String>) -> String {
+    words.into_iter().map(|word| format!("'{}'", word.replace('\'', "''"))).collect::<Vec<_>>().join(",")
+}
+
+fn ps_values(values: Values) -> String {
+    match values {
+        // Returning nothing lets PowerShell fall back to completing paths
+        Values::Free | Values::Files | Values::Dirs => "@()".to_string(),
+        Values::Choices(choices) => ps_list(choices.iter().map(|choice| choice.to_string())),
+        Values::Dynamic(kind) => format!("@(livemd __complete {} 2>$null)", kind),
+    }
+}
+
+fn powershell(root: &Node) -> String {
+    let nodes = walk(root);
+    let mut out = String::from(
+        "# PowerShell completion for livemd\nRegister-ArgumentCompleter -Native -CommandName livemd -ScriptBlock {\n    param($wordToComplete, $commandAst, $cursorPosition)\n    $words = @($commandAst.CommandElements | Select-Object -Skip 1 | ForEach-Object { $_.ToString() })\n    # The word being completed isn't part of the command so far\n    if ($wordToComplete -and $words.Count) { $words = @($words | Select-Object -SkipLast 1) }\n    $prev = if ($words.Count) { $words[-1] } else { '' }\n    $path = ''\n    foreach ($word in $words) {\n        switch -CaseSensitive (\"${path}:$word\") {\n",
+    );
+    for node in &nodes[1..] {
+        let (last, parents) = node.path.split_last().expect("subcommands have a name");
+        out.push_str(&format!("            '{}:{}' {{ $path = '{}' }}\n", parents.join(" "), last, node.path.join(" ")));
+    }
+    out.push_str("        }\n    }\n    $values = switch -CaseSensitive ($prev) {\n");
+    for node in &nodes {
+        for flag in node.flags.iter().filter(|flag| flag.values.is_some()) {
+            for name in names(flag) {
+                out.push_str(&format!("        '{}' {{ {} }}\n", name, ps_values(flag.values.unwrap_or(Values::Free))));
+            }
+        }
+    }
+    out.push_str("        default {\n            switch ($path) {\n");
+    let global: Vec<String> = root.flags.iter().flat_map(names).collect();
+    for node in &nodes {
+        let flags = global.iter().cloned().chain(node.flags.iter().filter(|_| !node.path.is_empty()).flat_map(names));
+        let words = if node.subcommands.is_empty() {
+            node.positional.map(ps_values).unwrap_or_else(|| "@()".to_string())
+        } else {
+            ps_list(node.subcommands.iter().map(|sub| sub.path.last().unwrap().clone()))
+        };
+        out.push_str(&format!("                '{}' {{ @({}) + @({}) }}\n", node.path.join(" "), words, ps_list(flags)));
+    }
+    out.push_str(
+        "            }\n        }\n    }\n    $values | Where-Object { $_ -like \"$wordToComplete*\" } | ForEach-Object {\n        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)\n    }\n}\n",
+    );
+    out
+}
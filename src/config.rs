@@ -1,7 +1,14 @@
 //! Configuration handling
+use crate::code_theme::CodeTheme;
+use crate::error::LivemdError;
+use crate::image::Protocol;
+use crate::table::TableStyle;
+use crate::terminal::{ColorDepth, ColorMode};
+use crate::theme::Theme;
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// LLM command configuration - either a single command or multiple named commands
 #[derive(Debug, Deserialize)]
@@ -13,18 +20,172 @@ pub enum LlmCmdConfig {
     Multiple(HashMap<String, String>),
 }
 
+/// A role for the LLM, chosen with `--persona` or `/persona` in chat
+#[derive(Debug, Clone, Deserialize)]
+pub struct Persona {
+    /// Instructions sent ahead of every query
+    pub prompt: String,
+    /// The `llm-cmd` preset (or command) to answer with
+    pub model: Option<String>,
+}
+
+/// Per-model pricing in dollars per million tokens
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Pricing {
+    /// Price of prompt (input) tokens
+    pub prompt: f64,
+    /// Price of completion (output) tokens
+    pub completion: f64,
+}
+
+/// How link targets are shown next to link text
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LinkStyle {
+    /// `text (url)`, or a clickable link where hyperlinks are supported
+    #[default]
+    Inline,
+    /// `text[1]`, with the numbered URLs listed at the end of the output
+    Footnote,
+    /// Only the text
+    Hidden,
+}
+
+impl LinkStyle {
+    /// Names accepted by `--link-style` and the `link-style` setting
+    pub const NAMES: [&'static str; 3] = ["inline", "footnote", "hidden"];
+
+    /// Parse a style name from config or the command line, defaulting to inline
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "footnote" => LinkStyle::Footnote,
+            "hidden" => LinkStyle::Hidden,
+            _ => LinkStyle::Inline,
+        }
+    }
+}
+
+/// How YAML frontmatter at the start of a document is shown
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum FrontmatterStyle {
+    /// A key/value table
+    #[default]
+    Table,
+    /// Not at all
+    Hidden,
+}
+
+impl FrontmatterStyle {
+    /// Names accepted by `--frontmatter` and the `frontmatter` setting
+    pub const NAMES: [&'static str; 2] = ["table", "hidden"];
+
+    /// Parse a style name from config or the command line, defaulting to a table
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "hidden" => FrontmatterStyle::Hidden,
+            _ => FrontmatterStyle::Table,
+        }
+    }
+}
+
+/// How to say a long response or command has finished
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Notify {
+    /// Ring the terminal bell, which many terminals turn into a flag on the window or tab
+    Bell,
+    /// A desktop notification, or the bell where there's no way to send one
+    Desktop,
+}
+
+impl Notify {
+    /// Names accepted by `--notify` and the `notify` setting
+    pub const NAMES: [&'static str; 2] = ["bell", "desktop"];
+
+    /// Parse a notification kind from config or the command line, defaulting to the desktop
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "bell" => Notify::Bell,
+            _ => Notify::Desktop,
+        }
+    }
+}
+
+/// How finely flushed output is revealed on the terminal
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Granularity {
+    /// A character at a time, like a typewriter
+    Char,
+    /// A word at a time
+    Word,
+    /// A sentence at a time
+    Sentence,
+    /// Whole blocks as soon as they are complete
+    #[default]
+    Block,
+}
+
+impl Granularity {
+    /// Names accepted by `--granularity` and the `granularity` setting
+    pub const NAMES: [&'static str; 4] = ["char", "word", "sentence", "block"];
+
+    /// Parse a granularity name from config or the command line, defaulting to blocks
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "char" => Granularity::Char,
+            "word" => Granularity::Word,
+            "sentence" => Granularity::Sentence,
+            _ => Granularity::Block,
+        }
+    }
+}
+
+/// What livemd writes to stdout
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputFormat {
+    /// Styled text for the terminal
+    #[default]
+    Terminal,
+    /// One JSON object per parsed block, a line each
+    Json,
+    /// A standalone HTML page styled with the theme
+    Html,
+    /// A roff man page
+    Man,
+}
+
+impl OutputFormat {
+    /// Names accepted by `--output`
+    pub const NAMES: [&'static str; 4] = ["terminal", "json", "html", "man"];
+
+    /// Parse a format name from the command line, defaulting to the terminal
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "json" => OutputFormat::Json,
+            "html" => OutputFormat::Html,
+            "man" => OutputFormat::Man,
+            _ => OutputFormat::Terminal,
+        }
+    }
+}
+
 /// Configuration file structure
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ConfigFile {
     /// Path to theme file (relative to config directory)
     #[serde(rename = "theme-file")]
     pub theme_file: Option<String>,
+    /// Shell for `--cmd` and `livemd run` command lines
+    #[serde(rename = "shell")]
+    pub shell: Option<String>,
     /// Default LLM command
     #[serde(rename = "llm-cmd")]
     pub llm_cmd: Option<LlmCmdConfig>,
     /// Default streaming speed
     #[serde(rename = "speed")]
     pub speed: Option<f64>,
+    /// Pace and animate output (false = render everything at once)
+    #[serde(rename = "stream")]
+    pub stream: Option<bool>,
     /// Default chunk size
     #[serde(rename = "chunk-size")]
     pub chunk_size: Option<usize>,
@@ -37,34 +198,503 @@ pub struct ConfigFile {
     /// Whether to inject markdown instruction by default
     #[serde(rename = "inject-md-instruction")]
     pub inject_md_instruction: Option<bool>,
+    /// Whether to cache LLM responses
+    #[serde(rename = "cache")]
+    pub cache: Option<bool>,
+    /// Maximum age of cached responses in seconds
+    #[serde(rename = "cache-ttl")]
+    pub cache_ttl: Option<u64>,
+    /// Whether to print a usage footer after LLM responses
+    #[serde(rename = "usage-footer")]
+    pub usage_footer: Option<bool>,
+    /// How to say a long response has finished: "bell" or "desktop"
+    #[serde(rename = "notify")]
+    pub notify: Option<String>,
+    /// Seconds a response must take before it's worth a notification
+    #[serde(rename = "notify-after")]
+    pub notify_after: Option<u64>,
+    /// Pricing table keyed by LLM preset name or command
+    #[serde(rename = "pricing")]
+    pub pricing: Option<HashMap<String, Pricing>>,
+    /// Approximate tokens of conversation sent with each chat message
+    #[serde(rename = "context-budget")]
+    pub context_budget: Option<usize>,
+    /// What happens to the oldest chat messages over the budget: "drop" or "summarize"
+    #[serde(rename = "context-overflow")]
+    pub context_overflow: Option<String>,
+    /// Whether chats are saved for `livemd history`, and typed messages for recall
+    #[serde(rename = "chat-history")]
+    pub chat_history: Option<bool>,
+    /// Personas keyed by name
+    #[serde(rename = "personas")]
+    pub personas: Option<HashMap<String, Persona>>,
+    /// Maximum width of the rendered column
+    #[serde(rename = "width")]
+    pub width: Option<usize>,
+    /// Whether to center the rendered column in the terminal
+    #[serde(rename = "center")]
+    pub center: Option<bool>,
+    /// Horizontal margin in columns
+    #[serde(rename = "margin")]
+    pub margin: Option<usize>,
+    /// Table separator style (grid/compact)
+    #[serde(rename = "table-style")]
+    pub table_style: Option<String>,
+    /// Whether to render table rows as they arrive
+    #[serde(rename = "stream-tables")]
+    pub stream_tables: Option<bool>,
+    /// When to use color: always, auto, never
+    #[serde(rename = "color")]
+    pub color: Option<String>,
+    /// Override detected color support: truecolor, 256, 16
+    #[serde(rename = "color-depth")]
+    pub color_depth: Option<String>,
+    /// Editor theme for code blocks: a .tmTheme path or a bat theme name
+    #[serde(rename = "code-theme")]
+    pub code_theme: Option<String>,
+    /// Whether to draw a border around code blocks
+    #[serde(rename = "code-frame")]
+    pub code_frame: Option<bool>,
+    /// Whether to reformat minified JSON code blocks
+    #[serde(rename = "pretty-json")]
+    pub pretty_json: Option<bool>,
+    /// Whether to make links clickable with OSC 8 (unset = detect)
+    #[serde(rename = "hyperlinks")]
+    pub hyperlinks: Option<bool>,
+    /// How link targets are shown: inline, footnote, hidden
+    #[serde(rename = "link-style")]
+    pub link_style: Option<String>,
+    /// Whether to show images inline on terminals with graphics support
+    #[serde(rename = "images")]
+    pub images: Option<bool>,
+    /// Whether images with http(s) URLs are downloaded
+    #[serde(rename = "images-remote")]
+    pub images_remote: Option<bool>,
+    /// Maximum width of inline images in columns
+    #[serde(rename = "image-width")]
+    pub image_width: Option<usize>,
+    /// How YAML frontmatter is shown: "table" or "hidden"
+    #[serde(rename = "frontmatter")]
+    pub frontmatter: Option<String>,
+    /// Show output taller than the terminal in the built-in pager
+    #[serde(rename = "pager")]
+    pub pager: Option<bool>,
+    /// Preview the block being streamed before it's complete
+    #[serde(rename = "live")]
+    pub live: Option<bool>,
+    /// How finely output is revealed: "char", "word", "sentence", or "block"
+    #[serde(rename = "granularity")]
+    pub granularity: Option<String>,
+}
+
+/// Directory livemd's config and themes live in: `$LIVEMD_CONFIG_DIR`, else `livemd` under
+/// `$XDG_CONFIG_HOME`, else `~/.config/livemd` if it exists, else `livemd` in the platform's config
+/// directory (`~/Library/Application Support` on macOS, `%APPDATA%` on Windows)
+pub fn config_dir() -> Option<PathBuf> {
+    let from_env = |name: &str| std::env::var_os(name).filter(|dir| !dir.is_empty()).map(PathBuf::from);
+    if let Some(dir) = from_env("LIVEMD_CONFIG_DIR") {
+        return Some(dir);
+    }
+    // The XDG spec says relative paths are invalid and should be ignored
+    if let Some(dir) = from_env("XDG_CONFIG_HOME").filter(|dir| dir.is_absolute()) {
+        return Some(dir.join("livemd"));
+    }
+    // Where livemd has always looked, kept on platforms whose config directory is elsewhere
+    let dotconfig = dirs::home_dir().map(|h| h.join(".config").join("livemd"));
+    if let Some(dir) = dotconfig.as_ref().filter(|dir| dir.is_dir()) {
+        return Some(dir.clone());
+    }
+    dirs::config_dir().map(|dir| dir.join("livemd")).or(dotconfig)
+}
+
+/// Config keys set by `LIVEMD_*` environment variables: `LIVEMD_CHUNK_SIZE` sets `chunk-size`.
+/// Values that read as booleans or numbers are used as such unless the key wants a string.
+fn env_overrides() -> crate::error::Result<serde_json::Map<String, serde_json::Value>> {
+    let mut overrides = serde_json::Map::new();
+    for (name, value) in std::env::vars() {
+        let Some(key) = name.strip_prefix("LIVEMD_") else { continue };
+        // Not config keys: where the config is, and the default for --profile
+        if matches!(key, "CONFIG_DIR" | "PROFILE") {
+            continue;
+        }
+        let key = key.to_lowercase().replace('_', "-");
+        // Other programs' variables can share the prefix, so unknown ones are only warned about
+        if !ConfigFile::keys().contains(&key.as_str()) {
+            if let Err(message) = ConfigFile::check_setting(&key, &serde_json::Value::Null) {
+                eprintln!("Warning: {}: {}, ignoring it", name, message);
+            }
+            continue;
+        }
+        let guessed = match value.as_str() {
+            "true" => serde_json::Value::Bool(true),
+            "false" => serde_json::Value::Bool(false),
+            _ => serde_json::from_str::<serde_json::Number>(&value)
+                .map(serde_json::Value::Number)
+                .unwrap_or_else(|_| serde_json::Value::String(value.clone())),
+        };
+        let fits = |value: &serde_json::Value| serde_json::from_value::<ConfigFile>(serde_json::json!({ key.as_str(): value }));
+        let value = if fits(&guessed).is_ok() { guessed } else { serde_json::Value::String(value) };
+        ConfigFile::check_setting(&key, &value).map_err(|e| LivemdError::Config(format!("{}: {}", name, e)))?;
+        log::info!("{} sets {} to {}", name, key, value);
+        overrides.insert(key, value);
+    }
+    // A relative theme file from the environment is relative to where livemd was run
+    if let (Some(serde_json::Value::String(path)), Ok(cwd)) = (overrides.get_mut("theme-file"), std::env::current_dir()) {
+        *path = cwd.join(&*path).to_string_lossy().into_owned();
+    }
+    Ok(overrides)
+}
+
+/// Something wrong in a config file, and the line it's on when that can be told
+#[derive(Debug)]
+pub struct Problem {
+    pub line: Option<usize>,
+    /// Path to the setting at fault, empty when the whole file is
+    pub key: Vec<String>,
+    pub message: String,
+}
+
+impl Problem {
+    /// The problem as `path:line: message`, the way compilers report errors
+    pub fn located(&self, path: &Path) -> String {
+        match self.line {
+            Some(line) => format!("{}:{}: {}", path.display(), line, self.message),
+            None => format!("{}: {}", path.display(), self.message),
+        }
+    }
+}
+
+/// The first line, after line `after`, that sets `key` or starts a table named by it. Matches
+/// `key = `, `"key": `, and `[key]`/`[key.sub]`.
+fn line_of(content: &str, key: &str, after: usize) -> Option<usize> {
+    let quoted = format!("\"{}\"", key);
+    content.lines().enumerate().skip(after).find_map(|(i, line)| {
+        let line = line.trim_start().trim_start_matches('[').trim_start();
+        let rest = line.strip_prefix(quoted.as_str()).or_else(|| line.strip_prefix(key))?;
+        rest.trim_start().starts_with(['=', ':', '.', ']']).then_some(i + 1)
+    })
+}
+
+/// Accept a value from a fixed set, suggesting the closest one otherwise
+pub fn one_of(value: &str, choices: &[&str]) -> std::result::Result<(), String> {
+    if choices.contains(&value) {
+        return Ok(());
+    }
+    Err(match suggest(value, choices.iter().copied()) {
+        Some(choice) => format!("\"{}\" isn't one of {} (did you mean \"{}\"?)", value, choices.join(", "), choice),
+        None => format!("\"{}\" isn't one of {}", value, choices.join(", ")),
+    })
+}
+
+/// Accept a built-in or custom theme name, or "auto", suggesting the closest one otherwise
+pub fn check_theme(name: &str) -> std::result::Result<(), String> {
+    if name == "auto" || Theme::builtin(name).is_some() || Theme::find_file(name).is_some() {
+        return Ok(());
+    }
+    let custom = Theme::custom_names();
+    let names = Theme::BUILTIN_NAMES.iter().copied().chain(custom.iter().map(String::as_str)).chain(["auto"]);
+    Err(match suggest(name, names) {
+        Some(suggestion) => format!("no theme named \"{}\" (did you mean \"{}\"?)", name, suggestion),
+        None => format!("no theme named \"{}\"", name),
+    })
+}
+
+/// The candidate closest to a mistyped word, if any is close enough to be what was meant
+fn suggest<'a>(word: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|candidate| (distance(word, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2.max(word.chars().count() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance: the number of single-character edits between two words
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (row[j + 1] + 1).min(row[j] + 1).min(diagonal + usize::from(ca != *cb));
+            diagonal = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+/// Make a config's relative `theme-file` relative to `dir` instead of the config directory
+fn rebase_theme_file(config: &mut serde_json::Value, dir: &Path) {
+    if let Some(theme_file) = config.get_mut("theme-file") {
+        if let Some(path) = theme_file.as_str().map(Path::new).filter(|path| path.is_relative()) {
+            *theme_file = serde_json::Value::String(dir.join(path).to_string_lossy().into_owned());
+        }
+    }
+}
+
+/// Layer one config over another: objects are merged key by key, anything else is replaced
+fn merge(base: &mut serde_json::Value, layer: serde_json::Value) {
+    match (base, layer) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
 }
 
 impl ConfigFile {
-    /// Load configuration from ~/.config/livemd/config.json
-    pub fn load() -> Option<Self> {
-        let config_path = dirs::home_dir()
-            .map(|h| h.join(".config").join("livemd").join("config.json"))
-            .unwrap_or_default();
-
-        if config_path.exists() {
-            match std::fs::read_to_string(&config_path) {
-                Ok(content) => match serde_json::from_str(&content) {
-                    Ok(config) => Some(config),
-                    Err(e) => {
-                        eprintln!("Warning: Failed to parse config file {:?}: {}", config_path, e);
-                        None
+    /// Load configuration from config.toml in the config directory (or config.json if there's no
+    /// TOML file), with the nearest project config layered over it, then the named profile, then
+    /// `LIVEMD_*` environment variables
+    pub fn load(profile: Option<&str>) -> crate::error::Result<Option<Self>> {
+        let global = Self::global_path();
+        let project = Self::project_path();
+
+        let mut merged: Option<serde_json::Value> = None;
+        if global.is_file() {
+            merged = Self::read(&global);
+        } else {
+            log::info!("no config file at {}, using defaults", global.display());
+        }
+        if let Some(project) = project.filter(|path| *path != global) {
+            if let Some(mut value) = Self::read(&project) {
+                // A relative theme file in a project config is relative to the project
+                if let Some(dir) = project.parent() {
+                    rebase_theme_file(&mut value, dir);
+                    if let Some(profiles) = value.get_mut("profiles").and_then(|p| p.as_object_mut()) {
+                        profiles.values_mut().for_each(|profile| rebase_theme_file(profile, dir));
                     }
-                },
-                Err(e) => {
-                    eprintln!("Warning: Failed to read config file {:?}: {}", config_path, e);
-                    None
                 }
+                merged = Some(match merged {
+                    Some(mut base) => {
+                        merge(&mut base, value);
+                        base
+                    }
+                    None => value,
+                });
+            }
+        }
+
+        if let Some(name) = profile {
+            let base = merged.get_or_insert_with(|| serde_json::json!({}));
+            let layer = base.get("profiles").and_then(|profiles| profiles.get(name)).cloned().ok_or_else(|| {
+                let names: Vec<&String> = base.get("profiles").and_then(|p| p.as_object()).map(|p| p.keys().collect()).unwrap_or_default();
+                LivemdError::Config(if names.is_empty() {
+                    format!("no profile named \"{}\": the config has no profiles", name)
+                } else {
+                    format!("no profile named \"{}\" (profiles: {})", name, names.iter().map(|n| n.as_str()).collect::<Vec<_>>().join(", "))
+                })
+            })?;
+            log::info!("using profile {}", name);
+            merge(base, layer);
+        }
+
+        let env = env_overrides()?;
+        if !env.is_empty() {
+            merge(merged.get_or_insert_with(|| serde_json::json!({})), serde_json::Value::Object(env));
+        }
+
+        let Some(mut merged) = merged else { return Ok(None) };
+        // Profiles have been applied and aren't settings themselves
+        if let Some(table) = merged.as_object_mut() {
+            table.remove("profiles");
+        }
+        let config = serde_json::from_value(merged).map_err(|e| match profile {
+            Some(name) => LivemdError::Config(format!("profile \"{}\": {}", name, e)),
+            None => LivemdError::Config(e.to_string()),
+        })?;
+        log::debug!("config file: {:?}", config);
+        Ok(Some(config))
+    }
+
+    /// The global config file, whether or not it exists
+    pub fn global_path() -> PathBuf {
+        Self::file_in(&config_dir().unwrap_or_default(), "config")
+    }
+
+    /// The nearest project config file, looking up from the current directory
+    pub fn project_path() -> Option<PathBuf> {
+        let cwd = std::env::current_dir().ok()?;
+        cwd.ancestors().map(|dir| Self::file_in(dir, ".livemd")).find(|path| path.is_file())
+    }
+
+    /// `<stem>.toml` in a directory, or `<stem>.json` if there's no TOML file
+    fn file_in(dir: &Path, stem: &str) -> PathBuf {
+        let toml_path = dir.join(format!("{}.toml", stem));
+        if toml_path.exists() { toml_path } else { dir.join(format!("{}.json", stem)) }
+    }
+
+    /// Read and check one config file. Problems are printed as warnings and the settings they're
+    /// in are left out; None if the file can't be read or parsed at all.
+    fn read(path: &Path) -> Option<serde_json::Value> {
+        log::info!("reading config from {}", path.display());
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                eprintln!("Warning: Failed to read config file {:?}: {}", path, e);
+                return None;
+            }
+        };
+        let mut value = match Self::parse(&content, path) {
+            Ok(value) => value,
+            Err(problem) => {
+                eprintln!("Warning: Failed to parse config file {}", problem.located(path));
+                return None;
             }
+        };
+        for problem in Self::problems(&content, &value) {
+            eprintln!("Warning: {}, ignoring it", problem.located(path));
+            // A problem with no setting to leave out is with the whole file
+            let (last, parents) = problem.key.split_last()?;
+            let table = parents.iter().try_fold(&mut value, |table, key| table.get_mut(key));
+            if let Some(table) = table.and_then(|table| table.as_object_mut()) {
+                table.remove(last);
+            }
+        }
+        Some(value)
+    }
+
+    /// Parse config file contents, as TOML or JSON depending on the file's extension
+    fn parse(content: &str, path: &Path) -> std::result::Result<serde_json::Value, Problem> {
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            crate::toml::parse(content).map_err(|e| Problem { line: Some(e.line), key: Vec::new(), message: e.message })
         } else {
-            None
+            serde_json::from_str(content).map_err(|e| {
+                // serde_json ends its messages with the position, which is reported separately
+                let message = e.to_string();
+                let message = message.split(" at line ").next().unwrap_or_default().to_string();
+                Problem { line: Some(e.line()), key: Vec::new(), message }
+            })
         }
     }
 
+    /// Every problem in a config file: a syntax error, or each setting that's unknown, has the
+    /// wrong type, or is out of range
+    pub fn check(path: &Path) -> std::io::Result<Vec<Problem>> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(match Self::parse(&content, path) {
+            Ok(value) => Self::problems(&content, &value),
+            Err(problem) => vec![problem],
+        })
+    }
+
+    /// Problems with the settings in a parsed config file, with their lines in `content`
+    fn problems(content: &str, value: &serde_json::Value) -> Vec<Problem> {
+        let Some(table) = value.as_object() else {
+            return vec![Problem { line: None, key: Vec::new(), message: "the config must be a table of settings".to_string() }];
+        };
+        let mut problems = Self::check_keys(content, table, &[], 0);
+        if let Some(profiles) = table.get("profiles") {
+            let profiles_line = line_of(content, "profiles", 0).unwrap_or(1);
+            match profiles.as_object() {
+                Some(profiles) => {
+                    for (name, profile) in profiles {
+                        // `[profiles.work]` in TOML, `"work": {` in JSON
+                        let header = format!("profiles.{}", name);
+                        let start = content.lines().position(|line| line.contains(&header)).map(|i| i + 1).or_else(|| line_of(content, name, profiles_line - 1)).unwrap_or(profiles_line);
+                        let path = ["profiles".to_string(), name.clone()];
+                        match profile.as_object() {
+                            Some(settings) => problems.extend(Self::check_keys(content, settings, &path, start - 1)),
+                            None => problems.push(Problem { line: Some(start), key: path.to_vec(), message: format!("{}: a profile must be a table of settings", header) }),
+                        }
+                    }
+                }
+                None => problems.push(Problem { line: Some(profiles_line), key: vec!["profiles".to_string()], message: "profiles: must be a table of named profiles".to_string() }),
+            }
+        }
+        problems.sort_by_key(|problem| problem.line);
+        problems
+    }
+
+    /// Problems with the settings in a table, checked a key at a time so each is reported on its
+    /// own line. `table_path` is where the table is (empty at the top); `after` is the line it starts after.
+    fn check_keys(content: &str, table: &serde_json::Map<String, serde_json::Value>, table_path: &[String], after: usize) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        for (key, value) in table {
+            if table_path.is_empty() && key == "profiles" {
+                continue;
+            }
+            if let Err(message) = Self::check_setting(key, value) {
+                let mut path = table_path.to_vec();
+                path.push(key.clone());
+                problems.push(Problem { line: line_of(content, key, after), message: format!("{}: {}", path.join("."), message), key: path });
+            }
+        }
+        problems
+    }
+
+    /// Check one setting: that the key exists, the value has the right type, and it's in range
+    pub fn check_setting(key: &str, value: &serde_json::Value) -> std::result::Result<(), String> {
+        if !Self::keys().contains(&key) {
+            return Err(match suggest(key, Self::keys().iter().copied().chain(["profiles"])) {
+                Some(known) => format!("unknown setting (did you mean \"{}\"?)", known),
+                None => "unknown setting".to_string(),
+            });
+        }
+        serde_json::from_value::<Self>(serde_json::json!({ key: value })).map_err(|e| match key {
+            // serde can't say which shape was meant for these
+            "llm-cmd" => "must be a command, or a table of named commands".to_string(),
+            "pricing" => "must be a table of prices, each with a prompt and a completion price".to_string(),
+            "personas" => "must be a table of personas, each with a prompt and optionally a model".to_string(),
+            _ => e.to_string(),
+        })?;
+        let number = value.as_f64().unwrap_or_default();
+        let text = value.as_str().unwrap_or_default();
+        match key {
+            "speed" if !(0.0..=10.0).contains(&number) => Err(format!("{} is out of range: it's the seconds between blocks, from 0 to 10", number)),
+            "chunk-size" | "image-width" | "context-budget" if number < 1.0 => Err("must be at least 1".to_string()),
+            "context-overflow" => one_of(text, &["drop", "summarize"]),
+            "notify" => one_of(text, &Notify::NAMES),
+            "width" if number < 20.0 => Err(format!("{} is too narrow: the column must be at least 20 wide", number)),
+            "color" => one_of(text, &ColorMode::NAMES),
+            "color-depth" => one_of(text, &["truecolor", "24bit", "256", "16"]),
+            "table-style" => one_of(text, &TableStyle::NAMES),
+            "link-style" => one_of(text, &LinkStyle::NAMES),
+            "frontmatter" => one_of(text, &FrontmatterStyle::NAMES),
+            "granularity" => one_of(text, &Granularity::NAMES),
+            "theme" => check_theme(text),
+            _ => Ok(()),
+        }
+    }
+
+    /// The keys a config file can set, as serde knows them
+    fn keys() -> &'static [&'static str] {
+        use serde::de::{self, Deserializer, Visitor};
+
+        /// A deserializer that only records the field names a struct asks for
+        struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+        impl<'de> Deserializer<'de> for FieldNames<'_> {
+            type Error = de::value::Error;
+
+            fn deserialize_any<V: Visitor<'de>>(self, _: V) -> std::result::Result<V::Value, Self::Error> {
+                Err(de::Error::custom("not a struct"))
+            }
+
+            fn deserialize_struct<V: Visitor<'de>>(self, _: &'static str, fields: &'static [&'static str], _: V) -> std::result::Result<V::Value, Self::Error> {
+                *self.0 = fields;
+                Err(de::Error::custom("only the field names are read"))
+            }
+
+            serde::forward_to_deserialize_any! {
+                bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+                option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+            }
+        }
+
+        let mut fields: &'static [&'static str] = &[];
+        let _ = Self::deserialize(FieldNames(&mut fields));
+        fields
+    }
+
     /// Resolve LLM command from config and CLI arg
     pub fn resolve_llm_cmd(&self, cli_llm_cmd: Option<&str>) -> Option<String> {
         match (&self.llm_cmd, cli_llm_cmd) {
@@ -76,13 +706,42 @@ impl ConfigFile {
             (Some(LlmCmdConfig::Multiple(map)), None) => {
                 map.get("default").or_else(|| map.values().next()).cloned()
             }
-            // If config has single command
-            (Some(LlmCmdConfig::Single(cmd)), _) => Some(cmd.clone()),
-            // No config, use CLI
-            (None, Some(cmd)) => Some(cmd.to_string()),
+            // A command given with --llm-cmd or a persona's model overrides a single default
+            (Some(LlmCmdConfig::Single(_)) | None, Some(cmd)) => Some(cmd.to_string()),
+            (Some(LlmCmdConfig::Single(cmd)), None) => Some(cmd.clone()),
             (None, None) => None,
         }
     }
+
+    /// Look up a persona by name
+    pub fn persona(&self, name: &str) -> crate::error::Result<&Persona> {
+        let personas = self.personas.as_ref();
+        personas.and_then(|personas| personas.get(name)).ok_or_else(|| {
+            let mut names: Vec<&str> = personas.map(|p| p.keys().map(String::as_str).collect()).unwrap_or_default();
+            names.sort_unstable();
+            LivemdError::Config(if names.is_empty() {
+                format!("no persona named \"{}\": the config has no personas", name)
+            } else {
+                format!("no persona named \"{}\" (personas: {})", name, names.join(", "))
+            })
+        })
+    }
+
+    /// Look up pricing for a resolved LLM command, by preset name or by the command itself
+    pub fn resolve_pricing(&self, llm_cmd: &str) -> Option<Pricing> {
+        let pricing = self.pricing.as_ref()?;
+        if let Some(price) = pricing.get(llm_cmd) {
+            return Some(*price);
+        }
+        match &self.llm_cmd {
+            Some(LlmCmdConfig::Multiple(map)) => map
+                .iter()
+                .filter(|(_, cmd)| cmd.as_str() == llm_cmd)
+                .find_map(|(preset, _)| pricing.get(preset))
+                .copied(),
+            _ => None,
+        }
+    }
 }
 
 /// Configuration for the Markdown streamer
@@ -90,16 +749,92 @@ impl ConfigFile {
 pub struct StreamerConfig {
     /// Maximum chunk size before forcing a flush
     pub chunk_size: usize,
+    /// Flush at every newline instead of waiting for block boundaries
+    pub line_buffered: bool,
     /// Delay between chunks in seconds for streaming effect
     pub speed: f64,
     /// Whether to convert ASCII box drawings to Markdown headers
     pub strip_boxes: bool,
+    /// Shell that runs `--cmd` command lines
+    pub shell: String,
+    /// Directory `--cmd` commands run in (None = the current directory)
+    pub cwd: Option<PathBuf>,
+    /// Environment variables set for `--cmd` commands
+    pub env: Vec<(String, String)>,
     /// Command to invoke for LLM functionality
     pub llm_cmd: Option<String>,
+    /// Instructions sent ahead of every query, from `--persona` or `/system` in chat
+    pub system: Option<String>,
     /// Whether to inject Markdown instruction for LLM queries
     pub inject_md_instruction: bool,
     /// Theme name for color selection
     pub theme_name: String,
     /// Path to custom theme JSON file
     pub theme_file: Option<PathBuf>,
+    /// Whether to replay and store LLM responses from the cache
+    pub cache: bool,
+    /// Maximum age of cached responses in seconds
+    pub cache_ttl: Option<u64>,
+    /// Whether to print a usage footer after LLM responses
+    pub usage_footer: bool,
+    /// Pricing used to estimate the cost of a response
+    pub pricing: Option<Pricing>,
+    /// Maximum width of the rendered column (None = terminal width)
+    pub width: Option<usize>,
+    /// Whether to center the rendered column in the terminal
+    pub center: bool,
+    /// Horizontal margin in columns on each side
+    pub margin: usize,
+    /// Where table row separators are drawn
+    pub table_style: TableStyle,
+    /// Render table rows as they arrive instead of buffering whole tables
+    pub stream_tables: bool,
+    /// Whether to emit colors and text attributes
+    pub color: bool,
+    /// Colors the terminal supports; theme colors are reduced to fit
+    pub color_depth: ColorDepth,
+    /// Editor theme colors for code blocks
+    pub code_theme: Option<CodeTheme>,
+    /// Draw a border around code blocks, labeled with the language
+    pub code_frame: bool,
+    /// Reformat minified JSON code blocks with indentation
+    pub pretty_json: bool,
+    /// Emit links as OSC 8 hyperlinks instead of printing their URLs
+    pub hyperlinks: bool,
+    /// How link targets are shown next to link text
+    pub link_style: LinkStyle,
+    /// Graphics protocol for inline images (None = show placeholders)
+    pub images: Option<Protocol>,
+    /// Download images with http(s) URLs instead of showing placeholders
+    pub images_remote: bool,
+    /// Maximum width of inline images in columns (None = the column width)
+    pub image_width: Option<usize>,
+    /// How YAML frontmatter at the start of a document is shown
+    pub frontmatter: FrontmatterStyle,
+    /// Print a table of contents: before a file, or after streamed output
+    pub toc: bool,
+    /// Only render the section under the first heading containing this text
+    pub section: Option<String>,
+    /// Hold output back and show it in the built-in pager when it's taller than the terminal
+    pub pager: bool,
+    /// Preview the unfinished block in place while it streams in
+    pub live: bool,
+    /// How finely rendered output is revealed on the terminal
+    pub granularity: Granularity,
+    /// Time rendering and report throughput once the output ends
+    pub bench: bool,
+    /// Print time to first byte, duration, size, and throughput once the output ends
+    pub stats: bool,
+    /// What to write: styled text or a machine-readable format
+    pub output: OutputFormat,
+    /// Output is piped without color: draw borders in ASCII and leave out decorations
+    pub plain: bool,
+    /// Keep a copy of everything rendered, for `--export`
+    pub export: bool,
+    /// Put the Markdown source of the whole output on the clipboard once it ends
+    pub copy: bool,
+    /// Say when output that took a while has finished (None = don't)
+    pub notify: Option<Notify>,
+    /// How long output must take before it's notified
+    pub notify_after: Duration,
 }
\ No newline at end of file
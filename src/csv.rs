@@ -0,0 +1,49 @@
+//! Delimiter-separated values (CSV/TSV) parsing
+
+/// Names accepted by `--format`: Markdown, or delimited data rendered as a table
+pub const FORMATS: [&str; 3] = ["markdown", "csv", "tsv"];
+
+/// Parse delimited text into records, following RFC 4180 quoting: fields may be wrapped in
+/// double quotes, which can contain delimiters, newlines, and `""` escaped quotes
+pub fn parse_delimited(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+        } else if c == '"' && field.is_empty() {
+            in_quotes = true;
+        } else if c == delimiter {
+            record.push(std::mem::take(&mut field));
+        } else if c == '\n' || c == '\r' {
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+            record.push(std::mem::take(&mut field));
+            // Skip blank lines
+            if !(record.len() == 1 && record[0].is_empty()) {
+                records.push(std::mem::take(&mut record));
+            }
+            record.clear();
+        } else {
+            field.push(c);
+        }
+    }
+
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
@@ -0,0 +1,100 @@
+//! Counts describing a Markdown document (`livemd stats FILE`): words, headings, code
+//! blocks, tables, links, and reading time
+
+use crate::frontmatter;
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use std::collections::BTreeMap;
+
+/// Words read a minute, for the reading time
+const WORDS_PER_MINUTE: usize = 230;
+
+/// What a document holds
+#[derive(Debug, Default)]
+pub struct DocStats {
+    /// Words of prose, not counting code blocks
+    pub words: usize,
+    /// Headings at each level, H1 first
+    pub headings: [usize; 6],
+    /// Code blocks by language, with "" for blocks that don't name one
+    pub code_blocks: BTreeMap<String, usize>,
+    pub tables: usize,
+    pub links: usize,
+}
+
+impl DocStats {
+    /// Count what's in `markdown`, parsed as it would be rendered. Frontmatter isn't counted.
+    pub fn of(markdown: &str) -> Self {
+        let body = frontmatter::split(markdown).map_or(markdown, |(_, rest)| rest);
+        let mut stats = DocStats::default();
+        let mut in_code = false;
+        for event in Parser::new_ext(body, Options::all()) {
+            match event {
+                Event::Start(Tag::Heading { level, .. }) => stats.headings[level as usize - 1] += 1,
+                Event::Start(Tag::CodeBlock(kind)) => {
+                    in_code = true;
+                    let language = match kind {
+                        CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or_default().to_lowercase(),
+                        CodeBlockKind::Indented => String::new(),
+                    };
+                    *stats.code_blocks.entry(language).or_default() += 1;
+                }
+                Event::End(TagEnd::CodeBlock) => in_code = false,
+                Event::Start(Tag::Table(_)) => stats.tables += 1,
+                Event::Start(Tag::Link { .. }) => stats.links += 1,
+                Event::Text(text) if !in_code => stats.words += text.split_whitespace().count(),
+                // Inline code and math read as a word each
+                Event::Code(_) | Event::InlineMath(_) => stats.words += 1,
+                _ => {}
+            }
+        }
+        stats
+    }
+
+    /// Minutes to read the prose, rounded up
+    pub fn reading_minutes(&self) -> usize {
+        self.words.div_ceil(WORDS_PER_MINUTE)
+    }
+
+    /// The counts as aligned lines, under `name`
+    pub fn report(&self, name: &str) -> String {
+        let mut lines = vec![name.to_string()];
+        let mut row = |label: &str, value: String| lines.push(format!("  {:<14}{}", label, value));
+        row("words", self.words.to_string());
+        row(
+            "reading time",
+            match self.reading_minutes() {
+                0 => "none".to_string(),
+                1 => "about 1 minute".to_string(),
+                minutes => format!("about {} minutes", minutes),
+            },
+        );
+        let levels = self
+            .headings
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(level, count)| format!("H{} {}", level + 1, count))
+            .collect::<Vec<_>>();
+        row("headings", breakdown(self.headings.iter().sum(), &levels));
+        // Most used languages first
+        let mut languages: Vec<(&String, &usize)> = self.code_blocks.iter().collect();
+        languages.sort_by_key(|&(_, count)| std::cmp::Reverse(*count));
+        let languages = languages
+            .iter()
+            .map(|(language, count)| format!("{} {}", if language.is_empty() { "no language" } else { language.as_str() }, count))
+            .collect::<Vec<_>>();
+        row("code blocks", breakdown(self.code_blocks.values().sum(), &languages));
+        row("tables", self.tables.to_string());
+        row("links", self.links.to_string());
+        lines.join("\n")
+    }
+}
+
+/// A total, followed by its parts in parentheses when there are any
+fn breakdown(total: usize, parts: &[String]) -> String {
+    if parts.is_empty() {
+        total.to_string()
+    } else {
+        format!("{} ({})", total, parts.join(", "))
+    }
+}
@@ -0,0 +1,42 @@
+//! Writing a query in the user's editor (`livemd ask -e`), for prompts too long to type on
+//! the command line
+
+use crate::error::{LivemdError, Result};
+use std::process::Command;
+
+/// The editor used when neither `VISUAL` nor `EDITOR` is set
+const DEFAULT: &str = if cfg!(windows) { "notepad" } else { "vi" };
+
+/// Open the editor on a file holding `initial` and return what was saved, without
+/// surrounding whitespace. Quitting the editor with an error or saving nothing cancels
+/// the query.
+pub fn compose(initial: &str) -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT.to_string());
+    // The variable may carry arguments, as in `code --wait`
+    let words = shell_words::split(&editor).map_err(|e| LivemdError::Config(format!("can't parse the editor command `{}`: {}", editor, e)))?;
+    let Some((program, args)) = words.split_first() else {
+        return Err(LivemdError::Config("the editor command is empty".to_string()));
+    };
+
+    // A .md name lets the editor highlight the prompt as Markdown
+    let path = std::env::temp_dir().join(format!("livemd-prompt-{}.md", std::process::id()));
+    std::fs::write(&path, initial)?;
+    log::info!("editing the query in {} with `{}`", path.display(), editor);
+    let status = Command::new(program).args(args).arg(&path).status();
+    let text = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+
+    let status = status.map_err(|source| LivemdError::Spawn { command: program.clone(), source })?;
+    if !status.success() {
+        return Err(LivemdError::Input(format!("`{}` exited with {}; not sending the query", editor, status)));
+    }
+    let text = text?.trim().to_string();
+    if text.is_empty() {
+        return Err(LivemdError::Input("the query is empty; not sending it".to_string()));
+    }
+    Ok(text)
+}
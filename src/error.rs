@@ -0,0 +1,48 @@
+//! Errors livemd reports
+
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// What went wrong, by where it went wrong, so callers can match on it and the CLI can
+/// suggest a fix
+#[derive(Debug, Error)]
+pub enum LivemdError {
+    /// Settings that can't be used, from the config file or the command line
+    #[error("{0}")]
+    Config(String),
+    /// A theme or code theme that can't be loaded
+    #[error("{0}")]
+    Theme(String),
+    /// A command that couldn't be started
+    #[error("can't run `{command}`: {source}")]
+    Spawn { command: String, source: io::Error },
+    /// A file named for output (`--output-file`, `--export`) that couldn't be written
+    #[error("can't write {}: {source}", path.display())]
+    Output { path: PathBuf, source: io::Error },
+    /// Reading input or writing output failed
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The LLM command is missing or failed
+    #[error("{0}")]
+    Provider(String),
+    /// The input can't be shown the way it was asked for
+    #[error("{0}")]
+    Input(String),
+}
+
+impl LivemdError {
+    /// A next step to print under the error, where there's an obvious one
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            LivemdError::Config(_) => Some("check ~/.config/livemd/config.toml or config.json and the flags given (see CONFIG.md)"),
+            LivemdError::Theme(_) => Some("see `livemd themes list` for the themes livemd can find"),
+            LivemdError::Spawn { source, .. } if source.kind() == io::ErrorKind::NotFound => {
+                Some("is the command installed and on your PATH?")
+            }
+            _ => None,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, LivemdError>;
@@ -0,0 +1,133 @@
+//! Parsed blocks as JSON objects, for `--output json`
+
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use serde_json::{json, Value};
+
+/// A list item being read: its nesting depth, number in an ordered list, and task checkbox
+struct Item {
+    depth: usize,
+    number: Option<u64>,
+    checked: Option<bool>,
+}
+
+/// Parse a flushed chunk of Markdown into one JSON object per block: headings, paragraphs,
+/// list items, code blocks with their language, table rows, rules, math, and HTML. Inline
+/// styling is dropped, leaving the plain text.
+pub fn blocks(markdown: &str) -> Vec<Value> {
+    let mut blocks = Vec::new();
+    let mut text = String::new();
+    // Next number of each open list (None for bullet lists)
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut items: Vec<Item> = Vec::new();
+    let mut quote = 0usize;
+    let mut cells: Vec<String> = Vec::new();
+    let mut language: Option<String> = None;
+
+    // Paragraphs outside lists, with the blockquote depth when quoted
+    let paragraph = |text: &str, quote: usize| {
+        let mut block = json!({"type": "paragraph", "text": text.trim()});
+        if quote > 0 {
+            block["quote"] = json!(quote);
+        }
+        block
+    };
+    let item_block = |item: &Item, text: &str| {
+        let mut block = json!({"type": "list_item", "depth": item.depth, "text": text.trim()});
+        if let Some(number) = item.number {
+            block["number"] = json!(number);
+        }
+        if let Some(checked) = item.checked {
+            block["checked"] = json!(checked);
+        }
+        block
+    };
+
+    for event in Parser::new_ext(markdown, Options::all()) {
+        match event {
+            Event::Start(Tag::List(start)) => {
+                // A nested list ends the text of the item it's in
+                if let Some(item) = items.last() {
+                    if !text.trim().is_empty() {
+                        blocks.push(item_block(item, &text));
+                    }
+                    text.clear();
+                }
+                lists.push(start);
+            }
+            Event::End(TagEnd::List(_)) => {
+                lists.pop();
+            }
+            Event::Start(Tag::Item) => {
+                let number = lists.last_mut().and_then(|next| {
+                    let number = *next;
+                    *next = next.map(|n| n + 1);
+                    number
+                });
+                items.push(Item { depth: lists.len(), number, checked: None });
+            }
+            Event::TaskListMarker(checked) => {
+                if let Some(item) = items.last_mut() {
+                    item.checked = Some(checked);
+                }
+            }
+            Event::End(TagEnd::Item) => {
+                if let Some(item) = items.pop() {
+                    if !text.trim().is_empty() {
+                        blocks.push(item_block(&item, &text));
+                    }
+                }
+                text.clear();
+            }
+            Event::Start(Tag::BlockQuote(_)) => quote += 1,
+            Event::End(TagEnd::BlockQuote(_)) => quote = quote.saturating_sub(1),
+            Event::End(TagEnd::Paragraph) => {
+                if items.is_empty() {
+                    // Display math on its own makes an empty paragraph around it
+                    if !text.trim().is_empty() {
+                        blocks.push(paragraph(&text, quote));
+                    }
+                    text.clear();
+                } else {
+                    // Later paragraphs of a list item go on a new line
+                    text.push('\n');
+                }
+            }
+            Event::End(TagEnd::Heading(level)) => {
+                blocks.push(json!({"type": "heading", "level": level as usize, "text": text.trim()}));
+                text.clear();
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                language = match kind {
+                    CodeBlockKind::Fenced(lang) if !lang.is_empty() => Some(lang.to_string()),
+                    _ => None,
+                };
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                blocks.push(json!({"type": "code", "language": language.take(), "text": std::mem::take(&mut text)}));
+            }
+            Event::End(TagEnd::TableCell) => cells.push(std::mem::take(&mut text).trim().to_string()),
+            Event::End(TagEnd::TableHead) => {
+                blocks.push(json!({"type": "table_row", "header": true, "cells": std::mem::take(&mut cells)}));
+            }
+            Event::End(TagEnd::TableRow) => {
+                blocks.push(json!({"type": "table_row", "header": false, "cells": std::mem::take(&mut cells)}));
+            }
+            Event::End(TagEnd::DefinitionListTitle) => {
+                blocks.push(json!({"type": "term", "text": std::mem::take(&mut text).trim()}));
+            }
+            Event::End(TagEnd::DefinitionListDefinition) => {
+                blocks.push(json!({"type": "definition", "text": std::mem::take(&mut text).trim()}));
+            }
+            Event::End(TagEnd::HtmlBlock) => {
+                blocks.push(json!({"type": "html", "text": std::mem::take(&mut text)}));
+            }
+            Event::Rule => blocks.push(json!({"type": "rule"})),
+            Event::DisplayMath(math) => blocks.push(json!({"type": "math", "text": math.trim()})),
+            Event::Text(t) | Event::Code(t) | Event::InlineMath(t) | Event::Html(t) | Event::InlineHtml(t) => text.push_str(&t),
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak => text.push('\n'),
+            _ => {}
+        }
+    }
+    blocks
+}
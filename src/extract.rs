@@ -0,0 +1,90 @@
+//! Writing rendered code blocks out to files (--extract-code)
+
+use std::path::{Path, PathBuf};
+
+/// A code block seen in the output
+#[derive(Debug, Clone)]
+pub struct CodeBlock {
+    /// Fence language (None for indented blocks)
+    pub lang: Option<String>,
+    /// Text of the closest heading above the block
+    pub heading: Option<String>,
+    pub code: String,
+}
+
+impl CodeBlock {
+    /// Lowercased fence language without attributes (```rust,ignore gives "rust")
+    pub fn language(&self) -> Option<String> {
+        self.lang.as_deref().map(|lang| lang.split([',', ' ']).next().unwrap_or_default().to_lowercase())
+    }
+}
+
+/// Write each fenced block to `dir` as `NN-<heading>.<ext>`, returning the files written.
+/// Existing files are left alone. Shell scripts, and anything with a shebang, are made executable.
+pub fn write_blocks(blocks: &[CodeBlock], dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    let mut written = Vec::new();
+    for (i, block) in blocks.iter().enumerate() {
+        let Some(lang) = block.language() else {
+            continue;
+        };
+        let name = block.heading.as_deref().map(slug).filter(|s| !s.is_empty()).unwrap_or_else(|| "block".to_string());
+        let path = dir.join(format!("{:02}-{}.{}", i + 1, name, extension(&lang)));
+        if path.exists() {
+            eprintln!("Warning: {} already exists, skipping", path.display());
+            continue;
+        }
+        std::fs::write(&path, &block.code)?;
+        if block.code.starts_with("#!") || matches!(lang.as_str(), "sh" | "bash" | "zsh" | "shell") {
+            make_executable(&path)?;
+        }
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// File extension for a fence language
+fn extension(lang: &str) -> &str {
+    match lang {
+        "" | "text" | "txt" | "plain" | "output" => "txt",
+        "rust" => "rs",
+        "python" | "py" => "py",
+        "sh" | "bash" | "zsh" | "shell" | "console" => "sh",
+        "javascript" | "js" => "js",
+        "typescript" | "ts" => "ts",
+        "ruby" => "rb",
+        "yaml" | "yml" => "yml",
+        "markdown" | "md" => "md",
+        "c++" | "cpp" => "cpp",
+        "csharp" | "c#" => "cs",
+        "golang" | "go" => "go",
+        "kotlin" => "kt",
+        "haskell" => "hs",
+        "perl" => "pl",
+        "powershell" | "ps1" => "ps1",
+        other if other.chars().all(|c| c.is_ascii_alphanumeric()) => other,
+        _ => "txt",
+    }
+}
+
+/// Lowercase a heading into a file-name-safe slug
+fn slug(heading: &str) -> String {
+    let words: Vec<String> = heading
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let slug = words.join("-");
+    slug.chars().take(40).collect::<String>().trim_end_matches('-').to_string()
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,86 @@
+//! YAML frontmatter at the start of a document
+
+/// Split a leading `---` frontmatter block from the document. Returns the top-level fields
+/// and the rest of the text, or None when the text doesn't open with frontmatter.
+pub fn split(text: &str) -> Option<(Vec<(String, String)>, &str)> {
+    let end = block_end(text)?;
+    let block = &text[..end];
+    let start = block.find('\n')? + 1;
+    let closing = block.trim_end().rfind('\n')? + 1;
+    Some((parse(&block[start..closing]), &text[end..]))
+}
+
+/// Offset just past the closing `---` (or `...`) line. None when the text doesn't start with
+/// frontmatter or the block hasn't ended yet.
+pub fn block_end(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix("---\n").or_else(|| text.strip_prefix("---\r\n"))?;
+    // Only `key: value` content counts; a leading rule before a paragraph stays a rule
+    let first = rest.lines().next()?;
+    if !is_key_line(first) {
+        return None;
+    }
+    let mut offset = text.len() - rest.len();
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        if line.ends_with('\n') && matches!(line.trim_end(), "---" | "...") {
+            return Some(offset);
+        }
+    }
+    None
+}
+
+/// Whether the text starts like frontmatter, so the flush should wait for its closing line
+pub fn is_open(text: &str) -> bool {
+    let rest = text.strip_prefix("---\n").or_else(|| text.strip_prefix("---\r\n"));
+    match rest {
+        Some(rest) => rest.lines().next().is_none_or(is_key_line),
+        None => "---\n".starts_with(text),
+    }
+}
+
+fn is_key_line(line: &str) -> bool {
+    line.split_once(':').is_some_and(|(key, _)| {
+        !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || "_-. ".contains(c))
+    })
+}
+
+/// Read the top-level fields of simple YAML. Lists (`- item` or `[a, b]`) are joined with
+/// commas, nested maps become `key: value` pairs, and block scalars (`|`, `>`) are joined
+/// into one line.
+fn parse(yaml: &str) -> Vec<(String, String)> {
+    // (key, values, separator the values are joined with)
+    let mut fields: Vec<(String, Vec<String>, &str)> = Vec::new();
+    for line in yaml.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let nested = line.starts_with(' ') || line.starts_with('\t') || line.starts_with('-');
+        match (nested, fields.last_mut()) {
+            (true, Some((_, values, _))) => values.push(unquote(trimmed.strip_prefix("- ").unwrap_or(trimmed))),
+            _ => {
+                let Some((key, value)) = line.split_once(':') else {
+                    continue;
+                };
+                let value = value.trim();
+                let (values, separator) = match value {
+                    "|" | ">" | "|-" | ">-" => (Vec::new(), " "),
+                    _ if value.starts_with('[') && value.ends_with(']') => {
+                        let items = value[1..value.len() - 1].split(',').map(|v| unquote(v.trim()));
+                        (items.filter(|v| !v.is_empty()).collect(), ", ")
+                    }
+                    "" => (Vec::new(), ", "),
+                    _ => (vec![unquote(value)], ", "),
+                };
+                fields.push((key.trim().to_string(), values, separator));
+            }
+        }
+    }
+    fields.into_iter().map(|(key, values, separator)| (key, values.join(separator))).collect()
+}
+
+fn unquote(value: &str) -> String {
+    let quoted = value.len() >= 2
+        && ((value.starts_with('"') && value.ends_with('"')) || (value.starts_with('\'') && value.ends_with('\'')));
+    if quoted { value[1..value.len() - 1].to_string() } else { value.to_string() }
+}
@@ -0,0 +1,115 @@
+//! Saved chat sessions (`livemd history`), kept as Markdown files named by when the chat
+//! started, in ~/.local/share/livemd/chats (or the platform equivalent)
+
+use crate::error::{LivemdError, Result};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn dir() -> Option<PathBuf> {
+    Some(dirs::data_dir()?.join("livemd").join("chats"))
+}
+
+/// Where a chat session is saved as it goes
+pub struct Session {
+    path: PathBuf,
+}
+
+impl Session {
+    /// A new session named by the current time, or None when there's nowhere to save it
+    pub fn start() -> Option<Self> {
+        let dir = dir()?;
+        let (date, time) = utc(SystemTime::now());
+        let id = format!("{}-{}", date, time.replace(':', ""));
+        // Two chats started in the same second get a suffix
+        let path = (1..)
+            .map(|n| dir.join(if n == 1 { format!("{}.md", id) } else { format!("{}-{}.md", id, n) }))
+            .find(|path| !path.exists())?;
+        Some(Session { path })
+    }
+
+    /// Replace the saved copy with the conversation so far
+    pub fn save(&self, markdown: &str) {
+        if let Err(e) = self.path.parent().map_or(Ok(()), std::fs::create_dir_all).and_then(|_| std::fs::write(&self.path, markdown)) {
+            eprintln!("Warning: Failed to save the chat to {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// The title for a chat's Markdown, with the time it started
+pub fn title() -> String {
+    let (date, time) = utc(SystemTime::now());
+    format!("# Chat, {} {} UTC", date, &time[..5])
+}
+
+/// Saved chats, oldest first, as (ID, path)
+fn sessions() -> Vec<(String, PathBuf)> {
+    let Some(entries) = dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut sessions: Vec<(String, PathBuf)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+        .filter_map(|path| Some((path.file_stem()?.to_str()?.to_string(), path)))
+        .collect();
+    sessions.sort();
+    sessions
+}
+
+/// IDs of the saved chats, oldest first
+pub fn ids() -> Vec<String> {
+    sessions().into_iter().map(|(id, _)| id).collect()
+}
+
+/// Print each saved chat's ID with the start of its first message
+pub fn list() {
+    let sessions = sessions();
+    if sessions.is_empty() {
+        eprintln!("No saved chats yet");
+        return;
+    }
+    for (id, path) in sessions {
+        let text = std::fs::read_to_string(&path).unwrap_or_default();
+        // The first message is the paragraph after the first `## You`
+        let first = text
+            .split_once("\n## You\n")
+            .and_then(|(_, rest)| rest.lines().map(str::trim).find(|line| !line.is_empty()))
+            .unwrap_or_default();
+        let first: String = first.chars().take(60).collect();
+        println!("{}  {}", id, first);
+    }
+}
+
+/// Print a saved chat's Markdown. `last` is the most recent one.
+pub fn export(id: &str) -> Result<()> {
+    let sessions = sessions();
+    let found = match id {
+        "last" => sessions.last(),
+        _ => sessions.iter().find(|(name, _)| name == id),
+    };
+    let Some((_, path)) = found else {
+        return Err(LivemdError::Input(format!("no saved chat \"{}\" (`livemd history list` shows them)", id)));
+    };
+    print!("{}", std::fs::read_to_string(path)?);
+    Ok(())
+}
+
+/// A time as a UTC date (YYYY-MM-DD) and time of day (HH:MM:SS)
+fn utc(time: SystemTime) -> (String, String) {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, of_day) = (seconds / 86_400, seconds % 86_400);
+    // Days since 1970 to a civil date, after Howard Hinnant's civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (
+        format!("{:04}-{:02}-{:02}", year, month, day),
+        format!("{:02}:{:02}:{:02}", of_day / 3600, of_day % 3600 / 60, of_day % 60),
+    )
+}
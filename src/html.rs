@@ -0,0 +1,229 @@
+//! Raw HTML in markdown, translated into the events livemd already renders
+
+use crate::math;
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag, TagEnd};
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+/// Turns inline HTML tags into the matching markdown events and HTML blocks into rendered
+/// markdown. Tags without a terminal equivalent are dropped, keeping their text.
+#[derive(Default)]
+pub struct Translator {
+    /// Text inside `<code>`/`<kbd>`, shown as inline code when the tag closes
+    code: Option<String>,
+    /// Inside `<sup>` (true) or `<sub>` (false)
+    script: Option<bool>,
+    /// `<a href>` links opened and not yet closed
+    open_links: usize,
+    /// Raw HTML of the block being read
+    block: Option<String>,
+}
+
+impl Translator {
+    pub fn translate<'a>(&mut self, event: Event<'a>) -> Vec<Event<'a>> {
+        match event {
+            Event::Start(Tag::HtmlBlock) => {
+                self.block = Some(String::new());
+                Vec::new()
+            }
+            Event::Html(html) => {
+                self.block.get_or_insert_with(String::new).push_str(&html);
+                Vec::new()
+            }
+            Event::End(TagEnd::HtmlBlock) => {
+                let markdown = to_markdown(&self.block.take().unwrap_or_default());
+                Parser::new_ext(&markdown, Options::all()).map(Event::into_static).collect()
+            }
+            Event::InlineHtml(html) => self.inline_tag(&html),
+            Event::Text(text) if self.code.is_some() => {
+                self.code.get_or_insert_with(String::new).push_str(&text);
+                Vec::new()
+            }
+            Event::Text(text) => match self.script {
+                Some(true) => vec![Event::Text(math::raise(&text).into())],
+                Some(false) => vec![Event::Text(math::lower(&text).into())],
+                None => vec![Event::Text(text)],
+            },
+            event => vec![event],
+        }
+    }
+
+    fn inline_tag<'a>(&mut self, html: &str) -> Vec<Event<'a>> {
+        let Some((closing, name, attributes)) = parse_tag(html) else {
+            return Vec::new();
+        };
+        match (name.as_str(), closing) {
+            ("br", _) => vec![Event::HardBreak],
+            ("b" | "strong", false) => vec![Event::Start(Tag::Strong)],
+            ("b" | "strong", true) => vec![Event::End(TagEnd::Strong)],
+            ("i" | "em" | "cite" | "var", false) => vec![Event::Start(Tag::Emphasis)],
+            ("i" | "em" | "cite" | "var", true) => vec![Event::End(TagEnd::Emphasis)],
+            ("s" | "del" | "strike", false) => vec![Event::Start(Tag::Strikethrough)],
+            ("s" | "del" | "strike", true) => vec![Event::End(TagEnd::Strikethrough)],
+            ("code" | "kbd" | "tt" | "samp", false) => {
+                self.code = Some(String::new());
+                Vec::new()
+            }
+            ("code" | "kbd" | "tt" | "samp", true) => match self.code.take() {
+                Some(code) => vec![Event::Code(code.into())],
+                None => Vec::new(),
+            },
+            ("sup", false) => {
+                self.script = Some(true);
+                Vec::new()
+            }
+            ("sub", false) => {
+                self.script = Some(false);
+                Vec::new()
+            }
+            ("sup" | "sub", true) => {
+                self.script = None;
+                Vec::new()
+            }
+            ("a", false) => match attribute(&attributes, "href") {
+                Some(href) => {
+                    self.open_links += 1;
+                    vec![Event::Start(Tag::Link {
+                        link_type: LinkType::Inline,
+                        dest_url: href.into(),
+                        title: attribute(&attributes, "title").unwrap_or_default().into(),
+                        id: "".into(),
+                    })]
+                }
+                None => Vec::new(),
+            },
+            ("a", true) if self.open_links > 0 => {
+                self.open_links -= 1;
+                vec![Event::End(TagEnd::Link)]
+            }
+            ("img", false) => match attribute(&attributes, "src") {
+                Some(src) => vec![
+                    Event::Start(Tag::Image {
+                        link_type: LinkType::Inline,
+                        dest_url: src.into(),
+                        title: attribute(&attributes, "title").unwrap_or_default().into(),
+                        id: "".into(),
+                    }),
+                    Event::Text(attribute(&attributes, "alt").unwrap_or_default().into()),
+                    Event::End(TagEnd::Image),
+                ],
+                None => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A tag anywhere: whether it closes, its name, and its attribute text
+static ANY_TAG_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<(/?)([A-Za-z][A-Za-z0-9-]*)((?:\s[^>]*)?)/?>").unwrap());
+/// Text that is one tag and nothing else, captured like `ANY_TAG_RE`
+static ONE_TAG_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^<(/?)([A-Za-z][A-Za-z0-9-]*)((?:\s[^>]*)?)/?>$").unwrap());
+/// Anything in angle brackets, for stripping tags out of text
+static MARKUP_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]+>").unwrap());
+static ATTRIBUTE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"([A-Za-z-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap());
+static COMMENT_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<!--.*?-->").unwrap());
+static PRE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<pre[^>]*>(.*?)</pre>").unwrap());
+static SCRIPT_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<(sup|sub)(?:\s[^>]*)?>(.*?)</(?:sup|sub)>").unwrap());
+static SUMMARY_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<summary(?:\s[^>]*)?>(.*?)</summary>").unwrap());
+static LINK_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<a\s([^>]*)>(.*?)</a>").unwrap());
+static BLANK_LINES_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\n{3,}").unwrap());
+static ENTITY_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[A-Za-z]+);").unwrap());
+
+/// Split a single tag into (closing, lowercase name, attribute text). None for comments,
+/// doctypes, and anything else that isn't one tag.
+fn parse_tag(html: &str) -> Option<(bool, String, String)> {
+    let caps = ONE_TAG_RE.captures(html.trim())?;
+    Some((!caps[1].is_empty(), caps[2].to_lowercase(), caps[3].to_string()))
+}
+
+/// Value of an attribute, quoted or not, with entities decoded
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let caps = ATTRIBUTE_RE.captures_iter(attributes).find(|caps| caps[1].eq_ignore_ascii_case(name))?;
+    let value = caps.get(2).or(caps.get(3)).or(caps.get(4))?;
+    Some(decode_entities(value.as_str()))
+}
+
+/// Rewrite an HTML block as markdown: common tags become their markdown equivalents and the
+/// rest are stripped, keeping their text
+fn to_markdown(html: &str) -> String {
+    let html = COMMENT_RE.replace_all(html, "");
+
+    // <pre> keeps its layout, so set it aside before lines are trimmed
+    let mut preformatted = Vec::new();
+    let html = PRE_RE.replace_all(&html, |caps: &Captures| {
+        preformatted.push(decode_entities(&MARKUP_RE.replace_all(&caps[1], "")));
+        format!("\n\n\u{0}{}\u{0}\n\n", preformatted.len() - 1)
+    });
+
+    let html = SCRIPT_RE.replace_all(&html, |caps: &Captures| {
+        let text = MARKUP_RE.replace_all(&caps[2], "");
+        if caps[1].eq_ignore_ascii_case("sup") {
+            math::raise(&text)
+        } else {
+            math::lower(&text)
+        }
+    });
+    let html = SUMMARY_RE.replace_all(&html, "\n\n▶ **$1**\n\n");
+    let html = LINK_RE.replace_all(&html, |caps: &Captures| match attribute(&caps[1], "href") {
+        Some(href) => format!("[{}]({})", &caps[2], href),
+        None => caps[2].to_string(),
+    });
+
+    let markdown = ANY_TAG_RE.replace_all(&html, |caps: &Captures| {
+        let closing = !caps[1].is_empty();
+        let name = caps[2].to_lowercase();
+        match name.as_str() {
+            "br" => "\\\n".to_string(),
+            "hr" => "\n\n---\n\n".to_string(),
+            "b" | "strong" => "**".to_string(),
+            "i" | "em" | "cite" | "var" => "*".to_string(),
+            "s" | "del" | "strike" => "~~".to_string(),
+            "code" | "kbd" | "tt" | "samp" => "`".to_string(),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" if !closing => {
+                let level = name[1..].parse().unwrap_or(1);
+                format!("\n\n{} ", "#".repeat(level))
+            }
+            "li" if !closing => "\n- ".to_string(),
+            "tr" | "li" => "\n".to_string(),
+            "td" | "th" => " ".to_string(),
+            "img" => match attribute(&caps[3], "src") {
+                Some(src) => format!("![{}]({})", attribute(&caps[3], "alt").unwrap_or_default(), src),
+                None => String::new(),
+            },
+            "p" | "div" | "details" | "center" | "blockquote" | "ul" | "ol" | "table" | "section"
+            | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => "\n\n".to_string(),
+            _ => String::new(),
+        }
+    });
+
+    // Indentation in HTML is layout, not markdown code blocks
+    let mut text = markdown.lines().map(str::trim).collect::<Vec<_>>().join("\n");
+    for (i, pre) in preformatted.iter().enumerate() {
+        text = text.replace(&format!("\u{0}{}\u{0}", i), &format!("```\n{}\n```", pre.trim_matches('\n')));
+    }
+    BLANK_LINES_RE.replace_all(text.trim(), "\n\n").into_owned()
+}
+
+/// Decode the entities that show up in attributes and `<pre>` text; the markdown parser
+/// decodes the rest
+fn decode_entities(text: &str) -> String {
+    ENTITY_RE
+        .replace_all(text, |caps: &Captures| {
+            let entity = &caps[1];
+            let decoded = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                    u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32)
+                }
+                _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+                _ => None,
+            };
+            decoded.map(String::from).unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
@@ -0,0 +1,314 @@
+//! Inline images through terminal graphics protocols (kitty, iTerm2, sixel)
+
+use crate::error::{LivemdError, Result};
+use crate::clipboard::base64;
+use crate::png;
+use std::collections::HashMap;
+use std::path::Path;
+use termimad::crossterm::terminal;
+
+/// Largest image file that will be loaded
+const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+/// Cell width in pixels assumed when the terminal doesn't report its size in pixels
+const DEFAULT_CELL_WIDTH: usize = 10;
+
+/// Terminal graphics protocol used to show images. kitty and iTerm2 take the encoded file and
+/// decode it in the terminal; sixel is sent as pixels, so livemd decodes the image itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    /// kitty graphics protocol (kitty, Ghostty); PNG only, since the terminal decodes it
+    Kitty,
+    /// iTerm2 inline images (iTerm2, WezTerm, mintty); any format the terminal can decode
+    Iterm,
+    /// DEC sixel graphics (foot, mlterm, Windows Terminal, Konsole); PNG only, decoded here
+    Sixel,
+}
+
+impl Protocol {
+    /// Graphics protocol supported by the terminal, if any. tmux would need every escape
+    /// wrapped for passthrough and doesn't track image placement, so images are off there.
+    pub fn detect() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        if !var("TMUX").is_empty() {
+            return None;
+        }
+        match var("TERM_PROGRAM").as_str() {
+            "iTerm.app" | "WezTerm" | "mintty" => return Some(Protocol::Iterm),
+            "ghostty" => return Some(Protocol::Kitty),
+            _ => {}
+        }
+        if !var("KITTY_WINDOW_ID").is_empty() || var("TERM").contains("kitty") || var("TERM").contains("ghostty") {
+            return Some(Protocol::Kitty);
+        }
+        // Konsole draws sixel since 22.04
+        let konsole = var("KONSOLE_VERSION").parse::<u32>().is_ok_and(|version| version >= 220400);
+        if var("TERM").starts_with("foot") || var("TERM").starts_with("mlterm") || !var("WT_SESSION").is_empty() || konsole {
+            return Some(Protocol::Sixel);
+        }
+        None
+    }
+
+    /// Escape sequence that draws `data` at the cursor, `columns` cells wide. None when the
+    /// protocol can't show this image format.
+    pub fn escape(self, data: &[u8], columns: usize) -> Option<String> {
+        match self {
+            Protocol::Kitty => {
+                if !data.starts_with(b"\x89PNG\r\n\x1a\n") {
+                    return None;
+                }
+                let encoded = base64(data);
+                // Payloads are sent in chunks of at most 4096 bytes
+                let chunks: Vec<&str> = encoded.as_bytes().chunks(4096).map(|c| std::str::from_utf8(c).unwrap_or_default()).collect();
+                let mut out = String::new();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let more = if i + 1 < chunks.len() { 1 } else { 0 };
+                    if i == 0 {
+                        out.push_str(&format!("\x1b_Gf=100,a=T,q=2,c={},m={};{}\x1b\\", columns, more, chunk));
+                    } else {
+                        out.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+                    }
+                }
+                Some(out)
+            }
+            Protocol::Iterm => Some(format!(
+                "\x1b]1337;File=inline=1;size={};width={};preserveAspectRatio=1:{}\x07",
+                data.len(),
+                columns,
+                base64(data)
+            )),
+            Protocol::Sixel => {
+                let image = png::decode(data).map_err(|e| log::debug!("can't decode the image: {}", e)).ok()?;
+                let cell_width = terminal::window_size()
+                    .ok()
+                    .filter(|size| size.width > 0 && size.columns > 0)
+                    .map_or(DEFAULT_CELL_WIDTH, |size| (size.width / size.columns) as usize);
+                Some(sixel(&shrink(image, columns * cell_width.max(1))))
+            }
+        }
+    }
+}
+
+/// Scale an image down to at most `width` pixels wide, averaging the pixels each one covers
+fn shrink(image: png::Image, width: usize) -> png::Image {
+    if image.width <= width {
+        return image;
+    }
+    let height = (image.height * width / image.width).max(1);
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let rows = y * image.height / height..((y + 1) * image.height / height).max(y * image.height / height + 1);
+        for x in 0..width {
+            let columns = x * image.width / width..((x + 1) * image.width / width).max(x * image.width / width + 1);
+            let mut sum = [0usize; 4];
+            for row in rows.clone() {
+                for pixel in &image.pixels[row * image.width + columns.start..row * image.width + columns.end] {
+                    for (total, &channel) in sum.iter_mut().zip(pixel) {
+                        *total += channel as usize;
+                    }
+                }
+            }
+            let count = rows.len() * columns.len();
+            pixels.push(sum.map(|total| (total / count) as u8));
+        }
+    }
+    png::Image { width, height, pixels }
+}
+
+/// A color cut to 5 bits a channel, packed into 15 bits
+fn color_key(pixel: [u8; 4]) -> u16 {
+    (pixel[0] as u16 >> 3) << 10 | (pixel[1] as u16 >> 3) << 5 | pixel[2] as u16 >> 3
+}
+
+/// Pick at most 256 colors for an image by median cut: the colors are split into boxes along
+/// their widest channel until there are enough boxes, and each box becomes its average color.
+/// Returns the palette and the palette index of each 15-bit color key.
+fn quantize(pixels: &[[u8; 4]]) -> (Vec<[u8; 3]>, HashMap<u16, u8>) {
+    let mut histogram: HashMap<u16, usize> = HashMap::new();
+    for &pixel in pixels.iter().filter(|p| p[3] >= 128) {
+        *histogram.entry(color_key(pixel)).or_default() += 1;
+    }
+    let channel = |key: u16, c: usize| (key >> (10 - 5 * c)) & 31;
+    let range = |colors: &[(u16, usize)], c: usize| {
+        let values = colors.iter().map(|&(key, _)| channel(key, c));
+        values.clone().max().unwrap_or(0) - values.min().unwrap_or(0)
+    };
+    let mut colors: Vec<(u16, usize)> = histogram.into_iter().collect();
+    colors.sort_unstable();
+    let mut boxes = vec![colors];
+    while boxes.len() < 256 {
+        // The box spanning the widest range of any channel is split next
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(i, colors)| (i, (0..3).max_by_key(|&c| range(colors, c)).unwrap_or(0), colors))
+            .max_by_key(|&(_, c, colors)| range(colors, c));
+        let Some((i, c, _)) = widest else { break };
+        let mut colors = boxes.swap_remove(i);
+        colors.sort_by_key(|&(key, _)| channel(key, c));
+        let half = colors.iter().map(|&(_, count)| count).sum::<usize>() / 2;
+        let mut seen = 0;
+        let split = colors.iter().position(|&(_, count)| {
+            seen += count;
+            seen > half
+        });
+        let split = split.unwrap_or(0).clamp(1, colors.len() - 1);
+        let upper = colors.split_off(split);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    let mut palette = Vec::new();
+    let mut indices = HashMap::new();
+    for (i, colors) in boxes.iter().filter(|colors| !colors.is_empty()).enumerate() {
+        let total: usize = colors.iter().map(|&(_, count)| count).sum();
+        let mean = |c: usize| {
+            let sum: usize = colors.iter().map(|&(key, count)| (channel(key, c) as usize * 255 / 31) * count).sum();
+            (sum / total).min(255) as u8
+        };
+        palette.push([mean(0), mean(1), mean(2)]);
+        for &(key, _) in colors {
+            indices.insert(key, i as u8);
+        }
+    }
+    (palette, indices)
+}
+
+/// Encode an image as sixel graphics: the palette, then bands six pixels high, each drawn once
+/// per color in it with run-length encoding. Mostly transparent pixels are left unpainted.
+fn sixel(image: &png::Image) -> String {
+    let (palette, indices) = quantize(&image.pixels);
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", image.width, image.height);
+    for (i, [r, g, b]) in palette.iter().enumerate() {
+        let percent = |c: &u8| *c as usize * 100 / 255;
+        out.push_str(&format!("#{};2;{};{};{}", i, percent(r), percent(g), percent(b)));
+    }
+    for top in (0..image.height).step_by(6) {
+        // Six bits per column for each color used in the band
+        let mut bands: Vec<Option<Vec<u8>>> = vec![None; palette.len()];
+        for y in top..(top + 6).min(image.height) {
+            for x in 0..image.width {
+                let pixel = image.pixels[y * image.width + x];
+                let Some(&index) = indices.get(&color_key(pixel)).filter(|_| pixel[3] >= 128) else {
+                    continue;
+                };
+                bands[index as usize].get_or_insert_with(|| vec![0; image.width])[x] |= 1 << (y - top);
+            }
+        }
+        for (index, columns) in bands.iter().enumerate() {
+            let Some(columns) = columns else { continue };
+            out.push_str(&format!("#{}", index));
+            let mut x = 0;
+            while x < columns.len() {
+                let run = columns[x..].iter().take_while(|&&bits| bits == columns[x]).count();
+                let c = (63 + columns[x]) as char;
+                if run > 3 {
+                    out.push_str(&format!("!{}{}", run, c));
+                } else {
+                    out.extend(std::iter::repeat_n(c, run));
+                }
+                x += run;
+            }
+            // Back to the start of the band for the next color
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+/// Read an image from a path, which must be inside `base_dir` (the document's directory, or
+/// the current directory without one), or download it from an http(s) URL when `remote` is on.
+/// The Markdown can come from an LLM or another program, so it mustn't reach further than that.
+pub fn load(source: &str, base_dir: Option<&Path>, remote: bool) -> Result<Vec<u8>> {
+    let data = if source.starts_with("http://") || source.starts_with("https://") {
+        if !remote {
+            return Err(LivemdError::Input("downloading images is off (set \"images-remote\": true to allow it)".to_string()));
+        }
+        // No HTTP client in the dependency tree; curl is everywhere images can be shown
+        log::debug!("downloading image {} with curl", source);
+        let output = std::process::Command::new("curl")
+            .args(["--silent", "--fail", "--location", "--max-time", "10", "--max-filesize"])
+            .arg(MAX_IMAGE_BYTES.to_string())
+            .arg(source)
+            .output()
+            .map_err(|source| LivemdError::Spawn { command: "curl".to_string(), source })?;
+        if !output.status.success() {
+            return Err(LivemdError::Input(format!("failed to download {}", source)));
+        }
+        output.stdout
+    } else {
+        let dir = match base_dir {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.canonicalize()?,
+            _ => std::env::current_dir()?,
+        };
+        let path = dir.join(source.strip_prefix("file://").unwrap_or(source)).canonicalize()?;
+        // Symlinks and `..` are resolved first, so neither leads out of the directory
+        if !path.starts_with(&dir) {
+            return Err(LivemdError::Input(format!("{} is outside {}", path.display(), dir.display())));
+        }
+        if std::fs::metadata(&path)?.len() as usize > MAX_IMAGE_BYTES {
+            return Err(LivemdError::Input(format!("{} is too large to show", path.display())));
+        }
+        std::fs::read(path)?
+    };
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_are_only_read_from_the_document_directory() {
+        let root = std::env::temp_dir().join(format!("livemd-image-test-{}", std::process::id()));
+        let docs = root.join("docs");
+        std::fs::create_dir_all(&docs).unwrap();
+        std::fs::write(docs.join("inside.png"), b"inside").unwrap();
+        std::fs::write(root.join("outside.png"), b"outside").unwrap();
+
+        assert_eq!(load("inside.png", Some(&docs), false).unwrap(), b"inside");
+        let absolute = docs.join("inside.png").display().to_string();
+        assert_eq!(load(&format!("file://{}", absolute), Some(&docs), false).unwrap(), b"inside");
+        assert!(load("../outside.png", Some(&docs), false).is_err());
+        assert!(load(&root.join("outside.png").display().to_string(), Some(&docs), false).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn remote_images_are_opt_in() {
+        let error = load("https://example.com/a.png", None, false).unwrap_err();
+        assert!(error.to_string().contains("images-remote"), "{}", error);
+    }
+
+    #[test]
+    fn sixel_draws_each_color_of_a_band_in_turn() {
+        let (red, blue, clear) = ([255, 0, 0, 255], [0, 0, 255, 255], [0, 0, 0, 0]);
+        let image = png::Image { width: 2, height: 2, pixels: vec![red, clear, blue, red] };
+        assert_eq!(sixel(&image), "\x1bP0;1;0q\"1;1;2;2#0;2;100;0;0#1;2;0;0;100#0@A$#1A?$-\x1b\\");
+    }
+
+    #[test]
+    fn sixel_repeats_are_run_length_encoded() {
+        let image = png::Image { width: 5, height: 1, pixels: vec![[0, 0, 0, 255]; 5] };
+        assert!(sixel(&image).contains("#0!5@$-"));
+    }
+
+    #[test]
+    fn quantizing_keeps_at_most_256_colors() {
+        let pixels: Vec<[u8; 4]> = (0..4096).map(|i| [(i % 16 * 16) as u8, (i / 16 % 16 * 16) as u8, (i / 256 * 16) as u8, 255]).collect();
+        let (palette, indices) = quantize(&pixels);
+        assert_eq!(palette.len(), 256);
+        assert!(pixels.iter().all(|&p| indices.contains_key(&color_key(p))));
+    }
+
+    #[test]
+    fn shrinking_averages_the_pixels_covered() {
+        let image = png::Image { width: 4, height: 2, pixels: [[0, 0, 0, 255], [200, 100, 50, 255], [10, 10, 10, 255], [10, 10, 10, 255]].repeat(2) };
+        let small = shrink(image, 2);
+        assert_eq!((small.width, small.height), (2, 1));
+        assert_eq!(small.pixels, vec![[100, 50, 25, 255], [10, 10, 10, 255]]);
+    }
+}
@@ -0,0 +1,45 @@
+//! Diagnostic logging to stderr for `--verbose`
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Writes each record to stderr, tagged with its level and module
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let module = record.target().trim_start_matches("livemd::");
+        let level = match record.level() {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        };
+        eprintln!("[{} {}] {}", level, module, record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Log at the level picked by how many times `-v` was given: info, debug, then trace.
+/// Without it nothing is logged.
+pub fn init(verbosity: u8) {
+    let level = match verbosity {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
@@ -5,16 +5,63 @@
 //! This tool streams Markdown content with basic formatting, supporting files,
 //! command output, and AI chat responses.
 
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand, ValueHint};
+use std::io::Read;
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
+mod bench;
+mod cache;
+mod chat;
+mod clipboard;
+mod code_theme;
+mod commands;
+mod completions;
 mod config;
+mod csv;
+mod doc_stats;
+mod editor;
+mod error;
+mod events;
+mod extract;
+mod frontmatter;
+mod history;
+mod html;
+mod image;
+mod json;
+mod logging;
+mod man;
+mod math;
+mod mermaid;
+mod notify;
+mod pacing;
+mod page;
+mod pager;
+mod picker;
+mod png;
+mod popup;
+mod readline;
+mod runner;
+mod section;
+mod serve;
+mod shell;
+mod slides;
 mod streamer;
 mod table;
+mod terminal;
 mod theme;
+mod toc;
+mod toml;
+mod wrap;
+mod ws;
 
-use config::{ConfigFile, StreamerConfig};
+use config::{ConfigFile, FrontmatterStyle, Granularity, LinkStyle, Notify, OutputFormat, StreamerConfig};
+use doc_stats::DocStats;
+use error::LivemdError;
+use theme::Theme;
 use streamer::MinimalStreamer;
+use table::TableStyle;
+use terminal::{ColorDepth, ColorMode};
 use atty::{is, Stream};
 
 #[derive(Parser)]
@@ -22,10 +69,15 @@ use atty::{is, Stream};
 #[command(about = "Live Markdown streaming tool", version, author)]
 #[command(after_help = "EXAMPLES:
   livemd explain rust ownership    # Query AI (no quotes needed!)
-  livemd --file README.md          # Stream a markdown file  
-  livemd --cmd 'ls -la'            # Stream command output
-  livemd --stdin < file.md         # Stream from stdin
+  livemd ask what is a monad       # The same, for queries starting with a subcommand name
+  livemd ask -e                    # Write a long query in $EDITOR first
+  livemd chat                      # Chat, keeping earlier messages as context
+  livemd render README.md          # Stream a markdown file (same as --file)
+  livemd run 'ls -la'              # Stream command output (same as --cmd)
+  livemd --cwd ../api -c make      # Run a command in another directory
+  livemd render < file.md          # Stream from stdin (same as --stdin)
   cat file.md | livemd             # Pipe content to livemd
+  livemd themes preview light      # Preview a theme
 
 NOTE: For queries with shell glob characters (?, *, [, ]), use quotes:
   livemd \"what is gnosticism?\"   # With quotes
@@ -33,94 +85,682 @@ NOTE: For queries with shell glob characters (?, *, [, ]), use quotes:
 
 For zsh users, add this to ~/.zshrc: ai() { noglob livemd \"$@\" }
 
-CONFIG: ~/.config/livemd/config.json
+CONFIG: ~/.config/livemd/config.toml (or config.json)
 THEMES: ~/.config/livemd/themes/")]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(trailing_var_arg = true, help = "Query to run with configured LLM command (default mode)")]
     query: Vec<String>,
 
-    #[arg(short, long, help = "Markdown file to stream")]
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, help = "Log what livemd is doing to stderr (-vv and -vvv for more detail)")]
+    verbose: u8,
+
+    #[arg(long, global = true, value_name = "NAME", help = "Use a named profile from the config's profiles section [env: LIVEMD_PROFILE]")]
+    profile: Option<String>,
+
+    #[arg(long, global = true, help = "Show the output in a tmux popup that closes on a keypress (inside tmux)")]
+    popup: bool,
+
+    #[arg(long = "popup-child", global = true, hide = true)]
+    popup_child: bool,
+
+    #[arg(short, long, global = true, value_hint = ValueHint::FilePath, help = "Markdown file to stream")]
     file: Option<PathBuf>,
 
-    #[arg(short, long, help = "Command to run and stream")]
+    #[arg(long, global = true, help = "Keep reading --file as it grows, or a named pipe as writers come and go, until interrupted")]
+    follow: bool,
+
+    #[arg(long, global = true, value_name = "SOCKET", value_hint = ValueHint::FilePath, help = "Listen on a Unix socket and render the Markdown each client sends, until interrupted")]
+    listen: Option<PathBuf>,
+
+    #[arg(long, global = true, value_name = "URL", help = "Connect to a ws:// WebSocket and stream the text messages it sends")]
+    ws: Option<String>,
+
+    #[arg(short, long, global = true, help = "Command to run and stream")]
     cmd: Option<String>,
 
-    #[arg(long, help = "Delay between chunks in seconds (smaller = faster)")]
+    #[arg(long, global = true, value_name = "DIR", value_hint = ValueHint::DirPath, help = "Run --cmd and `run` commands in DIR")]
+    cwd: Option<PathBuf>,
+
+    #[arg(long, global = true, value_name = "KEY=VAL", value_parser = parse_env, help = "Set an environment variable for --cmd and `run` commands (repeatable)")]
+    env: Vec<(String, String)>,
+
+    #[arg(long, global = true, help = "Delay between chunks in seconds (smaller = faster)")]
     speed: Option<f64>,
 
-    #[arg(long, help = "Max chunk size before flush")]
+    #[arg(long, global = true, help = "Render everything at once, without pacing or animation")]
+    no_stream: bool,
+
+    #[arg(long, global = true, value_name = "FORMAT", help = "Output format: terminal (styled text), json (one parsed block per line), html (a themed page), or man (roff)")]
+    output: Option<String>,
+
+    #[arg(long, global = true, value_hint = ValueHint::FilePath, value_name = "FILE", help = "Save the rendered output as an HTML transcript")]
+    export: Option<PathBuf>,
+
+    #[arg(long, global = true, value_hint = ValueHint::FilePath, value_name = "FILE", help = "Also write the rendered output to FILE while displaying it")]
+    output_file: Option<PathBuf>,
+
+    #[arg(long, global = true, requires = "output_file", help = "Write the Markdown source to --output-file instead of the rendered output")]
+    output_file_raw: bool,
+
+    #[arg(long, global = true, help = "Report rendering throughput and where the time went (on stderr)")]
+    bench: bool,
+
+    #[arg(long, global = true, help = "Once the output ends, print time to first byte, total time, size, and throughput (on stderr)")]
+    stats: bool,
+
+    #[arg(long, global = true, help = "Max chunk size before flush")]
     chunk_size: Option<usize>,
 
-    #[arg(long, help = "Convert simple boxed headings into Markdown headers")]
+    #[arg(long, global = true, help = "Render each line as soon as it arrives, for logs (`tail -f app.log | livemd --line-buffered`)")]
+    line_buffered: bool,
+
+    #[arg(long, global = true, help = "Convert simple boxed headings into Markdown headers")]
     strip_boxes: bool,
 
-    #[arg(long, help = "Command to invoke the LLM")]
+    #[arg(long, global = true, help = "Shell that runs --cmd and `run` command lines (default: sh, or cmd on Windows)")]
+    shell: Option<String>,
+
+    #[arg(long, global = true, help = "Command to invoke the LLM")]
     llm_cmd: Option<String>,
 
-    #[arg(long, help = "Color theme: dark, light, mono")]
+    #[arg(long, global = true, value_name = "NAME", help = "Answer as a persona from the config's personas section: its instructions go ahead of the query")]
+    persona: Option<String>,
+
+    #[arg(long, global = true, help = "Color theme: dark, light, mono, auto")]
     theme: Option<String>,
 
-    #[arg(long, help = "Path to custom theme JSON file")]
+    #[arg(long, global = true, value_hint = ValueHint::FilePath, help = "Path to custom theme JSON file")]
     theme_file: Option<PathBuf>,
 
-    #[arg(long, help = "Force reading from stdin (overrides other modes)")]
+    #[arg(long, global = true, help = "Force reading from stdin (overrides other modes)")]
     stdin: bool,
 
-    #[arg(long, help = "Do not inject the default 'respond only in Markdown' instruction")]
+    #[arg(long, global = true, help = "Do not inject the default 'respond only in Markdown' instruction")]
     no_inject: bool,
+
+    #[arg(long, global = true, help = "Bypass the response cache for this query")]
+    no_cache: bool,
+
+    #[arg(long, global = true, help = "Print estimated token usage, elapsed time, and cost after the response")]
+    usage: bool,
+
+    #[arg(long, global = true, help = "Constrain the rendered column to N characters")]
+    width: Option<usize>,
+
+    #[arg(long, global = true, help = "Center the rendered column in the terminal")]
+    center: bool,
+
+    #[arg(long, global = true, help = "Horizontal margin in columns")]
+    margin: Option<usize>,
+
+    #[arg(long, global = true, value_parser = TableStyle::NAMES, help = "Table separator style: grid, compact")]
+    table_style: Option<String>,
+
+    #[arg(long, global = true, help = "Render table rows as they arrive instead of buffering whole tables")]
+    stream_tables: bool,
+
+    #[arg(long, global = true, help = "Draw a border around code blocks, labeled with the language")]
+    code_frame: bool,
+
+    #[arg(long, global = true, help = "Show minified JSON code blocks as written instead of reformatting them")]
+    no_pretty_json: bool,
+
+    #[arg(long, global = true, value_name = "N", help = "Copy code block N to the clipboard once the output ends")]
+    copy_code: Option<usize>,
+
+    #[arg(long, global = true, conflicts_with = "copy_code", help = "Copy the Markdown of the whole output to the clipboard once it ends")]
+    copy: bool,
+
+    #[arg(long, global = true, value_parser = Notify::NAMES, value_name = "HOW", num_args = 0..=1, require_equals = true, default_missing_value = "desktop", help = "Once a response or command that took a while finishes, send a desktop notification, or with --notify=bell ring the bell")]
+    notify: Option<String>,
+
+    #[arg(long, global = true, value_hint = ValueHint::DirPath, value_name = "DIR", num_args = 0..=1, default_missing_value = ".", help = "Write each fenced code block to a numbered file in DIR (default: current directory)")]
+    extract_code: Option<PathBuf>,
+
+    #[arg(long, global = true, help = "Afterwards, offer to run shell and Python code blocks from the output")]
+    run_code: bool,
+
+    #[arg(long, global = true, value_parser = LinkStyle::NAMES, help = "How link URLs are shown: inline, footnote, hidden")]
+    link_style: Option<String>,
+
+    #[arg(long, global = true, help = "Show placeholders instead of drawing images inline")]
+    no_images: bool,
+
+    #[arg(long, global = true, help = "Present the document as slides, split at --- lines (or H1/H2 headings)")]
+    slides: bool,
+
+    #[arg(long, global = true, help = "Preview the block being streamed in place before it's complete")]
+    live: bool,
+
+    #[arg(long, global = true, value_parser = Granularity::NAMES, help = "How finely output is revealed: char, word, sentence, block")]
+    granularity: Option<String>,
+
+    #[arg(long, global = true, help = "Type output out a character at a time at --speed (same as --granularity char)")]
+    typewriter: bool,
+
+    #[arg(long, global = true, help = "Show output taller than the terminal in a pager with heading navigation")]
+    pager: bool,
+
+    #[arg(long, global = true, help = "Print a table of contents (before a --file, after streamed output)")]
+    toc: bool,
+
+    #[arg(long, global = true, value_name = "HEADING", help = "Only render the section under the first heading containing this text")]
+    section: Option<String>,
+
+    #[arg(long, global = true, value_parser = FrontmatterStyle::NAMES, help = "How YAML frontmatter is shown: table, hidden")]
+    frontmatter: Option<String>,
+
+    #[arg(long, global = true, help = "Print link URLs after the text instead of making links clickable")]
+    no_hyperlinks: bool,
+
+    #[arg(long, global = true, value_name = "WHEN", value_parser = ColorMode::NAMES, help = "When to use color: always, auto, never (default: auto)")]
+    color: Option<String>,
+
+    #[arg(long, global = true, help = "Disable colors and text attributes (same as --color never)")]
+    no_color: bool,
+
+    #[arg(long, global = true, help = "Input format: markdown, csv, tsv (default: detected from --file extension)")]
+    format: Option<String>,
+
+    #[arg(long, global = true, help = "Field delimiter for csv/tsv input")]
+    delimiter: Option<char>,
+
+    #[arg(long, global = true, help = "Treat the first csv/tsv row as data rather than a header")]
+    no_header: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Render a Markdown file, or stdin when FILE is - or left out
+    Render {
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: Option<PathBuf>,
+    },
+    /// Run a shell command and render its output
+    Run {
+        /// The command, as one quoted argument or as separate words
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Ask the configured LLM a question (the default when no subcommand is given)
+    Ask {
+        /// Write the query in $VISUAL or $EDITOR, starting from QUERY if given
+        #[arg(short, long)]
+        edit: bool,
+        /// Start the query in the editor from the contents of FILE
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, requires = "edit")]
+        template: Option<PathBuf>,
+        #[arg(trailing_var_arg = true, required_unless_present = "edit")]
+        query: Vec<String>,
+    },
+    /// Chat with the configured LLM, keeping earlier messages as context
+    Chat,
+    /// Render Markdown POSTed over HTTP, or a file whenever it changes, until interrupted
+    Serve {
+        /// Port to listen on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        /// Address to listen on; use 0.0.0.0 to accept other machines
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+        /// Also render FILE, clearing the screen first, each time it changes
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        watch: Option<PathBuf>,
+        /// Clear the screen before rendering each POSTed document
+        #[arg(long)]
+        clear: bool,
+    },
+    /// List and preview color themes
+    Themes {
+        #[command(subcommand)]
+        action: ThemesAction,
+    },
+    /// Create, check, and show the config file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Count words, headings, code blocks, tables, and links in a Markdown file, or stdin
+    Stats {
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: Option<PathBuf>,
+    },
+    /// List saved chats and export them as Markdown
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+    /// Print a shell completion script, e.g. `livemd completions bash > ~/.local/share/bash-completion/completions/livemd`
+    Completions {
+        #[arg(value_parser = completions::SHELLS)]
+        shell: String,
+    },
+    /// Print theme names or LLM presets for completion scripts
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        kind: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Write a commented starter config.toml to the config directory
+    Init {
+        /// Replace an existing config file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check the config files for errors and print them with line numbers
+    Validate {
+        /// Config file to check (default: the global and project config files)
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: Option<PathBuf>,
+    },
+    /// Print the settings in effect, after config files, profile, environment, and flags
+    Show,
+}
+
+#[derive(Subcommand)]
+enum HistoryAction {
+    /// List saved chats, oldest first, with the start of each one's first message
+    List,
+    /// Print a saved chat as Markdown, e.g. `livemd history export last > chat.md`
+    Export {
+        /// The chat's ID from `livemd history list`, or `last` for the most recent
+        id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ThemesAction {
+    /// List built-in and custom themes (* marks the active theme)
+    List,
+    /// Render a sample document with a theme
+    Preview {
+        /// Built-in theme, custom theme name, or theme file path
+        name: String,
+    },
+    /// Print a theme as JSON, e.g. `livemd themes export dark > mytheme.json`
+    Export {
+        /// Built-in theme, custom theme name, or theme file path
+        name: String,
+    },
+}
+
+/// Parse a `--env KEY=VAL` pair
+fn parse_env(pair: &str) -> Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VAL, got \"{}\"", pair)),
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+async fn main() -> error::Result<()> {
+    let mut cli = Cli::parse();
+    logging::init(cli.verbose);
+
+    if cli.popup_child {
+        popup::set_in_popup();
+    } else if cli.popup {
+        match popup::launch() {
+            Ok(true) => return Ok(()),
+            Ok(false) => eprintln!("Warning: --popup only works inside tmux; showing the output here"),
+            Err(e) => exit_with(e),
+        }
+    }
+    let _keep_open = popup::KeepOpen;
+
+    // The input subcommands do what the input flags do
+    let mut run_words = None;
+    match cli.command.take() {
+        Some(Command::Render { file }) => match file.filter(|file| file.as_os_str() != "-") {
+            Some(file) => cli.file = Some(file),
+            None => cli.stdin = true,
+        },
+        // A single argument is a shell command line as given; separate words are quoted once the
+        // shell is known
+        Some(Command::Run { command }) if command.len() == 1 => cli.cmd = command.into_iter().next(),
+        Some(Command::Run { command }) => run_words = Some(command),
+        Some(Command::Ask { query, edit: false, .. }) => cli.query = query,
+        Some(Command::Ask { query, template, .. }) => {
+            let mut initial = match &template {
+                Some(path) => std::fs::read_to_string(path)
+                    .unwrap_or_else(|e| exit_with(LivemdError::Input(format!("can't read {}: {}", path.display(), e)))),
+                None => String::new(),
+            };
+            initial.push_str(&query.join(" "));
+            cli.query = vec![editor::compose(&initial).unwrap_or_else(|e| exit_with(e))];
+        }
+        command => cli.command = command,
+    }
+    let chat = matches!(cli.command, Some(Command::Chat));
+    let serving = matches!(cli.command, Some(Command::Serve { .. }));
+
+    if let Some(Command::Completions { shell }) = &cli.command {
+        // value_parser only lets through shells there are scripts for
+        print!("{}", completions::script(shell, &Cli::command()).unwrap_or_default());
+        return Ok(());
+    }
+    if let Some(Command::Complete { kind }) = &cli.command {
+        for value in completions::dynamic_values(kind) {
+            println!("{}", value);
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::History { action }) = &cli.command {
+        match action {
+            HistoryAction::List => history::list(),
+            HistoryAction::Export { id } => history::export(id).unwrap_or_else(|e| exit_with(e)),
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Stats { file }) = &cli.command {
+        let (name, text) = match file.as_ref().filter(|file| file.as_os_str() != "-") {
+            Some(path) => (
+                path.display().to_string(),
+                std::fs::read_to_string(path).unwrap_or_else(|e| exit_with(LivemdError::Input(format!("can't read {}: {}", path.display(), e)))),
+            ),
+            None => {
+                let mut text = String::new();
+                std::io::stdin().read_to_string(&mut text).unwrap_or_else(|e| exit_with(e.into()));
+                ("stdin".to_string(), text)
+            }
+        };
+        println!("{}", DocStats::of(&text).report(&name));
+        return Ok(());
+    }
+
+    // These work on the config files themselves, so they mustn't depend on loading them
+    if let Some(Command::Config { action: ConfigAction::Init { force } }) = &cli.command {
+        return commands::init_config(*force).or_else(|e| exit_with(e));
+    }
+    if let Some(Command::Config { action: ConfigAction::Validate { file } }) = &cli.command {
+        match commands::validate_config(file.as_deref()) {
+            Ok(true) => return Ok(()),
+            Ok(false) => std::process::exit(1),
+            Err(e) => exit_with(e),
+        }
+    }
 
     // Load configuration file if it exists
-    let config_file = ConfigFile::load();
+    let profile = cli.profile.clone().or_else(|| std::env::var("LIVEMD_PROFILE").ok());
+    let config_file = ConfigFile::load(profile.as_deref()).unwrap_or_else(|e| exit_with(e));
+    let code_theme_name = config_file.as_ref().and_then(|c| c.code_theme.clone());
+    // Flags get the same checks as config settings, so a typo is caught rather than ignored
+    if let Some(format) = &cli.format {
+        config::one_of(format, &csv::FORMATS).unwrap_or_else(|e| exit_with(LivemdError::Config(format!("--format: {}", e))));
+    }
+    if let Some(name) = &cli.theme {
+        config::check_theme(name).unwrap_or_else(|e| exit_with(LivemdError::Theme(e)));
+    }
 
     // Apply defaults from config file, CLI args take precedence
     let theme_name = cli.theme.or_else(|| config_file.as_ref().and_then(|c| c.theme.as_ref()).cloned()).unwrap_or_else(|| "dark".to_string());
     let theme_file = cli.theme_file.or_else(|| {
         config_file.as_ref().and_then(|c| c.theme_file.as_ref()).map(|tf| {
-            dirs::home_dir()
-                .map(|h| h.join(".config").join("livemd").join(tf))
-                .unwrap_or_default()
+            config::config_dir().map(|dir| dir.join(tf)).unwrap_or_default()
         })
     }).or_else(|| {
         // Check for default theme file
-        let default_theme = dirs::home_dir()
-            .map(|h| h.join(".config").join("livemd").join("themes").join("default.json"))
-            .unwrap_or_default();
+        let default_theme = Theme::themes_dir().map(|dir| dir.join("default.json")).unwrap_or_default();
         if default_theme.exists() {
             Some(default_theme)
         } else {
             None
         }
     });
-    let speed = cli.speed.or_else(|| config_file.as_ref().and_then(|c| c.speed)).unwrap_or(0.001);
+    // "auto" picks dark or light from the terminal background; a theme file takes precedence
+    let theme_name = if theme_name == "auto" && theme_file.is_none() {
+        terminal::auto_theme_name().to_string()
+    } else {
+        theme_name
+    };
+    if let Some(output) = &cli.output {
+        config::one_of(output, &OutputFormat::NAMES).unwrap_or_else(|e| exit_with(LivemdError::Config(format!("--output: {}", e))));
+    }
+    let output = cli.output.as_deref().map(OutputFormat::from_name).unwrap_or_default();
+    // Machine-readable output is written as soon as it's parsed, without terminal extras
+    let styled = output == OutputFormat::Terminal;
+    // Benchmarks measure rendering, not the pauses between blocks
+    let no_stream = !styled || cli.bench || cli.no_stream || !config_file.as_ref().and_then(|c| c.stream).unwrap_or(true);
+    let speed = if no_stream { 0.0 } else { cli.speed.or_else(|| config_file.as_ref().and_then(|c| c.speed)).unwrap_or(0.001) };
     let chunk_size = cli.chunk_size.or_else(|| config_file.as_ref().and_then(|c| c.chunk_size)).unwrap_or(150);
     let strip_boxes = cli.strip_boxes || config_file.as_ref().and_then(|c| c.strip_boxes).unwrap_or(false);
-    let llm_cmd = config_file.as_ref().and_then(|c| c.resolve_llm_cmd(cli.llm_cmd.as_deref())).or_else(|| cli.llm_cmd);
+    let shell = cli.shell.or_else(|| config_file.as_ref().and_then(|c| c.shell.clone())).unwrap_or_else(|| shell::DEFAULT.to_string());
+    if let Some(words) = run_words {
+        cli.cmd = Some(shell::join(&shell, &words));
+    }
+    let persona = cli.persona.as_deref().map(|name| match config_file.as_ref() {
+        Some(config) => config.persona(name).unwrap_or_else(|e| exit_with(e)).clone(),
+        None => exit_with(LivemdError::Config(format!("no persona named \"{}\": there's no config file", name))),
+    });
+    // A persona's model stands in for --llm-cmd, which still wins when given
+    let llm_preset = cli.llm_cmd.or_else(|| persona.as_ref().and_then(|persona| persona.model.clone()));
+    let llm_cmd = config_file.as_ref().and_then(|c| c.resolve_llm_cmd(llm_preset.as_deref())).or(llm_preset);
     let inject_md_instruction = !cli.no_inject && config_file.as_ref().and_then(|c| c.inject_md_instruction).unwrap_or(true);
+    let cache = !cli.no_cache && config_file.as_ref().and_then(|c| c.cache).unwrap_or(false);
+    let cache_ttl = config_file.as_ref().and_then(|c| c.cache_ttl);
+    let usage_footer = styled && (cli.usage || config_file.as_ref().and_then(|c| c.usage_footer).unwrap_or(false));
+    let width = cli.width.or_else(|| config_file.as_ref().and_then(|c| c.width));
+    let center = cli.center || config_file.as_ref().and_then(|c| c.center).unwrap_or(false);
+    let margin = cli.margin.or_else(|| config_file.as_ref().and_then(|c| c.margin)).unwrap_or(0);
+    let table_style = cli.table_style.or_else(|| config_file.as_ref().and_then(|c| c.table_style.clone())).map(|s| TableStyle::from_name(&s)).unwrap_or_default();
+    let stream_tables = cli.stream_tables || config_file.as_ref().and_then(|c| c.stream_tables).unwrap_or(false);
+    let color_mode = if cli.no_color {
+        ColorMode::Never
+    } else {
+        cli.color.or_else(|| config_file.as_ref().and_then(|c| c.color.clone())).map(|s| ColorMode::from_name(&s)).unwrap_or_default()
+    };
+    let color = color_mode.enabled();
+    if color_mode == ColorMode::Always {
+        // crossterm drops colors on its own when NO_COLOR is set
+        termimad::crossterm::style::force_color_output(true);
+    }
+    let color_depth = config_file
+        .as_ref()
+        .and_then(|c| c.color_depth.as_deref())
+        .and_then(ColorDepth::from_name)
+        // Detection may shell out to tput, so skip it when nothing will be colored
+        .unwrap_or_else(|| if color { ColorDepth::detect() } else { ColorDepth::TrueColor });
+    let code_theme = config_file.as_ref().and_then(|c| c.code_theme.as_deref()).and_then(|name| {
+        code_theme::CodeTheme::load(name)
+            .map_err(|e| eprintln!("Warning: Failed to load code theme: {}", e))
+            .ok()
+    });
+    let code_frame = cli.code_frame || config_file.as_ref().and_then(|c| c.code_frame).unwrap_or(false);
+    let pretty_json = !cli.no_pretty_json && config_file.as_ref().and_then(|c| c.pretty_json).unwrap_or(true);
+    // OSC 8 escapes are only useful where colors and escapes go through
+    let hyperlinks = color
+        && !cli.no_hyperlinks
+        && config_file.as_ref().and_then(|c| c.hyperlinks).unwrap_or_else(terminal::supports_hyperlinks);
+    let link_style = cli.link_style.or_else(|| config_file.as_ref().and_then(|c| c.link_style.clone())).map(|s| LinkStyle::from_name(&s)).unwrap_or_default();
+    let images = (color && !cli.no_images && config_file.as_ref().and_then(|c| c.images).unwrap_or(true))
+        .then(image::Protocol::detect)
+        .flatten();
+    let images_remote = config_file.as_ref().and_then(|c| c.images_remote).unwrap_or(false);
+    let image_width = config_file.as_ref().and_then(|c| c.image_width);
+    let pager = styled && (cli.pager || config_file.as_ref().and_then(|c| c.pager).unwrap_or(false))
+        && !cli.run_code
+        && !cli.slides
+        && !chat
+        && !serving
+        && !cli.follow
+        && cli.listen.is_none()
+        && is(Stream::Stdout);
+    let live = !no_stream && (cli.live || config_file.as_ref().and_then(|c| c.live).unwrap_or(false)) && is(Stream::Stdout);
+    // Pacing only makes sense when someone is watching the terminal
+    let granularity = cli
+        .typewriter
+        .then(|| "char".to_string())
+        .or(cli.granularity)
+        .or_else(|| config_file.as_ref().and_then(|c| c.granularity.clone()))
+        .map(|s| Granularity::from_name(&s))
+        .filter(|_| !no_stream && is(Stream::Stdout))
+        .unwrap_or_default();
+    let frontmatter = cli.frontmatter.or_else(|| config_file.as_ref().and_then(|c| c.frontmatter.clone())).map(|s| FrontmatterStyle::from_name(&s)).unwrap_or_default();
+    let pricing = llm_cmd.as_deref().and_then(|cmd| config_file.as_ref().and_then(|c| c.resolve_pricing(cmd)));
 
     let config = StreamerConfig {
         chunk_size,
+        line_buffered: cli.line_buffered,
         speed,
         strip_boxes,
+        shell,
+        cwd: cli.cwd,
+        env: cli.env,
         llm_cmd,
+        system: persona.map(|persona| persona.prompt),
         inject_md_instruction,
         theme_name,
         theme_file,
+        cache,
+        cache_ttl,
+        usage_footer,
+        pricing,
+        width,
+        center,
+        margin,
+        table_style,
+        stream_tables,
+        color,
+        color_depth,
+        code_theme,
+        code_frame,
+        pretty_json,
+        hyperlinks,
+        link_style,
+        images,
+        images_remote,
+        image_width,
+        frontmatter,
+        toc: styled && cli.toc,
+        section: cli.section,
+        pager,
+        live,
+        granularity,
+        bench: cli.bench,
+        stats: cli.stats,
+        output,
+        // Piped output gets ASCII borders too, unless color was asked for
+        plain: !color && !is(Stream::Stdout),
+        export: cli.export.is_some(),
+        copy: cli.copy,
+        notify: cli.notify.or_else(|| config_file.as_ref().and_then(|c| c.notify.clone())).map(|s| Notify::from_name(&s)),
+        notify_after: config_file.as_ref().and_then(|c| c.notify_after).map_or(notify::DEFAULT_AFTER, Duration::from_secs),
     };
+    log::info!("theme {}{}", config.theme_name, config.theme_file.as_ref().map_or(String::new(), |f| format!(" from {}", f.display())));
+    log::info!("llm command: {}", config.llm_cmd.as_deref().unwrap_or("none"));
+    log::debug!("resolved config: {:?}", config);
+
+    if let Some(Command::Themes { action }) = cli.command {
+        let result = match action {
+            ThemesAction::List => {
+                commands::list_themes(&config);
+                Ok(())
+            }
+            ThemesAction::Preview { name } => commands::preview_theme(&name, config).await,
+            ThemesAction::Export { name } => commands::export_theme(&name),
+        };
+        if let Err(e) = result {
+            exit_with(e);
+        }
+        return Ok(());
+    }
+    if let Some(Command::Config { action: ConfigAction::Show }) = cli.command {
+        let mut sources: Vec<String> = [Some(ConfigFile::global_path()), ConfigFile::project_path()]
+            .into_iter()
+            .flatten()
+            .filter(|path| path.is_file())
+            .map(|path| path.display().to_string())
+            .collect();
+        if let Some(profile) = profile {
+            sources.push(format!("profile {}", profile));
+        }
+        sources.push("environment, flags, and defaults".to_string());
+        commands::show_config(&config, &sources, code_theme_name.as_deref());
+        return Ok(());
+    }
+
+    let mut streamer = MinimalStreamer::new(config);
 
-    let streamer = MinimalStreamer::new(config);
+    let format = cli.format.clone().or_else(|| {
+        cli.file
+            .as_ref()
+            .and_then(|f| f.extension())
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .filter(|e| e == "csv" || e == "tsv")
+    });
 
+    if cli.follow && (cli.file.is_none() || cli.stdin) {
+        exit_with(LivemdError::Input("--follow needs a file to read, from --file or `render FILE`".to_string()));
+    }
 
-    let result: Result<(), Box<dyn std::error::Error>> = async {
-        if cli.stdin {
+    // What the notification says has finished, following the order of the modes below
+    let finished = if cli.stdin || cli.file.is_some() {
+        "The output".to_string()
+    } else if let Some(cmd) = &cli.cmd {
+        format!("`{}`", cmd)
+    } else if !cli.query.is_empty() {
+        "The response".to_string()
+    } else {
+        "The output".to_string()
+    };
+    let started = Instant::now();
+    let result: error::Result<()> = async {
+        if let Some(path) = &cli.output_file {
+            let file = std::fs::File::create(path).map_err(|source| LivemdError::Output { path: path.clone(), source })?;
+            streamer.tee(file, cli.output_file_raw);
+        }
+        if let Some(format) = format.as_deref().filter(|f| *f == "csv" || *f == "tsv") {
+            // Delimited data is rendered as a single table, so read it whole
+            let text = match &cli.file {
+                Some(path) if !cli.stdin => std::fs::read_to_string(path)?,
+                _ => {
+                    let mut text = String::new();
+                    std::io::stdin().read_to_string(&mut text)?;
+                    text
+                }
+            };
+            let delimiter = cli.delimiter.unwrap_or(if format == "tsv" { '\t' } else { ',' });
+            streamer.render_delimited(&text, delimiter, !cli.no_header);
+        } else if cli.slides {
+            if !is(Stream::Stdout) {
+                return Err(LivemdError::Input("--slides needs a terminal".to_string()));
+            }
+            let text = match &cli.file {
+                Some(path) if !cli.stdin => std::fs::read_to_string(path)?,
+                _ => {
+                    let mut text = String::new();
+                    std::io::stdin().read_to_string(&mut text)?;
+                    text
+                }
+            };
+            streamer.present(&text)?;
+            return Ok(());
+        } else if chat {
+            chat::run(&mut streamer, config_file.as_ref()).await?;
+        } else if let Some(Command::Serve { port, bind, watch, clear }) = &cli.command {
+            serve::run(&mut streamer, bind, *port, watch.as_deref(), *clear).await?;
+        } else if let Some(url) = &cli.ws {
+            streamer.stream_websocket(url).await?;
+        } else if let Some(path) = &cli.listen {
+            streamer.listen(path).await?;
+        } else if cli.stdin {
             // Explicit --stdin flag
             streamer.stream_stdin().await?;
         } else if let Some(file_path) = cli.file {
-            streamer.stream_file(file_path).await?;
+            if cli.follow {
+                streamer.follow_file(file_path).await?;
+            } else {
+                streamer.stream_file(file_path).await?;
+            }
         } else if let Some(cmd) = cli.cmd {
             streamer.stream_command(&cmd).await?;
         } else if !cli.query.is_empty() {
@@ -130,16 +770,42 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             // If no other mode specified and stdin is available (piped)
             streamer.stream_stdin().await?;
         } else {
-            eprintln!("Error: Must specify a query, --file, --cmd, --stdin, or pipe input to stdin");
-            std::process::exit(1);
+            exit_with(LivemdError::Input("Must specify a query, --file, --cmd, --stdin, or pipe input to stdin".to_string()));
+        }
+        streamer.finish_stream(cli.copy_code, cli.extract_code.as_deref())?;
+        // Chat reports and notifies after each reply instead
+        if !chat {
+            streamer.print_stats();
+            streamer.notify_finished(&finished, started.elapsed());
+        }
+        streamer.page()?;
+        if let Some(report) = streamer.bench_report() {
+            eprint!("{}", report);
+        }
+        if cli.run_code {
+            streamer.run_code_blocks().await?;
+        }
+        if let Some(path) = &cli.export {
+            streamer.export(path)?;
         }
         Ok(())
     }.await;
 
     if let Err(e) = result {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+        // Closes the pager, if it's open, so the error shows on the normal screen
+        drop(streamer);
+        exit_with(e);
     }
 
     Ok(())
+}
+
+/// Print an error, with a hint at the fix where there is one, and exit
+fn exit_with(error: LivemdError) -> ! {
+    eprintln!("Error: {}", error);
+    if let Some(hint) = error.hint() {
+        eprintln!("  hint: {}", hint);
+    }
+    popup::wait_for_key();
+    std::process::exit(1);
 }
\ No newline at end of file
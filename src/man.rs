@@ -0,0 +1,176 @@
+//! roff man pages for `--output man`
+
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+/// Start of the page: a tbl hint for tables and the title line
+pub fn header(title: &str) -> String {
+    let name: String = title.split_whitespace().next().unwrap_or("livemd").to_uppercase();
+    format!("'\\\" t\n.TH \"{}\" 1 \"\" \"\" \"{}\"\n", quoted(&name), quoted(title))
+}
+
+/// Escape text for roff: backslashes, and leading dots and quotes that would start a request
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    text.lines()
+        .map(|line| if line.starts_with(['.', '\'']) { format!("\\&{}", line) } else { line.to_string() })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Text for a quoted macro argument
+fn quoted(text: &str) -> String {
+    escape(text).replace('"', "\\(dq")
+}
+
+/// Start a request on its own line
+fn request(out: &mut String, line: &str) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+    out.push_str(line);
+    out.push('\n');
+}
+
+/// Markdown as roff. Headings become `.SH`/`.SS` sections, lists indented paragraphs,
+/// code blocks unfilled text, and tables tbl tables.
+pub fn body(markdown: &str) -> String {
+    let mut out = String::new();
+    // Fonts in effect, innermost last, so the outer one can be restored
+    let mut fonts: Vec<&str> = Vec::new();
+    // Next number of each open list (None for bullet lists)
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut in_code = false;
+    // Set inside an H1/H2, whose text is uppercased
+    let mut in_heading = false;
+    let mut link: Option<String> = None;
+    let mut cells: Vec<String> = Vec::new();
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut in_cell = false;
+
+    for event in Parser::new_ext(markdown, Options::all()) {
+        // Table cells are collected and written with the table
+        if in_cell {
+            match event {
+                Event::End(TagEnd::TableCell) => {
+                    in_cell = false;
+                }
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some(cell) = cells.last_mut() {
+                        cell.push_str(&text);
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                // Section headings are uppercase by convention, subsections aren't
+                in_heading = matches!(level, HeadingLevel::H1 | HeadingLevel::H2);
+                request(&mut out, if in_heading { ".SH" } else { ".SS" });
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                out.push('\n');
+                in_heading = false;
+            }
+            Event::Start(Tag::Paragraph) => {
+                if lists.is_empty() {
+                    request(&mut out, ".PP");
+                } else if !out.ends_with('\n') {
+                    request(&mut out, ".sp");
+                }
+            }
+            Event::End(TagEnd::Paragraph) => out.push('\n'),
+            Event::Start(Tag::BlockQuote(_)) => request(&mut out, ".RS 4"),
+            Event::End(TagEnd::BlockQuote(_)) => request(&mut out, ".RE"),
+            Event::Start(Tag::List(start)) => {
+                if !lists.is_empty() {
+                    request(&mut out, ".RS 4");
+                }
+                lists.push(start);
+            }
+            Event::End(TagEnd::List(_)) => {
+                lists.pop();
+                if !lists.is_empty() {
+                    request(&mut out, ".RE");
+                }
+            }
+            Event::Start(Tag::Item) => {
+                let marker = match lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}.", *n - 1)
+                    }
+                    _ => "\\(bu".to_string(),
+                };
+                request(&mut out, &format!(".IP {} 4", marker));
+            }
+            Event::End(TagEnd::Item) => {}
+            Event::Start(Tag::CodeBlock(_)) => {
+                request(&mut out, ".PP");
+                request(&mut out, ".RS 4");
+                request(&mut out, ".nf");
+                in_code = true;
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                request(&mut out, ".fi");
+                request(&mut out, ".RE");
+                in_code = false;
+            }
+            Event::Start(Tag::Strong) => {
+                fonts.push("B");
+                out.push_str("\\fB");
+            }
+            Event::Start(Tag::Emphasis) => {
+                fonts.push("I");
+                out.push_str("\\fI");
+            }
+            Event::End(TagEnd::Strong | TagEnd::Emphasis) => {
+                fonts.pop();
+                out.push_str(&format!("\\f{}", fonts.last().unwrap_or(&"R")));
+            }
+            Event::Start(Tag::Link { dest_url, .. }) => link = Some(dest_url.to_string()),
+            Event::End(TagEnd::Link) => {
+                if let Some(url) = link.take().filter(|url| !out.ends_with(url.as_str())) {
+                    out.push_str(&format!(" <{}>", escape(&url)));
+                }
+            }
+            Event::Start(Tag::TableCell) => {
+                cells.push(String::new());
+                in_cell = true;
+            }
+            Event::End(TagEnd::TableHead | TagEnd::TableRow) => rows.push(std::mem::take(&mut cells)),
+            Event::End(TagEnd::Table) => {
+                let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+                request(&mut out, ".TS");
+                request(&mut out, "allbox;");
+                // The header row is bold, the rest plain
+                request(&mut out, &format!("{}\n{}.", vec!["lb"; columns].join(" "), vec!["l"; columns].join(" ")));
+                for row in std::mem::take(&mut rows) {
+                    let cells: Vec<String> = row.iter().map(|cell| escape(cell.trim())).collect();
+                    request(&mut out, &cells.join("\t"));
+                }
+                request(&mut out, ".TE");
+            }
+            Event::Rule => request(&mut out, ".sp"),
+            Event::Code(code) => out.push_str(&format!("\\fB{}\\f{}", escape(&code), fonts.last().unwrap_or(&"R"))),
+            Event::Text(text) if in_code => {
+                // Every code line is literal, blank ones included
+                for line in text.split_inclusive('\n') {
+                    let line = escape(line.trim_end_matches('\n'));
+                    out.push_str(if line.is_empty() { "\\&" } else { &line });
+                    out.push('\n');
+                }
+            }
+            Event::Text(text) => {
+                let text = if in_heading { text.to_uppercase() } else { text.to_string() };
+                out.push_str(&escape(&text));
+            }
+            Event::InlineMath(math) | Event::DisplayMath(math) => out.push_str(&escape(&math)),
+            Event::SoftBreak => out.push('\n'),
+            Event::HardBreak => request(&mut out, ".br"),
+            _ => {}
+        }
+    }
+    out
+}
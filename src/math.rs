@@ -0,0 +1,805 @@
+//! Converting LaTeX math to Unicode text
+
+use unicode_width::UnicodeWidthStr;
+
+/// Convert a LaTeX math expression to a single line of Unicode, e.g. `E = mc^2` to `E = mc²`
+/// and `\frac{\alpha}{2}` to `α/2`. Unknown commands are kept as written.
+pub fn to_unicode(latex: &str) -> String {
+    let chars: Vec<char> = latex.trim().chars().collect();
+    let mut pos = 0;
+    let text = convert(&chars, &mut pos, None);
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Convert until `stop` (unconsumed) or the end of input
+fn convert(chars: &[char], pos: &mut usize, stop: Option<char>) -> String {
+    let mut out = String::new();
+    while let Some(&c) = chars.get(*pos) {
+        if Some(c) == stop {
+            break;
+        }
+        *pos += 1;
+        match c {
+            '\\' => out.push_str(&command(chars, pos)),
+            '{' => {
+                out.push_str(&convert(chars, pos, Some('}')));
+                *pos += 1;
+            }
+            '^' => out.push_str(&script(&argument(chars, pos), superscript, '^')),
+            '_' => out.push_str(&script(&argument(chars, pos), subscript, '_')),
+            '\'' => out.push('′'),
+            '~' => out.push(' '),
+            // Alignment points in environments
+            '&' => out.push(' '),
+            '-' => out.push('−'),
+            '*' => out.push('∗'),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Read one argument: a braced group, a command, or a single character
+fn argument(chars: &[char], pos: &mut usize) -> String {
+    while chars.get(*pos).is_some_and(|c| c.is_whitespace()) {
+        *pos += 1;
+    }
+    match chars.get(*pos) {
+        Some('{') => {
+            *pos += 1;
+            let group = convert(chars, pos, Some('}'));
+            *pos += 1;
+            group
+        }
+        Some('\\') => {
+            *pos += 1;
+            command(chars, pos)
+        }
+        Some(&c) => {
+            *pos += 1;
+            convert(&[c], &mut 0, None)
+        }
+        None => String::new(),
+    }
+}
+
+/// Read a braced argument without converting it (for \text and \begin)
+fn raw_argument(chars: &[char], pos: &mut usize) -> String {
+    while chars.get(*pos).is_some_and(|c| c.is_whitespace()) {
+        *pos += 1;
+    }
+    if chars.get(*pos) != Some(&'{') {
+        return argument(chars, pos);
+    }
+    let start = *pos + 1;
+    let mut depth = 0;
+    while let Some(&c) = chars.get(*pos) {
+        *pos += 1;
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return chars[start..*pos - 1].iter().collect();
+                }
+            }
+            _ => {}
+        }
+    }
+    chars[start..].iter().collect()
+}
+
+/// Convert the command after a backslash
+fn command(chars: &[char], pos: &mut usize) -> String {
+    let start = *pos;
+    while chars.get(*pos).is_some_and(|c| c.is_ascii_alphabetic()) {
+        *pos += 1;
+    }
+    if *pos == start {
+        // Escaped character or spacing command: \{ \% \, \\ ...
+        let Some(&c) = chars.get(*pos) else {
+            return String::new();
+        };
+        *pos += 1;
+        return match c {
+            ',' | ':' | ';' | ' ' => " ".to_string(),
+            '!' => String::new(),
+            '\\' => "; ".to_string(),
+            _ => c.to_string(),
+        };
+    }
+    let name: String = chars[start..*pos].iter().collect();
+
+    match name.as_str() {
+        "frac" | "dfrac" | "tfrac" => {
+            let numerator = argument(chars, pos);
+            let denominator = argument(chars, pos);
+            fraction(&numerator, &denominator)
+        }
+        "sqrt" => {
+            let index = if chars.get(*pos) == Some(&'[') {
+                *pos += 1;
+                let index = convert(chars, pos, Some(']'));
+                *pos += 1;
+                Some(index)
+            } else {
+                None
+            };
+            let radicand = argument(chars, pos);
+            let root = match index.as_deref() {
+                None | Some("2") => "√".to_string(),
+                Some("3") => "∛".to_string(),
+                Some("4") => "∜".to_string(),
+                Some(n) => format!("{}√", script(n, superscript, '^')),
+            };
+            format!("{}{}", root, group(&radicand))
+        }
+        "binom" => {
+            let n = argument(chars, pos);
+            let k = argument(chars, pos);
+            format!("C({}, {})", n, k)
+        }
+        "text" | "textrm" | "textit" | "textbf" | "mbox" | "operatorname" => raw_argument(chars, pos),
+        "mathrm" | "mathit" | "mathbf" | "mathsf" | "mathtt" | "boldsymbol" | "mathcal" | "mathscr" | "displaystyle" => {
+            argument(chars, pos)
+        }
+        "mathbb" => argument(chars, pos).chars().map(double_struck).collect(),
+        "hat" | "widehat" => accent(&argument(chars, pos), '\u{0302}'),
+        "bar" | "overline" => accent(&argument(chars, pos), '\u{0305}'),
+        "vec" => accent(&argument(chars, pos), '\u{20D7}'),
+        "dot" => accent(&argument(chars, pos), '\u{0307}'),
+        "ddot" => accent(&argument(chars, pos), '\u{0308}'),
+        "tilde" | "widetilde" => accent(&argument(chars, pos), '\u{0303}'),
+        // Sizing commands only change the delimiter that follows
+        "left" | "right" | "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" => {
+            if chars.get(*pos) == Some(&'.') {
+                *pos += 1;
+            }
+            String::new()
+        }
+        "begin" => {
+            let env = raw_argument(chars, pos);
+            let body = environment_body(chars, pos, &env);
+            let rows: Vec<String> = body
+                .split("\\\\")
+                .map(|row| row.split('&').map(to_unicode).collect::<Vec<_>>().join(", "))
+                .filter(|row| !row.is_empty())
+                .collect();
+            let (open, close) = match env.trim_end_matches('*') {
+                "pmatrix" => ("(", ")"),
+                "bmatrix" => ("[", "]"),
+                "vmatrix" => ("|", "|"),
+                "Bmatrix" | "cases" => ("{", "}"),
+                _ => ("", ""),
+            };
+            format!("{}{}{}", open, rows.join("; "), close)
+        }
+        name => symbol(name).map_or_else(|| format!("\\{}", name), str::to_string),
+    }
+}
+
+/// Raw source of an environment up to its \end, which is consumed
+fn environment_body(chars: &[char], pos: &mut usize, env: &str) -> String {
+    let rest: String = chars[*pos..].iter().collect();
+    let end = format!("\\end{{{}}}", env);
+    match rest.find(&end) {
+        Some(i) => {
+            *pos += rest[..i].chars().count() + end.chars().count();
+            rest[..i].to_string()
+        }
+        None => {
+            *pos = chars.len();
+            rest
+        }
+    }
+}
+
+/// `a/b`, with parentheses around compound parts, or a vulgar fraction like ½
+fn fraction(numerator: &str, denominator: &str) -> String {
+    let vulgar = match (numerator, denominator) {
+        ("1", "2") => Some('½'),
+        ("1", "3") => Some('⅓'),
+        ("2", "3") => Some('⅔'),
+        ("1", "4") => Some('¼'),
+        ("3", "4") => Some('¾'),
+        ("1", "5") => Some('⅕'),
+        ("1", "6") => Some('⅙'),
+        ("1", "8") => Some('⅛'),
+        _ => None,
+    };
+    match vulgar {
+        Some(c) => c.to_string(),
+        None => format!("{}/{}", group(numerator), group(denominator)),
+    }
+}
+
+/// Parenthesize text that isn't a single term
+fn group(text: &str) -> String {
+    let text = text.trim();
+    if text.chars().count() > 1 && text.chars().any(|c| !c.is_alphanumeric() && !is_combining(c)) {
+        format!("({})", text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn is_combining(c: char) -> bool {
+    matches!(c, '\u{0300}'..='\u{036F}' | '\u{20D0}'..='\u{20FF}')
+}
+
+/// Put a combining accent on every character of the text
+fn accent(text: &str, mark: char) -> String {
+    text.chars().flat_map(|c| [c, mark]).collect()
+}
+
+/// Raise or lower text with Unicode script characters, falling back to `^(...)` when a
+/// character has no script form
+fn script(text: &str, map: fn(char) -> Option<char>, marker: char) -> String {
+    let text = text.trim();
+    match text.chars().map(map).collect::<Option<String>>() {
+        Some(scripted) => scripted,
+        None if text.chars().count() == 1 => format!("{}{}", marker, text),
+        None => format!("{}({})", marker, text),
+    }
+}
+
+/// Superscript text, e.g. `2` to `²` (HTML `<sup>`)
+pub fn raise(text: &str) -> String {
+    script(text, superscript, '^')
+}
+
+/// Subscript text, e.g. `2` to `₂` (HTML `<sub>`)
+pub fn lower(text: &str) -> String {
+    script(text, subscript, '_')
+}
+
+fn superscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰', '1' => '¹', '2' => '²', '3' => '³', '4' => '⁴',
+        '5' => '⁵', '6' => '⁶', '7' => '⁷', '8' => '⁸', '9' => '⁹',
+        '+' => '⁺', '−' | '-' => '⁻', '=' => '⁼', '(' => '⁽', ')' => '⁾',
+        'a' => 'ᵃ', 'b' => 'ᵇ', 'c' => 'ᶜ', 'd' => 'ᵈ', 'e' => 'ᵉ', 'f' => 'ᶠ', 'g' => 'ᵍ',
+        'h' => 'ʰ', 'i' => 'ⁱ', 'j' => 'ʲ', 'k' => 'ᵏ', 'l' => 'ˡ', 'm' => 'ᵐ', 'n' => 'ⁿ',
+        'o' => 'ᵒ', 'p' => 'ᵖ', 'r' => 'ʳ', 's' => 'ˢ', 't' => 'ᵗ', 'u' => 'ᵘ', 'v' => 'ᵛ',
+        'w' => 'ʷ', 'x' => 'ˣ', 'y' => 'ʸ', 'z' => 'ᶻ', 'T' => 'ᵀ',
+        'α' => 'ᵅ', 'β' => 'ᵝ', 'γ' => 'ᵞ', 'δ' => 'ᵟ', 'θ' => 'ᶿ', 'φ' => 'ᵠ',
+        '′' | '∗' | '†' | '°' => c,
+        ' ' => ' ',
+        _ => return None,
+    })
+}
+
+fn subscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '₀', '1' => '₁', '2' => '₂', '3' => '₃', '4' => '₄',
+        '5' => '₅', '6' => '₆', '7' => '₇', '8' => '₈', '9' => '₉',
+        '+' => '₊', '−' | '-' => '₋', '=' => '₌', '(' => '₍', ')' => '₎',
+        'a' => 'ₐ', 'e' => 'ₑ', 'h' => 'ₕ', 'i' => 'ᵢ', 'j' => 'ⱼ', 'k' => 'ₖ', 'l' => 'ₗ',
+        'm' => 'ₘ', 'n' => 'ₙ', 'o' => 'ₒ', 'p' => 'ₚ', 'r' => 'ᵣ', 's' => 'ₛ', 't' => 'ₜ',
+        'u' => 'ᵤ', 'v' => 'ᵥ', 'x' => 'ₓ',
+        'β' => 'ᵦ', 'γ' => 'ᵧ', 'ρ' => 'ᵨ', 'φ' => 'ᵩ', 'χ' => 'ᵪ',
+        ' ' => ' ',
+        _ => return None,
+    })
+}
+
+fn double_struck(c: char) -> char {
+    match c {
+        'N' => 'ℕ',
+        'Z' => 'ℤ',
+        'Q' => 'ℚ',
+        'R' => 'ℝ',
+        'C' => 'ℂ',
+        'P' => 'ℙ',
+        'H' => 'ℍ',
+        _ => c,
+    }
+}
+
+/// Unicode for a symbol command
+fn symbol(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "α", "beta" => "β", "gamma" => "γ", "delta" => "δ", "epsilon" => "ϵ",
+        "varepsilon" => "ε", "zeta" => "ζ", "eta" => "η", "theta" => "θ", "vartheta" => "ϑ",
+        "iota" => "ι", "kappa" => "κ", "lambda" => "λ", "mu" => "μ", "nu" => "ν", "xi" => "ξ",
+        "pi" => "π", "varpi" => "ϖ", "rho" => "ρ", "varrho" => "ϱ", "sigma" => "σ",
+        "varsigma" => "ς", "tau" => "τ", "upsilon" => "υ", "phi" => "ϕ", "varphi" => "φ",
+        "chi" => "χ", "psi" => "ψ", "omega" => "ω",
+        "Gamma" => "Γ", "Delta" => "Δ", "Theta" => "Θ", "Lambda" => "Λ", "Xi" => "Ξ",
+        "Pi" => "Π", "Sigma" => "Σ", "Upsilon" => "Υ", "Phi" => "Φ", "Psi" => "Ψ", "Omega" => "Ω",
+        "times" => "×", "cdot" => "⋅", "div" => "÷", "pm" => "±", "mp" => "∓", "ast" => "∗",
+        "star" => "⋆", "circ" => "∘", "bullet" => "∙", "oplus" => "⊕", "otimes" => "⊗",
+        "leq" | "le" => "≤", "geq" | "ge" => "≥", "neq" | "ne" => "≠", "approx" => "≈",
+        "equiv" => "≡", "sim" => "∼", "simeq" => "≃", "cong" => "≅", "propto" => "∝",
+        "ll" => "≪", "gg" => "≫", "prec" => "≺", "succ" => "≻", "mid" => "∣",
+        "in" => "∈", "notin" => "∉", "ni" => "∋", "subset" => "⊂", "subseteq" => "⊆",
+        "supset" => "⊃", "supseteq" => "⊇", "cup" => "∪", "cap" => "∩", "setminus" => "∖",
+        "emptyset" | "varnothing" => "∅", "forall" => "∀", "exists" => "∃", "nexists" => "∄",
+        "neg" | "lnot" => "¬", "land" | "wedge" => "∧", "lor" | "vee" => "∨",
+        "to" | "rightarrow" => "→", "leftarrow" | "gets" => "←", "leftrightarrow" => "↔",
+        "Rightarrow" => "⇒", "Leftarrow" => "⇐", "Leftrightarrow" => "⇔", "implies" => "⟹",
+        "iff" => "⟺", "mapsto" => "↦", "uparrow" => "↑", "downarrow" => "↓",
+        "infty" => "∞", "partial" => "∂", "nabla" => "∇", "hbar" => "ℏ", "ell" => "ℓ",
+        "Re" => "ℜ", "Im" => "ℑ", "aleph" => "ℵ", "angle" => "∠", "perp" => "⊥",
+        "parallel" => "∥", "degree" => "°", "prime" => "′", "dagger" => "†",
+        "sum" => "∑", "prod" => "∏", "coprod" => "∐", "int" => "∫", "iint" => "∬",
+        "iiint" => "∭", "oint" => "∮", "bigcup" => "⋃", "bigcap" => "⋂",
+        "cdots" | "dots" | "ldots" => "…", "vdots" => "⋮", "ddots" => "⋱",
+        "langle" => "⟨", "rangle" => "⟩", "lfloor" => "⌊", "rfloor" => "⌋",
+        "lceil" => "⌈", "rceil" => "⌉", "lvert" | "rvert" | "vert" => "|", "Vert" | "|" => "‖",
+        "lbrace" => "{", "rbrace" => "}",
+        "sin" => "sin", "cos" => "cos", "tan" => "tan", "cot" => "cot", "sec" => "sec",
+        "csc" => "csc", "arcsin" => "arcsin", "arccos" => "arccos", "arctan" => "arctan",
+        "sinh" => "sinh", "cosh" => "cosh", "tanh" => "tanh", "log" => "log", "ln" => "ln",
+        "exp" => "exp", "lim" => "lim", "max" => "max", "min" => "min", "sup" => "sup",
+        "inf" => "inf", "det" => "det", "gcd" => "gcd", "deg" => "deg", "arg" => "arg",
+        "mod" | "bmod" => " mod ", "quad" => "  ", "qquad" => "    ",
+        "limits" | "nolimits" => "",
+        _ => return None,
+    })
+}
+
+/// Parsed display math, for laying out over several lines
+enum Node {
+    Text(String),
+    Row(Vec<Node>),
+    Frac(Box<Node>, Box<Node>),
+    Sqrt(Option<String>, Box<Node>),
+    Scripts { base: Box<Node>, sup: Option<Box<Node>>, sub: Option<Box<Node>> },
+    /// ∑, ∏, lim, ...: limits go above and below instead of beside
+    BigOp(String),
+    Delimited(String, Box<Node>, String),
+    Matrix { open: &'static str, close: &'static str, rows: Vec<Vec<Node>> },
+}
+
+/// Where a row of nodes ends
+#[derive(PartialEq)]
+enum Stop {
+    End,
+    Brace,
+    Right,
+}
+
+/// Lay out display math over several lines, like a typeset formula: fractions are stacked
+/// over a bar, sums carry their limits above and below, and matrices get tall brackets
+pub fn layout(latex: &str) -> Vec<String> {
+    let chars: Vec<char> = latex.trim().chars().collect();
+    let mut pos = 0;
+    let row = parse_row(&chars, &mut pos, Stop::End);
+    let block = Block::from(&row);
+    block.lines.iter().map(|l| l.trim_end().to_string()).collect()
+}
+
+fn parse_row(chars: &[char], pos: &mut usize, stop: Stop) -> Node {
+    let mut nodes: Vec<Node> = Vec::new();
+    while let Some(&c) = chars.get(*pos) {
+        if c == '}' && stop == Stop::Brace {
+            *pos += 1;
+            break;
+        }
+        if c == '\\' && stop == Stop::Right && chars[*pos..].starts_with(&['\\', 'r', 'i', 'g', 'h', 't']) {
+            break;
+        }
+        match c {
+            '^' | '_' => {
+                *pos += 1;
+                let script = parse_argument(chars, pos);
+                let (base, mut sup, mut sub) = match nodes.pop() {
+                    Some(Node::Scripts { base, sup, sub }) => (base, sup, sub),
+                    Some(node) => (Box::new(node), None, None),
+                    None => (Box::new(Node::Text(String::new())), None, None),
+                };
+                if c == '^' {
+                    sup = Some(Box::new(script));
+                } else {
+                    sub = Some(Box::new(script));
+                }
+                nodes.push(Node::Scripts { base, sup, sub });
+            }
+            c if c.is_whitespace() => {
+                *pos += 1;
+                if !matches!(nodes.last(), Some(Node::Text(t)) if t == " ") {
+                    nodes.push(Node::Text(" ".to_string()));
+                }
+            }
+            _ => nodes.push(parse_argument(chars, pos)),
+        }
+    }
+    Node::Row(nodes)
+}
+
+/// Parse one unit: a braced group, a command with its arguments, or a character
+fn parse_argument(chars: &[char], pos: &mut usize) -> Node {
+    while chars.get(*pos).is_some_and(|c| c.is_whitespace()) {
+        *pos += 1;
+    }
+    let Some(&c) = chars.get(*pos) else {
+        return Node::Text(String::new());
+    };
+    *pos += 1;
+    match c {
+        '{' => parse_row(chars, pos, Stop::Brace),
+        '\\' => parse_command(chars, pos),
+        c => Node::Text(convert(&[c], &mut 0, None)),
+    }
+}
+
+fn parse_command(chars: &[char], pos: &mut usize) -> Node {
+    let start = *pos;
+    let name: String = chars[start..].iter().take_while(|c| c.is_ascii_alphabetic()).collect();
+    match name.as_str() {
+        "frac" | "dfrac" | "tfrac" => {
+            *pos += name.len();
+            let numerator = parse_argument(chars, pos);
+            let denominator = parse_argument(chars, pos);
+            Node::Frac(Box::new(numerator), Box::new(denominator))
+        }
+        "sqrt" => {
+            *pos += name.len();
+            let index = (chars.get(*pos) == Some(&'[')).then(|| {
+                *pos += 1;
+                let index = convert(chars, pos, Some(']'));
+                *pos += 1;
+                index
+            });
+            Node::Sqrt(index, Box::new(parse_argument(chars, pos)))
+        }
+        "sum" | "prod" | "coprod" | "bigcup" | "bigcap" | "lim" | "max" | "min" | "sup" | "inf" => {
+            *pos += name.len();
+            Node::BigOp(symbol(&name).unwrap_or_default().to_string())
+        }
+        "left" => {
+            *pos += name.len();
+            let open = delimiter(chars, pos);
+            let body = parse_row(chars, pos, Stop::Right);
+            *pos = (*pos + "\\right".len()).min(chars.len());
+            let close = delimiter(chars, pos);
+            Node::Delimited(open, Box::new(body), close)
+        }
+        "begin" => {
+            *pos += name.len();
+            let env = raw_argument(chars, pos);
+            let body = environment_body(chars, pos, &env);
+            let (open, close) = match env.trim_end_matches('*') {
+                "pmatrix" => ("(", ")"),
+                "bmatrix" => ("[", "]"),
+                "vmatrix" => ("|", "|"),
+                "Vmatrix" => ("‖", "‖"),
+                "Bmatrix" => ("{", "}"),
+                "cases" => ("{", ""),
+                _ => ("", ""),
+            };
+            let rows = body
+                .split("\\\\")
+                .filter(|row| !row.trim().is_empty())
+                .map(|row| {
+                    row.split('&')
+                        .map(|cell| {
+                            let cell: Vec<char> = cell.trim().chars().collect();
+                            parse_row(&cell, &mut 0, Stop::End)
+                        })
+                        .collect()
+                })
+                .collect();
+            Node::Matrix { open, close, rows }
+        }
+        // Everything else fits on one line
+        _ => Node::Text(command(chars, pos)),
+    }
+}
+
+/// Read the delimiter after \left or \right; `.` means none
+fn delimiter(chars: &[char], pos: &mut usize) -> String {
+    while chars.get(*pos).is_some_and(|c| c.is_whitespace()) {
+        *pos += 1;
+    }
+    match chars.get(*pos) {
+        Some('.') => {
+            *pos += 1;
+            String::new()
+        }
+        Some('\\') => {
+            *pos += 1;
+            command(chars, pos)
+        }
+        Some(&c) => {
+            *pos += 1;
+            c.to_string()
+        }
+        None => String::new(),
+    }
+}
+
+/// Rectangle of text with a baseline, the row that lines up with neighbouring text
+struct Block {
+    lines: Vec<String>,
+    width: usize,
+    baseline: usize,
+}
+
+impl Block {
+    fn text(text: &str) -> Self {
+        Block { lines: vec![text.to_string()], width: text.width(), baseline: 0 }
+    }
+
+    fn height(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Single-line content, if the block is one line
+    fn flat(&self) -> Option<&str> {
+        (self.height() == 1).then(|| self.lines[0].as_str())
+    }
+
+    /// Line `i` padded to the block width (blank outside the block)
+    fn line(&self, i: usize) -> String {
+        let line = self.lines.get(i).map_or("", String::as_str);
+        format!("{}{}", line, " ".repeat(self.width.saturating_sub(line.width())))
+    }
+
+    fn centered(&self, i: usize, width: usize) -> String {
+        let left = (width.saturating_sub(self.width)) / 2;
+        let right = width.saturating_sub(self.width + left);
+        format!("{}{}{}", " ".repeat(left), self.line(i), " ".repeat(right))
+    }
+
+    /// Blocks side by side, lined up on their baselines
+    fn beside(blocks: Vec<Block>) -> Self {
+        let ascent = blocks.iter().map(|b| b.baseline).max().unwrap_or(0);
+        let descent = blocks.iter().map(|b| b.height() - b.baseline - 1).max().unwrap_or(0);
+        let lines = (0..=ascent + descent)
+            .map(|row| {
+                blocks
+                    .iter()
+                    .map(|b| match (row + b.baseline).checked_sub(ascent) {
+                        Some(i) => b.line(i),
+                        None => " ".repeat(b.width),
+                    })
+                    .collect::<String>()
+            })
+            .collect();
+        Block { lines, width: blocks.iter().map(|b| b.width).sum(), baseline: ascent }
+    }
+
+    /// Blocks stacked and centered; `baseline` is the row of the result to line up on
+    fn stacked(blocks: &[&Block], baseline: usize) -> Self {
+        let width = blocks.iter().map(|b| b.width).max().unwrap_or(0);
+        let lines = blocks.iter().flat_map(|b| (0..b.height()).map(move |i| b.centered(i, width))).collect();
+        Block { lines, width, baseline }
+    }
+
+    fn from(node: &Node) -> Self {
+        match node {
+            Node::Text(text) | Node::BigOp(text) => Block::text(text),
+            Node::Row(nodes) => {
+                if nodes.is_empty() {
+                    return Block::text("");
+                }
+                Block::beside(nodes.iter().map(Block::from).collect())
+            }
+            Node::Frac(numerator, denominator) => {
+                let (numerator, denominator) = (Block::from(numerator), Block::from(denominator));
+                let bar = Block::text(&"─".repeat(numerator.width.max(denominator.width) + 2));
+                let baseline = numerator.height();
+                Block::stacked(&[&numerator, &bar, &denominator], baseline)
+            }
+            Node::Sqrt(index, radicand) => {
+                let radicand = Block::from(radicand);
+                let root = match index.as_deref() {
+                    None | Some("2") => "√".to_string(),
+                    Some("3") => "∛".to_string(),
+                    Some("4") => "∜".to_string(),
+                    Some(n) => format!("{}√", script(n, superscript, '^')),
+                };
+                let pad = " ".repeat(root.width() - 1);
+                let mut lines = vec![format!("{} {}", pad, "─".repeat(radicand.width))];
+                for i in 0..radicand.height() {
+                    let edge = if i + 1 == radicand.height() { root.clone() } else { format!("{}│", pad) };
+                    lines.push(format!("{}{}", edge, radicand.line(i)));
+                }
+                Block { lines, width: root.width() + radicand.width, baseline: radicand.baseline + 1 }
+            }
+            Node::Scripts { base, sup, sub } => {
+                let base_block = Block::from(base);
+                let sup = sup.as_deref().map(Block::from);
+                let sub = sub.as_deref().map(Block::from);
+                if let Node::BigOp(_) = **base {
+                    // Limits above and below
+                    let empty = Block::text("");
+                    let above = sup.as_ref().unwrap_or(&empty);
+                    let below = sub.as_ref().unwrap_or(&empty);
+                    let mut parts = Vec::new();
+                    if sup.is_some() {
+                        parts.push(above);
+                    }
+                    parts.push(&base_block);
+                    if sub.is_some() {
+                        parts.push(below);
+                    }
+                    let baseline = sup.as_ref().map_or(0, Block::height) + base_block.baseline;
+                    return Block::stacked(&parts, baseline);
+                }
+                // Unicode super/subscript characters when every character has one
+                let scripted = |block: Option<&Block>, map: fn(char) -> Option<char>| match block {
+                    None => Some(String::new()),
+                    Some(b) => b.flat().and_then(|t| t.chars().map(map).collect::<Option<String>>()),
+                };
+                if let (Some(up), Some(down)) = (scripted(sup.as_ref(), superscript), scripted(sub.as_ref(), subscript)) {
+                    return Block::beside(vec![base_block, Block::text(&format!("{}{}", down, up))]);
+                }
+                // Otherwise raise and lower whole blocks beside the base
+                let (up, down) = (sup.unwrap_or_else(|| Block::text("")), sub.unwrap_or_else(|| Block::text("")));
+                let (up_h, down_h) = (if up.width > 0 { up.height() } else { 0 }, if down.width > 0 { down.height() } else { 0 });
+                let height = up_h + base_block.height() + down_h;
+                let width = up.width.max(down.width);
+                let lines = (0..height)
+                    .map(|row| {
+                        let left = row.checked_sub(up_h).map_or_else(|| " ".repeat(base_block.width), |i| {
+                            if i < base_block.height() { base_block.line(i) } else { " ".repeat(base_block.width) }
+                        });
+                        let right = if row < up_h {
+                            format!("{:<w$}", up.line(row), w = width)
+                        } else if row >= up_h + base_block.height() {
+                            format!("{:<w$}", down.line(row - up_h - base_block.height()), w = width)
+                        } else {
+                            " ".repeat(width)
+                        };
+                        format!("{}{}", left, right)
+                    })
+                    .collect();
+                Block { lines, width: base_block.width + width, baseline: up_h + base_block.baseline }
+            }
+            Node::Delimited(open, body, close) => {
+                let body = Block::from(body);
+                let (height, baseline) = (body.height(), body.baseline);
+                Block::beside(vec![tall(open, height, baseline), body, tall(close, height, baseline)])
+            }
+            Node::Matrix { open, close, rows } => {
+                let cells: Vec<Vec<Block>> = rows.iter().map(|row| row.iter().map(Block::from).collect()).collect();
+                let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+                let widths: Vec<usize> = (0..columns)
+                    .map(|c| cells.iter().filter_map(|row| row.get(c)).map(|b| b.width).max().unwrap_or(0))
+                    .collect();
+                let mut lines = Vec::new();
+                for row in &cells {
+                    let padded: Vec<Block> = (0..columns)
+                        .map(|c| {
+                            let cell = row.get(c);
+                            let empty = Block::text("");
+                            let cell = cell.unwrap_or(&empty);
+                            // Cases are left-aligned; matrix entries are centered
+                            let lines = (0..cell.height())
+                                .map(|i| if open == &"{" && close.is_empty() { format!("{:<w$}", cell.line(i), w = widths[c]) } else { cell.centered(i, widths[c]) })
+                                .collect();
+                            let gap = if c + 1 < columns { "  " } else { "" };
+                            let block = Block { lines, width: widths[c], baseline: cell.baseline };
+                            Block::beside(vec![block, Block::text(gap)])
+                        })
+                        .collect();
+                    lines.extend(Block::beside(padded).lines);
+                }
+                let width = lines.iter().map(|l| l.width()).max().unwrap_or(0);
+                let body = Block { baseline: lines.len() / 2, lines, width };
+                let (height, baseline) = (body.height(), body.baseline);
+                let inner = Block::beside(vec![Block::text(" "), body, Block::text(" ")]);
+                Block::beside(vec![tall(open, height, baseline), inner, tall(close, height, baseline)])
+            }
+        }
+    }
+}
+
+/// A bracket stretched to `height` rows
+fn tall(delimiter: &str, height: usize, baseline: usize) -> Block {
+    if delimiter.is_empty() {
+        return Block { lines: vec![String::new(); height], width: 0, baseline };
+    }
+    if height == 1 {
+        return Block::text(delimiter);
+    }
+    let pieces = match delimiter {
+        "(" => ["⎛", "⎜", "⎝", "⎜"],
+        ")" => ["⎞", "⎟", "⎠", "⎟"],
+        "[" => ["⎡", "⎢", "⎣", "⎢"],
+        "]" => ["⎤", "⎥", "⎦", "⎥"],
+        "{" => ["⎧", "⎪", "⎩", "⎨"],
+        "}" => ["⎫", "⎪", "⎭", "⎬"],
+        "|" => ["│", "│", "│", "│"],
+        "‖" => ["‖", "‖", "‖", "‖"],
+        d => [" ", " ", " ", d],
+    };
+    let [top, middle, bottom, center] = pieces;
+    let lines = (0..height)
+        .map(|i| match i {
+            0 => top,
+            i if i + 1 == height => bottom,
+            i if i == height / 2 && (delimiter == "{" || delimiter == "}") => center,
+            _ => middle,
+        })
+        .map(str::to_string)
+        .collect();
+    Block { lines, width: 1, baseline }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbols_and_scripts() {
+        assert_eq!(to_unicode("E = mc^2"), "E = mc²");
+        assert_eq!(to_unicode(r"\alpha \leq \beta_{i+1}"), "α ≤ βᵢ₊₁");
+        assert_eq!(to_unicode(r"x^{n-1} \to \infty"), "xⁿ⁻¹ → ∞");
+    }
+
+    #[test]
+    fn scripts_without_unicode_forms_fall_back_to_carets() {
+        assert_eq!(to_unicode("x_{q+1}"), "x_(q+1)");
+        assert_eq!(to_unicode("a_q"), "a_q");
+    }
+
+    #[test]
+    fn fractions_and_roots() {
+        assert_eq!(to_unicode(r"\frac{1}{2}"), "½");
+        assert_eq!(to_unicode(r"\frac{\alpha}{2}"), "α/2");
+        assert_eq!(to_unicode(r"\frac{a+b}{c}"), "(a+b)/c");
+        assert_eq!(to_unicode(r"\sqrt{x} + \sqrt[3]{y} + \sqrt[n]{z}"), "√x + ∛y + ⁿ√z");
+    }
+
+    #[test]
+    fn text_fonts_and_accents() {
+        assert_eq!(to_unicode(r"\text{if } x \in \mathbb{R}"), "if x ∈ ℝ");
+        assert_eq!(to_unicode(r"\mathbf{v}"), "v");
+        assert_eq!(to_unicode(r"\hat{x}"), "x\u{0302}");
+    }
+
+    #[test]
+    fn matrices_and_cases_on_one_line() {
+        assert_eq!(to_unicode(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}"), "(a, b; c, d)");
+        assert_eq!(to_unicode(r"\left( x \right)"), "( x )");
+    }
+
+    #[test]
+    fn unknown_commands_are_kept() {
+        assert_eq!(to_unicode(r"\foo + 1"), r"\foo + 1");
+    }
+
+
+    #[test]
+    fn flat_math_stays_on_one_line() {
+        assert_eq!(layout("x = y^2"), vec!["x = y²"]);
+    }
+
+    #[test]
+    fn fractions_stack_over_a_bar() {
+        assert_eq!(layout(r"\frac{a+b}{2}"), vec![" a+b", "─────", "  2"]);
+    }
+
+    #[test]
+    fn sums_carry_limits_above_and_below() {
+        assert_eq!(layout(r"\sum_{i=1}^{n} i^2"), vec![" n", " ∑  i²", "i=1"]);
+    }
+
+    #[test]
+    fn roots_extend_over_tall_radicands() {
+        assert_eq!(layout(r"\sqrt{\frac{1}{x}}"), vec![" ───", "│ 1", "│───", "√ x"]);
+    }
+
+    #[test]
+    fn tall_scripts_are_raised_beside_the_base() {
+        assert_eq!(layout(r"e^{\frac{1}{2}}"), vec!["  1", " ───", "  2", "e"]);
+    }
+
+    #[test]
+    fn delimiters_stretch_to_their_contents() {
+        assert_eq!(layout(r"\left( \frac{a}{b} \right)"), vec!["⎛  a  ⎞", "⎜ ─── ⎟", "⎝  b  ⎠"]);
+    }
+
+    #[test]
+    fn matrices_and_cases() {
+        assert_eq!(layout(r"\begin{pmatrix} 1 & 0 \\ 0 & 1 \end{pmatrix}"), vec!["⎛ 1  0 ⎞", "⎝ 0  1 ⎠"]);
+        assert_eq!(
+            layout(r"|x| = \begin{cases} x & x \ge 0 \\ -x & x < 0 \end{cases}"),
+            vec!["      ⎧ x   x ≥ 0", "|x| = ⎩ −x  x < 0"]
+        );
+    }
+}
@@ -0,0 +1,552 @@
+//! Drawing simple Mermaid diagrams (flowcharts and sequence diagrams) with box-drawing
+//! characters. Anything outside that subset is left to be shown as source.
+
+use regex::Regex;
+use std::collections::HashMap;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Render a Mermaid diagram as text, or None when the diagram type or syntax isn't supported
+/// or the drawing would be wider than `max_width`
+pub fn render(source: &str, max_width: usize) -> Option<String> {
+    let mut lines = source.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with("%%"));
+    let header = lines.next()?;
+    let body: Vec<&str> = lines.collect();
+    let mut words = header.split_whitespace();
+    let diagram = match words.next()? {
+        "graph" | "flowchart" => {
+            let chart = Flowchart::parse(&body)?;
+            match words.next().unwrap_or("TD") {
+                "TD" | "TB" => chart.render_vertical(),
+                "LR" => chart.render_horizontal(),
+                _ => return None,
+            }
+        }
+        "sequenceDiagram" => Sequence::parse(&body)?.render(),
+        _ => return None,
+    };
+    diagram.lines().all(|l| l.width() <= max_width).then_some(diagram)
+}
+
+const UP: u8 = 1;
+const DOWN: u8 = 2;
+const LEFT: u8 = 4;
+const RIGHT: u8 = 8;
+
+/// Character grid. Lines are recorded as connection directions per cell and turned into
+/// box-drawing characters at the end, so crossings and junctions join up.
+#[derive(Default)]
+struct Canvas {
+    cells: Vec<Vec<char>>,
+    links: HashMap<(usize, usize), u8>,
+}
+
+/// Stands in for the second column of a wide character
+const WIDE_TAIL: char = '\0';
+
+impl Canvas {
+    fn put(&mut self, x: usize, y: usize, c: char) {
+        if self.cells.len() <= y {
+            self.cells.resize(y + 1, Vec::new());
+        }
+        let row = &mut self.cells[y];
+        if row.len() <= x {
+            row.resize(x + 1, ' ');
+        }
+        row[x] = c;
+    }
+
+    fn get(&self, x: usize, y: usize) -> char {
+        self.cells.get(y).and_then(|row| row.get(x)).copied().unwrap_or(' ')
+    }
+
+    fn text(&mut self, x: usize, y: usize, text: &str) {
+        let mut col = x;
+        for c in text.chars() {
+            self.put(col, y, c);
+            for extra in 1..c.width().unwrap_or(0) {
+                self.put(col + extra, y, WIDE_TAIL);
+            }
+            col += c.width().unwrap_or(0);
+        }
+    }
+
+    /// Whether `width` cells from (x, y) hold nothing yet
+    fn is_free(&self, x: usize, y: usize, width: usize) -> bool {
+        (x..x + width).all(|col| self.get(col, y) == ' ' && !self.links.contains_key(&(col, y)))
+    }
+
+    fn link(&mut self, x: usize, y: usize, dirs: u8) {
+        *self.links.entry((x, y)).or_default() |= dirs;
+    }
+
+    fn hline(&mut self, y: usize, from: usize, to: usize) {
+        let (a, b) = (from.min(to), from.max(to));
+        for x in a..=b {
+            let left = if x > a { LEFT } else { 0 };
+            let right = if x < b { RIGHT } else { 0 };
+            self.link(x, y, left | right);
+        }
+    }
+
+    fn vline(&mut self, x: usize, from: usize, to: usize) {
+        let (a, b) = (from.min(to), from.max(to));
+        for y in a..=b {
+            let up = if y > a { UP } else { 0 };
+            let down = if y < b { DOWN } else { 0 };
+            self.link(x, y, up | down);
+        }
+    }
+
+    /// Three-row box around a label, with its top-left corner at (x, y)
+    fn boxed(&mut self, x: usize, y: usize, label: &str) {
+        let inner = label.width() + 2;
+        self.text(x, y, &format!("┌{}┐", "─".repeat(inner)));
+        self.text(x, y + 1, &format!("│ {} │", label));
+        self.text(x, y + 2, &format!("└{}┘", "─".repeat(inner)));
+    }
+
+    fn finish(mut self) -> String {
+        let links: Vec<_> = self.links.drain().collect();
+        for ((x, y), dirs) in links {
+            if self.get(x, y) == ' ' {
+                self.put(x, y, link_char(dirs));
+            }
+        }
+        let mut out = String::new();
+        for row in &self.cells {
+            let line: String = row.iter().filter(|&&c| c != WIDE_TAIL).collect();
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn link_char(dirs: u8) -> char {
+    match dirs {
+        d if d == UP | DOWN | LEFT | RIGHT => '┼',
+        d if d == UP | DOWN | RIGHT => '├',
+        d if d == UP | DOWN | LEFT => '┤',
+        d if d == DOWN | LEFT | RIGHT => '┬',
+        d if d == UP | LEFT | RIGHT => '┴',
+        d if d == DOWN | RIGHT => '┌',
+        d if d == DOWN | LEFT => '┐',
+        d if d == UP | RIGHT => '└',
+        d if d == UP | LEFT => '┘',
+        d if d & (UP | DOWN) != 0 => '│',
+        _ => '─',
+    }
+}
+
+struct Edge {
+    from: usize,
+    to: usize,
+    label: Option<String>,
+}
+
+struct Flowchart {
+    /// Node labels, in order of first mention
+    labels: Vec<String>,
+    edges: Vec<Edge>,
+}
+
+impl Flowchart {
+    fn parse(lines: &[&str]) -> Option<Self> {
+        let node_re = Regex::new(r"^([A-Za-z0-9_]+)\s*(?:(\(\(|\(\[|\[\[|\[\(|\{\{|\[/|\[\\|\[|\(|\{|>)([^\]\)\}]*)[\]\)\}/\\]+)?").unwrap();
+        // A -- text --> B, or A -->|text| B, or a bare arrow
+        let text_link_re = Regex::new(r"^\s*(?:--|==|-\.)\s+(.+?)\s+(?:-{2,}>|={2,}>|\.->|-{3,}|={3,})\s*").unwrap();
+        let link_re = Regex::new(r"^\s*(?:<?(?:-{2,}[>ox]?|={2,}>?|-\.+->?|~~~))\s*(?:\|([^|]*)\|)?\s*").unwrap();
+
+        let mut chart = Flowchart { labels: Vec::new(), edges: Vec::new() };
+        let mut ids: HashMap<String, usize> = HashMap::new();
+        for statement in lines.iter().flat_map(|l| l.split(';')).map(str::trim).filter(|s| !s.is_empty()) {
+            let keyword = statement.split_whitespace().next().unwrap_or_default();
+            if ["subgraph", "end", "direction", "style", "classDef", "class", "linkStyle", "click"].contains(&keyword) {
+                continue;
+            }
+            let mut rest = statement;
+            let mut previous: Option<usize> = None;
+            let mut pending_label: Option<String> = None;
+            loop {
+                let caps = node_re.captures(rest)?;
+                let id = caps[1].to_string();
+                let label = caps.get(3).map(|m| m.as_str().trim().trim_matches('"').to_string());
+                let index = *ids.entry(id.clone()).or_insert_with(|| {
+                    chart.labels.push(id.clone());
+                    chart.labels.len() - 1
+                });
+                if let Some(label) = label.filter(|l| !l.is_empty()) {
+                    chart.labels[index] = label;
+                }
+                if let Some(from) = previous {
+                    chart.edges.push(Edge { from, to: index, label: pending_label.take() });
+                }
+                previous = Some(index);
+                rest = rest[caps[0].len()..].trim_start();
+                if rest.is_empty() {
+                    break;
+                }
+                if let Some(caps) = text_link_re.captures(rest) {
+                    pending_label = Some(caps[1].trim_matches('"').to_string());
+                    rest = &rest[caps[0].len()..];
+                } else if let Some(caps) = link_re.captures(rest) {
+                    pending_label = caps.get(1).map(|m| m.as_str().trim().trim_matches('"').to_string()).filter(|l| !l.is_empty());
+                    rest = &rest[caps[0].len()..];
+                } else {
+                    // `&` groups and other syntax aren't supported
+                    return None;
+                }
+            }
+        }
+        (!chart.labels.is_empty()).then_some(chart)
+    }
+
+    /// Layer of each node: the longest path to it from a root, ignoring edges that close a cycle
+    fn ranks(&self) -> (Vec<usize>, Vec<bool>) {
+        let n = self.labels.len();
+        let mut back = vec![false; self.edges.len()];
+        let mut state = vec![0u8; n]; // 0 = unvisited, 1 = on the stack, 2 = done
+        fn visit(node: usize, chart: &Flowchart, state: &mut [u8], back: &mut [bool]) {
+            state[node] = 1;
+            for (i, edge) in chart.edges.iter().enumerate().filter(|(_, e)| e.from == node) {
+                match state[edge.to] {
+                    0 => visit(edge.to, chart, state, back),
+                    1 => back[i] = true,
+                    _ => {}
+                }
+            }
+            state[node] = 2;
+        }
+        for node in 0..n {
+            if state[node] == 0 {
+                visit(node, self, &mut state, &mut back);
+            }
+        }
+
+        let mut rank = vec![0; n];
+        for _ in 0..n {
+            for (edge, _) in self.edges.iter().zip(&back).filter(|(_, &b)| !b) {
+                rank[edge.to] = rank[edge.to].max(rank[edge.from] + 1);
+            }
+        }
+        (rank, back)
+    }
+
+    /// Nodes grouped by rank, each layer ordered by where its parents sit in the layer above
+    fn layers(&self, rank: &[usize]) -> Vec<Vec<usize>> {
+        let depth = rank.iter().max().map_or(0, |r| r + 1);
+        let mut layers: Vec<Vec<usize>> = vec![Vec::new(); depth];
+        for (node, &r) in rank.iter().enumerate() {
+            layers[r].push(node);
+        }
+        for r in 1..depth {
+            let position: HashMap<usize, usize> = layers[r - 1].iter().enumerate().map(|(i, &n)| (n, i)).collect();
+            let barycenter = |node: usize| {
+                let parents: Vec<usize> = self.edges.iter().filter(|e| e.to == node).filter_map(|e| position.get(&e.from).copied()).collect();
+                if parents.is_empty() { f64::MAX } else { parents.iter().sum::<usize>() as f64 / parents.len() as f64 }
+            };
+            layers[r].sort_by(|&a, &b| barycenter(a).total_cmp(&barycenter(b)).then(a.cmp(&b)));
+        }
+        layers
+    }
+
+    /// Edges that skip a layer or point back up can't be drawn; list them under the diagram
+    fn extra_edges(&self, drawn: &[bool], out: &mut String) {
+        for (edge, _) in self.edges.iter().zip(drawn).filter(|(_, &d)| !d) {
+            out.push_str(&format!("{} → {}", self.labels[edge.from], self.labels[edge.to]));
+            if let Some(label) = &edge.label {
+                out.push_str(&format!(" ({})", label));
+            }
+            out.push('\n');
+        }
+    }
+
+    /// Top-down layout: layers are rows of boxes, joined by arrows running down
+    fn render_vertical(&self) -> String {
+        let (rank, back) = self.ranks();
+        let layers = self.layers(&rank);
+        let widths: Vec<usize> = self.labels.iter().map(|l| l.width() + 4).collect();
+        let gap = 3;
+        let layer_width = |layer: &Vec<usize>| layer.iter().map(|&n| widths[n]).sum::<usize>() + gap * layer.len().saturating_sub(1);
+        let total = layers.iter().map(layer_width).max().unwrap_or(0);
+
+        let mut canvas = Canvas::default();
+        let mut left = vec![0; self.labels.len()];
+        for (r, layer) in layers.iter().enumerate() {
+            let mut x = (total - layer_width(layer)) / 2;
+            for &node in layer {
+                left[node] = x;
+                canvas.boxed(x, r * 6, &self.labels[node]);
+                x += widths[node] + gap;
+            }
+        }
+
+        let mut drawn = vec![false; self.edges.len()];
+        for (i, edge) in self.edges.iter().enumerate() {
+            if back[i] || rank[edge.to] != rank[edge.from] + 1 {
+                continue;
+            }
+            drawn[i] = true;
+            let (px, cx) = (left[edge.from] + widths[edge.from] / 2, left[edge.to] + widths[edge.to] / 2);
+            let y = rank[edge.from] * 6;
+            canvas.put(px, y + 2, '┬');
+            canvas.vline(px, y + 3, y + 4);
+            canvas.hline(y + 4, px, cx);
+            canvas.link(cx, y + 4, DOWN);
+            canvas.put(cx, y + 5, '▼');
+            // Label beside the arrowhead, or failing that beside the line leaving the parent
+            if let Some(label) = &edge.label {
+                if canvas.is_free(cx + 1, y + 5, label.width() + 2) {
+                    canvas.text(cx + 2, y + 5, label);
+                } else if canvas.is_free(px + 1, y + 3, label.width() + 2) {
+                    canvas.text(px + 2, y + 3, label);
+                } else {
+                    drawn[i] = false;
+                }
+            }
+        }
+        let mut out = canvas.finish();
+        self.extra_edges(&drawn, &mut out);
+        out
+    }
+
+    /// Left-to-right layout: layers are columns of boxes, joined by arrows running right
+    fn render_horizontal(&self) -> String {
+        let (rank, back) = self.ranks();
+        let layers = self.layers(&rank);
+        let widths: Vec<usize> = self.labels.iter().map(|l| l.width() + 4).collect();
+        let tallest = layers.iter().map(Vec::len).max().unwrap_or(0);
+
+        // Each gap leaves room for the labels of the edges crossing it
+        let mut column_x = Vec::with_capacity(layers.len());
+        let mut x = 0;
+        for (r, layer) in layers.iter().enumerate() {
+            column_x.push(x);
+            let column_width = layer.iter().map(|&n| widths[n]).max().unwrap_or(0);
+            let label_width = self
+                .edges
+                .iter()
+                .filter(|e| rank[e.from] == r)
+                .filter_map(|e| e.label.as_ref().map(|l| l.width()))
+                .max()
+                .unwrap_or(0);
+            x += column_width + 6 + label_width;
+        }
+
+        let mut canvas = Canvas::default();
+        let mut top = vec![0; self.labels.len()];
+        for (r, layer) in layers.iter().enumerate() {
+            let offset = (tallest - layer.len()) * 2;
+            for (i, &node) in layer.iter().enumerate() {
+                top[node] = offset + i * 4;
+                canvas.boxed(column_x[r], top[node], &self.labels[node]);
+            }
+        }
+
+        let mut drawn = vec![false; self.edges.len()];
+        for (i, edge) in self.edges.iter().enumerate() {
+            let r = rank[edge.from];
+            if back[i] || rank[edge.to] != r + 1 {
+                continue;
+            }
+            drawn[i] = true;
+            let (py, cy) = (top[edge.from] + 1, top[edge.to] + 1);
+            let right = column_x[r] + widths[edge.from] - 1;
+            let column_width = layers[r].iter().map(|&n| widths[n]).max().unwrap_or(0);
+            let turn = column_x[r] + column_width + 1;
+            let arrow = column_x[r + 1] - 1;
+            canvas.put(right, py, '├');
+            canvas.hline(py, right + 1, turn);
+            canvas.link(right + 1, py, LEFT);
+            canvas.vline(turn, py, cy);
+            canvas.hline(cy, turn, arrow - 1);
+            canvas.link(arrow - 1, cy, RIGHT);
+            canvas.put(arrow, cy, '▶');
+            if let Some(label) = &edge.label {
+                if canvas.is_free(turn + 2, cy - 1, label.width()) {
+                    canvas.text(turn + 2, cy - 1, label);
+                } else {
+                    drawn[i] = false;
+                }
+            }
+        }
+        let mut out = canvas.finish();
+        self.extra_edges(&drawn, &mut out);
+        out
+    }
+}
+
+struct Message {
+    from: usize,
+    to: usize,
+    text: String,
+    dashed: bool,
+}
+
+struct Sequence {
+    participants: Vec<(String, String)>,
+    messages: Vec<Message>,
+}
+
+impl Sequence {
+    fn parse(lines: &[&str]) -> Option<Self> {
+        let participant_re = Regex::new(r"^(?:participant|actor)\s+(.+?)(?:\s+as\s+(.+))?$").unwrap();
+        let message_re = Regex::new(r"^(.+?)\s*(-->>|->>|-->|->|--x|-x|--\)|-\))\s*[+-]?\s*(.+?)\s*:\s*(.*)$").unwrap();
+        let mut sequence = Sequence { participants: Vec::new(), messages: Vec::new() };
+        let index = |participants: &mut Vec<(String, String)>, id: &str| {
+            participants.iter().position(|(p, _)| p == id).unwrap_or_else(|| {
+                participants.push((id.to_string(), id.to_string()));
+                participants.len() - 1
+            })
+        };
+        for line in lines {
+            if let Some(caps) = participant_re.captures(line) {
+                let i = index(&mut sequence.participants, &caps[1]);
+                if let Some(alias) = caps.get(2) {
+                    sequence.participants[i].1 = alias.as_str().to_string();
+                }
+            } else if let Some(caps) = message_re.captures(line) {
+                let from = index(&mut sequence.participants, &caps[1]);
+                let to = index(&mut sequence.participants, &caps[3]);
+                sequence.messages.push(Message { from, to, text: caps[4].to_string(), dashed: caps[2].starts_with("--") });
+            }
+            // Notes, activations, and loop/alt blocks aren't drawn
+        }
+        (!sequence.participants.is_empty()).then_some(sequence)
+    }
+
+    /// Participants in boxes across the top with lifelines down from them, and one labeled
+    /// arrow per message
+    fn render(&self) -> String {
+        let widths: Vec<usize> = self.participants.iter().map(|(_, label)| label.width() + 4).collect();
+        let widest_box = widths.iter().copied().max().unwrap_or(0);
+        let widest_text = self.messages.iter().map(|m| m.text.width()).max().unwrap_or(0);
+        let spacing = (widest_text + 4).max(widest_box + 2);
+        let center = |i: usize| widths[0] / 2 + i * spacing;
+
+        let mut canvas = Canvas::default();
+        for (i, (_, label)) in self.participants.iter().enumerate() {
+            canvas.boxed(center(i) - widths[i] / 2, 0, label);
+            canvas.put(center(i), 2, '┬');
+        }
+        let bottom = 3 + self.messages.len() * 2;
+        for i in 0..self.participants.len() {
+            canvas.vline(center(i), 3, bottom);
+            canvas.link(center(i), 3, UP);
+        }
+
+        for (m, message) in self.messages.iter().enumerate() {
+            let (label_y, arrow_y) = (3 + m * 2, 4 + m * 2);
+            let (from, to) = (center(message.from), center(message.to));
+            if from == to {
+                // Message to self: a loop off to the right
+                canvas.link(from, label_y, RIGHT);
+                canvas.text(from + 1, label_y, "─┐");
+                canvas.text(from + 1, arrow_y, "◀┘");
+                canvas.text(from + 4, label_y, &message.text);
+                continue;
+            }
+            canvas.text(from.min(to) + 2, label_y, &message.text);
+            let (start, head, end) = if from < to { (from, '▶', to - 1) } else { (from, '◀', to + 1) };
+            canvas.link(start, arrow_y, if from < to { RIGHT } else { LEFT });
+            let lifelines: Vec<usize> = (0..self.participants.len()).map(center).collect();
+            for x in start.min(end)..=start.max(end) {
+                if x == start {
+                    continue;
+                }
+                if message.dashed && !lifelines.contains(&x) {
+                    canvas.put(x, arrow_y, '╌');
+                } else {
+                    canvas.link(x, arrow_y, LEFT | RIGHT);
+                }
+            }
+            canvas.put(end, arrow_y, head);
+        }
+        canvas.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flowchart(source: &str) -> Flowchart {
+        let lines: Vec<&str> = source.lines().collect();
+        Flowchart::parse(&lines).unwrap()
+    }
+
+    #[test]
+    fn flowchart_nodes_take_their_labels_and_edges_their_text() {
+        let chart = flowchart("A[Start] --> B{Ok?}\nB -->|yes| C((Done))\nB -- no --> A; C --> D");
+        assert_eq!(chart.labels, vec!["Start", "Ok?", "Done", "D"]);
+        let edges: Vec<(usize, usize, Option<&str>)> = chart.edges.iter().map(|e| (e.from, e.to, e.label.as_deref())).collect();
+        assert_eq!(edges, vec![(0, 1, None), (1, 2, Some("yes")), (1, 0, Some("no")), (2, 3, None)]);
+    }
+
+    #[test]
+    fn flowchart_styling_statements_are_skipped() {
+        let chart = flowchart("subgraph one\nA --> B\nend\nstyle A fill:#f9f\nclassDef red fill:#f00");
+        assert_eq!(chart.labels, vec!["A", "B"]);
+        assert_eq!(chart.edges.len(), 1);
+    }
+
+    #[test]
+    fn top_down_flowchart_with_a_back_edge() {
+        let diagram = render("graph TD\nA[Start] --> B{Ok?}\nB -->|yes| C\nB -- no --> A", 80).unwrap();
+        let expected = [
+            "┌───────┐",
+            "│ Start │",
+            "└───┬───┘",
+            "    │",
+            "    │",
+            "    ▼",
+            " ┌─────┐",
+            " │ Ok? │",
+            " └──┬──┘",
+            "    │",
+            "    │",
+            "    ▼ yes",
+            "  ┌───┐",
+            "  │ C │",
+            "  └───┘",
+            "Ok? → Start (no)",
+        ];
+        assert_eq!(diagram.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn left_to_right_flowchart() {
+        let diagram = render("flowchart LR\nA --> B --> C", 80).unwrap();
+        assert_eq!(diagram.lines().collect::<Vec<_>>(), ["┌───┐      ┌───┐      ┌───┐", "│ A ├─────▶│ B ├─────▶│ C │", "└───┘      └───┘      └───┘"]);
+    }
+
+    #[test]
+    fn sequence_diagram_with_an_alias_and_a_dashed_reply() {
+        let diagram = render("sequenceDiagram\nparticipant A as Alice\nA->>Bob: hi\nNote over Bob: ignored\nBob-->>A: hello", 80).unwrap();
+        let expected = [
+            "┌───────┐   ┌─────┐",
+            "│ Alice │   │ Bob │",
+            "└───┬───┘   └──┬──┘",
+            "    │ hi       │",
+            "    ├─────────▶│",
+            "    │ hello    │",
+            "    │◀╌╌╌╌╌╌╌╌╌┤",
+            "    │          │",
+        ];
+        assert_eq!(diagram.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn unsupported_diagrams_are_left_as_source() {
+        assert!(render("pie\n\"a\": 1", 80).is_none());
+        assert!(render("graph RL\nA --> B", 80).is_none());
+        assert!(render("graph TD\nA & B --> C", 80).is_none());
+        assert!(render("", 80).is_none());
+    }
+
+    #[test]
+    fn diagrams_wider_than_the_terminal_are_left_as_source() {
+        assert!(render("graph LR\nA --> B --> C", 27).is_some());
+        assert!(render("graph LR\nA --> B --> C", 26).is_none());
+    }
+}
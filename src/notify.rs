@@ -0,0 +1,70 @@
+//! Saying that a long response or command has finished (`--notify`), for when the
+//! terminal isn't the window being looked at
+
+use crate::config::Notify;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// How long output must take to be notified, when `notify-after` isn't set: shorter than
+/// this, the terminal was most likely still being watched
+pub const DEFAULT_AFTER: Duration = Duration::from_secs(5);
+
+/// Notify that `what` has finished, if it took at least `after`
+pub fn finished(how: Notify, what: &str, took: Duration, after: Duration) {
+    if took < after {
+        return;
+    }
+    let message = format!("{} finished after {}", what, seconds(took));
+    log::info!("notifying: {}", message);
+    if how == Notify::Bell || !desktop(&message) {
+        bell();
+    }
+}
+
+/// Send a desktop notification, returning whether it went
+fn desktop(message: &str) -> bool {
+    let mut command = if cfg!(target_os = "macos") {
+        let script = format!("display notification {} with title \"livemd\"", applescript_string(message));
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(script);
+        command
+    } else if cfg!(windows) {
+        return false;
+    } else {
+        let mut command = Command::new("notify-send");
+        command.args(["--app-name", "livemd", "livemd", message]);
+        command
+    };
+    match command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).status() {
+        Ok(status) => status.success(),
+        Err(e) => {
+            log::info!("no desktop notification: {}", e);
+            false
+        }
+    }
+}
+
+/// Ring the terminal bell, on the terminal itself so it isn't lost when stdout is piped
+fn bell() {
+    let path = if cfg!(windows) { "CONOUT$" } else { "/dev/tty" };
+    let written = std::fs::OpenOptions::new().write(true).open(path).and_then(|mut tty| tty.write_all(b"\x07"));
+    if written.is_err() {
+        let mut stderr = std::io::stderr();
+        let _ = stderr.write_all(b"\x07").and_then(|_| stderr.flush());
+    }
+}
+
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A duration as `42s` or `3m 05s`
+fn seconds(took: Duration) -> String {
+    let secs = took.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
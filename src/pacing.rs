@@ -0,0 +1,38 @@
+//! Pacing between flushed blocks, adapted to how fast input arrives
+
+use std::time::{Duration, Instant};
+
+/// Weight of the newest gap in the running average, so one stall doesn't switch
+/// pacing off for the rest of the stream
+const SMOOTHING: f64 = 0.3;
+
+/// Decides how long to pause after each flushed block. Input that arrives in one burst
+/// (a file, a fast pipe) is paced at the configured speed; input that already trickles in
+/// slower than that (an LLM) is shown as soon as it's ready, without extra sleeps.
+pub struct Pacer {
+    speed: Duration,
+    last: Option<Instant>,
+    /// Running average of the time between input chunks
+    gap: Duration,
+}
+
+impl Pacer {
+    pub fn new(speed: f64) -> Self {
+        Self { speed: Duration::from_secs_f64(speed.max(0.0)), last: None, gap: Duration::ZERO }
+    }
+
+    /// Record that a chunk of input just arrived
+    pub fn arrived(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last {
+            let gap = now - last;
+            self.gap = self.gap.mul_f64(1.0 - SMOOTHING) + gap.mul_f64(SMOOTHING);
+        }
+        self.last = Some(now);
+    }
+
+    /// Pause to leave after a block: whatever the input's own arrival rate doesn't cover
+    pub fn delay(&self) -> Duration {
+        self.speed.saturating_sub(self.gap)
+    }
+}
@@ -0,0 +1,275 @@
+//! Standalone HTML pages for `--output html`, styled with the theme's colors
+
+use crate::theme::{Style, Theme};
+use pulldown_cmark::{html, Options, Parser};
+use termimad::crossterm::style::Color;
+
+/// Closes the page opened by [`header`]
+pub const FOOTER: &str = "</main>\n</body>\n</html>\n";
+
+/// CSS for a theme color, or None for the terminal default
+fn css_color(color: &str) -> Option<String> {
+    match Theme::parse_color(color) {
+        Color::Reset => None,
+        Color::Rgb { r, g, b } => Some(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+        Color::DarkGrey => Some("dimgray".to_string()),
+        Color::Grey => Some("silver".to_string()),
+        named => Some(format!("{:?}", named).to_lowercase()),
+    }
+}
+
+/// CSS declarations for a theme style: its color and any attributes it turns on or off
+fn declarations(style: &Style) -> String {
+    let mut css = String::new();
+    if let Some(color) = css_color(&style.color) {
+        css.push_str(&format!("color: {}; ", color));
+    }
+    if let Some(bold) = style.bold {
+        css.push_str(if bold { "font-weight: bold; " } else { "font-weight: normal; " });
+    }
+    if let Some(italic) = style.italic {
+        css.push_str(if italic { "font-style: italic; " } else { "font-style: normal; " });
+    }
+    if let Some(underline) = style.underline {
+        css.push_str(if underline { "text-decoration: underline; " } else { "text-decoration: none; " });
+    }
+    if style.dim == Some(true) {
+        css.push_str("opacity: 0.7; ");
+    }
+    css
+}
+
+/// Whether a theme's text color is light, meaning it was made for a dark background
+fn is_dark(theme: &Theme) -> bool {
+    let text = theme.text.as_ref().map_or("", |style| style.color.as_str());
+    match Theme::parse_color(text) {
+        Color::Rgb { r, g, b } => 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64 > 128.0,
+        Color::White | Color::Grey => true,
+        _ => false,
+    }
+}
+
+/// Start of the page: the document head with the theme as a stylesheet
+pub fn header(theme: &Theme, title: &str) -> String {
+    let (background, foreground) = if is_dark(theme) { ("#11111b", "#cdd6f4") } else { ("#ffffff", "#1f2328") };
+    let mut css = format!(
+        "body {{ background: {}; color: {}; font-family: system-ui, sans-serif; line-height: 1.5; }}\n\
+         main {{ max-width: 50rem; margin: 2rem auto; padding: 0 1rem; }}\n\
+         pre, code {{ font-family: ui-monospace, monospace; }}\n\
+         pre {{ padding: 0.75rem 1rem; overflow-x: auto; }}\n\
+         code {{ padding: 0 0.2em; }}\n\
+         pre code {{ padding: 0; }}\n\
+         blockquote {{ margin-left: 0; padding-left: 1rem; border-left: 3px solid; font-style: italic; }}\n\
+         table {{ border-collapse: collapse; }}\n\
+         th, td {{ border: 1px solid; padding: 0.2rem 0.6rem; }}\n\
+         hr {{ border: none; border-top: 1px solid; }}\n",
+        background, foreground
+    );
+    let mut rule = |selector: &str, declarations: String| {
+        if !declarations.is_empty() {
+            css.push_str(&format!("{} {{ {}}}\n", selector, declarations));
+        }
+    };
+    if let Some(text) = &theme.text {
+        rule("body", declarations(text));
+    }
+    for level in 1..=6 {
+        rule(&format!("h{}", level), declarations(&theme.get_heading_style(level)));
+    }
+    rule("strong", declarations(&theme.bold));
+    rule("em", declarations(&theme.italic));
+    rule("a", declarations(&theme.link));
+    rule("li::marker", declarations(&theme.list));
+    rule("th", declarations(&theme.table_header));
+    if let Some(code) = css_color(&theme.code.color) {
+        rule("pre, code", format!("background: {}; ", code));
+    }
+    for (selector, field) in [
+        ("blockquote", "blockquote"),
+        ("th, td", "table_border"),
+        ("hr", "rule"),
+        ("del", "strikethrough"),
+        (".math", "math"),
+    ] {
+        if let Some(style) = theme.get_style(field) {
+            let mut css = declarations(style);
+            if let Some(color) = css_color(&style.color).filter(|_| matches!(field, "blockquote" | "table_border" | "rule")) {
+                css = format!("border-color: {}; ", color);
+            }
+            rule(selector, css);
+        }
+    }
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n<main>\n",
+        escape(title),
+        css
+    )
+}
+
+/// Markdown as HTML, for the body of the page
+pub fn body(markdown: &str) -> String {
+    let mut out = String::new();
+    html::push_html(&mut out, Parser::new_ext(markdown, Options::all()));
+    out
+}
+
+/// Escape text for use in HTML
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// The 16 standard terminal colors, as xterm shows them
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0), (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0), (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+/// CSS for entry `n` of the 256-color palette
+fn palette_color(n: u8) -> String {
+    let (r, g, b) = match n {
+        0..=15 => PALETTE[n as usize],
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let n = n - 16;
+            (level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        _ => {
+            let grey = 8 + (n - 232) * 10;
+            (grey, grey, grey)
+        }
+    };
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Text attributes set by SGR escapes
+#[derive(Default)]
+struct Pen {
+    fg: Option<String>,
+    bg: Option<String>,
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underline: bool,
+    strike: bool,
+}
+
+impl Pen {
+    /// Apply the parameters of one SGR escape
+    fn apply(&mut self, params: &str) {
+        let mut codes = params.split([';', ':']).map(|p| p.parse::<u8>().unwrap_or(0));
+        while let Some(code) = codes.next() {
+            match code {
+                0 => *self = Pen::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                9 => self.strike = true,
+                22 => (self.bold, self.dim) = (false, false),
+                23 => self.italic = false,
+                24 => self.underline = false,
+                29 => self.strike = false,
+                30..=37 => self.fg = Some(palette_color(code - 30)),
+                90..=97 => self.fg = Some(palette_color(code - 90 + 8)),
+                40..=47 => self.bg = Some(palette_color(code - 40)),
+                100..=107 => self.bg = Some(palette_color(code - 100 + 8)),
+                39 => self.fg = None,
+                49 => self.bg = None,
+                38 | 48 => {
+                    let color = match codes.next() {
+                        Some(5) => codes.next().map(palette_color),
+                        Some(2) => match (codes.next(), codes.next(), codes.next()) {
+                            (Some(r), Some(g), Some(b)) => Some(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    if code == 38 {
+                        self.fg = color;
+                    } else {
+                        self.bg = color;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn css(&self) -> String {
+        let mut css = String::new();
+        if let Some(fg) = &self.fg {
+            css.push_str(&format!("color:{};", fg));
+        }
+        if let Some(bg) = &self.bg {
+            css.push_str(&format!("background:{};", bg));
+        }
+        if self.bold {
+            css.push_str("font-weight:bold;");
+        }
+        if self.dim {
+            css.push_str("opacity:0.7;");
+        }
+        if self.italic {
+            css.push_str("font-style:italic;");
+        }
+        match (self.underline, self.strike) {
+            (true, true) => css.push_str("text-decoration:underline line-through;"),
+            (true, false) => css.push_str("text-decoration:underline;"),
+            (false, true) => css.push_str("text-decoration:line-through;"),
+            (false, false) => {}
+        }
+        css
+    }
+}
+
+/// A page showing terminal output as it looked: SGR colors and attributes become styled
+/// spans and OSC 8 hyperlinks become links. Other escapes, such as inline images, are dropped.
+pub fn from_ansi(rendered: &str, title: &str) -> String {
+    let escape_re = regex::Regex::new(r"\x1b\[([0-9;:]*)([A-Za-z])|\x1b\]8;[^;\x07\x1b]*;([^\x07\x1b]*)(?:\x07|\x1b\\)|\x1b[\]_P][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b.").unwrap();
+    let mut html = String::new();
+    let mut pen = Pen::default();
+    let mut open_link = false;
+    let mut last = 0;
+    let write_text = |html: &mut String, text: &str, pen: &Pen| {
+        if text.is_empty() {
+            return;
+        }
+        let css = pen.css();
+        if css.is_empty() {
+            html.push_str(&escape(text));
+        } else {
+            html.push_str(&format!("<span style=\"{}\">{}</span>", css, escape(text)));
+        }
+    };
+    for caps in escape_re.captures_iter(rendered) {
+        let whole = caps.get(0).unwrap();
+        write_text(&mut html, &rendered[last..whole.start()], &pen);
+        last = whole.end();
+        if let (Some(params), Some(kind)) = (caps.get(1), caps.get(2)) {
+            if kind.as_str() == "m" {
+                pen.apply(params.as_str());
+            }
+        } else if let Some(url) = caps.get(3) {
+            if open_link {
+                html.push_str("</a>");
+                open_link = false;
+            }
+            if !url.as_str().is_empty() {
+                html.push_str(&format!("<a href=\"{}\">", escape(url.as_str())));
+                open_link = true;
+            }
+        }
+    }
+    write_text(&mut html, &rendered[last..], &pen);
+    if open_link {
+        html.push_str("</a>");
+    }
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n\
+         body {{ background: #11111b; color: #cdd6f4; margin: 2rem; }}\n\
+         pre {{ font-family: ui-monospace, monospace; line-height: 1.3; }}\n\
+         a {{ color: inherit; }}\n</style>\n</head>\n<body>\n<pre>{}</pre>\n</body>\n</html>\n",
+        escape(title),
+        html
+    )
+}
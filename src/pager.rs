@@ -0,0 +1,235 @@
+//! Full-screen viewer for output taller than the terminal
+
+use crate::picker::{Outcome, Picker};
+use crate::toc::Heading;
+use regex::Regex;
+use std::io::{self, Write};
+use std::time::Duration;
+use termimad::crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseEvent, MouseEventKind},
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+    QueueableCommand,
+};
+
+/// Split rendered output into screen lines. Styling carries over line breaks in the
+/// rendered text, so each line starts with the SGR codes still active from earlier lines.
+pub fn split_lines(rendered: &str) -> Vec<String> {
+    let sgr = Regex::new(r"\x1b\[[0-9;:]*m").unwrap();
+    let mut active = String::new();
+    let mut lines = Vec::new();
+    for line in rendered.lines() {
+        lines.push(format!("{}{}", active, line));
+        for code in sgr.find_iter(line) {
+            if matches!(code.as_str(), "\x1b[0m" | "\x1b[m") {
+                active.clear();
+            } else {
+                active.push_str(code.as_str());
+            }
+        }
+    }
+    lines
+}
+
+/// Run `f` on the alternate screen in raw mode, restoring the terminal afterwards even
+/// when `f` fails
+pub fn fullscreen<T>(f: impl FnOnce(&mut io::Stdout) -> io::Result<T>) -> io::Result<T> {
+    let mut out = io::stdout();
+    enable_raw_mode()?;
+    out.queue(EnterAlternateScreen)?.queue(Hide)?;
+    let result = f(&mut out);
+    out.queue(Show)?.queue(LeaveAlternateScreen)?;
+    out.flush()?;
+    disable_raw_mode()?;
+    result
+}
+
+/// Lines scrolled per mouse wheel step
+const WHEEL_LINES: usize = 3;
+
+/// What the pager should do after a key press or mouse event
+enum Action {
+    Stay,
+    Scroll,
+    Quit,
+}
+
+/// Full-screen view of rendered lines. While output streams in, new lines are shown as
+/// they arrive and keys are handled between flushes; once the stream ends, [`Pager::run`]
+/// takes over until the reader quits. The H1/H2 heading the view is in stays pinned as a
+/// title bar, and a status line shows the position and whether output is still streaming.
+/// The terminal is restored when the pager is dropped.
+pub struct Pager {
+    out: io::Stdout,
+    lines: Vec<String>,
+    /// Headings with their line numbers, which `n`/`p` jump between
+    headings: Vec<(usize, Heading)>,
+    /// First line on screen
+    top: usize,
+    /// Set once the stream has ended
+    complete: bool,
+    /// The heading picker, while it's open
+    picker: Option<Picker>,
+}
+
+impl Pager {
+    /// Switch to the alternate screen and raw mode, with mouse capture for the wheel
+    pub fn open() -> io::Result<Self> {
+        let mut out = io::stdout();
+        enable_raw_mode()?;
+        out.queue(EnterAlternateScreen)?.queue(Hide)?.queue(EnableMouseCapture)?;
+        out.flush()?;
+        Ok(Self { out, lines: Vec::new(), headings: Vec::new(), top: 0, complete: false, picker: None })
+    }
+
+    /// Replace the content with everything rendered so far and redraw
+    pub fn update(&mut self, lines: Vec<String>, headings: &[(usize, Heading)]) -> io::Result<()> {
+        self.lines = lines;
+        self.headings = headings.to_vec();
+        self.draw()
+    }
+
+    /// Handle the keys pressed since the last call, without waiting. Returns true when the
+    /// reader quit.
+    pub fn poll(&mut self) -> io::Result<bool> {
+        while event::poll(Duration::ZERO)? {
+            match self.handle(event::read()?) {
+                Action::Quit => return Ok(true),
+                Action::Scroll => self.draw()?,
+                Action::Stay => {}
+            }
+        }
+        Ok(false)
+    }
+
+    /// Scroll through the finished output until the reader quits
+    pub fn run(&mut self) -> io::Result<()> {
+        self.complete = true;
+        self.draw()?;
+        loop {
+            match self.handle(event::read()?) {
+                Action::Quit => return Ok(()),
+                Action::Scroll => self.draw()?,
+                Action::Stay => {}
+            }
+        }
+    }
+
+    /// Rows available for content: the screen less the status line and any title bar
+    fn page_height(&self) -> usize {
+        let rows = terminal::size().map_or(24, |(_, rows)| rows as usize);
+        rows.saturating_sub(1 + self.title_rows()).max(1)
+    }
+
+    /// The title bar takes a row once the output has an H1 or H2
+    fn title_rows(&self) -> usize {
+        usize::from(self.headings.iter().any(|(_, heading)| heading.level <= 2))
+    }
+
+    /// Text of the last H1/H2 at or above the top of the view
+    fn title(&self) -> &str {
+        self.headings
+            .iter()
+            .rev()
+            .find(|(line, heading)| *line <= self.top && heading.level <= 2)
+            .map_or("", |(_, heading)| heading.text.as_str())
+    }
+
+    fn handle(&mut self, event: Event) -> Action {
+        if let Some(picker) = self.picker.as_mut() {
+            match event {
+                Event::Key(KeyEvent { code, modifiers, kind: KeyEventKind::Press, .. }) => match picker.handle(code, modifiers, &self.headings) {
+                    Outcome::Stay => {}
+                    Outcome::Jump(line) => {
+                        self.top = line;
+                        self.picker = None;
+                    }
+                    Outcome::Cancel => self.picker = None,
+                },
+                Event::Resize(..) => {}
+                _ => return Action::Stay,
+            }
+            return Action::Scroll;
+        }
+        let page = self.page_height();
+        let top = self.top;
+        self.top = match event {
+            Event::Key(KeyEvent { code, modifiers, kind: KeyEventKind::Press, .. }) => match code {
+                KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Action::Quit,
+                KeyCode::Char('t') if !self.headings.is_empty() => {
+                    self.picker = Some(Picker::default());
+                    top
+                }
+                KeyCode::Char('n') => self.headings.iter().map(|(line, _)| *line).find(|&line| line > top).unwrap_or(top),
+                KeyCode::Char('p') => self.headings.iter().map(|(line, _)| *line).rev().find(|&line| line < top).unwrap_or(0),
+                KeyCode::Char('j') | KeyCode::Down | KeyCode::Enter => top + 1,
+                KeyCode::Char('k') | KeyCode::Up => top.saturating_sub(1),
+                KeyCode::Char(' ' | 'f') | KeyCode::PageDown => top + page,
+                KeyCode::Char('b') | KeyCode::PageUp => top.saturating_sub(page),
+                KeyCode::Char('d') => top + page / 2,
+                KeyCode::Char('u') => top.saturating_sub(page / 2),
+                KeyCode::Char('g') | KeyCode::Home => 0,
+                KeyCode::Char('G') | KeyCode::End => usize::MAX,
+                _ => return Action::Stay,
+            },
+            Event::Mouse(MouseEvent { kind: MouseEventKind::ScrollDown, .. }) => top + WHEEL_LINES,
+            Event::Mouse(MouseEvent { kind: MouseEventKind::ScrollUp, .. }) => top.saturating_sub(WHEEL_LINES),
+            Event::Resize(..) => top,
+            _ => return Action::Stay,
+        };
+        Action::Scroll
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let (columns, rows) = terminal::size()?;
+        let columns = columns as usize;
+        if let Some(picker) = self.picker.as_mut() {
+            return picker.draw(&mut self.out, &self.headings, columns, rows as usize);
+        }
+        let page = self.page_height();
+        self.top = self.top.min(self.lines.len().saturating_sub(page));
+        let offset = self.title_rows();
+        if offset > 0 {
+            let title: String = format!(" {}", self.title()).chars().take(columns).collect();
+            self.out
+                .queue(MoveTo(0, 0))?
+                .queue(SetAttribute(Attribute::Bold))?
+                .queue(SetAttribute(Attribute::Reverse))?
+                .queue(Print(format!("{:<width$}", title, width = columns)))?
+                .queue(SetAttribute(Attribute::Reset))?;
+        }
+        for row in 0..page {
+            self.out.queue(MoveTo(0, (offset + row) as u16))?;
+            if let Some(line) = self.lines.get(self.top + row) {
+                self.out.queue(Print(line))?;
+            }
+            self.out.queue(SetAttribute(Attribute::Reset))?.queue(Clear(ClearType::UntilNewLine))?;
+        }
+        let shown = (self.top + page).min(self.lines.len());
+        let state = if self.complete { "" } else { " · streaming…" };
+        let status = format!(
+            " {}-{} of {}{} · j/k: scroll · space/b: page · n/p: heading · t: headings · g/G: top/end · q: quit",
+            (self.top + 1).min(shown),
+            shown,
+            self.lines.len(),
+            state
+        );
+        let status: String = status.chars().take(columns).collect();
+        self.out
+            .queue(MoveTo(0, (offset + page) as u16))?
+            .queue(SetAttribute(Attribute::Reverse))?
+            .queue(Print(format!("{:<width$}", status, width = columns)))?
+            .queue(SetAttribute(Attribute::Reset))?;
+        self.out.flush()
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = self.out.queue(DisableMouseCapture).and_then(|out| out.queue(Show)).and_then(|out| out.queue(LeaveAlternateScreen));
+        let _ = self.out.flush();
+        let _ = disable_raw_mode();
+    }
+}
@@ -0,0 +1,138 @@
+//! Fuzzy heading picker for the pager: type part of a heading to narrow the list, then
+//! Enter to jump to it
+
+use crate::toc::Heading;
+use std::io::{self, Write};
+use termimad::crossterm::{
+    cursor::MoveTo,
+    event::{KeyCode, KeyModifiers},
+    style::{Attribute, Print, SetAttribute},
+    terminal::{Clear, ClearType},
+    QueueableCommand,
+};
+
+/// What the pager should do after a key press in the picker
+pub enum Outcome {
+    /// Keep the picker open and redraw it
+    Stay,
+    /// Close the picker and scroll to this line
+    Jump(usize),
+    /// Close the picker without moving
+    Cancel,
+}
+
+#[derive(Default)]
+pub struct Picker {
+    query: String,
+    /// Position of the highlighted heading among the matches
+    selected: usize,
+}
+
+impl Picker {
+    /// Indexes into `headings` of those matching the query, best match first. Without a
+    /// query every heading matches, in document order.
+    fn matches(&self, headings: &[(usize, Heading)]) -> Vec<usize> {
+        let mut scored: Vec<(i64, usize)> =
+            headings.iter().enumerate().filter_map(|(i, (_, heading))| score(&self.query, &heading.text).map(|score| (score, i))).collect();
+        // The sort is stable, so equally good matches stay in document order
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        scored.into_iter().map(|(_, i)| i).collect()
+    }
+
+    pub fn handle(&mut self, code: KeyCode, modifiers: KeyModifiers, headings: &[(usize, Heading)]) -> Outcome {
+        let control = modifiers.contains(KeyModifiers::CONTROL);
+        let count = self.matches(headings).len();
+        match code {
+            KeyCode::Esc => return Outcome::Cancel,
+            KeyCode::Char('c' | 'g') if control => return Outcome::Cancel,
+            KeyCode::Enter => {
+                return match self.matches(headings).get(self.selected) {
+                    Some(&i) => Outcome::Jump(headings[i].0),
+                    None => Outcome::Cancel,
+                }
+            }
+            KeyCode::Up | KeyCode::BackTab => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Char('p') if control => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Tab => self.selected += 1,
+            KeyCode::Char('n') if control => self.selected += 1,
+            KeyCode::Char('u') if control => {
+                self.query.clear();
+                self.selected = 0;
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.selected = 0;
+            }
+            KeyCode::Char(c) if !control => {
+                self.query.push(c);
+                self.selected = 0;
+            }
+            _ => {}
+        }
+        self.selected = self.selected.min(count.saturating_sub(1));
+        Outcome::Stay
+    }
+
+    /// Draw the query on the top row, the matching headings below it, indented by level,
+    /// and a status line on the bottom row
+    pub fn draw(&mut self, out: &mut io::Stdout, headings: &[(usize, Heading)], columns: usize, rows: usize) -> io::Result<()> {
+        let matches = self.matches(headings);
+        self.selected = self.selected.min(matches.len().saturating_sub(1));
+        let list_rows = rows.saturating_sub(2).max(1);
+        // Scroll the list so the highlighted heading stays on screen
+        let first = (self.selected + 1).saturating_sub(list_rows);
+
+        out.queue(MoveTo(0, 0))?
+            .queue(SetAttribute(Attribute::Bold))?
+            .queue(Print(format!(" Heading: {}", self.query)))?
+            .queue(SetAttribute(Attribute::Reset))?
+            .queue(SetAttribute(Attribute::Reverse))?
+            .queue(Print(" "))?
+            .queue(SetAttribute(Attribute::Reset))?
+            .queue(Clear(ClearType::UntilNewLine))?;
+        for row in 0..list_rows {
+            out.queue(MoveTo(0, (row + 1) as u16))?;
+            if let Some(&i) = matches.get(first + row) {
+                let heading = &headings[i].1;
+                let line = format!(" {}{}", "  ".repeat(heading.level.saturating_sub(1)), heading.text);
+                let line: String = line.chars().take(columns).collect();
+                if first + row == self.selected {
+                    out.queue(SetAttribute(Attribute::Reverse))?.queue(Print(format!("{:<width$}", line, width = columns)))?;
+                } else {
+                    out.queue(Print(line))?;
+                }
+            }
+            out.queue(SetAttribute(Attribute::Reset))?.queue(Clear(ClearType::UntilNewLine))?;
+        }
+        let status = format!(" {} of {} headings · type to filter · ↑/↓: select · enter: jump · esc: cancel", matches.len(), headings.len());
+        let status: String = status.chars().take(columns).collect();
+        out.queue(MoveTo(0, (list_rows + 1) as u16))?
+            .queue(SetAttribute(Attribute::Reverse))?
+            .queue(Print(format!("{:<width$}", status, width = columns)))?
+            .queue(SetAttribute(Attribute::Reset))?;
+        out.flush()
+    }
+}
+
+/// How well `text` matches `query`, or None if it doesn't contain the query's characters
+/// in order (ignoring case). Runs of consecutive characters and characters at the start of
+/// a word count for more, so "inst" ranks "Installation" above "Configuration settings".
+fn score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.chars().flat_map(char::to_lowercase).filter(|c| !c.is_whitespace()) {
+        let found = position + text[position..].iter().position(|&c| c == wanted)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
@@ -0,0 +1,470 @@
+//! Just enough of a PNG decoder for sixel images, which are sent to the terminal as pixels:
+//! zlib inflate, scanline filters, Adam7 interlacing, and every color type and bit depth.
+//! Ancillary chunks (gamma, color profiles, ...) are ignored.
+
+/// Largest image decoded, in pixels, which keeps a bad header from allocating without bound
+const MAX_PIXELS: usize = 16 * 1024 * 1024;
+
+/// Decoded image, as rows of RGBA pixels from the top left
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+/// Decode a PNG file, or say why it can't be
+pub fn decode(data: &[u8]) -> Result<Image, String> {
+    let mut rest = data.strip_prefix(b"\x89PNG\r\n\x1a\n").ok_or("not a PNG file")?;
+    let mut header = None;
+    let mut palette: Vec<[u8; 4]> = Vec::new();
+    let mut transparent: Option<Vec<u8>> = None;
+    let mut compressed = Vec::new();
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let kind = &rest[4..8];
+        let body = rest.get(8..8 + length).ok_or("truncated chunk")?;
+        rest = &rest[(12 + length).min(rest.len())..];
+        match kind {
+            b"IHDR" => header = Some(Header::parse(body)?),
+            b"PLTE" => palette = body.chunks_exact(3).map(|c| [c[0], c[1], c[2], 255]).collect(),
+            b"tRNS" => transparent = Some(body.to_vec()),
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+    let header = header.ok_or("no IHDR chunk")?;
+    if let Some(alpha) = &transparent {
+        if header.color == INDEXED {
+            for (entry, &a) in palette.iter_mut().zip(alpha) {
+                entry[3] = a;
+            }
+        }
+    }
+    // Deflate data behind a two-byte zlib header
+    let raw = inflate(compressed.get(2..).ok_or("no image data")?)?;
+    let samples = header.unfilter(&raw)?;
+
+    // A gray or RGB color shown as transparent, in 16-bit samples compared at 8 bits
+    let key: Option<Vec<u8>> = transparent
+        .filter(|_| header.color == GRAY || header.color == RGB)
+        .map(|key| key.chunks_exact(2).map(|c| if header.depth == 16 { c[0] } else { header.scale(c[1]) }).collect());
+    let pixels = samples
+        .chunks_exact(header.channels())
+        .map(|s| header.pixel(s, &palette, key.as_deref()))
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(Image { width: header.width, height: header.height, pixels })
+}
+
+const GRAY: u8 = 0;
+const RGB: u8 = 2;
+const INDEXED: u8 = 3;
+const GRAY_ALPHA: u8 = 4;
+const RGBA: u8 = 6;
+
+struct Header {
+    width: usize,
+    height: usize,
+    depth: u8,
+    color: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn parse(body: &[u8]) -> Result<Self, String> {
+        if body.len() < 13 {
+            return Err("short IHDR chunk".to_string());
+        }
+        let width = u32::from_be_bytes([body[0], body[1], body[2], body[3]]) as usize;
+        let height = u32::from_be_bytes([body[4], body[5], body[6], body[7]]) as usize;
+        let header = Header { width, height, depth: body[8], color: body[9], interlaced: body[12] == 1 };
+        let valid_depth = match header.color {
+            GRAY => matches!(header.depth, 1 | 2 | 4 | 8 | 16),
+            INDEXED => matches!(header.depth, 1 | 2 | 4 | 8),
+            RGB | GRAY_ALPHA | RGBA => matches!(header.depth, 8 | 16),
+            _ => false,
+        };
+        if !valid_depth {
+            return Err(format!("unsupported color type {} at depth {}", header.color, header.depth));
+        }
+        if width == 0 || height == 0 || width.saturating_mul(height) > MAX_PIXELS {
+            return Err(format!("unsupported size {}x{}", width, height));
+        }
+        Ok(header)
+    }
+
+    fn channels(&self) -> usize {
+        match self.color {
+            RGB => 3,
+            GRAY_ALPHA => 2,
+            RGBA => 4,
+            _ => 1,
+        }
+    }
+
+    /// Bytes in a row of `width` pixels, not counting the filter type
+    fn stride(&self, width: usize) -> usize {
+        (width * self.channels() * self.depth as usize).div_ceil(8)
+    }
+
+    /// Undo the scanline filters, and the interlacing, giving one sample per channel per
+    /// pixel, scaled to 8 bits except for palette indices
+    fn unfilter(&self, raw: &[u8]) -> Result<Vec<u8>, String> {
+        let channels = self.channels();
+        let mut samples = vec![0; self.width * self.height * channels];
+        // (x start, y start, x step, y step) of each Adam7 pass, or the whole image
+        let passes: &[(usize, usize, usize, usize)] = if self.interlaced {
+            &[(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)]
+        } else {
+            &[(0, 0, 1, 1)]
+        };
+        let mut pos = 0;
+        for &(x0, y0, dx, dy) in passes {
+            let width = (self.width + dx - 1 - x0) / dx;
+            let height = (self.height + dy - 1 - y0) / dy;
+            if width == 0 || height == 0 {
+                continue;
+            }
+            let stride = self.stride(width);
+            let mut previous = vec![0u8; stride];
+            for row in 0..height {
+                let filter = *raw.get(pos).ok_or("truncated image data")?;
+                let mut line = raw.get(pos + 1..pos + 1 + stride).ok_or("truncated image data")?.to_vec();
+                pos += 1 + stride;
+                self.unfilter_line(filter, &mut line, &previous)?;
+                for (i, sample) in self.samples(&line, width * channels).enumerate() {
+                    let (x, channel) = (x0 + i / channels * dx, i % channels);
+                    samples[((y0 + row * dy) * self.width + x) * channels + channel] = sample;
+                }
+                previous = line;
+            }
+        }
+        Ok(samples)
+    }
+
+    fn unfilter_line(&self, filter: u8, line: &mut [u8], previous: &[u8]) -> Result<(), String> {
+        // Bytes per pixel, at least one
+        let bpp = (self.channels() * self.depth as usize).div_ceil(8);
+        for i in 0..line.len() {
+            let left = if i >= bpp { line[i - bpp] } else { 0 };
+            let up = previous[i];
+            let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(format!("unknown filter type {}", filter)),
+            };
+            line[i] = line[i].wrapping_add(predicted);
+        }
+        Ok(())
+    }
+
+    /// The first `count` samples of a row, at 8 bits (16-bit samples keep their high byte)
+    fn samples<'a>(&'a self, line: &'a [u8], count: usize) -> impl Iterator<Item = u8> + 'a {
+        let depth = self.depth as usize;
+        (0..count).map(move |i| match depth {
+            16 => line[i * 2],
+            8 => line[i],
+            _ => {
+                let bit = i * depth;
+                let shift = 8 - depth - bit % 8;
+                self.scale((line[bit / 8] >> shift) & ((1 << depth) - 1) as u8)
+            }
+        })
+    }
+
+    /// A gray level below 8 bits scaled up to 8; palette indices keep their value
+    fn scale(&self, value: u8) -> u8 {
+        if self.depth < 8 && self.color != INDEXED {
+            value * (255 / ((1u16 << self.depth) - 1)) as u8
+        } else {
+            value
+        }
+    }
+
+    fn pixel(&self, s: &[u8], palette: &[[u8; 4]], key: Option<&[u8]>) -> Result<[u8; 4], String> {
+        let keyed = |value: &[u8]| if key == Some(value) { 0 } else { 255 };
+        Ok(match self.color {
+            GRAY => [s[0], s[0], s[0], keyed(&s[..1])],
+            RGB => [s[0], s[1], s[2], keyed(&s[..3])],
+            INDEXED => *palette.get(s[0] as usize).ok_or("palette index out of range")?,
+            GRAY_ALPHA => [s[0], s[0], s[0], s[1]],
+            _ => [s[0], s[1], s[2], s[3]],
+        })
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Reads a deflate stream a bit at a time, least significant bit first
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: u32) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = *self.data.get(self.pos).ok_or("truncated image data")?;
+            value |= ((byte >> self.bit) as u32 & 1) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        if self.bit > 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+/// Canonical Huffman code, as counts of codes per length and symbols in code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for i in 1..16 {
+            offsets[i] = offsets[i - 1] + counts[i - 1];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length > 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= bits.read(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("bad Huffman code in image data".to_string())
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+/// Order the code length code lengths are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Decompress a raw deflate stream
+fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut bits = Bits { data, pos: 0, bit: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => {
+                bits.align();
+                let header = data.get(bits.pos..bits.pos + 4).ok_or("truncated image data")?;
+                let length = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let stored = data.get(bits.pos + 4..bits.pos + 4 + length).ok_or("truncated image data")?;
+                out.extend_from_slice(stored);
+                bits.pos += 4 + length;
+            }
+            1 => {
+                let mut lengths = [8u8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                inflate_block(&mut bits, &mut out, &Huffman::new(&lengths), &Huffman::new(&[5; 30]))?;
+            }
+            2 => {
+                let literals = bits.read(5)? as usize + 257;
+                let distances = bits.read(5)? as usize + 1;
+                let code_lengths = bits.read(4)? as usize + 4;
+                let mut lengths = [0u8; 19];
+                for &i in &CODE_LENGTH_ORDER[..code_lengths] {
+                    lengths[i] = bits.read(3)? as u8;
+                }
+                let code = Huffman::new(&lengths);
+                let mut lengths = Vec::with_capacity(literals + distances);
+                while lengths.len() < literals + distances {
+                    let (value, repeat) = match code.decode(&mut bits)? {
+                        symbol @ 0..=15 => (symbol as u8, 1),
+                        16 => (*lengths.last().ok_or("bad code lengths in image data")?, 3 + bits.read(2)?),
+                        17 => (0, 3 + bits.read(3)?),
+                        _ => (0, 11 + bits.read(7)?),
+                    };
+                    lengths.extend(std::iter::repeat_n(value, repeat as usize));
+                }
+                lengths.truncate(literals + distances);
+                inflate_block(&mut bits, &mut out, &Huffman::new(&lengths[..literals]), &Huffman::new(&lengths[literals..]))?;
+            }
+            _ => return Err("bad block type in image data".to_string()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn inflate_block(bits: &mut Bits, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = symbol - 257;
+                let length = *LENGTH_BASE.get(i).ok_or("bad length in image data")? as usize + bits.read(LENGTH_EXTRA[i] as u32)? as usize;
+                let d = distances.decode(bits)? as usize;
+                let distance = *DISTANCE_BASE.get(d).ok_or("bad distance in image data")? as usize + bits.read(DISTANCE_EXTRA[d] as u32)? as usize;
+                let start = out.len().checked_sub(distance).ok_or("bad distance in image data")?;
+                // The copy can overlap what it's writing
+                for i in 0..length {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PNG file with `rows` (each starting with its filter type) in a stored deflate block.
+    /// CRCs aren't checked, so they're left as zeros.
+    fn png(width: u32, height: u32, depth: u8, color: u8, interlaced: bool, rows: &[u8], extra: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[depth, color, 0, 0, interlaced as u8]);
+        let mut deflate = vec![0x78, 0x01, 1];
+        deflate.extend_from_slice(&(rows.len() as u16).to_le_bytes());
+        deflate.extend_from_slice(&(!(rows.len() as u16)).to_le_bytes());
+        deflate.extend_from_slice(rows);
+        let mut chunks: Vec<(&[u8; 4], &[u8])> = vec![(b"IHDR", &header)];
+        chunks.extend_from_slice(extra);
+        chunks.push((b"IDAT", &deflate));
+        chunks.push((b"IEND", &[]));
+        let mut file = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (kind, body) in chunks {
+            file.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            file.extend_from_slice(kind);
+            file.extend_from_slice(body);
+            file.extend_from_slice(&[0; 4]);
+        }
+        file
+    }
+
+    #[test]
+    fn inflates_fixed_and_dynamic_huffman_blocks() {
+        // zlib.compress(b"sixel sixel sixel!", 9), without the zlib header and checksum
+        let fixed = [0x2b, 0xce, 0xac, 0x48, 0xcd, 0x51, 0x28, 0x46, 0x90, 0x8a, 0x00];
+        assert_eq!(inflate(&fixed).unwrap(), b"sixel sixel sixel!");
+        let dynamic = [
+            0x2d, 0x89, 0x49, 0x11, 0x00, 0x00, 0x10, 0x82, 0xb2, 0x0a, 0xfd, 0x3b, 0xec, 0xe9, 0x43, 0x46, 0x4c, 0xc8, 0x85, 0x20, 0xd8, 0x74, 0x96,
+            0xeb, 0xb9, 0xd7, 0x2f, 0x28,
+        ];
+        assert_eq!(inflate(&dynamic).unwrap(), b"aabaaaaaaababcbbcabacaaabcbaaaabbaaaaacaaaacaabb");
+        assert!(inflate(&fixed[..5]).is_err());
+    }
+
+    #[test]
+    fn rgba_rows_with_each_filter() {
+        #[rustfmt::skip]
+        let rows = [
+            0, 10, 20, 30, 255, 40, 50, 60, 255,
+            // Sub: each byte adds the one a pixel to the left
+            1, 1, 1, 1, 0, 5, 5, 5, 0,
+            // Up
+            2, 1, 1, 1, 0, 1, 1, 1, 0,
+            // Average of left and up
+            3, 6, 11, 16, 128, 0, 0, 0, 0,
+            // Paeth
+            4, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let image = decode(&png(2, 5, 8, RGBA, false, &rows, &[])).unwrap();
+        assert_eq!((image.width, image.height), (2, 5));
+        assert_eq!(
+            image.pixels,
+            vec![
+                [10, 20, 30, 255], [40, 50, 60, 255],
+                [1, 1, 1, 0], [6, 6, 6, 0],
+                [2, 2, 2, 0], [7, 7, 7, 0],
+                [7, 12, 17, 128], [7, 9, 12, 64],
+                [7, 12, 17, 128], [7, 9, 12, 64],
+            ]
+        );
+    }
+
+    #[test]
+    fn palette_images_below_8_bits_with_transparency() {
+        let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255, 9, 9, 9];
+        // Four 2-bit indices in one byte: 0, 1, 2, 3
+        let image = decode(&png(4, 1, 2, INDEXED, false, &[0, 0b00_01_10_11], &[(b"PLTE", &palette), (b"tRNS", &[0])])).unwrap();
+        assert_eq!(image.pixels, vec![[255, 0, 0, 0], [0, 255, 0, 255], [0, 0, 255, 255], [9, 9, 9, 255]]);
+    }
+
+    #[test]
+    fn gray_levels_scale_to_8_bits_and_keys_are_transparent() {
+        let image = decode(&png(3, 1, 4, GRAY, false, &[0, 0x0f, 0x70], &[(b"tRNS", &[0, 7])])).unwrap();
+        assert_eq!(image.pixels, vec![[0, 0, 0, 255], [255, 255, 255, 255], [119, 119, 119, 0]]);
+        let image = decode(&png(1, 1, 16, RGB, false, &[0, 1, 2, 3, 4, 5, 6], &[])).unwrap();
+        assert_eq!(image.pixels, vec![[1, 3, 5, 255]]);
+    }
+
+    #[test]
+    fn interlaced_passes_are_put_back_in_place() {
+        // A 3x2 gray image in Adam7 order: pass 1 has (0,0), pass 4 (2,0), pass 6 (1,0), and
+        // pass 7 the second row
+        let rows = [0, 1, 0, 3, 0, 2, 0, 4, 5, 6];
+        let image = decode(&png(3, 2, 8, GRAY, true, &rows, &[])).unwrap();
+        let levels: Vec<u8> = image.pixels.iter().map(|p| p[0]).collect();
+        assert_eq!(levels, vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn broken_files_are_errors() {
+        assert_eq!(decode(b"GIF89a").err().unwrap(), "not a PNG file");
+        assert!(decode(&png(2, 2, 8, RGB, false, &[0, 1, 2, 3], &[])).is_err());
+        assert!(decode(&png(1, 1, 8, RGB, false, &[5, 1, 2, 3], &[])).is_err());
+        assert!(decode(&png(1, 1, 3, RGB, false, &[0, 1], &[])).is_err());
+        assert!(decode(&png(1, 1, 8, INDEXED, false, &[0, 4], &[(b"PLTE", &[0, 0, 0])])).is_err());
+    }
+}
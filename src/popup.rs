@@ -0,0 +1,66 @@
+//! Showing output in a tmux popup (`--popup`): livemd runs itself again inside
+//! `tmux display-popup`, and the copy in the popup waits for a key before closing it
+
+use crate::error::{LivemdError, Result};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use termimad::crossterm::event::{self, Event, KeyEvent, KeyEventKind};
+use termimad::crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+
+/// Marks the copy of livemd running inside the popup
+pub const CHILD_FLAG: &str = "--popup-child";
+
+/// Set in the copy running inside the popup, so it waits for a key before exiting
+static IN_POPUP: AtomicBool = AtomicBool::new(false);
+
+/// Run this command line again in a tmux popup and wait for the popup to close. Returns
+/// false when not inside tmux, in which case the output should go to this pane instead.
+pub fn launch() -> Result<bool> {
+    if std::env::var_os("TMUX").is_none() {
+        return Ok(false);
+    }
+    if !atty::is(atty::Stream::Stdin) {
+        return Err(LivemdError::Input("--popup can't pass piped input on to the popup; use --file instead".to_string()));
+    }
+    let mut words = vec![std::env::current_exe()?.to_string_lossy().into_owned()];
+    words.extend(std::env::args_os().skip(1).map(|arg| arg.to_string_lossy().into_owned()).filter(|arg| arg != "--popup"));
+    words.push(CHILD_FLAG.to_string());
+    let line = shell_words::join(&words);
+    log::info!("opening a tmux popup for {}", line);
+    Command::new("tmux")
+        .args(["display-popup", "-E", "-w", "80%", "-h", "80%", "-d"])
+        .arg(std::env::current_dir()?)
+        .arg(line)
+        .status()
+        .map_err(|source| LivemdError::Spawn { command: "tmux display-popup".to_string(), source })?;
+    Ok(true)
+}
+
+/// Note that this is the copy of livemd running inside the popup
+pub fn set_in_popup() {
+    IN_POPUP.store(true, Ordering::Relaxed);
+}
+
+/// Waits for a key when dropped inside the popup, whichever way `main` returns
+pub struct KeepOpen;
+
+impl Drop for KeepOpen {
+    fn drop(&mut self) {
+        wait_for_key();
+    }
+}
+
+/// Inside the popup, hold it open until a key is pressed. The popup closes as soon as
+/// livemd exits, which would take the output (or an error) with it.
+pub fn wait_for_key() {
+    if !IN_POPUP.load(Ordering::Relaxed) || enable_raw_mode().is_err() {
+        return;
+    }
+    eprint!("\r\n(press any key to close)");
+    while let Ok(event) = event::read() {
+        if matches!(event, Event::Key(KeyEvent { kind: KeyEventKind::Press, .. })) {
+            break;
+        }
+    }
+    let _ = disable_raw_mode();
+}
@@ -0,0 +1,617 @@
+//! Line editing for the chat prompt: emacs keys, history kept across sessions in
+//! ~/.local/share/livemd/history (or the platform equivalent), and Ctrl-R search
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+use termimad::crossterm::{
+    cursor::{MoveTo, MoveToColumn, MoveUp},
+    event::{self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    style::Print,
+    terminal::{self, disable_raw_mode, enable_raw_mode, Clear, ClearType},
+    QueueableCommand,
+};
+use unicode_width::UnicodeWidthStr;
+
+/// Entries kept in the history file
+const HISTORY_SIZE: usize = 1000;
+
+/// Prompt for the second and later lines of a message
+const CONTINUATION: &str = "… ";
+
+/// How reading a line ended
+enum Done {
+    Line,
+    /// Ctrl-C: drop what was written
+    Cancel,
+    /// Ctrl-D on an empty line
+    Eof,
+}
+
+/// A Ctrl-R search through the history
+struct Search {
+    query: String,
+    /// The history entry matching, if any
+    found: Option<usize>,
+    /// What was written before the search, restored if it's cancelled
+    original: Vec<char>,
+}
+
+/// The line being edited
+struct State {
+    buffer: Vec<char>,
+    cursor: usize,
+    /// Rows from the top of the drawn input down to the terminal cursor
+    row: usize,
+    /// Position while browsing the history; the history's length means the line being written
+    browsing: usize,
+    /// The line being written, kept while browsing the history
+    draft: Vec<char>,
+    search: Option<Search>,
+    /// Set by `\` or Alt-Enter: Enter adds lines until one is left empty
+    continuing: bool,
+}
+
+impl State {
+    fn insert(&mut self, text: &str) {
+        // Tabs would throw off the cursor position, so they're indented with spaces instead
+        let text = text.replace("\r\n", "\n").replace('\r', "\n").replace('\t', "    ");
+        let chars: Vec<char> = text.chars().filter(|&c| c == '\n' || !c.is_control()).collect();
+        self.buffer.splice(self.cursor..self.cursor, chars.iter().copied());
+        self.cursor += chars.len();
+    }
+
+    /// Start of the line (within a message of several lines) the cursor is on
+    fn line_start(&self) -> usize {
+        self.buffer[..self.cursor].iter().rposition(|&c| c == '\n').map_or(0, |i| i + 1)
+    }
+
+    fn line_end(&self) -> usize {
+        self.buffer[self.cursor..].iter().position(|&c| c == '\n').map_or(self.buffer.len(), |i| self.cursor + i)
+    }
+
+    fn word_left(&self) -> usize {
+        let mut i = self.cursor;
+        while i > 0 && !self.buffer[i - 1].is_alphanumeric() {
+            i -= 1;
+        }
+        while i > 0 && self.buffer[i - 1].is_alphanumeric() {
+            i -= 1;
+        }
+        i
+    }
+
+    fn word_right(&self) -> usize {
+        let mut i = self.cursor;
+        while i < self.buffer.len() && !self.buffer[i].is_alphanumeric() {
+            i += 1;
+        }
+        while i < self.buffer.len() && self.buffer[i].is_alphanumeric() {
+            i += 1;
+        }
+        i
+    }
+
+    /// Move to the same column on the line `to` starts
+    fn move_to_line(&mut self, to: usize) {
+        let column = self.cursor - self.line_start();
+        let end = self.buffer[to..].iter().position(|&c| c == '\n').map_or(self.buffer.len(), |i| to + i);
+        self.cursor = (to + column).min(end);
+    }
+
+    fn replace(&mut self, text: Vec<char>) {
+        self.cursor = text.len();
+        self.buffer = text;
+    }
+
+    fn text(&self) -> String {
+        self.buffer.iter().collect()
+    }
+}
+
+pub struct LineEditor {
+    history: Vec<String>,
+    /// File the history is kept in, or None when it isn't saved
+    path: Option<PathBuf>,
+    output: File,
+    /// Text removed by the last kill command, for Ctrl-Y
+    killed: Vec<char>,
+}
+
+impl LineEditor {
+    /// An editor writing to `output`, the terminal. With `save_history`, entries are loaded
+    /// from and added to the history file.
+    pub fn new(output: File, save_history: bool) -> Self {
+        let path = dirs::data_dir().map(|dir| dir.join("livemd").join("history")).filter(|_| save_history);
+        let mut history: Vec<String> = path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(unescape).collect())
+            .unwrap_or_default();
+        if history.len() > HISTORY_SIZE {
+            history.drain(..history.len() - HISTORY_SIZE);
+            if let Some(path) = &path {
+                let text: String = history.iter().map(|entry| format!("{}\n", escape(entry))).collect();
+                let _ = std::fs::write(path, text);
+            }
+        }
+        LineEditor { history, path, output, killed: Vec::new() }
+    }
+
+    /// Read a message, or None at end of input. Newlines at the start of `prompt` are printed
+    /// once, above the input. Enter sends the message, unless the line ends with `\` or
+    /// Alt-Enter is pressed, which start another line; a message of several lines is sent
+    /// with Enter on an empty line, or Ctrl-D.
+    pub fn read(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let prompt_start = prompt.trim_start_matches('\n');
+        write!(self.output, "{}", &prompt[..prompt.len() - prompt_start.len()])?;
+        enable_raw_mode()?;
+        // Pasted text arrives in one piece, so its newlines don't send it early
+        let _ = self.output.queue(EnableBracketedPaste);
+        let result = self.edit(prompt_start);
+        let _ = self.output.queue(DisableBracketedPaste);
+        let _ = self.output.flush();
+        disable_raw_mode()?;
+        let line = result?;
+        if let Some(line) = &line {
+            self.remember(line);
+        }
+        Ok(line)
+    }
+
+    fn edit(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let mut state =
+            State { buffer: Vec::new(), cursor: 0, row: 0, browsing: self.history.len(), draft: Vec::new(), search: None, continuing: false };
+        self.draw(prompt, &mut state)?;
+        loop {
+            let done = match event::read()? {
+                Event::Paste(text) => {
+                    state.insert(&text);
+                    None
+                }
+                Event::Key(key) if key.kind != KeyEventKind::Release => self.key(key, &mut state),
+                Event::Resize(..) => None,
+                _ => continue,
+            };
+            if let Some(done) = done {
+                state.search = None;
+                state.cursor = state.buffer.len();
+                self.draw(prompt, &mut state)?;
+                if matches!(done, Done::Cancel) {
+                    self.output.queue(Print("^C"))?;
+                }
+                self.output.queue(Print("\r\n"))?;
+                return Ok(match done {
+                    Done::Line => Some(state.text()),
+                    Done::Cancel => Some(String::new()),
+                    Done::Eof => None,
+                });
+            }
+            self.draw(prompt, &mut state)?;
+        }
+    }
+
+    fn key(&mut self, key: KeyEvent, s: &mut State) -> Option<Done> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        if let Some(search) = s.search.as_mut() {
+            match key.code {
+                KeyCode::Char('r') if ctrl => {
+                    let before = search.found.unwrap_or(self.history.len());
+                    self.find(s, before);
+                    return None;
+                }
+                KeyCode::Char('g' | 'c') if ctrl => {
+                    let original = std::mem::take(&mut search.original);
+                    s.search = None;
+                    s.replace(original);
+                    return None;
+                }
+                KeyCode::Esc => {
+                    let original = std::mem::take(&mut search.original);
+                    s.search = None;
+                    s.replace(original);
+                    return None;
+                }
+                KeyCode::Backspace => {
+                    search.query.pop();
+                    self.find(s, self.history.len());
+                    return None;
+                }
+                KeyCode::Char(c) if !ctrl && !alt => {
+                    search.query.push(c);
+                    // A longer query can still match the entry found so far
+                    let before = search.found.map_or(self.history.len(), |found| found + 1);
+                    self.find(s, before);
+                    return None;
+                }
+                // Any other key takes the match and goes on to edit it
+                _ => s.search = None,
+            }
+        }
+
+        let len = s.buffer.len();
+        match key.code {
+            KeyCode::Enter if alt => {
+                s.insert("\n");
+                s.continuing = true;
+            }
+            KeyCode::Enter => return self.enter(s),
+            KeyCode::Char('c') if ctrl => return Some(Done::Cancel),
+            KeyCode::Char('d') if ctrl && len == 0 => return Some(Done::Eof),
+            KeyCode::Char('d') if ctrl && s.cursor == len => return Some(Done::Line),
+            KeyCode::Char('d') if ctrl => {
+                s.buffer.remove(s.cursor);
+            }
+            KeyCode::Delete if s.cursor < len => {
+                s.buffer.remove(s.cursor);
+            }
+            KeyCode::Char('a') if ctrl => s.cursor = s.line_start(),
+            KeyCode::Home => s.cursor = s.line_start(),
+            KeyCode::Char('e') if ctrl => s.cursor = s.line_end(),
+            KeyCode::End => s.cursor = s.line_end(),
+            KeyCode::Char('b') if alt => s.cursor = s.word_left(),
+            KeyCode::Left if ctrl || alt => s.cursor = s.word_left(),
+            KeyCode::Char('f') if alt => s.cursor = s.word_right(),
+            KeyCode::Right if ctrl || alt => s.cursor = s.word_right(),
+            KeyCode::Char('b') if ctrl => s.cursor = s.cursor.saturating_sub(1),
+            KeyCode::Left => s.cursor = s.cursor.saturating_sub(1),
+            KeyCode::Char('f') if ctrl => s.cursor = (s.cursor + 1).min(len),
+            KeyCode::Right => s.cursor = (s.cursor + 1).min(len),
+            KeyCode::Char('w') if ctrl => self.kill(s, s.word_left(), s.cursor),
+            KeyCode::Backspace if alt => self.kill(s, s.word_left(), s.cursor),
+            KeyCode::Char('d') if alt => self.kill(s, s.cursor, s.word_right()),
+            KeyCode::Backspace if s.cursor > 0 => {
+                s.cursor -= 1;
+                s.buffer.remove(s.cursor);
+            }
+            KeyCode::Char('h') if ctrl && s.cursor > 0 => {
+                s.cursor -= 1;
+                s.buffer.remove(s.cursor);
+            }
+            // At the end of a line, Ctrl-K joins it with the next
+            KeyCode::Char('k') if ctrl => self.kill(s, s.cursor, if s.cursor == s.line_end() { (s.cursor + 1).min(len) } else { s.line_end() }),
+            KeyCode::Char('u') if ctrl => self.kill(s, s.line_start(), s.cursor),
+            KeyCode::Char('y') if ctrl => {
+                let killed: String = self.killed.iter().collect();
+                s.insert(&killed);
+            }
+            KeyCode::Char('t') if ctrl && s.cursor > 0 && len > 1 => {
+                let at = s.cursor.min(len - 1);
+                s.buffer.swap(at - 1, at);
+                s.cursor = at + 1;
+            }
+            KeyCode::Char('l') if ctrl => {
+                let _ = self.output.queue(Clear(ClearType::All)).and_then(|out| out.queue(MoveTo(0, 0)));
+                s.row = 0;
+            }
+            KeyCode::Char('r') if ctrl => {
+                s.search = Some(Search { query: String::new(), found: None, original: s.buffer.clone() });
+            }
+            KeyCode::Up if s.line_start() > 0 => {
+                let previous = s.buffer[..s.line_start() - 1].iter().rposition(|&c| c == '\n').map_or(0, |i| i + 1);
+                s.move_to_line(previous);
+            }
+            KeyCode::Char('p') if ctrl && s.line_start() > 0 => {
+                let previous = s.buffer[..s.line_start() - 1].iter().rposition(|&c| c == '\n').map_or(0, |i| i + 1);
+                s.move_to_line(previous);
+            }
+            KeyCode::Up => self.browse(s, -1),
+            KeyCode::Char('p') if ctrl => self.browse(s, -1),
+            KeyCode::Down if s.line_end() < len => s.move_to_line(s.line_end() + 1),
+            KeyCode::Char('n') if ctrl && s.line_end() < len => s.move_to_line(s.line_end() + 1),
+            KeyCode::Down => self.browse(s, 1),
+            KeyCode::Char('n') if ctrl => self.browse(s, 1),
+            KeyCode::Tab => s.insert("\t"),
+            KeyCode::Char(c) if !ctrl && !alt => s.insert(&c.to_string()),
+            _ => {}
+        }
+        None
+    }
+
+    /// Enter sends the line, except where it starts another line of the message
+    fn enter(&mut self, s: &mut State) -> Option<Done> {
+        // Without bracketed paste, more input already waiting means this Enter was pasted
+        if event::poll(Duration::ZERO).unwrap_or(false) {
+            s.insert("\n");
+            return None;
+        }
+        let len = s.buffer.len();
+        if s.cursor == len && s.buffer.last() == Some(&'\\') {
+            s.buffer.pop();
+            s.cursor -= 1;
+            s.insert("\n");
+            s.continuing = true;
+            return None;
+        }
+        if !s.continuing {
+            return Some(Done::Line);
+        }
+        // Continuing a message, Enter on an empty last line sends it
+        if s.cursor == len && s.buffer[s.line_start()..].iter().all(|c| c.is_whitespace()) {
+            return Some(Done::Line);
+        }
+        s.insert("\n");
+        None
+    }
+
+    fn kill(&mut self, s: &mut State, from: usize, to: usize) {
+        if from < to {
+            self.killed = s.buffer.drain(from..to).collect();
+            s.cursor = from;
+        }
+    }
+
+    /// Step through the history: -1 for older, 1 for newer
+    fn browse(&mut self, s: &mut State, step: isize) {
+        let Some(to) = s.browsing.checked_add_signed(step).filter(|&to| to <= self.history.len()) else {
+            return;
+        };
+        if s.browsing == self.history.len() {
+            s.draft = s.buffer.clone();
+        }
+        s.browsing = to;
+        let text = match self.history.get(to) {
+            Some(entry) => entry.chars().collect(),
+            None => s.draft.clone(),
+        };
+        s.replace(text);
+    }
+
+    /// Find the newest history entry before `before` containing the search query
+    fn find(&self, s: &mut State, before: usize) {
+        let Some(search) = s.search.as_mut() else { return };
+        if search.query.is_empty() {
+            search.found = None;
+            return;
+        }
+        let found = self.history[..before.min(self.history.len())].iter().rposition(|entry| entry.contains(&search.query));
+        // Without a match, the last match stays up
+        if let Some(found) = found {
+            search.found = Some(found);
+            let entry = &self.history[found];
+            let at = entry.find(&search.query).unwrap_or(0);
+            s.buffer = entry.chars().collect();
+            s.cursor = entry[..at].chars().count();
+        }
+    }
+
+    /// Redraw the input from the top, leaving the terminal cursor where the editing cursor is
+    fn draw(&mut self, prompt: &str, s: &mut State) -> io::Result<()> {
+        let width = terminal::size().map_or(80, |(columns, _)| columns as usize).max(1);
+        let search_prompt;
+        let prompt = match &s.search {
+            Some(search) => {
+                let failed = if search.found.is_none() && !search.query.is_empty() { "failed " } else { "" };
+                search_prompt = format!("({}search) {}: ", failed, search.query);
+                search_prompt.as_str()
+            }
+            None => prompt,
+        };
+
+        let out = &mut self.output;
+        out.queue(MoveToColumn(0))?;
+        if s.row > 0 {
+            out.queue(MoveUp(s.row as u16))?;
+        }
+        out.queue(Clear(ClearType::FromCursorDown))?;
+
+        let lines: Vec<&[char]> = s.buffer.split(|&c| c == '\n').collect();
+        let (mut row, mut end_row, mut cursor) = (0, 0, (0, 0));
+        let mut offset = 0;
+        for (i, line) in lines.iter().enumerate() {
+            let prefix = if i == 0 { prompt } else { CONTINUATION };
+            let text: String = line.iter().collect();
+            if i > 0 {
+                out.queue(Print("\r\n"))?;
+            }
+            out.queue(Print(prefix))?.queue(Print(&text))?;
+            let line_width = prefix.width() + text.width();
+            // At the right edge the terminal waits to wrap; make it, so rows can be counted
+            if line_width > 0 && line_width.is_multiple_of(width) {
+                out.queue(Print(" \r"))?.queue(Clear(ClearType::UntilNewLine))?;
+            }
+            if (offset..=offset + line.len()).contains(&s.cursor) {
+                let before: String = line[..s.cursor - offset].iter().collect();
+                let column = prefix.width() + before.width();
+                cursor = (row + column / width, column % width);
+            }
+            end_row = row + line_width / width;
+            row += line_width / width + 1;
+            offset += line.len() + 1;
+        }
+        if end_row > cursor.0 {
+            out.queue(MoveUp((end_row - cursor.0) as u16))?;
+        }
+        out.queue(MoveToColumn(cursor.1 as u16))?;
+        s.row = cursor.0;
+        out.flush()
+    }
+
+    /// Add a sent message to the history, and to the history file
+    fn remember(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        let Some(path) = &self.path else { return };
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::OpenOptions::new().create(true).append(true).open(path))
+            .and_then(|mut file| writeln!(file, "{}", escape(line)));
+        if let Err(e) = written {
+            log::info!("can't save the history to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// One history entry per line in the file, so newlines and backslashes are escaped
+fn escape(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut entry = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                entry.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                entry.push('\\');
+                chars.next();
+            }
+            _ => entry.push(c),
+        }
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An editor with `history` that draws to a scratch file instead of the terminal
+    fn editor(history: &[&str]) -> LineEditor {
+        let path = std::env::temp_dir().join(format!("livemd-readline-test-{}", std::process::id()));
+        let output = File::create(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        LineEditor { history: history.iter().map(|s| s.to_string()).collect(), path: None, output, killed: Vec::new() }
+    }
+
+    fn state(editor: &LineEditor) -> State {
+        State { buffer: Vec::new(), cursor: 0, row: 0, browsing: editor.history.len(), draft: Vec::new(), search: None, continuing: false }
+    }
+
+    /// Press keys, given as `C-x`, `M-x`, a named key, or text to type
+    fn press(editor: &mut LineEditor, s: &mut State, keys: &[&str]) {
+        for key in keys {
+            let (code, modifiers) = match *key {
+                "Left" => (KeyCode::Left, KeyModifiers::NONE),
+                "Right" => (KeyCode::Right, KeyModifiers::NONE),
+                "Up" => (KeyCode::Up, KeyModifiers::NONE),
+                "Down" => (KeyCode::Down, KeyModifiers::NONE),
+                "Backspace" => (KeyCode::Backspace, KeyModifiers::NONE),
+                "Esc" => (KeyCode::Esc, KeyModifiers::NONE),
+                "Tab" => (KeyCode::Tab, KeyModifiers::NONE),
+                key if key.starts_with("C-") => (KeyCode::Char(key.chars().nth(2).unwrap()), KeyModifiers::CONTROL),
+                key if key.starts_with("M-") => (KeyCode::Char(key.chars().nth(2).unwrap()), KeyModifiers::ALT),
+                text => {
+                    for c in text.chars() {
+                        editor.key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE), s);
+                    }
+                    continue;
+                }
+            };
+            editor.key(KeyEvent::new(code, modifiers), s);
+        }
+    }
+
+    #[test]
+    fn moving_by_words_and_lines() {
+        let mut editor = editor(&[]);
+        let mut s = state(&editor);
+        press(&mut editor, &mut s, &["hello, big world", "M-b", "M-b"]);
+        assert_eq!(s.cursor, 7);
+        press(&mut editor, &mut s, &["M-f", "C-a"]);
+        assert_eq!(s.cursor, 0);
+        press(&mut editor, &mut s, &["C-e"]);
+        assert_eq!(s.cursor, 16);
+    }
+
+    #[test]
+    fn killed_text_is_yanked_back() {
+        let mut editor = editor(&[]);
+        let mut s = state(&editor);
+        press(&mut editor, &mut s, &["one two three", "C-w"]);
+        assert_eq!(s.text(), "one two ");
+        press(&mut editor, &mut s, &["C-a", "C-y"]);
+        assert_eq!(s.text(), "threeone two ");
+        press(&mut editor, &mut s, &["C-k"]);
+        assert_eq!(s.text(), "three");
+        press(&mut editor, &mut s, &["C-a", "C-y"]);
+        assert_eq!(s.text(), "one two three");
+    }
+
+    #[test]
+    fn editing_keys() {
+        let mut editor = editor(&[]);
+        let mut s = state(&editor);
+        press(&mut editor, &mut s, &["ab", "C-t"]);
+        assert_eq!(s.text(), "ba");
+        press(&mut editor, &mut s, &["Left", "Backspace", "Tab"]);
+        assert_eq!(s.text(), "    a");
+        press(&mut editor, &mut s, &["C-a", "C-d"]);
+        assert_eq!(s.text(), "   a");
+    }
+
+    #[test]
+    fn pasted_text_drops_control_characters_and_keeps_newlines() {
+        let mut editor = editor(&[]);
+        let mut s = state(&editor);
+        s.insert("a\r\nb\x07\tc");
+        assert_eq!(s.text(), "a\nb    c");
+        // Up and Down move between the lines of the message before browsing the history
+        press(&mut editor, &mut s, &["Up"]);
+        assert_eq!(s.cursor, 1);
+        press(&mut editor, &mut s, &["Down"]);
+        assert_eq!(s.cursor, 3);
+    }
+
+    #[test]
+    fn browsing_the_history_keeps_the_draft() {
+        let mut editor = editor(&["first", "second"]);
+        let mut s = state(&editor);
+        press(&mut editor, &mut s, &["draft", "Up"]);
+        assert_eq!(s.text(), "second");
+        press(&mut editor, &mut s, &["Up", "Up"]);
+        assert_eq!(s.text(), "first");
+        press(&mut editor, &mut s, &["Down", "Down"]);
+        assert_eq!(s.text(), "draft");
+    }
+
+    #[test]
+    fn reverse_search_finds_older_matches() {
+        let mut editor = editor(&["cargo build", "git status", "cargo test"]);
+        let mut s = state(&editor);
+        press(&mut editor, &mut s, &["C-r", "cargo"]);
+        assert_eq!(s.text(), "cargo test");
+        press(&mut editor, &mut s, &["C-r"]);
+        assert_eq!(s.text(), "cargo build");
+        // No older match leaves the last one up
+        press(&mut editor, &mut s, &["C-r"]);
+        assert_eq!(s.text(), "cargo build");
+        press(&mut editor, &mut s, &["C-e", "!"]);
+        assert!(s.search.is_none());
+        assert_eq!(s.text(), "cargo build!");
+    }
+
+    #[test]
+    fn cancelled_search_restores_the_line() {
+        let mut editor = editor(&["git status"]);
+        let mut s = state(&editor);
+        press(&mut editor, &mut s, &["mine", "C-r", "git", "C-g"]);
+        assert_eq!(s.text(), "mine");
+    }
+
+    #[test]
+    fn history_entries_are_one_line_each() {
+        let entry = "first\nsecond \\n";
+        assert_eq!(escape(entry), "first\\nsecond \\\\n");
+        assert_eq!(unescape(&escape(entry)), entry);
+    }
+
+    #[test]
+    fn remembering_skips_blanks_and_repeats() {
+        let mut editor = editor(&[]);
+        editor.remember("  ");
+        editor.remember(" hi ");
+        editor.remember("hi");
+        assert_eq!(editor.history, vec!["hi"]);
+    }
+}
@@ -0,0 +1,48 @@
+//! Running shell and Python code blocks from the output (--run-code)
+
+use crate::extract::CodeBlock;
+use std::io::{BufRead, BufReader, Write};
+use std::process::Command;
+
+/// Build the command that runs a block, if its language is one we know how to run
+pub fn command_for(block: &CodeBlock) -> Option<Command> {
+    let (program, flag) = match block.language()?.as_str() {
+        "sh" | "shell" | "console" => ("sh", "-c"),
+        "bash" => ("bash", "-c"),
+        "zsh" => ("zsh", "-c"),
+        "powershell" | "pwsh" | "ps1" => ("powershell", "-Command"),
+        "bat" | "batch" | "cmd" => ("cmd", "/C"),
+        "python" | "py" | "python3" if cfg!(windows) => ("python", "-c"),
+        "python" | "py" | "python3" => ("python3", "-c"),
+        _ => return None,
+    };
+    let mut command = Command::new(program);
+    command.arg(flag).arg(&block.code);
+    Some(command)
+}
+
+/// Ask a question on the terminal and read a line back. Stdin may be the piped document, so
+/// this talks to the controlling terminal directly. Returns None at end of input.
+pub fn prompt(question: &str) -> std::io::Result<Option<String>> {
+    let (input, mut output) = terminal()?;
+    write!(output, "{}", question)?;
+    output.flush()?;
+    let mut line = String::new();
+    if BufReader::new(input).read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+/// The controlling terminal, for reading and for writing
+pub fn terminal() -> std::io::Result<(std::fs::File, std::fs::File)> {
+    #[cfg(unix)]
+    {
+        let tty = std::fs::OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        Ok((tty.try_clone()?, tty))
+    }
+    #[cfg(windows)]
+    {
+        Ok((std::fs::File::open("CONIN$")?, std::fs::OpenOptions::new().write(true).open("CONOUT$")?))
+    }
+}
@@ -0,0 +1,100 @@
+//! Filtering streamed markdown down to one section
+
+use crate::toc;
+
+/// Passes through only the lines under the first heading that matches a name, up to the
+/// next heading of the same or a higher level. Works on flushed chunks, so sections can be
+/// picked out of streamed output too.
+pub struct SectionFilter {
+    /// Lowercase text to look for in headings
+    name: String,
+    /// Level of the matched heading, while inside the section
+    level: Option<usize>,
+    /// Set once the section has ended
+    done: bool,
+    /// Set once a matching heading was seen
+    found: bool,
+    /// Inside a fenced code block, where `#` lines aren't headings
+    fence: Option<String>,
+    /// A partial line held back until it's known whether it's a heading or fence
+    pending: String,
+}
+
+impl SectionFilter {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.trim().to_lowercase(),
+            level: None,
+            done: false,
+            found: false,
+            fence: None,
+            pending: String::new(),
+        }
+    }
+
+    /// Keep the parts of a chunk that fall inside the section
+    pub fn filter(&mut self, chunk: &str) -> String {
+        let text = std::mem::take(&mut self.pending) + chunk;
+        let mut kept = String::new();
+        for line in text.split_inclusive('\n') {
+            if !line.ends_with('\n') && is_marker_start(line) {
+                self.pending = line.to_string();
+                break;
+            }
+            if self.keep(line) {
+                kept.push_str(line);
+            }
+        }
+        kept
+    }
+
+    /// Whatever was held back, once the input ends
+    pub fn finish(&mut self) -> String {
+        let line = std::mem::take(&mut self.pending);
+        if self.keep(&line) { line } else { String::new() }
+    }
+
+    /// Whether a heading matched
+    pub fn found(&self) -> bool {
+        self.found
+    }
+
+    fn keep(&mut self, line: &str) -> bool {
+        if self.done {
+            return false;
+        }
+        let trimmed = line.trim_start();
+        if let Some(fence) = &self.fence {
+            if trimmed.starts_with(fence.as_str()) && trimmed.trim_end().chars().all(|c| fence.starts_with(c)) {
+                self.fence = None;
+            }
+            return self.level.is_some();
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            let marker = trimmed.chars().next().unwrap_or('`');
+            self.fence = Some(trimmed.chars().take_while(|&c| c == marker).collect());
+            return self.level.is_some();
+        }
+        if let Some(heading) = toc::headings(line).into_iter().next().filter(|_| trimmed.starts_with('#')) {
+            match self.level {
+                Some(level) if heading.level <= level => {
+                    self.level = None;
+                    self.done = true;
+                    return false;
+                }
+                None if heading.text.to_lowercase().contains(&self.name) => {
+                    self.level = Some(heading.level);
+                    self.found = true;
+                }
+                _ => {}
+            }
+        }
+        self.level.is_some()
+    }
+}
+
+/// A line start that could turn out to be a heading or code fence
+fn is_marker_start(line: &str) -> bool {
+    let trimmed = line.trim_start_matches(' ');
+    line.len() - trimmed.len() < 4 && (trimmed.is_empty() || trimmed.starts_with(['#', '`', '~']))
+}
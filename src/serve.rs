@@ -0,0 +1,170 @@
+//! Render sink over HTTP (`livemd serve`): Markdown POSTed to it, or a watched file, is
+//! rendered on the terminal it runs in
+
+use crate::error::{LivemdError, Result};
+use crate::streamer::MinimalStreamer;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use termimad::crossterm::{cursor::MoveTo, execute, terminal::{Clear, ClearType}};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Largest request body accepted
+const MAX_BODY: usize = 16 * 1024 * 1024;
+/// How long a client has to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often a watched file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+const USAGE: &str = "livemd is listening. POST Markdown here to render it, e.g.\n  curl --data-binary @notes.md http://HOST/\n";
+
+/// Listen on `host` and `port` and render each POSTed body, and `watch` whenever it changes,
+/// until interrupted. With `clear`, the screen is cleared before each POSTed body.
+pub async fn run(streamer: &mut MinimalStreamer, host: &str, port: u16, watch: Option<&Path>, clear: bool) -> Result<()> {
+    let listener = TcpListener::bind((host, port)).await.map_err(|e| LivemdError::Input(format!("can't listen on {} port {}: {}", host, port, e)))?;
+    eprintln!("Listening on http://{} (Ctrl-C to stop)", listener.local_addr()?);
+
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    let mut ticks = tokio::time::interval(WATCH_INTERVAL);
+    let mut rendered: Option<SystemTime> = None;
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (mut client, peer) = accepted?;
+                match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut client)).await {
+                    Ok(Ok(Some(body))) => {
+                        log::info!("rendering {} bytes from {}", body.len(), peer);
+                        if clear {
+                            clear_screen();
+                        }
+                        streamer.stream_text(&body).await?;
+                    }
+                    Ok(Ok(None)) => {}
+                    Ok(Err(e)) => log::info!("request from {} failed: {}", peer, e),
+                    Err(_) => log::info!("request from {} timed out", peer),
+                }
+            }
+            _ = ticks.tick(), if watch.is_some() => {
+                let Some(path) = watch else { continue };
+                let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+                if modified.is_some() && modified != rendered {
+                    log::info!("{} changed, rendering it", path.display());
+                    rendered = modified;
+                    clear_screen();
+                    streamer.stream_file(path.to_path_buf()).await?;
+                }
+            }
+            _ = &mut interrupted => return Ok(()),
+        }
+    }
+}
+
+/// Read a request and answer it. Returns the body of a POST or PUT, which is answered
+/// before it's rendered so the client doesn't wait on the pacing.
+async fn read_request(client: &mut TcpStream) -> std::io::Result<Option<String>> {
+    let (reader, mut writer) = client.split();
+    let mut reader = BufReader::new(reader);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let method = request_line.split_whitespace().next().unwrap_or_default().to_string();
+
+    let mut length = None;
+    let mut chunked = false;
+    let mut expect_continue = false;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else { continue };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => length = value.parse::<usize>().ok(),
+            "transfer-encoding" => chunked = value.to_ascii_lowercase().contains("chunked"),
+            "expect" => expect_continue = value.eq_ignore_ascii_case("100-continue"),
+            _ => {}
+        }
+    }
+
+    let response = match (method.as_str(), length) {
+        ("GET", _) => ("200 OK", USAGE),
+        ("POST" | "PUT", Some(length)) if length > MAX_BODY => ("413 Content Too Large", "That's too much Markdown.\n"),
+        ("POST" | "PUT", Some(_)) => ("202 Accepted", ""),
+        ("POST" | "PUT", None) if chunked => ("202 Accepted", ""),
+        ("POST" | "PUT", None) => ("411 Length Required", "Send a Content-Length or a chunked body.\n"),
+        _ => ("405 Method Not Allowed", USAGE),
+    };
+    if response.0 != "202 Accepted" {
+        respond(&mut writer, response).await?;
+        return Ok(None);
+    }
+
+    if expect_continue {
+        writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await?;
+    }
+    let body = match length {
+        Some(length) => {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await?;
+            body
+        }
+        None => match read_chunked(&mut reader).await? {
+            Some(body) => body,
+            None => {
+                respond(&mut writer, ("413 Content Too Large", "That's too much Markdown.\n")).await?;
+                return Ok(None);
+            }
+        },
+    };
+    respond(&mut writer, response).await?;
+    Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+}
+
+/// Read a chunked request body, or None if it grows past `MAX_BODY`
+async fn read_chunked<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<Option<Vec<u8>>> {
+    let mut body = Vec::new();
+    loop {
+        let mut size_line = String::new();
+        reader.read_line(&mut size_line).await?;
+        // The size is hex, maybe followed by extensions after a `;`
+        let size = size_line.split(';').next().map(str::trim).and_then(|size| usize::from_str_radix(size, 16).ok());
+        let Some(size) = size else {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "bad chunk size"));
+        };
+        if size == 0 {
+            // Skip any trailers up to the blank line that ends the body
+            let mut line = String::new();
+            while reader.read_line(&mut line).await? > 0 && !line.trim().is_empty() {
+                line.clear();
+            }
+            return Ok(Some(body));
+        }
+        if body.len() + size > MAX_BODY {
+            return Ok(None);
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..]).await?;
+        // Each chunk ends with CRLF
+        reader.read_line(&mut String::new()).await?;
+    }
+}
+
+async fn respond<W: AsyncWriteExt + Unpin>(writer: &mut W, (status, body): (&str, &str)) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
+
+/// Clear the terminal for a fresh rendering, when output goes to one
+fn clear_screen() {
+    if atty::is(atty::Stream::Stdout) {
+        let _ = execute!(std::io::stdout(), Clear(ClearType::All), MoveTo(0, 0));
+    }
+}
@@ -0,0 +1,68 @@
+//! The shell that runs `--cmd` and `livemd run` command lines: sh, or cmd on Windows, unless
+//! the `shell` setting names another
+
+use std::path::Path;
+use std::process::Command;
+
+/// The shell used when none is configured
+pub const DEFAULT: &str = if cfg!(windows) { "cmd" } else { "sh" };
+
+/// How a shell takes a command line, which decides its flag and how words are quoted
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    /// sh, bash, zsh, and the like: `-c`
+    Posix,
+    /// cmd.exe: `/C`
+    Cmd,
+    /// Windows PowerShell or PowerShell 7: `-Command`
+    PowerShell,
+}
+
+fn kind(shell: &str) -> Kind {
+    let name = Path::new(shell).file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_lowercase();
+    match name.as_str() {
+        "cmd" => Kind::Cmd,
+        "powershell" | "pwsh" => Kind::PowerShell,
+        _ => Kind::Posix,
+    }
+}
+
+/// A command that runs `line` with `shell`
+pub fn command(shell: &str, line: &str) -> Command {
+    let mut command = Command::new(shell);
+    match kind(shell) {
+        Kind::Posix => {
+            command.arg("-c").arg(line);
+        }
+        Kind::Cmd => {
+            command.arg("/C");
+            // cmd parses its own command line, so the usual argument quoting would end up in it
+            #[cfg(windows)]
+            std::os::windows::process::CommandExt::raw_arg(&mut command, line);
+            #[cfg(not(windows))]
+            command.arg(line);
+        }
+        Kind::PowerShell => {
+            command.args(["-NoProfile", "-Command", line]);
+        }
+    }
+    command
+}
+
+/// Join words into a command line for `shell`, quoting those that need it
+pub fn join(shell: &str, words: &[String]) -> String {
+    let plain = |word: &str| !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=@+".contains(c));
+    match kind(shell) {
+        Kind::Posix => shell_words::join(words),
+        Kind::Cmd => words
+            .iter()
+            .map(|word| if plain(word) { word.clone() } else { format!("\"{}\"", word.replace('"', "\\\"")) })
+            .collect::<Vec<_>>()
+            .join(" "),
+        Kind::PowerShell => words
+            .iter()
+            .map(|word| if plain(word) { word.clone() } else { format!("'{}'", word.replace('\'', "''")) })
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
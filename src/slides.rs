@@ -0,0 +1,98 @@
+//! Presenting a markdown document one slide at a time
+
+use crate::frontmatter;
+use crate::pager;
+use std::io::{self, Write};
+use termimad::crossterm::{
+    cursor::MoveTo,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, Clear, ClearType},
+    QueueableCommand,
+};
+
+/// Split a document into slides at `---` lines. Documents without any are split before each
+/// H1 and H2 heading instead. Frontmatter is left out.
+pub fn split(text: &str) -> Vec<String> {
+    let text = frontmatter::split(text).map_or(text, |(_, rest)| rest);
+    let mut by_rule = Vec::new();
+    let mut by_heading = Vec::new();
+    let mut rule_slide = String::new();
+    let mut heading_slide = String::new();
+    let mut fence: Option<char> = None;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) && trimmed.chars().all(|c| c == marker) {
+                fence = None;
+            }
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = trimmed.chars().next();
+        } else if trimmed == "---" {
+            by_rule.push(std::mem::take(&mut rule_slide));
+            heading_slide.push_str(line);
+            continue;
+        } else if trimmed.starts_with("# ") || trimmed.starts_with("## ") {
+            by_heading.push(std::mem::take(&mut heading_slide));
+        }
+        rule_slide.push_str(line);
+        heading_slide.push_str(line);
+    }
+    by_rule.push(rule_slide);
+    by_heading.push(heading_slide);
+    let slides = if by_rule.len() > 1 { by_rule } else { by_heading };
+    slides.into_iter().filter(|slide| !slide.trim().is_empty()).collect()
+}
+
+/// Show slides on the alternate screen, centered vertically, until the presenter quits.
+/// `render` turns a slide's markdown into screen lines, and is called again after a resize.
+pub fn present(slides: &[String], mut render: impl FnMut(&str) -> Vec<String>) -> io::Result<()> {
+    if slides.is_empty() {
+        return Ok(());
+    }
+    pager::fullscreen(|out| {
+        let mut current = 0usize;
+        loop {
+            let lines = render(&slides[current]);
+            draw(out, &lines, current, slides.len())?;
+            let next = loop {
+                match event::read()? {
+                    Event::Key(KeyEvent { code, modifiers, kind: KeyEventKind::Press, .. }) => {
+                        break match code {
+                            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                            KeyCode::Right | KeyCode::Down | KeyCode::PageDown | KeyCode::Enter | KeyCode::Char(' ' | 'n' | 'l' | 'j') => {
+                                (current + 1).min(slides.len() - 1)
+                            }
+                            KeyCode::Left | KeyCode::Up | KeyCode::PageUp | KeyCode::Backspace | KeyCode::Char('p' | 'h' | 'k') => {
+                                current.saturating_sub(1)
+                            }
+                            KeyCode::Home | KeyCode::Char('g') => 0,
+                            KeyCode::End | KeyCode::Char('G') => slides.len() - 1,
+                            _ => continue,
+                        };
+                    }
+                    Event::Resize(..) => break current,
+                    _ => {}
+                }
+            };
+            current = next;
+        }
+    })
+}
+
+fn draw(out: &mut impl Write, lines: &[String], current: usize, count: usize) -> io::Result<()> {
+    let (columns, rows) = terminal::size()?;
+    let page = (rows as usize).saturating_sub(1);
+    out.queue(Clear(ClearType::All))?;
+    let top = page.saturating_sub(lines.len()) / 2;
+    for (row, line) in lines.iter().take(page).enumerate() {
+        out.queue(MoveTo(0, (top + row) as u16))?.queue(Print(line))?.queue(SetAttribute(Attribute::Reset))?;
+    }
+    let counter = format!("{}/{}", current + 1, count);
+    out.queue(MoveTo((columns as usize).saturating_sub(counter.len() + 1) as u16, page as u16))?
+        .queue(SetAttribute(Attribute::Dim))?
+        .queue(Print(counter))?
+        .queue(SetAttribute(Attribute::Reset))?;
+    out.flush()
+}
@@ -1,110 +1,597 @@
 //! Core streaming functionality
 
-use crate::config::StreamerConfig;
-use crate::table::TableRenderer;
+use crate::bench::{Bench, Stats};
+use crate::cache::ResponseCache;
+use crate::clipboard;
+use crate::error::{LivemdError, Result};
+use crate::events;
+use crate::extract::{self, CodeBlock};
+use crate::frontmatter;
+use crate::html;
+use crate::image;
+use crate::json;
+use crate::man;
+use crate::math;
+use crate::mermaid;
+use crate::notify;
+use crate::pacing::Pacer;
+use crate::page;
+use crate::pager::{self, Pager};
+use crate::runner;
+use crate::section::SectionFilter;
+use crate::slides;
+use crate::code_theme::CodeTheme;
+use crate::config::{FrontmatterStyle, Granularity, LinkStyle, OutputFormat, Pricing, StreamerConfig};
+use crate::csv::parse_delimited;
+use crate::terminal::{quantize_colors, ColorDepth};
+use crate::table::{Border, Cell, Span, SpanStyle, TableRenderer, TableStyle};
 use crate::theme::Theme;
-use pulldown_cmark::{Parser as MarkdownParser, Options, Event, Tag, TagEnd, CodeBlockKind};
+use crate::toc::{self, Heading};
+use crate::wrap::{split_at_width, wrap_text, wrap_text_indented};
+use crate::ws::WebSocket;
+use unicode_width::UnicodeWidthStr;
+use pulldown_cmark::{Alignment, Parser as MarkdownParser, Options, Event, Tag, TagEnd, CodeBlockKind, BlockQuoteKind, BrokenLink};
 use regex::Regex;
 use std::io::{Read, BufReader, Write, stdout};
-use std::path::PathBuf;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LazyLock, OnceLock};
+use std::time::SystemTime;
 use termimad::crossterm::{
-    style::{Print, ResetColor, SetForegroundColor, SetAttribute, Attribute},
+    style::{Print, Color, ResetColor, SetBackgroundColor, SetForegroundColor, SetAttribute, Attribute},
     terminal::size,
     QueueableCommand,
 };
 use termimad::MadSkin;
-use tokio::time::{sleep, Duration};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+use tokio::time::{sleep, Duration, Instant};
+
+/// Escape sequences in text from an LLM or command, removed before it's parsed
+static ANSI_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\x1B\[[0-?]*[ -/]*[@-~]").unwrap());
+/// Reference link definitions, `[ref]: url`
+static DEFINITION_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?m)^ {0,3}\[([^\]]+)\]:\s*<?([^\s>]+)>?").unwrap());
+/// A line of buffered list Markdown: indent, marker, and item text
+static LIST_ITEM_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\s*)(- |\d+\. )(.*)$").unwrap());
+/// Newlines and styling at the end of a live preview
+static PREVIEW_TRAILING_RE: LazyLock<regex::bytes::Regex> = LazyLock::new(|| regex::bytes::Regex::new(r"(?:\n|\x1b\[[0-9;:]*m)+$").unwrap());
+/// SGR (color and attribute) escape sequences
+static SGR_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\x1b\[[0-9;:]*m").unwrap());
+/// A table's top, middle, or bottom border line
+static TABLE_BORDER_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?m)^ *[┌├└][─┬┼┴]*[┐┤┘]\n").unwrap());
+/// A line of table cells between `│` bars
+static TABLE_ROW_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?m)^( *)│ (.*?) *│$").unwrap());
+/// Spaces at the end of a line
+static TRAILING_SPACE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?m)[ \t]+$").unwrap());
+
+/// Rough token count of a prompt or response. External LLM commands don't report usage, so
+/// this assumes ~4 characters per token.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
 
 /// Core Markdown streaming implementation
 pub struct MinimalStreamer {
     config: StreamerConfig,
     theme: Theme,
     mad_skin: MadSkin,
+    /// Output column in the paragraph path, carried across flushes for soft wrapping
+    column: usize,
+    /// Rendered output for the current flush, emitted with margins applied
+    out: Vec<u8>,
+    /// Whether the next emitted byte starts a new output line
+    at_line_start: bool,
+    /// Terminal resizes seen as of the last flush, so the next can tell the width changed
+    resizes: usize,
+    /// Table currently being streamed row by row
+    open_table: Option<OpenTable>,
+    /// Modification time of the theme file when it was last loaded
+    theme_mtime: Option<SystemTime>,
+    /// Code blocks rendered so far, numbered from 1 for --copy-code
+    code_blocks: Vec<CodeBlock>,
+    /// Text of the last heading rendered, to name extracted code blocks
+    last_heading: Option<String>,
+    /// Reference link definitions seen so far, keyed by lowercased label
+    link_refs: HashMap<String, String>,
+    /// Reference labels used before their definition arrived
+    pending_refs: Vec<String>,
+    /// Link URLs numbered for `link-style: footnote`, listed when the output ends
+    link_footnotes: Vec<String>,
+    /// Indent of the text of the last list item written, for the rest of an item split there
+    list_hang: usize,
+    /// URLs of the links marked in heading and list Markdown, in order, for their hyperlinks
+    buffered_links: VecDeque<String>,
+    /// Directory of the file being streamed, for relative image paths
+    base_dir: Option<PathBuf>,
+    /// Set once the start of the document has been checked for frontmatter
+    frontmatter_checked: bool,
+    /// Headings rendered so far, for the table of contents
+    headings: Vec<Heading>,
+    /// Set once the table of contents has been printed
+    toc_written: bool,
+    /// Drops everything outside the `--section` being shown
+    section: Option<SectionFilter>,
+    /// Output held back for the pager (None = written straight to stdout)
+    captured: Option<Vec<u8>>,
+    /// Headings in the captured output, with their line numbers
+    heading_lines: Vec<(usize, Heading)>,
+    /// The pager, open from the first output until the reader quits
+    pager_view: Option<Pager>,
+    /// Where the live preview of the unfinished block starts, as (lines above the cursor,
+    /// column), while one is on screen
+    preview_start: Option<(usize, usize)>,
+    /// Sleeps between flushed blocks, adapted to the input's arrival rate
+    pacer: Pacer,
+    /// Timings for `--bench`
+    bench: Option<Bench>,
+    /// Arrival times and size for `--stats`
+    stats: Option<Stats>,
+    /// Set once the head of the `--output html` page or `man` page has been written
+    page_started: bool,
+    /// Everything written so far, for `--export`
+    transcript: Option<Vec<u8>>,
+    /// The Markdown source so far, for `--copy`
+    source: Option<Vec<u8>>,
+    /// File for `--output-file`, and whether it gets the Markdown source rather than the
+    /// rendered output
+    tee: Option<(std::fs::File, bool)>,
+}
+
+/// A table being streamed row by row, with column widths fixed when its header arrived
+struct OpenTable {
+    alignments: Vec<Alignment>,
+    widths: Vec<usize>,
+    /// Number of rows rendered so far, including the header
+    rows: usize,
+}
+
+/// Whether a line looks like a Markdown table row
+fn is_table_row(line: &str) -> bool {
+    line.trim_start().starts_with('|')
+}
+
+/// Whether a line is a table delimiter row such as `|:---|---:|`
+fn is_delimiter_row(line: &str) -> bool {
+    let line = line.trim();
+    line.contains('-') && line.chars().all(|c| matches!(c, '|' | '-' | ':' | ' ' | '\t'))
+}
+
+/// Split a table row line into cells, honoring escaped pipes
+fn split_table_row(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    let mut cells = vec![String::new()];
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cells.last_mut().unwrap().push('|');
+                chars.next();
+            }
+            '|' => cells.push(String::new()),
+            _ => cells.last_mut().unwrap().push(c),
+        }
+    }
+    cells.iter().map(|c| c.trim().to_string()).collect()
+}
+
+/// Parse a delimiter row into column alignments
+fn parse_alignments(line: &str) -> Vec<Alignment> {
+    split_table_row(line)
+        .iter()
+        .map(|cell| match (cell.starts_with(':'), cell.ends_with(':')) {
+            (true, true) => Alignment::Center,
+            (false, true) => Alignment::Right,
+            (true, false) => Alignment::Left,
+            (false, false) => Alignment::None,
+        })
+        .collect()
+}
+
+/// Parse a table row line into cells of styled spans
+fn parse_table_row(line: &str) -> Vec<Cell> {
+    split_table_row(line)
+        .iter()
+        .map(|cell| {
+            let mut style = SpanStyle::default();
+            MarkdownParser::new_ext(cell, Options::all())
+                .filter_map(|event| span_event(&mut style, event))
+                .collect()
+        })
+        .collect()
+}
+
+/// Track inline styling for table cells; returns a span for text events
+fn span_event(style: &mut SpanStyle, event: Event) -> Option<Span> {
+    match event {
+        Event::Start(Tag::Strong) => style.bold = true,
+        Event::End(TagEnd::Strong) => style.bold = false,
+        Event::Start(Tag::Emphasis) => style.italic = true,
+        Event::End(TagEnd::Emphasis) => style.italic = false,
+        Event::Start(Tag::Strikethrough) => style.strikethrough = true,
+        Event::End(TagEnd::Strikethrough) => style.strikethrough = false,
+        Event::Start(Tag::Link { .. }) => style.link = true,
+        Event::End(TagEnd::Link) => style.link = false,
+        Event::Text(text) => return Some(Span { text: text.to_string(), style: *style }),
+        Event::Code(code) => {
+            return Some(Span { text: code.to_string(), style: SpanStyle { code: true, ..*style } })
+        }
+        Event::InlineMath(latex) => return Some(Span { text: math::to_unicode(&latex), style: *style }),
+        _ => {}
+    }
+    None
+}
+
+/// Whether a path is a named pipe, which `--follow` reopens for each writer
+#[cfg(unix)]
+fn is_fifo(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(path).is_ok_and(|m| m.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_path: &Path) -> bool {
+    false
+}
+
+/// Terminal resizes so far, counted by the one SIGWINCH watcher every streamer shares
+static RESIZES: AtomicUsize = AtomicUsize::new(0);
+
+/// Start watching for terminal resizes (SIGWINCH), once however many streamers are made, and
+/// return the count so far
+fn watch_resize() -> usize {
+    #[cfg(unix)]
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        static WATCHER: OnceLock<()> = OnceLock::new();
+        WATCHER.get_or_init(|| {
+            handle.spawn(async {
+                use tokio::signal::unix::{signal, SignalKind};
+                if let Ok(mut winch) = signal(SignalKind::window_change()) {
+                    while winch.recv().await.is_some() {
+                        RESIZES.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            });
+        });
+    }
+    RESIZES.load(Ordering::Relaxed)
+}
+
+/// Build the termimad skin used for headers, lists, and code blocks from a theme
+fn build_skin(theme: &Theme, code_theme: Option<&CodeTheme>) -> MadSkin {
+    let mut mad_skin = MadSkin::default();
+    mad_skin.set_fg(match theme.text {
+        Some(_) => theme.get_color("text"),
+        None => termimad::crossterm::style::Color::AnsiValue(15), // White text
+    });
+    mad_skin.set_bg(termimad::crossterm::style::Color::AnsiValue(0));  // Black background
+
+    // Left-align paragraphs and headers
+    mad_skin.paragraph.align = termimad::Alignment::Left;
+    mad_skin.paragraph.set_bg(termimad::crossterm::style::Color::Reset);
+
+    for header in &mut mad_skin.headers {
+        header.align = termimad::Alignment::Left;
+        header.set_bg(termimad::crossterm::style::Color::Reset); // No background
+    }
+
+    // Remove backgrounds from other elements
+    mad_skin.bold.set_bg(termimad::crossterm::style::Color::Reset);
+    mad_skin.italic.set_bg(termimad::crossterm::style::Color::Reset);
+    mad_skin.strikeout.set_bg(termimad::crossterm::style::Color::Reset);
+    mad_skin.inline_code.set_bg(termimad::crossterm::style::Color::Reset);
+
+    // Code blocks follow the editor theme, if one is configured
+    if let Some(code_theme) = code_theme {
+        if let Some(bg) = &code_theme.background {
+            mad_skin.code_block.set_bg(Theme::parse_color(bg));
+        }
+        if let Some(fg) = &code_theme.foreground {
+            mad_skin.code_block.set_fg(Theme::parse_color(fg));
+        }
+    }
+
+    // Configure header colors (termimad handles the sizing automatically)
+    // Set header colors from theme
+    for (i, header) in mad_skin.headers.iter_mut().enumerate() {
+        let level = i + 1; // 1-indexed levels
+        header.set_fg(theme.get_heading_color(level));
+        let style = theme.get_heading_style(level);
+        for (attr, set) in [
+            (Attribute::Bold, style.bold),
+            (Attribute::Italic, style.italic),
+            (Attribute::Underlined, style.underline),
+            (Attribute::Dim, style.dim),
+        ] {
+            match set {
+                Some(true) => header.compound_style.add_attr(attr),
+                Some(false) => header.compound_style.remove_attr(attr),
+                None => {}
+            }
+        }
+    }
+    mad_skin
+}
+
+/// Marks where a link starts in heading or list Markdown. Both markers are zero-width, so
+/// they don't change how the text wraps before they're swapped for hyperlink escapes.
+const LINK_START: char = '\u{2060}';
+/// Marks where a link ends in heading or list Markdown
+const LINK_END: char = '\u{feff}';
+
+/// How an image that isn't drawn is shown: `🖼 [alt] (url)`
+fn image_placeholder(alt: &str, url: &str) -> String {
+    match (alt.trim(), url) {
+        ("", url) => format!("🖼 {}", url),
+        (alt, "") => format!("🖼 [{}]", alt),
+        (alt, url) => format!("🖼 [{}] ({})", alt, url),
+    }
+}
+
+/// Remove SGR (color and attribute) escape sequences from rendered output
+fn strip_sgr(rendered: &[u8]) -> Vec<u8> {
+    SGR_RE.replace_all(&String::from_utf8_lossy(rendered), "").into_owned().into_bytes()
+}
+
+/// Pause after each word revealed with `--granularity word`
+const WORD_DELAY: std::time::Duration = std::time::Duration::from_millis(25);
+/// Pause after each sentence revealed with `--granularity sentence`
+const SENTENCE_DELAY: std::time::Duration = std::time::Duration::from_millis(250);
+/// How often `--follow` checks a file for appended text
+const FOLLOW_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Split rendered output into the pieces revealed one at a time: words end after a run
+/// of whitespace, sentences after `.`, `!`, or `?` followed by whitespace, or at a line
+/// break. Escape sequences contain no whitespace, so they are never cut in half.
+/// Characters carry the escape sequences in front of them.
+fn reveal_units(rendered: &[u8], granularity: Granularity) -> Vec<&[u8]> {
+    let mut units = Vec::new();
+    let mut start = 0;
+    if granularity == Granularity::Char {
+        let mut i = 0;
+        while i < rendered.len() {
+            if rendered[i] == 0x1b {
+                i += escape_len(&rendered[i..]);
+                continue;
+            }
+            // Step over the whole UTF-8 sequence
+            i += match rendered[i] {
+                b if b >= 0xf0 => 4,
+                b if b >= 0xe0 => 3,
+                b if b >= 0xc0 => 2,
+                _ => 1,
+            };
+            units.push(&rendered[start..i.min(rendered.len())]);
+            start = i;
+        }
+        if start < rendered.len() {
+            units.push(&rendered[start..]);
+        }
+        return units;
+    }
+    for i in 0..rendered.len() {
+        let next = rendered.get(i + 1);
+        let ends = match granularity {
+            Granularity::Word => rendered[i].is_ascii_whitespace() && next.is_none_or(|b| !b.is_ascii_whitespace()),
+            Granularity::Sentence => {
+                rendered[i] == b'\n'
+                    || (rendered[i] == b' '
+                        && next.is_none_or(|b| !b.is_ascii_whitespace())
+                        && last_visible(&rendered[start..i]).is_some_and(|b| matches!(b, b'.' | b'!' | b'?')))
+            }
+            Granularity::Char | Granularity::Block => false,
+        };
+        if ends {
+            units.push(&rendered[start..=i]);
+            start = i + 1;
+        }
+    }
+    if start < rendered.len() {
+        units.push(&rendered[start..]);
+    }
+    units
+}
+
+/// Length of the escape sequence at the start of `text`: CSI sequences run to their final
+/// byte, OSC/APC/DCS strings (hyperlinks, images) to BEL or the string terminator
+fn escape_len(text: &[u8]) -> usize {
+    match text.get(1) {
+        Some(b'[') => text[2..].iter().position(|b| (0x40..=0x7e).contains(b)).map_or(text.len(), |end| end + 3),
+        Some(b']' | b'_' | b'P') => (2..text.len())
+            .find_map(|i| match text[i] {
+                0x07 => Some(i + 1),
+                0x1b if text.get(i + 1) == Some(&b'\\') => Some(i + 2),
+                _ => None,
+            })
+            .unwrap_or(text.len()),
+        Some(_) => 2,
+        None => 1,
+    }
+}
+
+/// Last byte of `text` that isn't trailing whitespace or part of a trailing SGR escape
+fn last_visible(mut text: &[u8]) -> Option<u8> {
+    loop {
+        text = text.trim_ascii_end();
+        let Some(escape) = text.iter().rposition(|&b| b == 0x1b) else {
+            return text.last().copied();
+        };
+        let code = &text[escape..];
+        if code.len() >= 3 && code[1] == b'[' && code.ends_with(b"m") && code[2..code.len() - 1].iter().all(|b| b.is_ascii_digit() || *b == b';') {
+            text = &text[..escape];
+        } else {
+            return text.last().copied();
+        }
+    }
+}
+
+/// Plain text for piped output: tables lose their borders and keep their columns lined up
+/// with spaces, other box-drawing characters become ASCII, and trailing spaces are dropped
+fn to_plain(rendered: &str) -> String {
+    let unboxed = TABLE_BORDER_RE.replace_all(rendered, "");
+    let unboxed = TABLE_ROW_RE.replace_all(&unboxed, |caps: &regex::Captures| format!("{}{}", &caps[1], caps[2].replace(" │ ", "   ")));
+    let ascii: String = unboxed
+        .chars()
+        .map(|c| match c {
+            '┌' | '┐' | '└' | '┘' | '├' | '┤' | '┬' | '┴' | '┼' | '╭' | '╮' | '╰' | '╯' => '+',
+            '─' | '━' | '═' => '-',
+            '│' | '┃' | '║' => '|',
+            c => c,
+        })
+        .collect();
+    TRAILING_SPACE_RE.replace_all(&ascii, "").into_owned()
+}
+
+/// A command as it would be typed, for error messages
+fn command_line(command: &std::process::Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|part| shell_words::quote(&part.to_string_lossy()).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether inline math found by the parser at `range` is really dollar amounts
+fn is_price(text: &str, range: std::ops::Range<usize>) -> bool {
+    let before = text[..range.start].chars().next_back();
+    let after = text[range.end..].chars().next();
+    before.is_some_and(char::is_alphanumeric) || after.is_some_and(|c| c.is_ascii_digit())
+}
+
+/// Whether an untagged code block looks like a unified diff
+fn is_diff(code: &str) -> bool {
+    let first = code.lines().find(|l| !l.trim().is_empty()).unwrap_or_default();
+    first.starts_with("diff --git ") || first.starts_with("--- ") || first.starts_with("+++ ") || first.starts_with("@@ ")
+}
+
+/// Modification time of a file, if it can be read
+fn modified_time(path: &std::path::Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
 }
 
 impl MinimalStreamer {
     /// Create a new streamer instance
     pub fn new(config: StreamerConfig) -> Self {
+        let section = config.section.as_deref().map(SectionFilter::new);
+        let captured = config.pager.then(Vec::new);
+        let pacer = Pacer::new(config.speed);
+        let bench = config.bench.then(Bench::new);
+        let stats = config.stats.then(Stats::new);
+        let transcript = config.export.then(Vec::new);
+        let source = config.copy.then(Vec::new);
         let theme = if let Some(ref theme_file) = config.theme_file {
             match Theme::from_file(theme_file) {
-                Ok(theme) => theme,
+                Ok(theme) => {
+                    log::info!("loaded theme from {}", theme_file.display());
+                    theme
+                }
                 Err(e) => {
                     eprintln!("Warning: Failed to load theme from {:?}: {}", theme_file, e);
                     eprintln!("Falling back to built-in theme: {}", config.theme_name);
-                    match config.theme_name.as_str() {
-                        "light" => Theme::light(),
-                        "mono" => Theme::mono(),
-                        _ => Theme::dark(),
-                    }
+                    Theme::builtin(&config.theme_name).unwrap_or_else(Theme::dark)
                 }
             }
         } else {
-            match config.theme_name.as_str() {
-                "light" => Theme::light(),
-                "mono" => Theme::mono(),
-                _ => Theme::dark(),
-            }
+            Theme::builtin(&config.theme_name).unwrap_or_else(Theme::dark)
         };
 
-        // Create termimad skin for rich text rendering
-        let mut mad_skin = MadSkin::default();
-        mad_skin.set_fg(termimad::crossterm::style::Color::AnsiValue(15)); // White text
-        mad_skin.set_bg(termimad::crossterm::style::Color::AnsiValue(0));  // Black background
-
-        // Left-align paragraphs and headers
-        mad_skin.paragraph.align = termimad::Alignment::Left;
-        mad_skin.paragraph.set_bg(termimad::crossterm::style::Color::Reset);
+        let mad_skin = build_skin(&theme, config.code_theme.as_ref());
+        let theme_mtime = config.theme_file.as_deref().and_then(modified_time);
 
-        for header in &mut mad_skin.headers {
-            header.align = termimad::Alignment::Left;
-            header.set_bg(termimad::crossterm::style::Color::Reset); // No background
+        Self {
+            config,
+            theme,
+            mad_skin,
+            column: 0,
+            out: Vec::new(),
+            at_line_start: true,
+            resizes: watch_resize(),
+            open_table: None,
+            theme_mtime,
+            code_blocks: Vec::new(),
+            last_heading: None,
+            link_refs: HashMap::new(),
+            pending_refs: Vec::new(),
+            link_footnotes: Vec::new(),
+            list_hang: 0,
+            buffered_links: VecDeque::new(),
+            base_dir: None,
+            frontmatter_checked: false,
+            headings: Vec::new(),
+            toc_written: false,
+            section,
+            captured,
+            heading_lines: Vec::new(),
+            pager_view: None,
+            preview_start: None,
+            pacer,
+            bench,
+            stats,
+            page_started: false,
+            transcript,
+            source,
+            tee: None,
         }
-
-        // Remove backgrounds from other elements
-        mad_skin.bold.set_bg(termimad::crossterm::style::Color::Reset);
-        mad_skin.italic.set_bg(termimad::crossterm::style::Color::Reset);
-        mad_skin.strikeout.set_bg(termimad::crossterm::style::Color::Reset);
-        mad_skin.inline_code.set_bg(termimad::crossterm::style::Color::Reset);
-
-        // Configure header colors (termimad handles the sizing automatically)
-        // Set header colors from theme
-        for (i, header) in mad_skin.headers.iter_mut().enumerate() {
-            let color = theme.get_heading_color(i + 1); // 1-indexed levels
-            header.set_fg(color);
-        }
-
-        Self { config, theme, mad_skin }
     }
 
-    /// Render math expressions with special formatting
-    fn render_math(&self, math_text: &str) -> String {
-        // For now, just return the math with special markers
-        // Could render LaTeX to ASCII art or similar?
-        format!("[Math: {}]", math_text.trim())
-    }
-
-    /// Process text for math expressions before markdown parsing
-    fn preprocess_math(&self, text: &str) -> String {
-        let math_re = Regex::new(r"\$\$([^$]+)\$\$").unwrap();
-        math_re.replace_all(text, |caps: &regex::Captures| {
-            let math_content = &caps[1];
-            self.render_math(math_content)
-        }).to_string()
+    /// Reload the theme file if it changed since it was last read, so edits to a custom
+    /// theme show up on the next flush. A file that fails to load keeps the current theme.
+    fn reload_theme_if_changed(&mut self) {
+        let Some(path) = self.config.theme_file.as_deref() else {
+            return;
+        };
+        let mtime = modified_time(path);
+        if mtime.is_none() || mtime == self.theme_mtime {
+            return;
+        }
+        self.theme_mtime = mtime;
+        log::info!("theme file {} changed, reloading", path.display());
+        match Theme::from_file(path) {
+            Ok(theme) => {
+                self.mad_skin = build_skin(&theme, self.config.code_theme.as_ref());
+                self.theme = theme;
+            }
+            Err(e) => eprintln!("Warning: Failed to reload theme from {:?}: {}", path, e),
+        }
     }
 
     /// Find the optimal boundary for flushing content during streaming
     /// Prioritizes code fences, table boundaries, then paragraph boundaries, then size thresholds
     fn find_flush_boundary(&self, buffer: &str) -> usize {
+        // Line-buffered logs: every complete line goes out as soon as it arrives, except that
+        // fenced code waits for its closing fence to be highlighted as a whole
+        if self.config.line_buffered {
+            if buffer.starts_with("```") && buffer.len() < self.config.chunk_size * 8 {
+                let closing = buffer[3..].find("\n```").map(|i| i + 4);
+                return closing.and_then(|start| buffer[start..].find('\n').map(|n| start + n + 1)).unwrap_or(0);
+            }
+            return buffer.find('\n').map_or(0, |newline| newline + 1);
+        }
+        // 0. Hold back frontmatter until its closing line, so it's read as a whole
+        if !self.frontmatter_checked && frontmatter::is_open(buffer) && buffer.len() < self.config.chunk_size * 8 {
+            let end = frontmatter::block_end(buffer).unwrap_or(0);
+            log::trace!("frontmatter: {}", if end > 0 { "complete" } else { "holding back until its closing line" });
+            return end;
+        }
         // 1. Prioritize code fences
-        if let Some(mat) = Regex::new(r"```").unwrap().find_iter(buffer).nth(1) {
-            let mut flush_at = mat.start() + 3;
+        if let Some((start, _)) = buffer.match_indices("```").nth(1) {
+            let mut flush_at = start + 3;
             // include following newline if present
             if flush_at < buffer.len() && buffer.chars().nth(flush_at) == Some('\n') {
                 flush_at += 1;
             }
+            log::trace!("flush at {}: end of code fence", flush_at);
             return flush_at;
         }
+        // In table streaming mode, flush complete rows as soon as they arrive
+        if self.config.stream_tables {
+            if let Some(flush_at) = self.table_row_boundary(buffer) {
+                log::trace!("flush at {}: streamed table rows", flush_at);
+                return flush_at;
+            }
+        }
         // 2. Don't break inside table rows
         if let Some(table_row_start) = buffer.find("|") {
             // Look for the end of the current table row
@@ -112,6 +599,7 @@ impl MinimalStreamer {
                 let potential_flush = table_row_start + row_end + 1;
                 if potential_flush < buffer.len() && buffer.chars().nth(potential_flush) != Some('|') {
                     // Not in the middle of a table, safe to flush after this row
+                    log::trace!("flush at {}: after table row", potential_flush);
                     return potential_flush;
                 }
             }
@@ -123,10 +611,17 @@ impl MinimalStreamer {
             while flush_at < buffer.len() && buffer.chars().nth(flush_at) == Some('\n') {
                 flush_at += 1;
             }
+            log::trace!("flush at {}: paragraph break", flush_at);
             return flush_at;
         }
         // 4. Size threshold - prefer sentence boundaries over word boundaries
         if buffer.len() >= self.config.chunk_size {
+            // Wait for the end of display math, which is laid out as a whole
+            if buffer[..self.config.chunk_size].matches("$$").count() % 2 == 1 && buffer.len() < self.config.chunk_size * 8 {
+                log::trace!("over chunk size, holding back open display math");
+                return 0;
+            }
+            log::trace!("{} bytes buffered, over chunk size {}: splitting the paragraph", buffer.len(), self.config.chunk_size);
             // First, try to find a sentence boundary (period + space)
             if let Some(sentence_end) = buffer[..self.config.chunk_size].rfind(". ") {
                 return sentence_end + 2; // Include period and space
@@ -163,17 +658,433 @@ impl MinimalStreamer {
         0
     }
 
-    /// Parse and render Markdown with terminal styling using Crossterm
-    fn print_styled_markdown(&self, text: &str) {
+    /// Render a flushed chunk, streaming table rows directly when table streaming is enabled
+    fn print_styled_markdown(&mut self, text: &str) {
+        log::debug!("rendering {} bytes: {:?}", text.len(), text.chars().take(60).collect::<String>());
+        self.tee_output(text.as_bytes(), true);
+        match self.section.as_mut() {
+            Some(section) => {
+                let kept = section.filter(text);
+                self.render_flush(&kept);
+            }
+            None => self.render_flush(text),
+        }
+    }
+
+    /// Render a flushed chunk that has passed the `--section` filter
+    fn render_flush(&mut self, text: &str) {
+        if let Some(stats) = self.stats.as_mut() {
+            // Output that never arrived in pieces, like a cached response, starts here
+            stats.arrived();
+            stats.bytes += text.len();
+        }
+        let Some(printed) = self.bench.as_ref().map(|bench| bench.print) else {
+            self.render_text(text);
+            return;
+        };
+        let started = Instant::now();
+        self.render_text(text);
+        if let Some(bench) = self.bench.as_mut() {
+            bench.bytes += text.len();
+            bench.render += started.elapsed().saturating_sub(bench.print - printed);
+        }
+    }
+
+    /// The work of [`Self::render_flush`], which times it for `--bench`
+    fn render_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.reload_theme_if_changed();
+        let mut text = text;
+        if !std::mem::replace(&mut self.frontmatter_checked, true) {
+            if let Some((fields, rest)) = frontmatter::split(text) {
+                self.write_frontmatter(&fields);
+                text = rest;
+            }
+        }
+        match self.config.output {
+            OutputFormat::Json => return self.write_events(&events::blocks(text)),
+            OutputFormat::Html => return self.write_document(&page::body(text), toc::headings(text).first().map(|h| h.text.as_str())),
+            OutputFormat::Man => return self.write_document(&man::body(text), toc::headings(text).first().map(|h| h.text.as_str())),
+            OutputFormat::Terminal => {}
+        }
+        if !self.config.stream_tables {
+            self.render_markdown(text);
+            return;
+        }
+
+        let (width, margin) = self.layout();
+        let mut pending = String::new();
+        let mut lines = text.split_inclusive('\n').peekable();
+        while let Some(line) = lines.next() {
+            if self.open_table.is_some() {
+                if is_table_row(line) {
+                    self.stream_table_row(&parse_table_row(line), margin);
+                    continue;
+                }
+                self.close_table(margin);
+            } else if is_table_row(line) && lines.peek().is_some_and(|next| is_delimiter_row(next)) {
+                if !pending.is_empty() {
+                    self.render_markdown(&std::mem::take(&mut pending));
+                }
+                let alignments = parse_alignments(lines.next().unwrap_or_default());
+                // Pre-measure columns from the header and any rows that arrived with it
+                let mut rows = vec![parse_table_row(line)];
+                while let Some(row) = lines.next_if(|l| is_table_row(l)) {
+                    rows.push(parse_table_row(row));
+                }
+                self.emit(margin);
+                let widths = TableRenderer::measure_streamed(&rows, width);
+                TableRenderer::render_border(&mut self.out, &widths, Border::Top, &self.theme, 0);
+                self.open_table = Some(OpenTable { alignments, widths, rows: 0 });
+                for row in &rows {
+                    self.stream_table_row(row, margin);
+                }
+                continue;
+            }
+            pending.push_str(line);
+        }
+        if !pending.is_empty() {
+            self.render_markdown(&pending);
+        }
+        let _ = stdout().flush();
+    }
+
+    /// Render one row of the open table, with separators drawn ahead of it
+    fn stream_table_row(&mut self, row: &[Cell], margin: usize) {
+        let Some(table) = self.open_table.as_mut() else {
+            return;
+        };
+        if table.rows == 1 || (table.rows > 1 && self.config.table_style == TableStyle::Grid) {
+            TableRenderer::render_border(&mut self.out, &table.widths, Border::Middle, &self.theme, 0);
+        }
+        TableRenderer::render_row(&mut self.out, row, table.rows, &table.widths, &table.alignments, &self.theme, 0);
+        table.rows += 1;
+        self.emit(margin);
+    }
+
+    /// Draw the bottom border of a streamed table
+    fn close_table(&mut self, margin: usize) {
+        if let Some(table) = self.open_table.take() {
+            TableRenderer::render_border(&mut self.out, &table.widths, Border::Bottom, &self.theme, 0);
+            self.emit(margin);
+        }
+    }
+
+    /// Render delimiter-separated values (CSV/TSV) as a bordered table. Without a header
+    /// row, columns are numbered; columns whose values are all numeric are right-aligned.
+    pub fn render_delimited(&mut self, text: &str, delimiter: char, has_header: bool) {
+        let mut records = parse_delimited(text, delimiter);
+        if records.is_empty() {
+            return;
+        }
+        let columns = records.iter().map(Vec::len).max().unwrap_or(0);
+        if !has_header {
+            records.insert(0, (1..=columns).map(|i| i.to_string()).collect());
+        }
+
+        let alignments: Vec<Alignment> = (0..columns)
+            .map(|col| {
+                let mut values = records[1..].iter().filter_map(|r| r.get(col)).filter(|v| !v.is_empty());
+                let mut any = false;
+                let numeric = values.all(|v| {
+                    any = true;
+                    v.trim().parse::<f64>().is_ok()
+                });
+                if any && numeric { Alignment::Right } else { Alignment::None }
+            })
+            .collect();
+
+        let rows: Vec<Vec<Cell>> = records
+            .into_iter()
+            .map(|record| {
+                record
+                    .into_iter()
+                    .map(|text| vec![Span { text, style: SpanStyle::default() }])
+                    .collect()
+            })
+            .collect();
+
+        let (width, margin) = self.layout();
+        self.emit(margin);
+        TableRenderer::render_table(&mut self.out, &rows, &alignments, &self.theme, self.config.table_style, width, 0);
+        self.emit(margin);
+    }
+
+    /// Show frontmatter fields as a key/value table, unless frontmatter is hidden
+    fn write_frontmatter(&mut self, fields: &[(String, String)]) {
+        if self.config.frontmatter == FrontmatterStyle::Hidden || fields.is_empty() {
+            return;
+        }
+        if self.config.output == OutputFormat::Json {
+            let fields: serde_json::Map<String, serde_json::Value> = fields.iter().map(|(key, value)| (key.clone(), value.clone().into())).collect();
+            self.write_events(&[serde_json::json!({"type": "frontmatter", "fields": fields})]);
+            return;
+        }
+        if self.config.output == OutputFormat::Html {
+            let rows: String = fields
+                .iter()
+                .map(|(key, value)| format!("<tr><th>{}</th><td>{}</td></tr>\n", page::escape(key), page::escape(value)))
+                .collect();
+            let title = fields.iter().find(|(key, _)| key == "title").map(|(_, value)| value.as_str());
+            self.write_document(&format!("<table class=\"frontmatter\">\n{}</table>\n", rows), title);
+            return;
+        }
+        if self.config.output == OutputFormat::Man {
+            // Man pages have no place for metadata beyond the title
+            let title = fields.iter().find(|(key, _)| key == "title").map(|(_, value)| value.as_str());
+            self.write_document("", title);
+            return;
+        }
+        let cell = |text: &str| vec![Span { text: text.to_string(), style: SpanStyle::default() }];
+        let rows: Vec<Vec<Cell>> = std::iter::once(vec![cell("Key"), cell("Value")])
+            .chain(fields.iter().map(|(key, value)| vec![cell(key), cell(value)]))
+            .collect();
+        let (width, margin) = self.layout();
+        self.emit(margin);
+        // Always compact: the fields are a header for the document, not content
+        TableRenderer::render_table(&mut self.out, &rows, &[Alignment::None; 2], &self.theme, TableStyle::Compact, width, 0);
+        let _ = self.out.queue(Print("\n"));
+        self.emit(margin);
+    }
+
+    /// Write part of the document for `--output html` or `man`, starting the document first
+    /// if this is the first output. Its title is the first heading, or `title` from the
+    /// frontmatter.
+    fn write_document(&mut self, body: &str, title: Option<&str>) {
+        let mut stdout = stdout();
+        if !std::mem::replace(&mut self.page_started, true) {
+            let title = title.unwrap_or("livemd");
+            let header = match self.config.output {
+                OutputFormat::Man => man::header(title),
+                _ => page::header(&self.theme, title),
+            };
+            self.tee_output(header.as_bytes(), false);
+            let _ = stdout.write_all(header.as_bytes());
+        }
+        self.tee_output(body.as_bytes(), false);
+        let _ = stdout.write_all(body.as_bytes());
+        let _ = stdout.flush();
+    }
+
+    /// Bar drawn before blockquote lines: a box-drawing line, or `>` in plain output
+    fn quote_bar(&self) -> &'static str {
+        if self.config.plain {
+            "> "
+        } else {
+            "│ "
+        }
+    }
+
+    /// Write parsed blocks for `--output json`, one object per line
+    fn write_events(&mut self, blocks: &[serde_json::Value]) {
+        let lines: String = blocks.iter().map(|block| format!("{}\n", block)).collect();
+        self.tee_output(lines.as_bytes(), false);
+        let mut stdout = stdout();
+        let _ = stdout.write_all(lines.as_bytes());
+        let _ = stdout.flush();
+    }
+
+    /// Send output to the `--output-file` as well as stdout
+    pub fn tee(&mut self, file: std::fs::File, raw: bool) {
+        self.tee = Some((file, raw));
+    }
+
+    /// Copy to the `--output-file`, when its contents are the kind given: Markdown source
+    /// (`raw`) or what was displayed. The source is also kept for `--copy`.
+    fn tee_output(&mut self, bytes: &[u8], raw: bool) {
+        if let Some(source) = self.source.as_mut().filter(|_| raw) {
+            source.extend_from_slice(bytes);
+        }
+        if let Some((file, _)) = self.tee.as_mut().filter(|(_, source)| *source == raw) {
+            let _ = file.write_all(bytes);
+        }
+    }
+
+    /// Print a table of contents: headings indented by level, in the theme's heading colors
+    fn write_toc(&mut self, headings: &[Heading]) {
+        self.toc_written = true;
+        if headings.is_empty() {
+            return;
+        }
+        let (width, margin) = self.layout();
+        if self.column > 0 {
+            let _ = self.out.queue(Print("\n"));
+            self.column = 0;
+        }
+        let _ = self.out.queue(SetAttribute(Attribute::Bold));
+        let _ = self.out.queue(Print("Contents\n"));
+        let _ = self.out.queue(SetAttribute(Attribute::Reset));
+        let top = headings.iter().map(|h| h.level).min().unwrap_or(1);
+        for heading in headings {
+            let indent = 2 * (heading.level - top + 1);
+            let _ = self.out.queue(SetForegroundColor(self.theme.get_heading_color(heading.level)));
+            let (wrapped, _) = wrap_text_indented(&heading.text, indent, width, indent);
+            let _ = self.out.queue(Print(format!("{}{}\n", " ".repeat(indent), wrapped)));
+            let _ = self.out.queue(ResetColor);
+        }
+        let _ = self.out.queue(Print("\n"));
+        self.emit(margin);
+    }
+
+    /// Show the unfinished block at the end of the buffer as it would render now, replacing
+    /// the previous preview. The real rendering takes its place once the block is flushed.
+    /// Renderer state the preview touches is put back, so it leaves no trace.
+    fn preview(&mut self, tail: &str) {
+        let frontmatter_pending = !self.frontmatter_checked && frontmatter::is_open(tail);
+        if !self.config.live || self.captured.is_some() || self.section.is_some() || frontmatter_pending {
+            return;
+        }
+        if tail.trim().is_empty() {
+            self.clear_preview();
+            let _ = stdout().flush();
+            return;
+        }
+        let (column, at_line_start, last_heading) = (self.column, self.at_line_start, self.last_heading.clone());
+        let counts = (
+            self.code_blocks.len(),
+            self.headings.len(),
+            self.heading_lines.len(),
+            self.pending_refs.len(),
+            self.link_footnotes.len(),
+        );
+        // Render into a capture buffer rather than to the screen
+        self.captured = Some(Vec::new());
+        self.render_markdown(tail);
+        let (_, margin) = self.layout();
+        self.emit(margin);
+        let mut rendered = self.captured.take().unwrap_or_default();
+        // Drop the block's closing spacing so the preview ends where the text does, and reset
+        // styling so erasing it doesn't paint a background
+        let end = PREVIEW_TRAILING_RE.find(&rendered).map_or(rendered.len(), |m| m.start());
+        rendered.truncate(end);
+        let lines = rendered.iter().filter(|&&b| b == b'\n').count();
+        let rows = size().map_or(24, |(_, rows)| rows as usize);
         let mut stdout = stdout();
-        // Preprocess math expressions
-        let processed_text = self.preprocess_math(text);
-        let parser = MarkdownParser::new_ext(&processed_text, Options::all());
+        // Too tall to erase reliably once it scrolls; wait for the real rendering then
+        if lines + 1 < rows {
+            let _ = stdout.write_all(&rendered);
+            if self.config.color {
+                let _ = stdout.write_all(b"\x1b[0m");
+            }
+            let start_column = if at_line_start { 0 } else { margin + column };
+            self.preview_start = Some((lines, start_column));
+        }
+        let _ = stdout.flush();
+        self.column = column;
+        self.at_line_start = at_line_start;
+        self.last_heading = last_heading;
+        self.code_blocks.truncate(counts.0);
+        self.headings.truncate(counts.1);
+        self.heading_lines.truncate(counts.2);
+        self.pending_refs.truncate(counts.3);
+        self.link_footnotes.truncate(counts.4);
+    }
+
+    /// Erase the live preview, leaving the cursor where it started
+    fn clear_preview(&mut self) {
+        if let Some((lines, column)) = self.preview_start.take() {
+            let mut stdout = stdout();
+            let _ = write!(stdout, "\r");
+            if lines > 0 {
+                let _ = write!(stdout, "\x1b[{}A", lines);
+            }
+            if column > 0 {
+                let _ = write!(stdout, "\x1b[{}C", column);
+            }
+            let _ = write!(stdout, "\x1b[J");
+        }
+    }
+
+    /// Render whatever is left in the buffer once the input ends
+    fn finish(&mut self, buffer: &str) {
+        self.tee_output(buffer.as_bytes(), true);
+        if buffer.trim().is_empty() {
+            self.clear_preview();
+        }
+        let rest = match self.section.as_mut() {
+            Some(section) => section.filter(buffer) + &section.finish(),
+            None => buffer.to_string(),
+        };
+        if !rest.trim().is_empty() {
+            self.render_flush(&rest);
+        }
+        if self.open_table.is_some() {
+            let (_, margin) = self.layout();
+            self.close_table(margin);
+        }
+    }
+
+    /// Find where to flush table rows when streaming tables: after the last complete row,
+    /// or (for a new table) once its header and delimiter rows have both arrived
+    fn table_row_boundary(&self, buffer: &str) -> Option<usize> {
+        // Complete lines with the offset just past their newline
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for (i, byte) in buffer.bytes().enumerate() {
+            if byte == b'\n' {
+                lines.push((&buffer[start..i], i + 1));
+                start = i + 1;
+            }
+        }
+
+        let first = lines.iter().position(|(line, _)| is_table_row(line))?;
+        let mut last = first;
+        if self.open_table.is_none() || first > 0 {
+            match lines.get(first + 1) {
+                Some((line, _)) if is_delimiter_row(line) => last = first + 1,
+                Some(_) => return None,
+                // Flush the text before the header and wait for the delimiter row
+                None => return Some(if first > 0 { lines[first - 1].1 } else { 0 }),
+            }
+        }
+        while lines.get(last + 1).is_some_and(|(line, _)| is_table_row(line)) {
+            last += 1;
+        }
+        Some(lines[last].1)
+    }
+
+    /// Parse and render Markdown with terminal styling using Crossterm
+    fn render_markdown(&mut self, text: &str) {
+        // Re-query per flush so wrapping follows the current terminal width
+        let (width, margin) = self.layout();
+        if self.config.line_buffered && text.trim().is_empty() {
+            // A blank line on its own parses to nothing, but a log's blank lines are part of it
+            self.out.push(b'\n');
+            self.emit(margin);
+            return;
+        }
+        let resizes = RESIZES.load(Ordering::Relaxed);
+        if std::mem::replace(&mut self.resizes, resizes) != resizes && self.column >= width {
+            // The rest of a paragraph wraps at the new width from where the last flush left
+            // off, unless the terminal narrowed past that point; then it goes on a fresh line
+            self.out.push(b'\n');
+            self.column = 0;
+        }
+        // Reference definitions can arrive in an earlier flush than the links that use them,
+        // so resolve `[text][ref]` against every definition seen so far
+        for caps in DEFINITION_RE.captures_iter(text) {
+            self.link_refs.insert(caps[1].to_lowercase(), caps[2].to_string());
+        }
+        let link_refs = self.link_refs.clone();
+        let mut missing_refs: Vec<String> = Vec::new();
+        let resolve = |link: BrokenLink| match link_refs.get(&link.reference.to_lowercase()) {
+            Some(url) => Some((url.clone().into(), "".into())),
+            None => {
+                missing_refs.push(link.reference.to_string());
+                None
+            }
+        };
+        let parser = MarkdownParser::new_with_broken_link_callback(text, Options::all(), Some(resolve));
         let mut list_depth = 0;
-        let mut table_buffer = String::new();
+        let mut table_rows: Vec<Vec<Cell>> = Vec::new();
+        let mut cell_style = SpanStyle::default();
         let mut in_table = false;
-        let mut table_cell_count = 0;
+        let mut table_alignments: Vec<Alignment> = Vec::new();
         let mut header_buffer = String::new();
+        // Where link URLs were added to the heading, which its name leaves out
+        let mut header_suffixes: Vec<std::ops::Range<usize>> = Vec::new();
         let mut in_header = false;
         let mut list_buffer = String::new();
         let mut in_list = false;
@@ -181,83 +1092,135 @@ impl MinimalStreamer {
         let mut list_types: Vec<Option<u64>> = Vec::new();
         let mut item_numbers: Vec<usize> = Vec::new();
         let mut code_block_buffer = String::new();
+        let mut code_lang: Option<String> = None;
         let mut in_code_block = false;
         let mut in_paragraph = false;
+        let mut quote_depth = 0usize;
+        // Set after display math and images, which end their own line
+        let mut after_display_math = false;
+        // Hanging indent for definition list definitions
+        let mut def_indent = 0usize;
+        // Target and text of the link being rendered
+        let mut link_url: Option<String> = None;
+        let mut link_text = String::new();
+        // Source and alt text of the image being read
+        let mut image_url: Option<String> = None;
+        let mut image_alt = String::new();
 
-        for event in parser {
+        // Raw HTML comes through as the equivalent markdown events
+        let mut html = html::Translator::default();
+        let events = parser
+            .into_offset_iter()
+            .flat_map(|(event, range)| html.translate(event).into_iter().map(move |event| (event, range.clone())));
+        for (event, range) in events {
+            if let Some(bench) = self.bench.as_mut() {
+                bench.events += 1;
+            }
+            // The parser takes `US$5 to US$10` and `$5/$10` as math; like Pandoc, don't when the
+            // opening `$` follows a word character or the closing one is followed by a digit
+            let event = match event {
+                Event::InlineMath(_) if is_price(text, range.clone()) => Event::Text(text[range].to_string().into()),
+                event => event,
+            };
+            let follows_display_math = std::mem::take(&mut after_display_math);
             if in_table {
                 match event {
                     Event::End(TagEnd::Table) => {
                         in_table = false;
-                        // Remove trailing separator and render table with borders
-                        let table_md = table_buffer.trim_end_matches(" | ").trim_end_matches("| ");
-                        if !table_md.is_empty() {
-                            TableRenderer::render_table(table_md);
+                        // Render table with borders
+                        if !table_rows.is_empty() {
+                            self.emit(margin);
+                            TableRenderer::render_table(
+                                &mut self.out,
+                                &table_rows,
+                                &table_alignments,
+                                &self.theme,
+                                self.config.table_style,
+                                width,
+                                0,
+                            );
+                            self.emit(margin);
                         }
-                        table_buffer.clear();
+                        table_rows.clear();
                     }
                     Event::Start(Tag::TableHead) | Event::Start(Tag::TableRow) => {
-                        table_cell_count = 0;
-                    }
-                    Event::End(TagEnd::TableHead) | Event::End(TagEnd::TableRow) => {
-                        if table_cell_count > 0 {
-                            table_buffer.push('\n');
-                        }
+                        table_rows.push(Vec::new());
                     }
                     Event::Start(Tag::TableCell) => {
-                        if table_cell_count > 0 {
-                            table_buffer.push_str(" | ");
+                        if let Some(row) = table_rows.last_mut() {
+                            row.push(Vec::new());
                         }
-                        table_cell_count += 1;
                     }
-                    Event::Text(text) => {
-                        table_buffer.push_str(&text);
+                    event => {
+                        if let Some(span) = span_event(&mut cell_style, event) {
+                            if let Some(cell) = table_rows.last_mut().and_then(|row| row.last_mut()) {
+                                cell.push(span);
+                            }
+                        }
                     }
-                    _ => {}
                 }
             } else {
                 match event {
-                    Event::Start(Tag::Table(_)) => {
+                    Event::Start(Tag::Table(alignments)) => {
                         // Flush any pending header or list before starting table
                         if in_header && !header_buffer.is_empty() {
-                            let _ = self.mad_skin.print_text(&header_buffer);
+                            self.write_md(&header_buffer, width);
                             header_buffer.clear();
                             in_header = false;
                         }
                         if in_list && !list_buffer.is_empty() {
-                            let _ = self.mad_skin.print_text(&list_buffer);
+                            self.write_list(&list_buffer, width);
                             list_buffer.clear();
                             in_list = false;
                         }
                         in_table = true;
-                        table_buffer.clear();
-                        table_cell_count = 0;
+                        table_rows.clear();
+                        cell_style = SpanStyle::default();
+                        table_alignments = alignments;
                     }
                     Event::Start(Tag::Heading { level, .. }) => {
                         // Flush any pending content
                         if in_list && !list_buffer.is_empty() {
-                            let _ = self.mad_skin.print_text(&list_buffer);
+                            self.write_list(&list_buffer, width);
                             list_buffer.clear();
                             in_list = false;
                         }
                         in_header = true;
                         header_buffer.clear();
+                        header_suffixes.clear();
                         // Add markdown header prefix
                         header_buffer.push_str(&"#".repeat(level as usize));
                         header_buffer.push(' ');
                     }
-                    Event::End(TagEnd::Heading(_)) => {
-                        if in_header {
-                            let _ = self.mad_skin.print_text(&header_buffer);
-                            header_buffer.clear();
-                            in_header = false;
+                    Event::End(TagEnd::Heading(_)) if in_header => {
+                        let mut source = header_buffer.clone();
+                        for range in header_suffixes.drain(..).rev() {
+                            source.replace_range(range, "");
                         }
+                        source.retain(|c| c != LINK_START && c != LINK_END);
+                        self.last_heading = Some(source.trim_start_matches('#').trim().to_string());
+                        for heading in toc::headings(&source) {
+                            if let Some(captured) = &self.captured {
+                                let line = captured.iter().chain(&self.out).filter(|&&b| b == b'\n').count();
+                                self.heading_lines.push((line, heading.clone()));
+                            }
+                            self.headings.push(heading);
+                        }
+                        if self.config.color || self.config.plain {
+                            self.write_md(&header_buffer, width);
+                        } else {
+                            // Without color on a terminal, keep the `#` markers so headings stand out
+                            let heading = self.link_escapes(&header_buffer);
+                            let _ = writeln!(self.out, "{}", heading);
+                        }
+                        header_buffer.clear();
+                        in_header = false;
                     }
                     Event::Start(Tag::List(list_type)) => {
                         if !in_list {
                             // Flush any pending header
                             if in_header && !header_buffer.is_empty() {
-                                let _ = self.mad_skin.print_text(&header_buffer);
+                                self.write_md(&header_buffer, width);
                                 header_buffer.clear();
                                 in_header = false;
                             }
@@ -280,84 +1243,90 @@ impl MinimalStreamer {
                         list_types.pop();
                         item_numbers.pop();
                         if list_depth == 0 && in_list {
-                            let _ = self.mad_skin.print_text(&list_buffer);
+                            self.write_list(&list_buffer, width);
                             list_buffer.clear();
                             in_list = false;
                         } else if list_depth > 0 {
                             list_indent_level = list_depth - 1;
                         }
                     }
-                    Event::Start(Tag::Item) => {
-                        if in_list {
-                            let indent_len = 2 * list_indent_level;
-                            let indent = " ".repeat(indent_len.min(3));
-                            list_buffer.push_str(&indent);
-                            let level = list_depth - 1;
-                            let item_num = item_numbers[level];
-                            item_numbers[level] = item_num + 1;
-                            if let Some(start) = list_types[level] {
-                                // ordered list
-                                list_buffer.push_str(&format!("{}. ", start + item_num as u64));
-                            } else {
-                                // unordered list
-                                list_buffer.push_str("- ");
-                            }
+                    Event::Start(Tag::Item) if in_list => {
+                        let indent_len = 2 * list_indent_level;
+                        let indent = " ".repeat(indent_len.min(3));
+                        list_buffer.push_str(&indent);
+                        let level = list_depth - 1;
+                        let item_num = item_numbers[level];
+                        item_numbers[level] = item_num + 1;
+                        if let Some(start) = list_types[level] {
+                            // ordered list
+                            list_buffer.push_str(&format!("{}. ", start + item_num as u64));
+                        } else {
+                            // unordered list
+                            list_buffer.push_str("- ");
                         }
                     }
-                    Event::End(TagEnd::Item) => {
-                        if in_list {
-                            list_buffer.push('\n');
-                        }
+                    Event::End(TagEnd::Item) if in_list => {
+                        list_buffer.push('\n');
                     }
                     Event::Start(Tag::CodeBlock(kind)) => {
                         // Flush any pending content before code block
                         if in_header && !header_buffer.is_empty() {
-                            let _ = self.mad_skin.print_text(&header_buffer);
+                            self.write_md(&header_buffer, width);
                             header_buffer.clear();
                             in_header = false;
                         }
                         if in_list && !list_buffer.is_empty() {
-                            let _ = self.mad_skin.print_text(&list_buffer);
+                            self.write_list(&list_buffer, width);
                             list_buffer.clear();
                             in_list = false;
                         }
                         in_code_block = true;
                         code_block_buffer.clear();
-                        // Add opening backticks and language
-                        code_block_buffer.push_str("```");
-                        match kind {
-                            CodeBlockKind::Fenced(lang) => {
-                                if !lang.is_empty() {
-                                    code_block_buffer.push_str(&lang);
+                        code_lang = match kind {
+                            CodeBlockKind::Fenced(lang) => Some(lang.to_string()),
+                            CodeBlockKind::Indented => None,
+                        };
+                    }
+                    Event::End(TagEnd::CodeBlock) => {
+                        let is_json = code_lang.as_deref().is_some_and(|l| l.split([',', ' ']).next().unwrap_or_default().eq_ignore_ascii_case("json"));
+                        if is_json && self.config.pretty_json {
+                            if let Some(pretty) = json::pretty(&code_block_buffer) {
+                                code_block_buffer = pretty;
+                            }
+                        }
+                        let is_mermaid = code_lang.as_deref().is_some_and(|l| l.eq_ignore_ascii_case("mermaid"));
+                        let inner = width.saturating_sub(if self.config.code_frame { 4 } else { 2 });
+                        match mermaid::render(&code_block_buffer, inner).filter(|_| is_mermaid) {
+                            Some(diagram) => {
+                                self.write_code_block(&diagram, code_lang.as_deref(), width);
+                                // Copy and extract the source rather than the drawing
+                                if let Some(block) = self.code_blocks.last_mut() {
+                                    block.code = code_block_buffer.clone();
                                 }
                             }
-                            _ => {}
+                            None => self.write_code_block(&code_block_buffer, code_lang.as_deref(), width),
                         }
-                        code_block_buffer.push('\n');
-                    }
-                    Event::End(TagEnd::CodeBlock) => {
-                        code_block_buffer.push_str("\n```");
-                        let _ = self.mad_skin.print_text(&code_block_buffer);
                         code_block_buffer.clear();
                         in_code_block = false;
                     }
                     Event::Start(Tag::Emphasis) => {
                         if in_header {
-                            header_buffer.push_str("*");
+                            header_buffer.push('*');
                         } else if in_list {
-                            list_buffer.push_str("*");
+                            list_buffer.push('*');
                         } else {
-                            let _ = stdout.queue(SetAttribute(Attribute::Italic));
-                            let _ = stdout.queue(SetForegroundColor(self.theme.get_color("italic")));
+                            self.theme.apply_style(&mut self.out, "italic", &[Attribute::Italic]);
+                            self.plain_marker("*");
                         }
                     }
                     Event::End(TagEnd::Emphasis) => {
                         if in_header {
-                            header_buffer.push_str("*");
+                            header_buffer.push('*');
                         } else if in_list {
-                            list_buffer.push_str("*");
+                            list_buffer.push('*');
                         } else {
-                            let _ = stdout.queue(ResetColor);
+                            self.plain_marker("*");
+                            self.restore_text_style(quote_depth);
                         }
                     }
                     Event::Start(Tag::Strong) => {
@@ -366,8 +1335,8 @@ impl MinimalStreamer {
                         } else if in_list {
                             list_buffer.push_str("**");
                         } else {
-                            let _ = stdout.queue(SetAttribute(Attribute::Bold));
-                            let _ = stdout.queue(SetForegroundColor(self.theme.get_color("bold")));
+                            self.theme.apply_style(&mut self.out, "bold", &[Attribute::Bold]);
+                            self.plain_marker("**");
                         }
                     }
                     Event::End(TagEnd::Strong) => {
@@ -376,79 +1345,295 @@ impl MinimalStreamer {
                         } else if in_list {
                             list_buffer.push_str("**");
                         } else {
-                            let _ = stdout.queue(ResetColor);
+                            self.plain_marker("**");
+                            self.restore_text_style(quote_depth);
                         }
                     }
-                    Event::Text(text) => {
+                    Event::Start(Tag::Strikethrough) => {
                         if in_header {
-                            header_buffer.push_str(&text);
+                            header_buffer.push_str("~~");
                         } else if in_list {
-                            list_buffer.push_str(&text);
-                        } else if in_code_block {
-                            code_block_buffer.push_str(&text);
+                            list_buffer.push_str("~~");
                         } else {
-                            let _ = stdout.queue(Print(text));
+                            self.theme.apply_style(&mut self.out, "strikethrough", &[Attribute::CrossedOut]);
+                            self.plain_marker("~~");
                         }
                     }
-                    Event::SoftBreak => {
-                        if in_list {
-                            // For lists, soft breaks should create new lines
-                            list_buffer.push('\n');
+                    Event::End(TagEnd::Strikethrough) => {
+                        if in_header {
+                            header_buffer.push_str("~~");
+                        } else if in_list {
+                            list_buffer.push_str("~~");
                         } else {
-                            let _ = stdout.queue(Print("\n"));
+                            self.plain_marker("~~");
+                            self.restore_text_style(quote_depth);
                         }
                     }
-                    Event::HardBreak => {
-                        if in_list {
-                            list_buffer.push_str("\n\n");
-                        } else {
-                            let _ = stdout.queue(Print("\n\n"));
+                    Event::Start(Tag::Link { dest_url, .. }) if !in_header && !in_list => {
+                        self.theme.apply_style(&mut self.out, "link", &[Attribute::Underlined]);
+                        if self.config.hyperlinks {
+                            let _ = write!(self.out, "\x1b]8;;{}\x1b\\", dest_url);
                         }
+                        link_url = Some(dest_url.to_string());
+                        link_text.clear();
                     }
-                    Event::Rule => {
-                        // Flush any pending content before rule
-                        if in_header && !header_buffer.is_empty() {
-                            let _ = self.mad_skin.print_text(&header_buffer);
-                            header_buffer.clear();
-                            in_header = false;
+                    Event::End(TagEnd::Link) if !in_header && !in_list => {
+                        if self.config.hyperlinks {
+                            let _ = write!(self.out, "\x1b]8;;\x1b\\");
                         }
-                        if in_list && !list_buffer.is_empty() {
-                            let _ = self.mad_skin.print_text(&list_buffer);
-                            list_buffer.clear();
-                            in_list = false;
+                        self.restore_text_style(quote_depth);
+                        if let Some(suffix) = self.link_suffix(link_url.take(), &link_text) {
+                            let (wrapped, column) = wrap_text_indented(&suffix, self.column, width, def_indent);
+                            self.column = column;
+                            let _ = self.out.queue(Print(wrapped));
+                        }
+                    }
+                    // Headings and list items are rendered from their Markdown, so a link there is
+                    // marked in the text, to become a hyperlink once it's rendered
+                    Event::Start(Tag::Link { dest_url, .. }) => {
+                        let buffer = if in_header { &mut header_buffer } else { &mut list_buffer };
+                        if self.config.hyperlinks {
+                            buffer.push(LINK_START);
+                            self.buffered_links.push_back(dest_url.to_string());
+                        }
+                        link_url = Some(dest_url.to_string());
+                        link_text.clear();
+                    }
+                    Event::End(TagEnd::Link) => {
+                        let buffer = if in_header { &mut header_buffer } else { &mut list_buffer };
+                        if self.config.hyperlinks {
+                            buffer.push(LINK_END);
+                        }
+                        if let Some(suffix) = self.link_suffix(link_url.take(), &link_text) {
+                            if in_header {
+                                // Not part of the heading's name in the table of contents
+                                header_suffixes.push(header_buffer.len()..header_buffer.len() + suffix.len());
+                                header_buffer.push_str(&suffix);
+                            } else {
+                                list_buffer.push_str(&suffix);
+                            }
+                        }
+                    }
+                    Event::Start(Tag::Image { dest_url, .. }) => {
+                        image_url = Some(dest_url.to_string());
+                        image_alt.clear();
+                    }
+                    // A heading can't hold a drawn image, so it gets the placeholder
+                    Event::End(TagEnd::Image) if in_header => {
+                        if let Some(url) = image_url.take() {
+                            header_buffer.push_str(&image_placeholder(&image_alt, &url));
+                        }
+                    }
+                    Event::End(TagEnd::Image) => {
+                        if let Some(url) = image_url.take() {
+                            // The item so far goes first, and the rest of it follows the image
+                            if in_list && !list_buffer.is_empty() {
+                                self.write_list(&list_buffer, width);
+                                list_buffer.clear();
+                            }
+                            self.write_image(&url, &image_alt, width);
+                            after_display_math = true;
+                        }
+                    }
+                    Event::Text(text) if image_url.is_some() => image_alt.push_str(&text),
+                    Event::Code(code) => {
+                        if link_url.is_some() {
+                            link_text.push_str(&code);
+                        }
+                        if in_header {
+                            header_buffer.push_str(&format!("`{}`", code));
+                        } else if in_list {
+                            list_buffer.push_str(&format!("`{}`", code));
+                        } else {
+                            let _ = self.out.queue(SetBackgroundColor(self.theme.get_color("code")));
+                            self.plain_marker("`");
+                            let (wrapped, column) = wrap_text_indented(&code, self.column, width, def_indent);
+                            self.column = column;
+                            let _ = self.out.queue(Print(wrapped));
+                            self.plain_marker("`");
+                            let _ = self.out.queue(SetBackgroundColor(Color::Reset));
+                        }
+                    }
+                    Event::Text(text) => {
+                        if link_url.is_some() {
+                            link_text.push_str(&text);
+                        }
+                        if in_header {
+                            header_buffer.push_str(&text);
+                        } else if in_list {
+                            list_buffer.push_str(&text);
+                        } else if in_code_block {
+                            code_block_buffer.push_str(&text);
+                        } else {
+                            let (wrapped, column) = wrap_text_indented(&text, self.column, width, def_indent);
+                            self.column = column;
+                            let _ = self.out.queue(Print(wrapped));
+                        }
+                    }
+                    Event::DisplayMath(latex) if !in_header => {
+                        if in_list && !list_buffer.is_empty() {
+                            self.write_list(&list_buffer, width);
+                            list_buffer.clear();
+                        }
+                        self.write_display_math(&latex, width, quote_depth);
+                        after_display_math = true;
+                    }
+                    Event::DisplayMath(latex) | Event::InlineMath(latex) => {
+                        let text = math::to_unicode(&latex);
+                        if in_header {
+                            header_buffer.push_str(&text);
+                        } else if in_list {
+                            list_buffer.push_str(&text);
+                        } else {
+                            self.theme.apply_style(&mut self.out, "math", &[]);
+                            let (wrapped, column) = wrap_text(&text, self.column, width);
+                            self.column = column;
+                            let _ = self.out.queue(Print(wrapped));
+                            self.restore_text_style(quote_depth);
                         }
-                        if let Ok((width, _)) = size() {
-                            let rule = "─".repeat(width as usize);
-                            let _ = stdout.queue(Print(format!("\n{}\n", rule)));
+                    }
+                    // Display math already ended the line
+                    Event::SoftBreak if follows_display_math => {}
+                    Event::SoftBreak => {
+                        if in_list {
+                            // For lists, soft breaks should create new lines
+                            list_buffer.push('\n');
                         } else {
-                            let _ = stdout.queue(Print("\n─────────────────────────────────────────────────────────────────────────────────────────────────────\n"));
+                            // Soft breaks reflow into spaces; wrapping decides where lines end
+                            let (wrapped, column) = wrap_text_indented(" ", self.column, width, def_indent);
+                            self.column = column;
+                            let _ = self.out.queue(Print(wrapped));
                         }
                     }
+                    Event::HardBreak => {
+                        if in_list {
+                            list_buffer.push_str("\n\n");
+                        } else {
+                            let _ = self.out.queue(Print("\n\n"));
+                            self.column = 0;
+                        }
+                    }
+                    Event::Rule => {
+                        // Flush any pending content before rule
+                        if in_header && !header_buffer.is_empty() {
+                            self.write_md(&header_buffer, width);
+                            header_buffer.clear();
+                            in_header = false;
+                        }
+                        if in_list && !list_buffer.is_empty() {
+                            self.write_list(&list_buffer, width);
+                            list_buffer.clear();
+                            in_list = false;
+                        }
+                        let rule = "─".repeat(width);
+                        let _ = self.out.queue(Print("\n"));
+                        self.theme.apply_style(&mut self.out, "rule", &[]);
+                        let _ = self.out.queue(Print(rule));
+                        let _ = self.out.queue(SetAttribute(Attribute::Reset));
+                        let _ = self.out.queue(Print("\n"));
+                        self.column = 0;
+                    }
+                    Event::Start(Tag::BlockQuote(Some(kind))) if !in_list => {
+                        // GitHub alert: a banner with an icon and the type, then the body
+                        let (icon, title, name) = match kind {
+                            BlockQuoteKind::Note => ("ℹ", "Note", "note"),
+                            BlockQuoteKind::Tip => ("✦", "Tip", "tip"),
+                            BlockQuoteKind::Important => ("❢", "Important", "important"),
+                            BlockQuoteKind::Warning => ("⚠", "Warning", "warning"),
+                            BlockQuoteKind::Caution => ("✖", "Caution", "caution"),
+                        };
+                        let style = self.theme.alert_style(name).clone();
+                        let color = Theme::parse_color(&style.color);
+                        if self.column > 0 {
+                            let _ = self.out.queue(Print("\n"));
+                        }
+                        let _ = self.out.queue(SetForegroundColor(color));
+                        let _ = self.out.queue(Print(self.quote_bar()));
+                        for attr in style.attributes(&[Attribute::Bold]) {
+                            let _ = self.out.queue(SetAttribute(attr));
+                        }
+                        let _ = self.out.queue(Print(format!("{} {}", icon, title)));
+                        let _ = self.out.queue(SetAttribute(Attribute::Reset));
+                        let _ = self.out.queue(SetForegroundColor(color));
+                        let _ = self.out.queue(Print(format!("\n{}", self.quote_bar())));
+                        self.theme.apply_style(&mut self.out, "text", &[]);
+                        self.column = 2;
+                        quote_depth += 1;
+                    }
                     Event::Start(Tag::BlockQuote(_)) => {
                         if in_list {
                             list_buffer.push_str("> ");
                         } else {
-                            let _ = stdout.queue(SetForegroundColor(self.theme.get_color("italic")));
-                            let _ = stdout.queue(Print("│ "));
+                            self.theme.apply_style(&mut self.out, "blockquote", &[]);
+                            let _ = self.out.queue(Print(self.quote_bar()));
+                            let _ = self.out.queue(SetAttribute(Attribute::Reset));
+                            let _ = self.out.queue(SetForegroundColor(self.theme.get_color("italic")));
+                            self.column += 2;
+                            quote_depth += 1;
                         }
                     }
                     Event::End(TagEnd::BlockQuote(_)) => {
                         if in_list {
                             list_buffer.push('\n');
                         } else {
-                            let _ = stdout.queue(ResetColor);
-                            let _ = stdout.queue(Print("\n"));
+                            let _ = self.out.queue(ResetColor);
+                            let _ = self.out.queue(Print("\n"));
+                            self.column = 0;
+                            quote_depth = quote_depth.saturating_sub(1);
+                        }
+                    }
+                    Event::Start(Tag::DefinitionListTitle) => {
+                        if self.column > 0 {
+                            let _ = self.out.queue(Print("\n"));
+                            self.column = 0;
+                        }
+                        self.theme.apply_style(&mut self.out, "bold", &[Attribute::Bold]);
+                    }
+                    Event::End(TagEnd::DefinitionListTitle) => {
+                        let _ = self.out.queue(SetAttribute(Attribute::Reset));
+                        let _ = self.out.queue(Print("\n"));
+                        self.column = 0;
+                    }
+                    Event::Start(Tag::DefinitionListDefinition) => {
+                        def_indent = 4;
+                        self.theme.apply_style(&mut self.out, "text", &[]);
+                        let _ = self.out.queue(Print(" ".repeat(def_indent)));
+                        self.column = def_indent;
+                    }
+                    Event::End(TagEnd::DefinitionListDefinition) => {
+                        if self.column > 0 {
+                            let _ = self.out.queue(Print("\n"));
                         }
+                        self.column = 0;
+                        def_indent = 0;
+                    }
+                    Event::End(TagEnd::DefinitionList) => {
+                        let _ = self.out.queue(SetAttribute(Attribute::Reset));
+                        let _ = self.out.queue(Print("\n"));
+                        self.column = 0;
                     }
                     Event::Start(Tag::Paragraph) => {
                         in_paragraph = true;
+                        if def_indent > 0 && self.column == 0 {
+                            // Later paragraphs of a definition
+                            let _ = self.out.queue(Print(" ".repeat(def_indent)));
+                            self.column = def_indent;
+                        }
+                        if !in_list && quote_depth == 0 {
+                            self.theme.apply_style(&mut self.out, "text", &[]);
+                        }
                     }
                     Event::End(TagEnd::Paragraph) => {
                         if in_list {
                             // In lists, paragraphs are handled differently
                         } else if in_paragraph {
                             // Only add paragraph spacing if we actually had paragraph content
-                            let _ = stdout.queue(Print("\n\n"));
+                            if quote_depth == 0 {
+                                let _ = self.out.queue(SetAttribute(Attribute::Reset));
+                            }
+                            // Lines of a log are kept together; its own blank lines separate them
+                            let _ = self.out.queue(Print(if follows_display_math || self.config.line_buffered { "\n" } else { "\n\n" }));
+                            self.column = 0;
                         }
                         in_paragraph = false;
                     }
@@ -456,12 +1641,624 @@ impl MinimalStreamer {
                 }
             }
         }
-        let _ = stdout.flush();
+        self.pending_refs.extend(missing_refs);
+        self.emit(margin);
+    }
+
+    /// Compute the rendered column width and left margin for the current terminal size
+    fn layout(&self) -> (usize, usize) {
+        let term_width = size().map(|(w, _)| w as usize).unwrap_or(80);
+        let available = term_width.saturating_sub(self.config.margin * 2).max(20);
+        let width = self.config.width.map_or(available, |w| w.min(available));
+        let margin = if self.config.center {
+            term_width.saturating_sub(width) / 2
+        } else {
+            self.config.margin
+        };
+        (width, margin)
     }
 
-    fn strip_ansi(&self, text: &str) -> String {
-        let ansi_re = Regex::new(r"\x1B\[[0-?]*[ -/]*[@-~]").unwrap();
-        ansi_re.replace_all(text, "").to_string()
+    /// Render Markdown through termimad at the given width into the output buffer
+    fn write_md(&mut self, md: &str, width: usize) {
+        let rendered = self.mad_skin.text(md, Some(width)).to_string();
+        let rendered = self.link_escapes(&rendered);
+        let _ = write!(self.out, "{}", rendered);
+    }
+
+    /// What follows a link's text to say where it goes, for the link style: a footnote
+    /// number, or the URL unless a hyperlink already carries it. Autolinks, whose text is
+    /// the URL, get nothing.
+    fn link_suffix(&mut self, url: Option<String>, text: &str) -> Option<String> {
+        let url = url.filter(|u| !u.is_empty() && !text.contains(u.trim_start_matches("mailto:")));
+        match (url, self.config.link_style) {
+            (Some(url), LinkStyle::Footnote) => {
+                let n = match self.link_footnotes.iter().position(|u| *u == url) {
+                    Some(i) => i + 1,
+                    None => {
+                        self.link_footnotes.push(url);
+                        self.link_footnotes.len()
+                    }
+                };
+                Some(format!("[{}]", n))
+            }
+            (Some(url), LinkStyle::Inline) if !self.config.hyperlinks => Some(format!(" ({})", url)),
+            _ => None,
+        }
+    }
+
+    /// Swap the link markers in rendered heading or list text for underlined OSC 8 hyperlinks
+    fn link_escapes(&mut self, rendered: &str) -> String {
+        if !rendered.contains([LINK_START, LINK_END]) {
+            return rendered.to_string();
+        }
+        let mut out = String::with_capacity(rendered.len());
+        for c in rendered.chars() {
+            match c {
+                LINK_START => {
+                    let url = self.buffered_links.pop_front().unwrap_or_default();
+                    out.push_str(&format!("\x1b]8;;{}\x1b\\\x1b[4m", url));
+                }
+                // Underline off rather than a reset, so the text keeps its color
+                LINK_END => out.push_str("\x1b[24m\x1b]8;;\x1b\\"),
+                c => out.push(c),
+            }
+        }
+        out
+    }
+
+    /// Draw an image on its own lines through the terminal's graphics protocol, or show a
+    /// `🖼 [alt] (url)` placeholder when it can't be drawn
+    fn write_image(&mut self, url: &str, alt: &str, width: usize) {
+        if self.column > 0 {
+            let _ = self.out.queue(Print("\n"));
+            self.column = 0;
+        }
+        let columns = self.config.image_width.unwrap_or(width).min(width);
+        let drawn = self.config.images.and_then(|protocol| {
+            let data = image::load(url, self.base_dir.as_deref(), self.config.images_remote)
+                .map_err(|e| eprintln!("Warning: Failed to load image {}: {}", url, e))
+                .ok()?;
+            protocol.escape(&data, columns)
+        });
+        match drawn {
+            Some(escape) => {
+                let _ = self.out.queue(Print(escape));
+            }
+            None => {
+                self.theme.apply_style(&mut self.out, "image", &[]);
+                let (wrapped, _) = wrap_text(&image_placeholder(alt, url), 0, width);
+                let _ = self.out.queue(Print(wrapped));
+                let _ = self.out.queue(SetAttribute(Attribute::Reset));
+            }
+        }
+        let _ = self.out.queue(Print("\n"));
+        self.theme.apply_style(&mut self.out, "text", &[]);
+    }
+
+    /// Render display math on its own lines, laid out over several rows when it has fractions,
+    /// limits, or matrices, and indented like a block quote. Falls back to a single wrapped
+    /// line when the layout is too wide.
+    fn write_display_math(&mut self, latex: &str, width: usize, quote_depth: usize) {
+        if self.column > 0 {
+            let _ = self.out.queue(Print("\n"));
+            self.column = 0;
+        }
+        let indent = 2;
+        let lines = math::layout(latex);
+        let lines = if lines.iter().all(|l| l.width() + indent <= width) {
+            lines
+        } else {
+            let (wrapped, _) = wrap_text(&math::to_unicode(latex), 0, width.saturating_sub(indent));
+            wrapped.lines().map(str::to_string).collect()
+        };
+        for line in lines {
+            self.theme.apply_style(&mut self.out, "math", &[]);
+            let _ = self.out.queue(Print(format!("{}{}", " ".repeat(indent), line)));
+            let _ = self.out.queue(SetAttribute(Attribute::Reset));
+            let _ = self.out.queue(Print("\n"));
+        }
+        self.restore_text_style(quote_depth);
+    }
+
+    /// Render a code block on a background filled to the full width, with one space of
+    /// padding either side. Lines too long for the width are wrapped. With `code-frame`, the
+    /// block is boxed in the table border color, with the language in the top border.
+    fn write_code_block(&mut self, code: &str, fence_lang: Option<&str>, width: usize) {
+        // Language info strings can carry attributes (```rust,ignore); use the name only
+        let lang = fence_lang.unwrap_or_default().split([',', ' ']).next().unwrap_or_default();
+        // Per-language theme overrides win over the code theme, which wins over the theme
+        let code_theme = self.config.code_theme.as_ref();
+        let lang_style = self.theme.code_style(lang).cloned().unwrap_or_default();
+        let bg = match lang_style.background.as_deref().or(code_theme.and_then(|t| t.background.as_deref())) {
+            Some(bg) => Theme::parse_color(bg),
+            None => self.theme.get_color("code"),
+        };
+        let fg = match lang_style.color.as_deref().or(code_theme.and_then(|t| t.foreground.as_deref())) {
+            Some(fg) => Theme::parse_color(fg),
+            None => self.theme.get_color("text"),
+        };
+        let attributes = lang_style.attributes();
+        // Diffs get added/removed lines colored, whether tagged or recognized from their headers
+        let diff = lang.eq_ignore_ascii_case("diff")
+            || lang.eq_ignore_ascii_case("patch")
+            || (lang.is_empty() && is_diff(code));
+        let frame = self.config.code_frame;
+        let border = self.theme.get_color("table_border");
+        let inner = width.saturating_sub(if frame { 4 } else { 2 }).max(1);
+
+        if frame {
+            // ┌─ rust ──────┐, or a plain top border without a language
+            let label = if lang.is_empty() { String::new() } else { format!(" {} ", lang) };
+            let rule = "─".repeat((inner + 1).saturating_sub(label.width()));
+            let _ = self.out.queue(SetForegroundColor(border));
+            let _ = self.out.queue(Print(format!("┌─{}{}┐", label, rule)));
+            let _ = self.out.queue(ResetColor);
+            let _ = self.out.queue(Print("\n"));
+        }
+        // Blocks are numbered for --copy-code, in the corner of their first line
+        let mut number = Some(format!("[{}]", self.code_blocks.len() + 1)).filter(|_| !self.config.plain);
+        for line in code.trim_end_matches('\n').split('\n') {
+            // File and hunk headers are bold; +/- lines take the theme's diff colors
+            let diff_color = |field| self.theme.get_style(field).map_or(fg, |s| Theme::parse_color(&s.color));
+            let (line_fg, line_bold) = if !diff {
+                (fg, false)
+            } else if ["+++", "---", "@@", "diff ", "index "].iter().any(|p| line.starts_with(p)) {
+                (fg, true)
+            } else if line.starts_with('+') {
+                (diff_color("diff_added"), false)
+            } else if line.starts_with('-') {
+                (diff_color("diff_removed"), false)
+            } else {
+                (fg, false)
+            };
+            let mut rest = line.replace('\t', "    ");
+            loop {
+                let split = if rest.width() > inner { split_at_width(&rest, inner) } else { rest.len() };
+                let piece: String = rest.drain(..split).collect();
+                let fill = inner.saturating_sub(piece.width());
+                let number = number.take().filter(|n| fill > n.len());
+                let fill = fill - number.as_ref().map_or(0, String::len);
+                if frame {
+                    let _ = self.out.queue(SetForegroundColor(border));
+                    let _ = self.out.queue(Print("│"));
+                }
+                let _ = self.out.queue(SetBackgroundColor(bg));
+                let _ = self.out.queue(SetForegroundColor(line_fg));
+                for &attr in &attributes {
+                    let _ = self.out.queue(SetAttribute(attr));
+                }
+                if line_bold {
+                    let _ = self.out.queue(SetAttribute(Attribute::Bold));
+                }
+                let _ = self.out.queue(Print(format!(" {}{}", piece, " ".repeat(fill))));
+                if let Some(number) = number {
+                    let _ = self.out.queue(SetForegroundColor(border));
+                    let _ = self.out.queue(Print(number));
+                    let _ = self.out.queue(SetForegroundColor(fg));
+                }
+                let _ = self.out.queue(Print(" "));
+                let _ = self.out.queue(SetAttribute(Attribute::Reset));
+                if frame {
+                    let _ = self.out.queue(SetForegroundColor(border));
+                    let _ = self.out.queue(Print("│"));
+                    let _ = self.out.queue(ResetColor);
+                }
+                let _ = self.out.queue(Print("\n"));
+                if rest.is_empty() {
+                    break;
+                }
+            }
+        }
+        if frame {
+            let _ = self.out.queue(SetForegroundColor(border));
+            let _ = self.out.queue(Print(format!("└{}┘", "─".repeat(inner + 2))));
+            let _ = self.out.queue(ResetColor);
+            let _ = self.out.queue(Print("\n"));
+        }
+        let _ = self.out.queue(Print("\n"));
+        self.column = 0;
+        self.code_blocks.push(CodeBlock {
+            lang: fence_lang.map(str::to_string),
+            heading: self.last_heading.clone(),
+            code: code.to_string(),
+        });
+    }
+
+    /// Wrap up after the input ends: write code blocks out for --extract-code, copy the
+    /// requested code block or, for --copy, the whole output to the clipboard, or point out
+    /// that blocks can be copied when some were shown on a terminal
+    pub fn finish_stream(&mut self, copy_code: Option<usize>, extract_dir: Option<&Path>) -> Result<()> {
+        if let (Some(section), Some(name)) = (&self.section, &self.config.section) {
+            if !section.found() {
+                return Err(LivemdError::Input(format!("no heading matching \"{}\"", name)));
+            }
+        }
+        match self.config.output {
+            OutputFormat::Html => self.write_document(page::FOOTER, None),
+            OutputFormat::Man => self.write_document("", None),
+            _ => {}
+        }
+        if self.config.toc && !self.toc_written && !self.headings.is_empty() {
+            let headings = std::mem::take(&mut self.headings);
+            let _ = self.out.queue(Print("\n"));
+            self.write_toc(&headings);
+        }
+        if !self.link_footnotes.is_empty() {
+            for (i, url) in std::mem::take(&mut self.link_footnotes).into_iter().enumerate() {
+                let _ = self.out.queue(Print(format!("[{}] ", i + 1)));
+                self.theme.apply_style(&mut self.out, "link", &[Attribute::Underlined]);
+                let _ = self.out.queue(Print(url));
+                let _ = self.out.queue(SetAttribute(Attribute::Reset));
+                let _ = self.out.queue(Print("\n"));
+            }
+            let (_, margin) = self.layout();
+            self.emit(margin);
+        }
+        // Links whose definitions only arrived after they were shown: list the targets
+        let mut late_refs: Vec<(String, String)> = Vec::new();
+        for label in std::mem::take(&mut self.pending_refs) {
+            if let Some(url) = self.link_refs.get(&label.to_lowercase()) {
+                if !late_refs.iter().any(|(l, _)| l.eq_ignore_ascii_case(&label)) {
+                    late_refs.push((label, url.clone()));
+                }
+            }
+        }
+        if !late_refs.is_empty() {
+            for (label, url) in late_refs {
+                let _ = self.out.queue(Print(format!("[{}]: ", label)));
+                self.theme.apply_style(&mut self.out, "link", &[Attribute::Underlined]);
+                let _ = self.out.queue(Print(url));
+                let _ = self.out.queue(SetAttribute(Attribute::Reset));
+                let _ = self.out.queue(Print("\n"));
+            }
+            let (_, margin) = self.layout();
+            self.emit(margin);
+        }
+        if let Some(dir) = extract_dir {
+            let written = extract::write_blocks(&self.code_blocks, dir)?;
+            eprintln!("Wrote {} code block{} to {}", written.len(), if written.len() == 1 { "" } else { "s" }, dir.display());
+            for path in written {
+                eprintln!("  {}", path.display());
+            }
+        }
+        if let Some(n) = copy_code {
+            let code = n
+                .checked_sub(1)
+                .and_then(|i| self.code_blocks.get(i))
+                .map(|block| &block.code)
+                .ok_or_else(|| LivemdError::Input(format!("no code block {} (the output had {})", n, self.code_blocks.len())))?;
+            // Without the final newline, so pasting into a shell doesn't run it straight away
+            clipboard::copy(code.trim_end_matches('\n'))?;
+            eprintln!("Copied code block {} to the clipboard", n);
+        } else if let Some(source) = self.source.take().filter(|source| !source.iter().all(u8::is_ascii_whitespace)) {
+            let source = String::from_utf8_lossy(&source);
+            let source = source.trim_end();
+            clipboard::copy(source)?;
+            let lines = source.lines().count();
+            eprintln!("Copied the response to the clipboard ({} line{})", lines, if lines == 1 { "" } else { "s" });
+        } else if !self.code_blocks.is_empty() && extract_dir.is_none() && atty::is(atty::Stream::Stdout) {
+            let count = self.code_blocks.len();
+            let _ = self.out.queue(SetForegroundColor(self.theme.get_color("table_border")));
+            let _ = self.out.queue(Print(format!(
+                "{} code block{} · rerun with --copy-code N to copy one\n",
+                count,
+                if count == 1 { "" } else { "s" }
+            )));
+            let _ = self.out.queue(ResetColor);
+            let (_, margin) = self.layout();
+            self.emit(margin);
+        }
+        Ok(())
+    }
+
+    /// Show newly rendered output in the pager, opening it on the first output, and handle
+    /// any keys pressed since the last flush
+    fn update_pager(&mut self) {
+        if self.pager_view.is_none() {
+            match Pager::open() {
+                Ok(view) => self.pager_view = Some(view),
+                Err(e) => {
+                    eprintln!("Warning: Failed to open the pager: {}", e);
+                    self.config.pager = false;
+                    return;
+                }
+            }
+        }
+        let lines = pager::split_lines(&String::from_utf8_lossy(self.captured.as_deref().unwrap_or_default()));
+        let Some(view) = self.pager_view.as_mut() else {
+            return;
+        };
+        if view.update(lines, &self.heading_lines).is_err() || view.poll().unwrap_or(true) {
+            // Quitting mid-stream ends the program, like quitting less
+            self.pager_view = None;
+            std::process::exit(0);
+        }
+    }
+
+    /// Once the output is complete, let the reader scroll through it in the pager. Output
+    /// that fits on the screen is printed as usual instead.
+    pub fn page(&mut self) -> std::io::Result<()> {
+        let Some(captured) = self.captured.take() else {
+            return Ok(());
+        };
+        let lines = pager::split_lines(&String::from_utf8_lossy(&captured));
+        let rows = size().map(|(_, rows)| rows as usize).unwrap_or(24);
+        match self.pager_view.take() {
+            Some(mut view) if lines.len() >= rows => {
+                view.update(lines, &self.heading_lines)?;
+                view.run()
+            }
+            view => {
+                drop(view);
+                let mut stdout = stdout();
+                stdout.write_all(&captured)?;
+                stdout.flush()
+            }
+        }
+    }
+
+    /// Present the document as slides on the alternate screen. Slides are centered in a
+    /// column 80 wide unless `--width` says otherwise.
+    pub fn present(&mut self, text: &str) -> std::io::Result<()> {
+        self.config.center = true;
+        self.config.width.get_or_insert(80);
+        self.frontmatter_checked = true;
+        let slides = slides::split(text);
+        slides::present(&slides, |slide| self.render_lines(slide))
+    }
+
+    /// Render markdown on its own into screen lines, for the full-screen views
+    fn render_lines(&mut self, markdown: &str) -> Vec<String> {
+        let previous = self.captured.replace(Vec::new());
+        // Views re-render as they go; only count each code block once
+        let code_blocks = self.code_blocks.len();
+        self.column = 0;
+        self.at_line_start = true;
+        self.render_flush(markdown);
+        let (_, margin) = self.layout();
+        self.close_table(margin);
+        self.code_blocks.truncate(code_blocks);
+        let captured = std::mem::replace(&mut self.captured, previous).unwrap_or_default();
+        pager::split_lines(&String::from_utf8_lossy(&captured))
+    }
+
+    /// Offer to run the shell and Python code blocks from the output, one at a time, after
+    /// confirmation. Each block's output is streamed back through the renderer.
+    pub async fn run_code_blocks(&mut self) -> Result<()> {
+        let runnable: Vec<usize> = (0..self.code_blocks.len())
+            .filter(|&i| runner::command_for(&self.code_blocks[i]).is_some())
+            .collect();
+        if runnable.is_empty() {
+            eprintln!("No shell or Python code blocks to run");
+            return Ok(());
+        }
+
+        loop {
+            let _ = stdout().flush();
+            eprintln!();
+            for &i in &runnable {
+                let block = &self.code_blocks[i];
+                let first_line = block.code.lines().find(|l| !l.trim().is_empty()).unwrap_or_default();
+                eprintln!("  [{}] {:<7} {}", i + 1, block.language().unwrap_or_default(), first_line.trim());
+            }
+            let Some(answer) = runner::prompt("Run which block? (number, or q to quit) ")
+                .map_err(|e| LivemdError::Input(format!("--run-code needs a terminal: {}", e)))?
+            else {
+                return Ok(());
+            };
+            if answer.is_empty() || answer.eq_ignore_ascii_case("q") {
+                return Ok(());
+            }
+            let Some(i) = answer.parse::<usize>().ok().and_then(|n| n.checked_sub(1)).filter(|i| runnable.contains(i)) else {
+                eprintln!("No runnable block {}", answer);
+                continue;
+            };
+
+            let block = self.code_blocks[i].clone();
+            let lang = block.language().unwrap_or_default();
+            eprintln!();
+            for line in block.code.lines() {
+                eprintln!("  {}", line);
+            }
+            let confirm = runner::prompt(&format!("Run this {} block? [y/N] ", lang))?.unwrap_or_default();
+            if !confirm.eq_ignore_ascii_case("y") && !confirm.eq_ignore_ascii_case("yes") {
+                continue;
+            }
+
+            let _ = self.out.queue(SetForegroundColor(self.theme.get_color("table_border")));
+            let _ = self.out.queue(Print(format!("── output of [{}] ──\n", i + 1)));
+            let _ = self.out.queue(ResetColor);
+            let (_, margin) = self.layout();
+            self.emit(margin);
+            let _ = stdout().flush();
+
+            let mut command = runner::command_for(&block).expect("block was filtered as runnable");
+            command.stdin(Stdio::null());
+            if let Err(e) = self.stream_process(&mut command).await {
+                eprintln!("Error: Failed to run block {}: {}", i + 1, e);
+            }
+        }
+    }
+
+    /// Render a buffered list with hanging indents, so wrapped lines align under the item text
+    fn write_list(&mut self, list_md: &str, width: usize) {
+        // A list split around an image or display math carries on under the item it was in
+        let mut hang = self.list_hang;
+        for line in list_md.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (indent, marker, body) = match LIST_ITEM_RE.captures(line) {
+                Some(caps) => (caps[1].to_string(), caps[2].to_string(), caps[3].to_string()),
+                // Continuation lines hang under the previous item's text
+                None => (" ".repeat(hang), String::new(), line.trim_start().to_string()),
+            };
+            hang = indent.width() + marker.width();
+
+            let body_width = width.saturating_sub(hang).max(10);
+            let rendered = self.mad_skin.text(&body, Some(body_width)).to_string();
+            let rendered = self.link_escapes(&rendered);
+            // An item whose text is all after an image still shows its marker
+            let rendered = if rendered.trim().is_empty() { "\n".to_string() } else { rendered };
+            for (i, body_line) in rendered.lines().enumerate() {
+                if i == 0 {
+                    let _ = self.out.queue(Print(&indent));
+                    self.theme.apply_style(&mut self.out, "list", &[]);
+                    let _ = self.out.queue(Print(&marker));
+                    let _ = self.out.queue(SetAttribute(Attribute::Reset));
+                } else {
+                    let _ = self.out.queue(Print(" ".repeat(hang)));
+                }
+                let _ = self.out.queue(Print(format!("{}\n", body_line)));
+            }
+        }
+        self.list_hang = hang;
+    }
+
+    /// Go back to the surrounding text style after a styled span: italic inside a block
+    /// quote, the theme's text color elsewhere
+    fn restore_text_style(&mut self, quote_depth: usize) {
+        let _ = self.out.queue(SetAttribute(Attribute::Reset));
+        if quote_depth > 0 {
+            let _ = self.out.queue(SetForegroundColor(self.theme.get_color("italic")));
+        } else {
+            self.theme.apply_style(&mut self.out, "text", &[]);
+        }
+    }
+
+    /// Print a Markdown-style marker around inline styles when color is off on a terminal.
+    /// Piped plain text leaves them out.
+    fn plain_marker(&mut self, marker: &str) {
+        if !self.config.color && !self.config.plain {
+            let (wrapped, column) = wrap_text(marker, self.column, self.layout().0);
+            self.column = column;
+            let _ = self.out.queue(Print(wrapped));
+        }
+    }
+
+    /// Write the rendered output to stdout, indenting each line by the left margin.
+    /// With color off, all styling escapes are removed first; otherwise colors are reduced
+    /// to what the terminal can show.
+    fn emit(&mut self, margin: usize) {
+        let started = Instant::now();
+        self.write_rendered(margin);
+        if let Some(bench) = self.bench.as_mut() {
+            bench.print += started.elapsed();
+        }
+    }
+
+    /// The work of [`Self::emit`], which times it for `--bench`
+    fn write_rendered(&mut self, margin: usize) {
+        self.clear_preview();
+        let mut rendered = std::mem::take(&mut self.out);
+        if !self.config.color {
+            rendered = strip_sgr(&rendered);
+            if self.config.plain {
+                rendered = to_plain(&String::from_utf8_lossy(&rendered)).into_bytes();
+            }
+        } else if self.config.color_depth != ColorDepth::TrueColor {
+            rendered = quantize_colors(&String::from_utf8_lossy(&rendered), self.config.color_depth).into_bytes();
+        }
+        let pad = " ".repeat(margin);
+        let mut written = Vec::with_capacity(rendered.len());
+        for line in rendered.split_inclusive(|&b| b == b'\n') {
+            if self.at_line_start && line != b"\n" {
+                written.extend_from_slice(pad.as_bytes());
+            }
+            written.extend_from_slice(line);
+            self.at_line_start = line.ends_with(b"\n");
+        }
+        if let Some(transcript) = self.transcript.as_mut() {
+            transcript.extend_from_slice(&written);
+        }
+        self.tee_output(&written, false);
+        match self.captured.as_mut() {
+            Some(captured) => {
+                captured.extend_from_slice(&written);
+                if self.config.pager {
+                    self.update_pager();
+                }
+            }
+            None => {
+                let mut stdout = stdout();
+                let delay = match self.config.granularity {
+                    Granularity::Char => std::time::Duration::from_secs_f64(self.config.speed),
+                    Granularity::Word => WORD_DELAY,
+                    Granularity::Sentence => SENTENCE_DELAY,
+                    Granularity::Block => {
+                        let _ = stdout.write_all(&written);
+                        let _ = stdout.flush();
+                        return;
+                    }
+                };
+                for piece in reveal_units(&written, self.config.granularity) {
+                    let _ = stdout.write_all(piece);
+                    let _ = stdout.flush();
+                    // Escapes and whitespace show nothing, so only pause after visible text. The
+                    // pause hands this worker's other tasks (the resize watcher, `--listen` and
+                    // `serve` connections) to the rest of the runtime rather than stalling them.
+                    if strip_sgr(piece).iter().any(|b| !b.is_ascii_whitespace()) {
+                        tokio::task::block_in_place(|| std::thread::sleep(delay));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Note that input has arrived, for pacing and `--stats`
+    fn arrived(&mut self) {
+        self.pacer.arrived();
+        if let Some(stats) = self.stats.as_mut() {
+            stats.arrived();
+        }
+    }
+
+    /// Sleep between flushed blocks, as long as the input's arrival rate calls for
+    async fn pace(&self) {
+        let delay = self.pacer.delay();
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+    }
+
+    fn strip_ansi(&mut self, text: &str) -> String {
+        let started = Instant::now();
+        let stripped = ANSI_RE.replace_all(text, "").to_string();
+        if let Some(bench) = self.bench.as_mut() {
+            bench.strip += started.elapsed();
+        }
+        stripped
+    }
+
+    /// Save everything rendered so far as an HTML transcript
+    pub fn export(&self, path: &Path) -> Result<()> {
+        let transcript = String::from_utf8_lossy(self.transcript.as_deref().unwrap_or_default());
+        let title = path.file_stem().map_or_else(|| "livemd".into(), |stem| stem.to_string_lossy());
+        std::fs::write(path, page::from_ansi(&transcript, &title))
+            .map_err(|source| LivemdError::Output { path: path.to_path_buf(), source })?;
+        Ok(())
+    }
+
+    /// The `--bench` summary, once the output has ended
+    pub fn bench_report(&self) -> Option<String> {
+        self.bench.as_ref().map(Bench::report)
+    }
+
+    /// Start timing `--stats` over, as each chat reply is sent for
+    pub fn restart_stats(&mut self) {
+        if let Some(stats) = self.stats.as_mut() {
+            *stats = Stats::new();
+        }
+    }
+
+    /// Print the `--stats` line on stderr, dim on a terminal
+    pub fn print_stats(&self) {
+        let Some(stats) = &self.stats else { return };
+        if atty::is(atty::Stream::Stderr) && self.config.color {
+            eprintln!("\x1b[2m{}\x1b[0m", stats.summary());
+        } else {
+            eprintln!("{}", stats.summary());
+        }
     }
 
     fn sanitize_boxes(&self, text: &str) -> String {
@@ -482,9 +2279,7 @@ impl MinimalStreamer {
 
                     // Extract inner text from middle line
                     let inner = b
-                        .replace('┃', "")
-                        .replace('│', "")
-                        .replace('|', "")
+                        .replace(['┃', '│', '|'], "")
                         .trim()
                         .to_string();
 
@@ -511,15 +2306,18 @@ impl MinimalStreamer {
     }
 
     /// Stream text content
-    pub async fn stream_text(&self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn stream_text(&mut self, text: &str) -> Result<()> {
         let mut pos = 0;
         let mut buffer = String::new();
         let step = 240; // Increased chunk size for better throughput
-        let text_bytes = text.as_bytes();
 
-        while pos < text_bytes.len() {
-            let end = std::cmp::min(pos + step, text_bytes.len());
-            let chunk = std::str::from_utf8(&text_bytes[pos..end]).unwrap_or("");
+        while pos < text.len() {
+            let mut end = std::cmp::min(pos + step, text.len());
+            // Don't cut a multibyte character in half
+            while !text.is_char_boundary(end) {
+                end += 1;
+            }
+            let chunk = &text[pos..end];
             pos = end;
 
             buffer.push_str(chunk);
@@ -530,44 +2328,62 @@ impl MinimalStreamer {
             }
 
             let mut flush_pos;
-            let mut chunks_processed = 0;
             while {
                 flush_pos = self.find_flush_boundary(&buffer);
                 flush_pos > 0
             } {
                 let to_print = buffer.drain(..flush_pos).collect::<String>();
                 self.print_styled_markdown(&to_print);
-                chunks_processed += 1;
-
-                // Only sleep after processing a few chunks to reduce latency
-                if chunks_processed % 5 == 0 {
-                    sleep(Duration::from_secs_f64(self.config.speed)).await;
-                }
+                self.pace().await;
             }
         }
 
-        if !buffer.trim().is_empty() {
-            self.print_styled_markdown(&buffer);
-        }
+        self.finish(&buffer);
         Ok(())
     }
 
     /// Stream content from a file
-    pub async fn stream_file(&self, path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn stream_file(&mut self, path: PathBuf) -> Result<()> {
+        self.base_dir = path.parent().map(Path::to_path_buf);
         let mut file = std::fs::File::open(path)?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
+        if self.config.toc {
+            let shown = match self.config.section.as_deref() {
+                Some(name) => {
+                    let mut section = SectionFilter::new(name);
+                    section.filter(&contents) + &section.finish()
+                }
+                None => contents.clone(),
+            };
+            self.write_toc(&toc::headings(&shown));
+        }
         self.stream_text(&contents).await?;
         Ok(())
     }
 
     /// Stream output from a command
-    pub async fn stream_command(&self, cmd: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let mut child = std::process::Command::new("sh")
-            .arg("-c")
-            .arg(cmd)
+    pub async fn stream_command(&mut self, cmd: &str) -> Result<()> {
+        let mut command = crate::shell::command(&self.config.shell, cmd);
+        if let Some(dir) = &self.config.cwd {
+            if !dir.is_dir() {
+                return Err(LivemdError::Input(format!("--cwd {}: not a directory", dir.display())));
+            }
+            command.current_dir(dir);
+            // Images the command's output refers to are relative to where it ran
+            self.base_dir = Some(dir.clone());
+        }
+        command.envs(self.config.env.iter().map(|(key, value)| (key, value)));
+        self.stream_process(&mut command).await
+    }
+
+    /// Stream the stdout of a process as Markdown
+    async fn stream_process(&mut self, command: &mut std::process::Command) -> Result<()> {
+        let mut child = command
             .stdout(Stdio::piped())
-            .spawn()?;
+            .spawn()
+            .map_err(|source| LivemdError::Spawn { command: command_line(command), source })?;
+        log::info!("spawned `{}` (pid {})", command_line(command), child.id());
 
         let stdout = child.stdout.take().expect("Failed to capture stdout.");
         let mut reader = BufReader::new(stdout);
@@ -578,6 +2394,7 @@ impl MinimalStreamer {
             match reader.read(&mut chunk) {
                 Ok(0) => break, // EOF
                 Ok(n) => {
+                    self.arrived();
                     let chunk_str = String::from_utf8_lossy(&chunk[..n]);
                     buffer.push_str(&chunk_str);
                     buffer = self.strip_ansi(&buffer);
@@ -587,66 +2404,134 @@ impl MinimalStreamer {
                     }
 
                     let mut flush_pos;
-                    let mut chunks_processed = 0;
                     while {
                         flush_pos = self.find_flush_boundary(&buffer);
                         flush_pos > 0
                     } {
                         let to_print = buffer.drain(..flush_pos).collect::<String>();
                         self.print_styled_markdown(&to_print);
-                        chunks_processed += 1;
-
-                        // Only sleep after processing a few chunks to reduce latency
-                        if chunks_processed % 3 == 0 {
-                            sleep(Duration::from_secs_f64(self.config.speed)).await;
-                        }
+                        self.pace().await;
                     }
+                    self.preview(&buffer);
                 }
                 Err(e) => return Err(e.into()),
             }
         }
 
-        if !buffer.trim().is_empty() {
-            self.print_styled_markdown(&buffer);
-        }
+        self.finish(&buffer);
+        let status = child.wait()?;
+        log::info!("`{}` exited with {}", command_line(command), status);
         Ok(())
     }
 
-    /// Stream output from an LLM query
-    pub async fn stream_query(&self, query: &str) -> Result<(), Box<dyn std::error::Error>> {
-        eprintln!("🤔 Thinking..."); // Simple progress indicator
+    /// The LLM command queries are sent to
+    pub fn llm_cmd(&self) -> Option<&str> {
+        self.config.llm_cmd.as_deref()
+    }
+
+    /// Answer later queries with another LLM command, priced with `pricing`
+    pub fn set_llm_cmd(&mut self, llm_cmd: String, pricing: Option<Pricing>) {
+        self.config.llm_cmd = Some(llm_cmd);
+        self.config.pricing = pricing;
+    }
+
+    /// Set the instructions sent ahead of every query, or remove them with None
+    pub fn set_system(&mut self, system: Option<String>) {
+        self.config.system = system;
+    }
+
+    /// With `--notify`, say that `what` has finished if it took long enough to be worth it
+    pub fn notify_finished(&self, what: &str, took: Duration) {
+        if let Some(how) = self.config.notify {
+            notify::finished(how, what, took, self.config.notify_after);
+        }
+    }
 
-        let llm_cmd = self.config.llm_cmd.as_ref().ok_or("Error: No LLM command configured for queries.\n\nTo fix this:\n1. Install an AI tool like aichat: https://github.com/sigoden/aichat\n2. Set it up with: --llm-cmd 'aichat'\n3. Or configure it in ~/.config/livemd/config.json:\n   {\"llm-cmd\": \"aichat\"}\n\nSee https://github.com/victoria-riley-barnett/livemd for setup instructions.")?;
+    /// Run the LLM command on `prompt` and return its answer without showing it, for work done
+    /// behind the scenes such as summarizing a long chat
+    pub fn complete(&mut self, prompt: &str) -> Result<String> {
+        let llm_cmd = self.config.llm_cmd.as_ref().ok_or_else(|| LivemdError::Provider("no LLM command configured".to_string()))?;
+        let mut parts = llm_cmd.split_whitespace();
+        let program = parts.next().ok_or_else(|| LivemdError::Config("Invalid LLM command: no program specified".to_string()))?;
+        let output = std::process::Command::new(program)
+            .args(parts)
+            .arg(prompt)
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|source| LivemdError::Spawn { command: program.to_string(), source })?;
+        if !output.status.success() {
+            return Err(LivemdError::Provider(format!("`{}` exited with {}", program, output.status)));
+        }
+        Ok(self.strip_ansi(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Stream output from an LLM query, returning the response
+    pub async fn stream_query(&mut self, query: &str) -> Result<String> {
+        let llm_cmd = self.config.llm_cmd.as_ref().ok_or_else(|| LivemdError::Provider("No LLM command configured for queries.\n\nTo fix this:\n1. Install an AI tool like aichat: https://github.com/sigoden/aichat\n2. Set it up with: --llm-cmd 'aichat'\n3. Or configure it in ~/.config/livemd/config.json:\n   {\"llm-cmd\": \"aichat\"}\n\nSee https://github.com/victoria-riley-barnett/livemd for setup instructions.".to_string()))?;
 
         let mut query_str = query.to_string();
         if self.config.inject_md_instruction {
             query_str = format!("Please respond only in Markdown.\n{}", query);
         }
+        if let Some(system) = &self.config.system {
+            query_str = format!("{}\n\n{}", system, query_str);
+        }
+
+        let cache = if self.config.cache {
+            ResponseCache::open(self.config.cache_ttl)
+        } else {
+            None
+        };
+        let cache_key = ResponseCache::key(llm_cmd, &query_str);
+        if let Some(cached) = cache.as_ref().and_then(|c| c.get(&cache_key)) {
+            // Replay the stored response without pacing
+            log::info!("answering from the response cache");
+            // The cache holds the response as the command wrote it, so it's cleaned up the
+            // way the live output is
+            let mut text = self.strip_ansi(&cached);
+            if self.config.strip_boxes {
+                text = self.sanitize_boxes(&text);
+            }
+            self.finish(&text);
+            return Ok(cached);
+        }
+
+        eprintln!("🤔 Thinking..."); // Simple progress indicator
+        let started = Instant::now();
 
         // Parse the LLM command into program and arguments
         let mut parts = llm_cmd.split_whitespace();
-        let program = parts.next().ok_or("Invalid LLM command: no program specified")?;
+        let program = parts.next().ok_or_else(|| LivemdError::Config("Invalid LLM command: no program specified".to_string()))?;
         let mut args: Vec<String> = parts.map(|s| s.to_string()).collect();
-        
-        // Add the query as the final argument, properly quoted
-        args.push(shell_words::quote(&query_str).to_string());
+
+        // Add the query as the final argument. No shell is involved, so it goes as is.
+        args.push(query_str.clone());
 
         let mut child = std::process::Command::new(program)
             .args(&args)
             .stdout(Stdio::piped())
-            .spawn()?;
+            .spawn()
+            .map_err(|source| LivemdError::Spawn { command: program.to_string(), source })?;
+        let program = program.to_string();
+        log::info!("spawned `{}` (pid {})", program, child.id());
+        log::debug!("llm arguments: {:?}", args);
 
         let stdout = child.stdout.take().expect("Failed to capture stdout.");
         let mut reader = BufReader::new(stdout);
         let mut buffer = String::new();
+        let mut response = String::new();
+        let mut completion_chars = 0;
 
         loop {
             let mut chunk = vec![0; 4096]; // Increased buffer size for better throughput
             match reader.read(&mut chunk) {
                 Ok(0) => break, // EOF
                 Ok(n) => {
+                    self.arrived();
                     let chunk_str = String::from_utf8_lossy(&chunk[..n]);
                     buffer.push_str(&chunk_str);
+                    completion_chars += chunk_str.chars().count();
+                    response.push_str(&chunk_str);
                     buffer = self.strip_ansi(&buffer);
 
                     if self.config.strip_boxes {
@@ -654,72 +2539,219 @@ impl MinimalStreamer {
                     }
 
                     let mut flush_pos;
-                    let mut chunks_processed = 0;
                     while {
                         flush_pos = self.find_flush_boundary(&buffer);
                         flush_pos > 0
                     } {
                         let to_print = buffer.drain(..flush_pos).collect::<String>();
                         self.print_styled_markdown(&to_print);
-                        chunks_processed += 1;
-
-                        // Only sleep after processing a few chunks to reduce latency
-                        if chunks_processed % 3 == 0 {
-                            sleep(Duration::from_secs_f64(self.config.speed)).await;
-                        }
+                        self.pace().await;
                     }
+                    self.preview(&buffer);
                 }
                 Err(e) => return Err(e.into()),
             }
         }
 
-        if !buffer.trim().is_empty() {
-            self.print_styled_markdown(&buffer);
+        self.finish(&buffer);
+
+        let status = child.wait()?;
+        log::info!("`{}` exited with {} after {:.1?}", program, status, started.elapsed());
+        if self.config.usage_footer {
+            self.print_usage_footer(&query_str, completion_chars, started.elapsed());
         }
-        Ok(())
+        if let Some(cache) = cache {
+            if status.success() && !response.trim().is_empty() {
+                cache.put(&cache_key, &response);
+            }
+        }
+        Ok(response)
+    }
+
+    /// Print a dim footer with estimated token usage, elapsed time, and cost
+    fn print_usage_footer(&mut self, prompt: &str, completion_chars: usize, elapsed: Duration) {
+        let prompt_tokens = estimate_tokens(prompt);
+        let completion_tokens = completion_chars.div_ceil(4);
+        let mut footer = format!(
+            "~{} prompt + ~{} completion tokens · {:.1}s",
+            prompt_tokens,
+            completion_tokens,
+            elapsed.as_secs_f64()
+        );
+        if let Some(pricing) = self.config.pricing {
+            let cost = (prompt_tokens as f64 * pricing.prompt
+                + completion_tokens as f64 * pricing.completion)
+                / 1_000_000.0;
+            footer.push_str(&format!(" · ~${:.4}", cost));
+        }
+
+        let _ = self.out.queue(SetAttribute(Attribute::Dim));
+        let _ = self.out.queue(Print(format!("{}\n", footer)));
+        let _ = self.out.queue(SetAttribute(Attribute::Reset));
+        let (_, margin) = self.layout();
+        self.emit(margin);
     }
 
     /// Stream content from stdin
-    pub async fn stream_stdin(&self) -> Result<(), Box<dyn std::error::Error>> {
-        use tokio::io::{AsyncReadExt, stdin};
-        let mut reader = stdin();
+    pub async fn stream_stdin(&mut self) -> Result<()> {
         let mut buffer = String::new();
-        let mut chunk = vec![0; 4096];
+        self.stream_reader(&mut tokio::io::stdin(), &mut buffer).await?;
+        self.finish(&buffer);
+        Ok(())
+    }
 
+    /// Stream a file as it grows, or each writer's output to a named pipe in turn, until
+    /// interrupted (`--follow`)
+    pub async fn follow_file(&mut self, path: PathBuf) -> Result<()> {
+        self.base_dir = path.parent().map(Path::to_path_buf);
+        if is_fifo(&path) {
+            loop {
+                // Opening waits for a writer, and reading ends when the last writer closes
+                let mut pipe = tokio::fs::File::open(&path).await?;
+                log::info!("reading {}", path.display());
+                let mut buffer = String::new();
+                self.stream_reader(&mut pipe, &mut buffer).await?;
+                self.finish(&buffer);
+                log::info!("writers closed {}, reopening it", path.display());
+            }
+        }
+        let mut file = tokio::fs::File::open(&path).await?;
+        let mut buffer = String::new();
         loop {
-            match reader.read(&mut chunk).await {
-                Ok(0) => break, // EOF
-                Ok(n) => {
-                    let chunk_str = String::from_utf8_lossy(&chunk[..n]);
-                    buffer.push_str(&chunk_str);
+            self.stream_reader(&mut file, &mut buffer).await?;
+            // Show complete lines without waiting for the paragraph to end, as `tail -f` would.
+            // The file goes on, so a `--section` or streamed table stays open for what follows.
+            if let Some(end) = buffer.rfind('\n') {
+                let lines: String = buffer.drain(..=end).collect();
+                self.print_styled_markdown(&lines);
+                self.preview(&buffer);
+            }
+            tokio::time::sleep(FOLLOW_INTERVAL).await;
+            let position = file.stream_position().await?;
+            if tokio::fs::metadata(&path).await.is_ok_and(|m| m.len() < position) {
+                log::info!("{} was truncated, reading it from the start", path.display());
+                // What's read next is a new document: a partial line from before belongs to
+                // text that's gone, and the section and table state start over
+                buffer.clear();
+                self.clear_preview();
+                if self.open_table.is_some() {
+                    let (_, margin) = self.layout();
+                    self.close_table(margin);
+                }
+                self.section = self.config.section.as_deref().map(SectionFilter::new);
+                file = tokio::fs::File::open(&path).await?;
+            }
+        }
+    }
 
-                    if self.config.strip_boxes {
-                        buffer = self.sanitize_boxes(&buffer);
-                    }
+    /// Accept connections on a Unix socket and render what each client sends, one client at a
+    /// time, until interrupted (`--listen`)
+    #[cfg(unix)]
+    pub async fn listen(&mut self, path: &Path) -> Result<()> {
+        use std::os::unix::fs::FileTypeExt;
+        use tokio::net::{UnixListener, UnixStream};
 
-                    let mut flush_pos;
-                    let mut chunks_processed = 0;
-                    while {
-                        flush_pos = self.find_flush_boundary(&buffer);
-                        flush_pos > 0
-                    } {
-                        let to_print = buffer.drain(..flush_pos).collect::<String>();
-                        self.print_styled_markdown(&to_print);
-                        chunks_processed += 1;
+        // A socket left behind by a run that didn't get to remove it
+        let is_socket = std::fs::metadata(path).is_ok_and(|m| m.file_type().is_socket());
+        if is_socket && UnixStream::connect(path).await.is_err() {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path).map_err(|e| LivemdError::Input(format!("can't listen on {}: {}", path.display(), e)))?;
+        eprintln!("Listening on {} (Ctrl-C to stop)", path.display());
 
-                        // Only sleep after processing a few chunks to reduce latency
-                        if chunks_processed % 3 == 0 {
-                            sleep(Duration::from_secs_f64(self.config.speed)).await;
-                        }
+        let interrupted = tokio::signal::ctrl_c();
+        tokio::pin!(interrupted);
+        loop {
+            let mut client = tokio::select! {
+                accepted = listener.accept() => accepted?.0,
+                _ = &mut interrupted => break,
+            };
+            log::info!("client connected to {}", path.display());
+            let mut buffer = String::new();
+            tokio::select! {
+                result = self.stream_reader(&mut client, &mut buffer) => {
+                    if let Err(e) = result {
+                        log::info!("client connection failed: {}", e);
                     }
                 }
+                _ = &mut interrupted => break,
+            }
+            self.finish(&buffer);
+        }
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub async fn listen(&mut self, _path: &Path) -> Result<()> {
+        Err(LivemdError::Input("--listen needs Unix domain sockets, which this platform doesn't have".to_string()))
+    }
+
+    /// Read to the end of `reader`, rendering each block as it completes. What's left of the
+    /// last block stays in `buffer`.
+    async fn stream_reader<R: AsyncRead + Unpin>(&mut self, reader: &mut R, buffer: &mut String) -> Result<()> {
+        let mut chunk = vec![0; 4096];
+
+        loop {
+            match reader.read(&mut chunk).await {
+                Ok(0) => break, // EOF
+                Ok(n) => self.feed(buffer, &String::from_utf8_lossy(&chunk[..n])).await,
                 Err(e) => return Err(e.into()),
             }
         }
+        Ok(())
+    }
+
+    /// Add text that has just arrived to `buffer` and render the blocks it completes
+    async fn feed(&mut self, buffer: &mut String, text: &str) {
+        self.arrived();
+        buffer.push_str(text);
+
+        if self.config.strip_boxes {
+            *buffer = self.sanitize_boxes(buffer);
+        }
+
+        let mut flush_pos;
+        while {
+            flush_pos = self.find_flush_boundary(buffer);
+            flush_pos > 0
+        } {
+            let to_print = buffer.drain(..flush_pos).collect::<String>();
+            self.print_styled_markdown(&to_print);
+            self.pace().await;
+        }
+        self.preview(buffer);
+    }
 
-        if !buffer.trim().is_empty() {
-            self.print_styled_markdown(&buffer);
+    /// Stream the text messages of a WebSocket until the server closes it (`--ws`)
+    pub async fn stream_websocket(&mut self, url: &str) -> Result<()> {
+        let mut socket = WebSocket::connect(url).await?;
+        log::info!("connected to {}", url);
+        let mut buffer = String::new();
+        while let Some(text) = socket.next_text().await? {
+            self.feed(&mut buffer, &text).await;
         }
+        log::info!("{} closed the connection", url);
+        self.finish(&buffer);
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_visible_without_escapes_is_the_last_byte() {
+        assert_eq!(last_visible(b"Hello world."), Some(b'.'));
+        assert_eq!(last_visible(b"Hello world!  \n"), Some(b'!'));
+        assert_eq!(last_visible(b"   "), None);
+    }
+
+    #[test]
+    fn last_visible_skips_trailing_styling() {
+        assert_eq!(last_visible(b"\x1b[1mHello.\x1b[0m"), Some(b'.'));
+        assert_eq!(last_visible(b"Hello?\x1b[0m \x1b[37m"), Some(b'?'));
+        assert_eq!(last_visible(b"\x1b[1mHello\x1b[0m"), Some(b'o'));
+    }
+}
@@ -1,96 +1,531 @@
 //! Table rendering functionality
 
-use std::io::stdout;
+use crate::theme::Theme;
+use crate::wrap::split_at_width;
+use pulldown_cmark::Alignment;
+use std::io::Write;
+use unicode_width::UnicodeWidthStr;
 use termimad::crossterm::{
-    style::Print,
+    style::{Attribute, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor},
     QueueableCommand,
 };
 
+/// Narrowest a column is squeezed to when a table doesn't fit
+const MIN_COLUMN_WIDTH: usize = 3;
+
+/// Width streamed table columns are widened to, leaving room for rows that haven't arrived
+const STREAMED_COLUMN_WIDTH: usize = 24;
+
+/// Inline styling applied to a run of cell text
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SpanStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub code: bool,
+    pub strikethrough: bool,
+    pub link: bool,
+}
+
+/// A run of cell text sharing one inline style
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: SpanStyle,
+}
+
+/// A table cell is a sequence of styled spans
+pub type Cell = Vec<Span>;
+
+/// Part of a word with a single style
+type Piece<'a> = (&'a str, SpanStyle);
+
+/// A word of cell text, the unit of cell wrapping. Adjacent spans without whitespace
+/// between them (e.g. `**bold**,`) form one word of several pieces.
+type Word<'a> = Vec<Piece<'a>>;
+
+/// Where horizontal separators are drawn between rows
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TableStyle {
+    /// Separator after the header and between every data row
+    #[default]
+    Grid,
+    /// Separator only after the header
+    Compact,
+}
+
+impl TableStyle {
+    /// Names accepted by `--table-style` and the `table-style` setting
+    pub const NAMES: [&'static str; 2] = ["grid", "compact"];
+
+    /// Parse a style name from config or the command line, defaulting to grid
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "compact" => TableStyle::Compact,
+            _ => TableStyle::Grid,
+        }
+    }
+}
+
+/// Which horizontal border line to draw
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Border {
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// How a row is decorated as a whole, beneath the inline styles of its cells
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RowKind {
+    Header,
+    Body,
+    /// Every other body row when the theme enables zebra striping
+    Striped,
+}
+
 /// Table rendering functionality
 pub struct TableRenderer;
 
 impl TableRenderer {
-    /// Render a table with proper ASCII borders
-    pub fn render_table(table_md: &str) {
-        let lines: Vec<&str> = table_md.lines().collect();
-        if lines.is_empty() {
+    /// Render a table with proper ASCII borders into `out`, indented by `margin` columns.
+    /// `alignments` holds the per-column alignment from the Markdown delimiter row, and
+    /// cells are word-wrapped so the table fits within `max_width` columns. The first row is
+    /// the header and is drawn bold in the theme's table header color.
+    pub fn render_table(
+        out: &mut impl Write,
+        rows: &[Vec<Cell>],
+        alignments: &[Alignment],
+        theme: &Theme,
+        style: TableStyle,
+        max_width: usize,
+        margin: usize,
+    ) {
+        if rows.is_empty() {
             return;
         }
 
-        // Parse table rows
-        let mut rows: Vec<Vec<String>> = Vec::new();
-        for line in lines {
-            if line.trim().is_empty() {
-                continue;
-            }
-            let cells: Vec<String> = line.split('|').map(|s| s.trim().to_string()).collect();
-            if !cells.is_empty() {
-                rows.push(cells);
-            }
-        }
+        let col_widths = Self::measure(rows, max_width);
 
-        if rows.is_empty() {
-            return;
+        Self::render_border(out, &col_widths, Border::Top, theme, margin);
+        for (row_idx, row) in rows.iter().enumerate() {
+            Self::render_row(out, row, row_idx, &col_widths, alignments, theme, margin);
+
+            // Separator row (after header, and between data rows in grid style)
+            if row_idx == 0 || (style == TableStyle::Grid && row_idx < rows.len() - 1) {
+                Self::render_border(out, &col_widths, Border::Middle, theme, margin);
+            }
         }
+        Self::render_border(out, &col_widths, Border::Bottom, theme, margin);
+    }
 
-        // Calculate column widths
+    /// Compute column widths for `rows`, shrunk so the table fits in `max_width` columns
+    pub fn measure(rows: &[Vec<Cell>], max_width: usize) -> Vec<usize> {
         let mut col_widths: Vec<usize> = Vec::new();
-        for row in &rows {
+        for row in rows {
             for (i, cell) in row.iter().enumerate() {
                 if i >= col_widths.len() {
                     col_widths.push(0);
                 }
-                col_widths[i] = col_widths[i].max(cell.len());
+                col_widths[i] = col_widths[i].max(Self::words_width(&Self::cell_words(cell)));
             }
         }
+        Self::fit_widths(&col_widths, max_width)
+    }
 
-        // Render table with borders
-        let mut stdout = stdout();
+    /// Like `measure`, but for a table whose rows are still streaming in: every column is
+    /// widened towards an even share of the width so later rows wrap less
+    pub fn measure_streamed(rows: &[Vec<Cell>], max_width: usize) -> Vec<usize> {
+        let widths = Self::measure(rows, max_width);
+        let available = max_width.saturating_sub(widths.len() * 3 + 1);
+        let share = (available / widths.len().max(1)).min(STREAMED_COLUMN_WIDTH);
+        widths.into_iter().map(|w| w.max(share)).collect()
+    }
 
-        // Top border
-        let _ = stdout.queue(Print("┌"));
+    /// Render a horizontal border line
+    pub fn render_border(out: &mut impl Write, col_widths: &[usize], border: Border, theme: &Theme, margin: usize) {
+        let (left, join, right) = match border {
+            Border::Top => ("┌", "┬", "┐"),
+            Border::Middle => ("├", "┼", "┤"),
+            Border::Bottom => ("└", "┴", "┘"),
+        };
+        let _ = out.queue(Print(" ".repeat(margin)));
+        let _ = out.queue(SetForegroundColor(theme.get_color("table_border")));
+        let _ = out.queue(Print(left));
         for (i, &width) in col_widths.iter().enumerate() {
             if i > 0 {
-                let _ = stdout.queue(Print("┬"));
+                let _ = out.queue(Print(join));
             }
-            let _ = stdout.queue(Print("─".repeat(width + 2)));
+            let _ = out.queue(Print("─".repeat(width + 2)));
         }
-        let _ = stdout.queue(Print("┐\n"));
+        let _ = out.queue(Print(right));
+        let _ = out.queue(ResetColor);
+        let _ = out.queue(Print("\n"));
+    }
 
-        // Table rows
-        for (row_idx, row) in rows.iter().enumerate() {
-            // Data row
-            let _ = stdout.queue(Print("│"));
-            for (i, cell) in row.iter().enumerate() {
-                if i > 0 {
-                    let _ = stdout.queue(Print("│"));
-                }
-                let width = col_widths.get(i).copied().unwrap_or(0);
-                let _ = stdout.queue(Print(format!(" {:<width$} ", cell, width = width)));
+    /// Render one table row (row 0 is the header), wrapping cells over several physical lines
+    pub fn render_row(
+        out: &mut impl Write,
+        row: &[Cell],
+        row_idx: usize,
+        col_widths: &[usize],
+        alignments: &[Alignment],
+        theme: &Theme,
+        margin: usize,
+    ) {
+        let pad = " ".repeat(margin);
+        let words: Vec<Vec<Word>> = row.iter().map(Self::cell_words).collect();
+        let wrapped: Vec<Vec<Vec<Word>>> = (0..col_widths.len())
+            .map(|i| {
+                let cell = words.get(i).map(Vec::as_slice).unwrap_or(&[]);
+                Self::wrap_words(cell, col_widths[i])
+            })
+            .collect();
+        let height = wrapped.iter().map(Vec::len).max().unwrap_or(1).max(1);
+        let kind = if row_idx == 0 {
+            RowKind::Header
+        } else if row_idx.is_multiple_of(2) && theme.table_stripe.is_some() {
+            RowKind::Striped
+        } else {
+            RowKind::Body
+        };
+
+        for line_idx in 0..height {
+            let _ = out.queue(Print(&pad));
+            for (i, cell_lines) in wrapped.iter().enumerate() {
+                Self::print_bar(out, theme);
+                let line = cell_lines.get(line_idx).map(Vec::as_slice).unwrap_or(&[]);
+                let fill = col_widths[i].saturating_sub(Self::words_width(line));
+                // Pad by display width; `format!` padding counts chars, which
+                // misaligns wide (CJK, emoji) and zero-width (combining) characters
+                let (left, right) = match alignments.get(i) {
+                    Some(Alignment::Center) => (fill / 2, fill - fill / 2),
+                    Some(Alignment::Right) => (fill, 0),
+                    _ => (0, fill),
+                };
+                Self::apply_row(out, theme, kind);
+                let _ = out.queue(Print(" ".repeat(left + 1)));
+                Self::print_words(out, line, theme, kind);
+                let _ = out.queue(Print(" ".repeat(right + 1)));
+                let _ = out.queue(SetAttribute(Attribute::Reset));
+                let _ = out.queue(ResetColor);
             }
-            let _ = stdout.queue(Print("│\n"));
+            Self::print_bar(out, theme);
+            let _ = out.queue(Print("\n"));
+        }
+    }
+
+    /// Print a vertical cell border in the theme's border color
+    fn print_bar(out: &mut impl Write, theme: &Theme) {
+        let _ = out.queue(SetForegroundColor(theme.get_color("table_border")));
+        let _ = out.queue(Print("│"));
+        let _ = out.queue(ResetColor);
+    }
 
-            // Separator row (after header or between data rows)
-            if row_idx == 0 || row_idx < rows.len() - 1 {
-                let _ = stdout.queue(Print("├"));
-                for (i, &width) in col_widths.iter().enumerate() {
-                    if i > 0 {
-                        let _ = stdout.queue(Print("┼"));
+    /// Split a cell into styled words, dropping the whitespace between them
+    fn cell_words(cell: &Cell) -> Vec<Word<'_>> {
+        let mut words = Vec::new();
+        let mut current: Word = Vec::new();
+        for span in cell {
+            let mut rest = span.text.as_str();
+            while !rest.is_empty() {
+                if rest.starts_with(char::is_whitespace) {
+                    if !current.is_empty() {
+                        words.push(std::mem::take(&mut current));
                     }
-                    let _ = stdout.queue(Print("─".repeat(width + 2)));
+                    rest = rest.trim_start();
+                } else {
+                    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                    current.push((&rest[..end], span.style));
+                    rest = &rest[end..];
                 }
-                let _ = stdout.queue(Print("┤\n"));
             }
         }
+        if !current.is_empty() {
+            words.push(current);
+        }
+        words
+    }
 
-        // Bottom border
-        let _ = stdout.queue(Print("└"));
-        for (i, &width) in col_widths.iter().enumerate() {
+    /// Display width of a single word
+    fn word_width(word: &[Piece]) -> usize {
+        word.iter().map(|(text, _)| text.width()).sum()
+    }
+
+    /// Display width of words laid out on one line, separated by single spaces
+    fn words_width(words: &[Word]) -> usize {
+        let text: usize = words.iter().map(|w| Self::word_width(w)).sum();
+        text + words.len().saturating_sub(1)
+    }
+
+    /// Greedily wrap words into lines no wider than `width`, hard-splitting overlong words
+    fn wrap_words<'a>(words: &[Word<'a>], width: usize) -> Vec<Vec<Word<'a>>> {
+        let width = width.max(1);
+        let mut lines: Vec<Vec<Word>> = Vec::new();
+        let mut line: Vec<Word> = Vec::new();
+        let mut line_width = 0;
+        for word in words {
+            let mut word = word.clone();
+            if !line.is_empty() && line_width + 1 + Self::word_width(&word) > width {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            while Self::word_width(&word) > width {
+                let (head, tail) = Self::split_word(&word, width);
+                lines.push(vec![head]);
+                word = tail;
+            }
+            let w = Self::word_width(&word);
+            line_width += if line.is_empty() { w } else { w + 1 };
+            line.push(word);
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Split a word into a prefix that fits in `width` columns and the remainder
+    fn split_word<'a>(word: &[Piece<'a>], width: usize) -> (Word<'a>, Word<'a>) {
+        let mut head = Vec::new();
+        let mut used = 0;
+        for (i, &(text, style)) in word.iter().enumerate() {
+            let w = text.width();
+            if used + w <= width {
+                head.push((text, style));
+                used += w;
+                continue;
+            }
+            let mut tail = Vec::new();
+            let split = if used < width { split_at_width(text, width - used) } else { 0 };
+            // Always make progress, even if a single character is wider than the column
+            let split = if head.is_empty() && split == 0 { split_at_width(text, 1) } else { split };
+            if split > 0 {
+                head.push((&text[..split], style));
+            }
+            if split < text.len() {
+                tail.push((&text[split..], style));
+            }
+            tail.extend_from_slice(&word[i + 1..]);
+            return (head, tail);
+        }
+        (head, Vec::new())
+    }
+
+    /// Print one line of styled words
+    fn print_words(out: &mut impl Write, words: &[Word], theme: &Theme, kind: RowKind) {
+        for (i, word) in words.iter().enumerate() {
             if i > 0 {
-                let _ = stdout.queue(Print("┴"));
+                let _ = out.queue(Print(" "));
+            }
+            for &(text, style) in word {
+                Self::print_piece(out, text, style, theme, kind);
+            }
+        }
+    }
+
+    /// Apply the row-wide decoration: bold header color, or body text with the zebra stripe
+    fn apply_row(out: &mut impl Write, theme: &Theme, kind: RowKind) {
+        if kind == RowKind::Header {
+            theme.apply_style(out, "table_header", &[Attribute::Bold]);
+            return;
+        }
+        theme.apply_style(out, "text", &[]);
+        if kind == RowKind::Striped {
+            match theme.table_stripe.as_deref() {
+                Some("dim") => {
+                    let _ = out.queue(SetAttribute(Attribute::Dim));
+                }
+                Some(color) => {
+                    let _ = out.queue(SetBackgroundColor(Theme::parse_color(color)));
+                }
+                None => {}
             }
-            let _ = stdout.queue(Print("─".repeat(width + 2)));
         }
-        let _ = stdout.queue(Print("┘\n"));
     }
-}
\ No newline at end of file
+
+    /// Print a piece of text with its inline style, restoring the row decoration afterwards
+    fn print_piece(out: &mut impl Write, text: &str, style: SpanStyle, theme: &Theme, kind: RowKind) {
+        if style == SpanStyle::default() {
+            let _ = out.queue(Print(text));
+            return;
+        }
+        if style.bold {
+            theme.apply_style(out, "bold", &[Attribute::Bold]);
+        }
+        if style.italic {
+            theme.apply_style(out, "italic", &[Attribute::Italic]);
+        }
+        if style.strikethrough {
+            theme.apply_style(out, "strikethrough", &[Attribute::CrossedOut]);
+        }
+        if style.link {
+            theme.apply_style(out, "link", &[Attribute::Underlined]);
+        }
+        if style.code {
+            let _ = out.queue(SetBackgroundColor(theme.get_color("code")));
+        }
+        let _ = out.queue(Print(text));
+        let _ = out.queue(SetAttribute(Attribute::Reset));
+        let _ = out.queue(ResetColor);
+        Self::apply_row(out, theme, kind);
+    }
+
+    /// Shrink column widths so the bordered table fits in `max_width` columns.
+    /// Narrow columns keep their natural width; the remaining space is shared evenly.
+    fn fit_widths(natural: &[usize], max_width: usize) -> Vec<usize> {
+        // Each column adds a border and two spaces of padding, plus the closing border
+        let overhead = natural.len() * 3 + 1;
+        let available = max_width.saturating_sub(overhead);
+        if natural.iter().sum::<usize>() <= available {
+            return natural.to_vec();
+        }
+
+        let mut widths = vec![0; natural.len()];
+        let mut remaining: Vec<usize> = (0..natural.len()).collect();
+        let mut space = available;
+        loop {
+            let share = space / remaining.len().max(1);
+            let (fits, wide): (Vec<usize>, Vec<usize>) =
+                remaining.iter().partition(|&&i| natural[i] <= share);
+            if fits.is_empty() {
+                // Split what's left evenly among the wide columns
+                let mut extra = space % wide.len().max(1);
+                for &i in &wide {
+                    widths[i] = share.max(MIN_COLUMN_WIDTH) + usize::from(extra > 0);
+                    extra = extra.saturating_sub(1);
+                }
+                return widths;
+            }
+            for &i in &fits {
+                widths[i] = natural[i];
+                space -= natural[i];
+            }
+            remaining = wide;
+            if remaining.is_empty() {
+                return widths;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(text: &str) -> Cell {
+        vec![Span { text: text.to_string(), style: SpanStyle::default() }]
+    }
+
+    fn row(cells: &[&str]) -> Vec<Cell> {
+        cells.iter().map(|c| cell(c)).collect()
+    }
+
+    /// Render a table and return its lines with ANSI escapes stripped
+    fn render(rows: &[Vec<Cell>], alignments: &[Alignment], style: TableStyle, max_width: usize, margin: usize) -> Vec<String> {
+        let mut out = Vec::new();
+        TableRenderer::render_table(&mut out, rows, alignments, &Theme::dark(), style, max_width, margin);
+        let text = String::from_utf8(out).unwrap();
+        let ansi = regex::Regex::new(r"\x1b\[[0-9;]*m").unwrap();
+        ansi.replace_all(&text, "").lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn empty_table_renders_nothing() {
+        assert!(render(&[], &[], TableStyle::Grid, 80, 0).is_empty());
+    }
+
+    #[test]
+    fn grid_table_has_borders_between_every_row() {
+        let rows = vec![row(&["a", "bb"]), row(&["ccc", "d"]), row(&["e", "f"])];
+        let lines = render(&rows, &[], TableStyle::Grid, 80, 0);
+        assert_eq!(
+            lines,
+            vec![
+                "┌─────┬────┐",
+                "│ a   │ bb │",
+                "├─────┼────┤",
+                "│ ccc │ d  │",
+                "├─────┼────┤",
+                "│ e   │ f  │",
+                "└─────┴────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn compact_table_only_separates_the_header() {
+        let rows = vec![row(&["a"]), row(&["b"]), row(&["c"])];
+        let lines = render(&rows, &[], TableStyle::Compact, 80, 0);
+        assert_eq!(lines, vec!["┌───┐", "│ a │", "├───┤", "│ b │", "│ c │", "└───┘"]);
+    }
+
+    #[test]
+    fn margin_indents_every_line() {
+        let rows = vec![row(&["a"]), row(&["b"])];
+        for line in render(&rows, &[], TableStyle::Grid, 80, 4) {
+            assert!(line.starts_with("    ") && !line.starts_with("     "), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn alignment_pads_by_column() {
+        let rows = vec![row(&["left", "center", "right"]), row(&["a", "b", "c"])];
+        let alignments = [Alignment::Left, Alignment::Center, Alignment::Right];
+        let lines = render(&rows, &alignments, TableStyle::Grid, 80, 0);
+        assert_eq!(lines[3], "│ a    │   b    │     c │");
+    }
+
+    #[test]
+    fn wide_characters_are_padded_by_display_width() {
+        let rows = vec![row(&["名前", "x"]), row(&["a", "y"])];
+        let lines = render(&rows, &[], TableStyle::Grid, 80, 0);
+        let widths: Vec<usize> = lines.iter().map(|l| l.width()).collect();
+        assert!(widths.iter().all(|&w| w == widths[0]), "{:?}", lines);
+        assert_eq!(lines[3], "│ a    │ y │");
+    }
+
+    #[test]
+    fn narrow_width_wraps_cells_to_fit() {
+        let rows = vec![row(&["key", "value"]), row(&["k", "a long value that needs several lines"])];
+        let lines = render(&rows, &[], TableStyle::Grid, 24, 0);
+        assert!(lines.iter().all(|l| l.width() <= 24), "{:?}", lines);
+        assert!(lines.len() > 5);
+        let text: String = lines.concat();
+        for word in ["long", "value", "several", "lines"] {
+            assert!(text.contains(word));
+        }
+    }
+
+    #[test]
+    fn overlong_words_are_hard_split() {
+        let rows = vec![row(&["h"]), row(&["abcdefghijklmnopqrstuvwxyz"])];
+        let lines = render(&rows, &[], TableStyle::Grid, 12, 0);
+        assert!(lines.iter().all(|l| l.width() <= 12), "{:?}", lines);
+    }
+
+    #[test]
+    fn ragged_rows_get_empty_cells() {
+        let rows = vec![row(&["a", "b", "c"]), row(&["x"])];
+        let lines = render(&rows, &[], TableStyle::Grid, 80, 0);
+        assert_eq!(lines[3], "│ x │   │   │");
+    }
+
+    #[test]
+    fn fit_widths_keeps_natural_widths_when_they_fit() {
+        assert_eq!(TableRenderer::fit_widths(&[3, 10], 80), vec![3, 10]);
+    }
+
+    #[test]
+    fn fit_widths_shares_space_among_wide_columns() {
+        // 3 columns: 10 columns of overhead, 30 left to share
+        let widths = TableRenderer::fit_widths(&[4, 50, 50], 40);
+        assert_eq!(widths[0], 4);
+        assert_eq!(widths.iter().sum::<usize>(), 30);
+    }
+
+    #[test]
+    fn fit_widths_never_goes_below_the_minimum() {
+        let widths = TableRenderer::fit_widths(&[20, 20, 20], 10);
+        assert!(widths.iter().all(|&w| w == MIN_COLUMN_WIDTH));
+    }
+}
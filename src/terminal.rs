@@ -0,0 +1,270 @@
+//! Terminal capability detection
+
+use regex::Regex;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// When to emit colors and text attributes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ColorMode {
+    /// Always, even when output is piped or NO_COLOR is set
+    Always,
+    /// When stdout is a terminal and NO_COLOR is unset
+    #[default]
+    Auto,
+    /// Never
+    Never,
+}
+
+impl ColorMode {
+    /// Names accepted by `--color` and the `color` setting
+    pub const NAMES: [&'static str; 3] = ["auto", "always", "never"];
+
+    /// Parse a mode name from config or the command line, defaulting to auto
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "always" => ColorMode::Always,
+            "never" => ColorMode::Never,
+            _ => ColorMode::Auto,
+        }
+    }
+
+    /// Whether output should be colored under this mode
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            // https://no-color.org: any non-empty NO_COLOR disables color
+            ColorMode::Auto => {
+                std::env::var("NO_COLOR").map_or(true, |v| v.is_empty()) && atty::is(atty::Stream::Stdout)
+            }
+        }
+    }
+}
+
+/// How many colors the terminal can show
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorDepth {
+    /// 24-bit RGB
+    TrueColor,
+    /// The xterm 256-color palette
+    Ansi256,
+    /// The 16 basic ANSI colors
+    Ansi16,
+}
+
+impl ColorDepth {
+    /// Parse a depth from config ("truecolor", "256", "16"); None for anything else
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "truecolor" | "24bit" => Some(ColorDepth::TrueColor),
+            "256" => Some(ColorDepth::Ansi256),
+            "16" => Some(ColorDepth::Ansi16),
+            _ => None,
+        }
+    }
+
+    /// Detect the color depth from `COLORTERM`, `TERM`, and terminfo
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if matches!(colorterm.as_str(), "truecolor" | "24bit")
+            || term.ends_with("-direct")
+            || term.contains("truecolor")
+            // Windows Terminal
+            || std::env::var_os("WT_SESSION").is_some()
+        {
+            return ColorDepth::TrueColor;
+        }
+        if term.contains("256color") || terminfo_colors().is_some_and(|n| n >= 256) {
+            return ColorDepth::Ansi256;
+        }
+        ColorDepth::Ansi16
+    }
+}
+
+/// Number of colors terminfo reports for the current terminal
+fn terminfo_colors() -> Option<u32> {
+    let output = std::process::Command::new("tput").arg("colors").stderr(std::process::Stdio::null()).output().ok()?;
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// The 16 ANSI colors as xterm draws them, in SGR order (30-37, then 90-97)
+const ANSI16: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Channel levels of the 6x6x6 color cube in the 256-color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// Nearest color in the 256-color palette, from the color cube or the grayscale ramp
+fn nearest_256(rgb: (u8, u8, u8)) -> u8 {
+    let level = |c: u8| {
+        (0..6).min_by_key(|&i| (CUBE_LEVELS[i] as i32 - c as i32).abs()).unwrap_or(0)
+    };
+    let (ri, gi, bi) = (level(rgb.0), level(rgb.1), level(rgb.2));
+    let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+    let cube_index = 16 + 36 * ri + 6 * gi + bi;
+
+    // Grayscale ramp 232-255 runs from 8 to 238 in steps of 10
+    let avg = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+    let gray_step = ((avg.saturating_sub(8) + 5) / 10).min(23);
+    let gray = (8 + 10 * gray_step) as u8;
+
+    if distance(rgb, (gray, gray, gray)) < distance(rgb, cube) {
+        232 + gray_step as u8
+    } else {
+        cube_index as u8
+    }
+}
+
+/// Index (0-15) of the nearest basic ANSI color
+fn nearest_16(rgb: (u8, u8, u8)) -> usize {
+    (0..16).min_by_key(|&i| distance(rgb, ANSI16[i])).unwrap_or(7)
+}
+
+/// 24-bit foreground and background color escapes
+static RGB_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\x1b\[([34])8;2;(\d+);(\d+);(\d+)m").unwrap());
+
+/// Rewrite 24-bit color escapes in rendered output to the nearest colors the terminal supports
+pub fn quantize_colors(rendered: &str, depth: ColorDepth) -> String {
+    if depth == ColorDepth::TrueColor {
+        return rendered.to_string();
+    }
+    RGB_RE
+        .replace_all(rendered, |caps: &regex::Captures| {
+            let channel = |i: usize| caps[i].parse::<u8>().unwrap_or(0);
+            let rgb = (channel(2), channel(3), channel(4));
+            let background = &caps[1] == "4";
+            match depth {
+                ColorDepth::Ansi16 => {
+                    let index = nearest_16(rgb);
+                    let base = match (background, index < 8) {
+                        (false, true) => 30,
+                        (false, false) => 90 - 8,
+                        (true, true) => 40,
+                        (true, false) => 100 - 8,
+                    };
+                    format!("\x1b[{}m", base + index)
+                }
+                _ => format!("\x1b[{}8;5;{}m", &caps[1], nearest_256(rgb)),
+            }
+        })
+        .into_owned()
+}
+
+/// Whether the terminal is known to support OSC 8 hyperlinks. Terminals without support
+/// usually ignore the escape, but some print it, so only known terminals get links.
+pub fn supports_hyperlinks() -> bool {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    let term_program = var("TERM_PROGRAM");
+    let term = var("TERM");
+    matches!(term_program.as_str(), "iTerm.app" | "WezTerm" | "vscode" | "ghostty" | "Hyper" | "rio")
+        || ["kitty", "alacritty", "foot", "wezterm", "ghostty"].iter().any(|t| term.contains(t))
+        || var("VTE_VERSION").parse::<u32>().is_ok_and(|v| v >= 5000)
+        || !var("WT_SESSION").is_empty()
+        || !var("KONSOLE_VERSION").is_empty()
+        || !var("KITTY_WINDOW_ID").is_empty()
+}
+
+/// How long to wait for the terminal to answer a query before giving up
+const QUERY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Pick the built-in theme matching the terminal background: "light" or "dark".
+/// Asks the terminal for its background color (OSC 11), then falls back to `COLORFGBG`,
+/// and assumes a dark background if neither answers.
+pub fn auto_theme_name() -> &'static str {
+    match query_background().or_else(colorfgbg_background) {
+        Some(true) => "light",
+        _ => "dark",
+    }
+}
+
+/// Whether the background is light according to `COLORFGBG` ("fg;bg", set by rxvt, Konsole, and others)
+fn colorfgbg_background() -> Option<bool> {
+    let value = std::env::var("COLORFGBG").ok()?;
+    let bg: u8 = value.rsplit(';').next()?.parse().ok()?;
+    // ANSI white and the bright colors other than bright black are light backgrounds
+    Some(matches!(bg, 7 | 9..=15))
+}
+
+/// Whether the background is light, parsed from an OSC 11 reply like `\x1b]11;rgb:ffff/ffff/ffff\x07`
+fn parse_osc11(reply: &str) -> Option<bool> {
+    let rgb = reply.split("rgb:").nth(1)?;
+    let rgb = rgb.trim_end_matches(['\x07', '\\']).trim_end_matches('\x1b');
+    let channels: Vec<f64> = rgb
+        .split('/')
+        .map(|hex| {
+            let digits = hex.len().clamp(1, 4) as u32;
+            u32::from_str_radix(hex, 16).ok().map(|v| v as f64 / (16f64.powi(digits as i32) - 1.0))
+        })
+        .collect::<Option<_>>()?;
+    let [r, g, b] = channels[..] else {
+        return None;
+    };
+    // Relative luminance
+    Some(0.2126 * r + 0.7152 * g + 0.0722 * b > 0.5)
+}
+
+/// Ask the terminal for its background color with OSC 11
+#[cfg(unix)]
+fn query_background() -> Option<bool> {
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::time::Instant;
+    use termimad::crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+
+    if !atty::is(atty::Stream::Stdout) {
+        return None;
+    }
+    let mut tty = std::fs::OpenOptions::new().read(true).write(true).open("/dev/tty").ok()?;
+    enable_raw_mode().ok()?;
+
+    let mut read_reply = || -> Option<Vec<u8>> {
+        tty.write_all(b"\x1b]11;?\x07").ok()?;
+        tty.flush().ok()?;
+        let deadline = Instant::now() + QUERY_TIMEOUT;
+        let mut reply = Vec::new();
+        let mut byte = [0u8; 1];
+        // The reply ends with BEL or ST (ESC \)
+        while !(reply.ends_with(b"\x07") || reply.ends_with(b"\x1b\\")) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let mut fd = libc::pollfd { fd: tty.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            // SAFETY: `fd` is a valid pollfd for the duration of the call
+            let ready = unsafe { libc::poll(&mut fd, 1, remaining.as_millis() as libc::c_int) };
+            if ready <= 0 || tty.read(&mut byte).ok()? == 0 || reply.len() > 64 {
+                return None;
+            }
+            reply.push(byte[0]);
+        }
+        Some(reply)
+    };
+    let reply = read_reply();
+    let _ = disable_raw_mode();
+    parse_osc11(&String::from_utf8_lossy(&reply?))
+}
+
+#[cfg(not(unix))]
+fn query_background() -> Option<bool> {
+    None
+}
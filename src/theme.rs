@@ -1,8 +1,17 @@
 //! Theme handling for markdown rendering
 
+use crate::error::{LivemdError, Result};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use termimad::crossterm::style::Color;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use termimad::crossterm::{
+    style::{Attribute, Color, SetAttribute, SetForegroundColor},
+    QueueableCommand,
+};
+
+/// How many `"extends"` links a theme may follow before we assume a cycle
+const MAX_EXTENDS_DEPTH: usize = 8;
 
 /// Color theme for markdown rendering
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,19 +20,180 @@ pub struct Theme {
     #[serde(default = "default_heading")]
     pub heading: HeadingColors,
     /// Code block background/border color
-    pub code: String,
+    pub code: Style,
     /// Bold text color
-    pub bold: String,
+    pub bold: Style,
     /// Italic text color
-    pub italic: String,
+    pub italic: Style,
     /// Link color
-    pub link: String,
+    pub link: Style,
     /// List bullet color
-    pub list: String,
+    pub list: Style,
+    /// Table header row color
+    #[serde(rename = "table-header", default = "default_table_header")]
+    pub table_header: Style,
+    /// Background color for every other table row, or "dim" to dim them instead (None = no striping)
+    #[serde(rename = "table-stripe", default, skip_serializing_if = "Option::is_none")]
+    pub table_stripe: Option<String>,
+    /// Blockquote bar color (None = italic color)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blockquote: Option<Style>,
+    /// Table border color (None = terminal default)
+    #[serde(rename = "table-border", default, skip_serializing_if = "Option::is_none")]
+    pub table_border: Option<Style>,
+    /// Horizontal rule color (None = terminal default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<Style>,
+    /// Body text color (None = terminal default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<Style>,
+    /// Added lines in diff code blocks (None = code text color)
+    #[serde(rename = "diff-added", default, skip_serializing_if = "Option::is_none")]
+    pub diff_added: Option<Style>,
+    /// Removed lines in diff code blocks (None = code text color)
+    #[serde(rename = "diff-removed", default, skip_serializing_if = "Option::is_none")]
+    pub diff_removed: Option<Style>,
+    /// Strikethrough text color (None = body text color)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strikethrough: Option<Style>,
+    /// Inline math color (None = italic color)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub math: Option<Style>,
+    /// Image placeholder color (None = link color)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<Style>,
+    /// Code block overrides keyed by language (the fence's info string)
+    #[serde(rename = "code-languages", default, skip_serializing_if = "HashMap::is_empty")]
+    pub code_languages: HashMap<String, CodeStyle>,
+    /// GitHub alert (`> [!NOTE]`) colors keyed by type: note, tip, important, warning, caution
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub alerts: HashMap<String, Style>,
 }
 
 fn default_heading() -> HeadingColors {
-    HeadingColors::Single("#ffffff".to_string())
+    HeadingColors::Single("#ffffff".into())
+}
+
+fn default_table_header() -> Style {
+    "#ffffff".into()
+}
+
+/// Style for a theme element: a color plus optional text attributes. Theme files give either a
+/// bare color string or an object like `{"color": "#89b4fa", "bold": true, "underline": false}`.
+/// Unset attributes keep the element's usual look (e.g. bold text stays bold).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StyleEntry", into = "StyleEntry")]
+pub struct Style {
+    /// Color (hex or named); empty means the terminal default
+    pub color: String,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underline: Option<bool>,
+    pub dim: Option<bool>,
+}
+
+impl From<&str> for Style {
+    fn from(color: &str) -> Self {
+        Self { color: color.to_string(), ..Default::default() }
+    }
+}
+
+/// Code block overrides for one language, e.g. `{"dim": true}` for `text` blocks or
+/// `{"color": "#a6e3a1", "background": "#1e2e1e"}` for `diff` blocks
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CodeStyle {
+    /// Text color (None = the usual code text color)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// Background color (None = the theme's `code` color)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bold: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub italic: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub underline: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dim: Option<bool>,
+}
+
+impl CodeStyle {
+    /// The text attributes this override turns on
+    pub fn attributes(&self) -> Vec<Attribute> {
+        Style {
+            color: String::new(),
+            bold: self.bold,
+            italic: self.italic,
+            underline: self.underline,
+            dim: self.dim,
+        }
+        .attributes(&[])
+    }
+}
+
+/// How a style is written in a theme file
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StyleEntry {
+    Color(String),
+    Styled {
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        color: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bold: Option<bool>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        italic: Option<bool>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        underline: Option<bool>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        dim: Option<bool>,
+    },
+}
+
+impl From<StyleEntry> for Style {
+    fn from(entry: StyleEntry) -> Self {
+        match entry {
+            StyleEntry::Color(color) => Self { color, ..Default::default() },
+            StyleEntry::Styled { color, bold, italic, underline, dim } => Self { color, bold, italic, underline, dim },
+        }
+    }
+}
+
+impl From<Style> for StyleEntry {
+    fn from(style: Style) -> Self {
+        // Write plain colors back as strings
+        if style.bold.is_none() && style.italic.is_none() && style.underline.is_none() && style.dim.is_none() {
+            return StyleEntry::Color(style.color);
+        }
+        StyleEntry::Styled {
+            color: style.color,
+            bold: style.bold,
+            italic: style.italic,
+            underline: style.underline,
+            dim: style.dim,
+        }
+    }
+}
+
+impl Style {
+    /// Attributes to apply, starting from the element's usual `defaults` and letting the
+    /// style turn each one on or off. Defaults a style can't set (e.g. strikethrough) are kept.
+    pub fn attributes(&self, defaults: &[Attribute]) -> Vec<Attribute> {
+        let settable = [
+            (Attribute::Bold, self.bold),
+            (Attribute::Italic, self.italic),
+            (Attribute::Underlined, self.underline),
+            (Attribute::Dim, self.dim),
+        ];
+        let fixed = defaults.iter().copied().filter(|attr| !settable.iter().any(|(a, _)| a == attr));
+        settable
+            .into_iter()
+            .filter(|&(attr, set)| set.unwrap_or(defaults.contains(&attr)))
+            .map(|(attr, _)| attr)
+            .chain(fixed)
+            .collect()
+    }
 }
 
 /// Heading color configuration - either single color for all headers or individual colors
@@ -31,14 +201,18 @@ fn default_heading() -> HeadingColors {
 #[serde(untagged)]
 pub enum HeadingColors {
     /// Single color for all heading levels
-    Single(String),
+    Single(Style),
     /// Individual colors for each heading level (H1-H6)
-    Multiple(Vec<String>),
+    Multiple(Vec<Style>),
 }
 
 impl Theme {
     /// Convert string color name to crossterm Color
     pub fn parse_color(color_str: &str) -> Color {
+        if color_str.is_empty() {
+            return Color::Reset;
+        }
+
         // Check if it's a hex color
         if color_str.starts_with('#') && color_str.len() == 7 {
             if let (Ok(r), Ok(g), Ok(b)) = (
@@ -65,75 +239,318 @@ impl Theme {
         }
     }
 
+    /// Get the style for a theme field (None = terminal default)
+    pub fn get_style(&self, field: &str) -> Option<&Style> {
+        match field {
+            "code" => Some(&self.code),
+            "bold" => Some(&self.bold),
+            "italic" => Some(&self.italic),
+            "link" => Some(&self.link),
+            "list" => Some(&self.list),
+            "table_header" => Some(&self.table_header),
+            "blockquote" => Some(self.blockquote.as_ref().unwrap_or(&self.italic)),
+            "table_border" => self.table_border.as_ref(),
+            "rule" => self.rule.as_ref(),
+            "text" => self.text.as_ref(),
+            "diff_added" => self.diff_added.as_ref(),
+            "diff_removed" => self.diff_removed.as_ref(),
+            "strikethrough" => self.strikethrough.as_ref().or(self.text.as_ref()),
+            "math" => Some(self.math.as_ref().unwrap_or(&self.italic)),
+            "image" => Some(self.image.as_ref().unwrap_or(&self.link)),
+            _ => None,
+        }
+    }
+
+    /// Code block overrides for a fence language, matched case-insensitively
+    pub fn code_style(&self, lang: &str) -> Option<&CodeStyle> {
+        if lang.is_empty() {
+            return None;
+        }
+        self.code_languages
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(lang))
+            .map(|(_, style)| style)
+    }
+
+    /// Style for a GitHub alert type (note, tip, ...), falling back to the blockquote style
+    pub fn alert_style(&self, kind: &str) -> &Style {
+        self.alerts
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(kind))
+            .map(|(_, style)| style)
+            .unwrap_or_else(|| self.blockquote.as_ref().unwrap_or(&self.italic))
+    }
+
     /// Get color for a theme field
     pub fn get_color(&self, field: &str) -> Color {
-        let color_str = match field {
-            "code" => &self.code,
-            "bold" => &self.bold,
-            "italic" => &self.italic,
-            "link" => &self.link,
-            "list" => &self.list,
-            _ => "white",
+        self.get_style(field).map_or(Color::Reset, |style| Self::parse_color(&style.color))
+    }
+
+    /// Queue a theme field's color and attributes, where `defaults` are the attributes the
+    /// element normally has (e.g. bold for bold text)
+    pub fn apply_style(&self, out: &mut impl Write, field: &str, defaults: &[Attribute]) {
+        let attributes = match self.get_style(field) {
+            Some(style) => style.attributes(defaults),
+            None => defaults.to_vec(),
         };
-        Self::parse_color(color_str)
+        for attr in attributes {
+            let _ = out.queue(SetAttribute(attr));
+        }
+        let _ = out.queue(SetForegroundColor(self.get_color(field)));
     }
 
-    /// Get heading color for a specific level (1-6)
-    pub fn get_heading_color(&self, level: usize) -> Color {
+    /// Get heading style for a specific level (1-6)
+    pub fn get_heading_style(&self, level: usize) -> Style {
         match &self.heading {
-            HeadingColors::Single(color) => Self::parse_color(color.as_str()),
-            HeadingColors::Multiple(colors) => {
-                if let Some(c) = colors.get(level.saturating_sub(1)) {
-                    Self::parse_color(c.as_str())
-                } else if let Some(last) = colors.last() {
-                    Self::parse_color(last.as_str())
-                } else {
-                    Self::parse_color("#ffffff")
-                }
-            }
+            HeadingColors::Single(style) => style.clone(),
+            HeadingColors::Multiple(styles) => styles
+                .get(level.saturating_sub(1))
+                .or(styles.last())
+                .cloned()
+                .unwrap_or_else(|| "#ffffff".into()),
         }
     }
 
+    /// Get heading color for a specific level (1-6)
+    pub fn get_heading_color(&self, level: usize) -> Color {
+        Self::parse_color(&self.get_heading_style(level).color)
+    }
+
     /// Default dark theme
     pub fn dark() -> Self {
         Self {
-            heading: HeadingColors::Single("#89b4fa".to_string()), // Catppuccin blue
-            code: "#1e1e2e".to_string(), // Catppuccin base
-            bold: "#cdd6f4".to_string(), // Catppuccin text
-            italic: "#f5c2e7".to_string(), // Catppuccin pink
-            link: "#a6e3a1".to_string(), // Catppuccin green
-            list: "#f9e2af".to_string(), // Catppuccin yellow
+            heading: HeadingColors::Single("#89b4fa".into()), // Catppuccin blue
+            code: "#1e1e2e".into(), // Catppuccin base
+            bold: "#cdd6f4".into(), // Catppuccin text
+            italic: "#f5c2e7".into(), // Catppuccin pink
+            link: "#a6e3a1".into(), // Catppuccin green
+            list: "#f9e2af".into(), // Catppuccin yellow
+            table_header: "#89b4fa".into(), // Catppuccin blue
+            table_stripe: None,
+            blockquote: Some("#9399b2".into()), // Catppuccin overlay2
+            table_border: Some("#6c7086".into()), // Catppuccin overlay0
+            rule: Some("#6c7086".into()), // Catppuccin overlay0
+            text: Some("#cdd6f4".into()), // Catppuccin text
+            diff_added: Some("#a6e3a1".into()), // Catppuccin green
+            diff_removed: Some("#f38ba8".into()), // Catppuccin red
+            strikethrough: Some("#7f849c".into()), // Catppuccin overlay1
+            math: Some("#fab387".into()), // Catppuccin peach
+            image: Some("#94e2d5".into()), // Catppuccin teal
+            code_languages: HashMap::new(),
+            // Catppuccin blue, green, mauve, yellow, red
+            alerts: alert_colors(["#89b4fa", "#a6e3a1", "#cba6f7", "#f9e2af", "#f38ba8"]),
         }
     }
 
     /// Light theme for light backgrounds
     pub fn light() -> Self {
         Self {
-            heading: HeadingColors::Single("#1e66f5".to_string()), // Catppuccin blue
-            code: "#eff1f5".to_string(), // Catppuccin base
-            bold: "#4c4f69".to_string(), // Catppuccin text
-            italic: "#ea76cb".to_string(), // Catppuccin pink
-            link: "#40a02b".to_string(), // Catppuccin green
-            list: "#df8e1d".to_string(), // Catppuccin yellow
+            heading: HeadingColors::Single("#1e66f5".into()), // Catppuccin blue
+            code: "#eff1f5".into(), // Catppuccin base
+            bold: "#4c4f69".into(), // Catppuccin text
+            italic: "#ea76cb".into(), // Catppuccin pink
+            link: "#40a02b".into(), // Catppuccin green
+            list: "#df8e1d".into(), // Catppuccin yellow
+            table_header: "#1e66f5".into(), // Catppuccin blue
+            table_stripe: None,
+            blockquote: Some("#7c7f93".into()), // Catppuccin overlay2
+            table_border: Some("#9ca0b0".into()), // Catppuccin overlay0
+            rule: Some("#9ca0b0".into()), // Catppuccin overlay0
+            text: Some("#4c4f69".into()), // Catppuccin text
+            diff_added: Some("#40a02b".into()), // Catppuccin green
+            diff_removed: Some("#d20f39".into()), // Catppuccin red
+            strikethrough: Some("#8c8fa1".into()), // Catppuccin overlay1
+            math: Some("#fe640b".into()), // Catppuccin peach
+            image: Some("#179299".into()), // Catppuccin teal
+            code_languages: HashMap::new(),
+            // Catppuccin blue, green, mauve, yellow, red
+            alerts: alert_colors(["#1e66f5", "#40a02b", "#8839ef", "#df8e1d", "#d20f39"]),
         }
     }
 
     /// Monochrome theme
     pub fn mono() -> Self {
         Self {
-            heading: HeadingColors::Single("#4c4f69".to_string()), // Catppuccin blue
-            code: "#dce0e8".to_string(), // Catppuccin surface0
-            bold: "#4c4f69".to_string(), // Catppuccin text
-            italic: "#4c4f69".to_string(), // Catppuccin text
-            link: "#40a02b".to_string(), // Catppuccin green
-            list: "#df8e1d".to_string(), // Catppuccin yellow
+            heading: HeadingColors::Single("#4c4f69".into()), // Catppuccin blue
+            code: "#dce0e8".into(), // Catppuccin surface0
+            bold: "#4c4f69".into(), // Catppuccin text
+            italic: "#4c4f69".into(), // Catppuccin text
+            link: "#40a02b".into(), // Catppuccin green
+            list: "#df8e1d".into(), // Catppuccin yellow
+            table_header: "#4c4f69".into(), // Catppuccin text
+            table_stripe: None,
+            blockquote: Some("#8c8fa1".into()), // Catppuccin overlay1
+            table_border: Some("#8c8fa1".into()), // Catppuccin overlay1
+            rule: Some("#8c8fa1".into()), // Catppuccin overlay1
+            text: None,
+            diff_added: None,
+            diff_removed: None,
+            strikethrough: None,
+            math: None,
+            image: None,
+            code_languages: HashMap::new(),
+            alerts: HashMap::new(),
         }
     }
 
-    /// Load theme from JSON file
-    pub fn from_file(path: &PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
+    /// Names of the built-in themes
+    pub const BUILTIN_NAMES: [&'static str; 3] = ["dark", "light", "mono"];
+
+    /// Directory custom themes are loaded from (`themes` in the config directory)
+    pub fn themes_dir() -> Option<PathBuf> {
+        crate::config::config_dir().map(|dir| dir.join("themes"))
+    }
+
+    /// Names of the custom themes in the themes directory, sorted
+    pub fn custom_names() -> Vec<String> {
+        let mut names: Vec<String> = Self::themes_dir()
+            .and_then(|dir| std::fs::read_dir(dir).ok())
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| Self::is_theme_file(path))
+            .filter_map(|path| path.file_stem().and_then(|s| s.to_str()).map(str::to_string))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Find a custom theme file by name in the themes directory, or by path
+    pub fn find_file(name: &str) -> Option<PathBuf> {
+        let dir = Self::themes_dir();
+        ["json", "yaml", "yml"]
+            .iter()
+            .find_map(|ext| dir.as_ref().map(|d| d.join(format!("{}.{}", name, ext))).filter(|p| p.exists()))
+            .or_else(|| Some(PathBuf::from(name)).filter(|p| p.is_file()))
+    }
+
+    /// Whether a path looks like a theme: livemd JSON or a base16 YAML scheme
+    fn is_theme_file(path: &Path) -> bool {
+        path.extension().is_some_and(|ext| ext == "json" || ext == "yaml" || ext == "yml")
+    }
+
+    /// Whether a path is a base16 YAML scheme rather than livemd JSON
+    fn is_base16(path: &Path) -> bool {
+        path.extension().is_some_and(|ext| ext == "yaml" || ext == "yml")
+    }
+
+    /// Build a theme from a base16 scheme file, mapping the palette onto theme slots the way
+    /// the base16 styling guidelines do (headings base0D, bold base0A, italic base0E, ...)
+    pub fn from_base16(path: &Path) -> Result<Self> {
         let content = std::fs::read_to_string(path)?;
-        let theme: Theme = serde_json::from_str(&content)?;
-        Ok(theme)
+        let palette = parse_base16(&content);
+        let hex = |slot: &str| -> Result<String> {
+            let hex = palette.get(slot).ok_or_else(|| LivemdError::Theme(format!("base16 scheme is missing {}", slot)))?;
+            Ok(format!("#{}", hex))
+        };
+        let color = |slot: &str| hex(slot).map(|h| Style::from(h.as_str()));
+        Ok(Self {
+            heading: HeadingColors::Single(color("base0D")?),
+            code: color("base01")?,
+            bold: color("base0A")?,
+            italic: color("base0E")?,
+            link: color("base08")?,
+            list: color("base08")?,
+            table_header: color("base0D")?,
+            table_stripe: None,
+            blockquote: Some(color("base0C")?),
+            table_border: Some(color("base03")?),
+            rule: Some(color("base03")?),
+            text: Some(color("base05")?),
+            diff_added: Some(color("base0B")?),
+            diff_removed: Some(color("base08")?),
+            strikethrough: Some(color("base04")?),
+            math: Some(color("base09")?),
+            image: Some(color("base0C")?),
+            code_languages: HashMap::new(),
+            alerts: alert_colors([
+                &hex("base0D")?,
+                &hex("base0B")?,
+                &hex("base0E")?,
+                &hex("base0A")?,
+                &hex("base08")?,
+            ]),
+        })
     }
-}
\ No newline at end of file
+
+    /// Look up a built-in theme by name
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "mono" => Some(Self::mono()),
+            _ => None,
+        }
+    }
+
+    /// Load theme from a JSON file, resolving any `"extends"` chain, or from a base16 YAML scheme
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let value = Self::load_value(path, 0)?;
+        serde_json::from_value(value).map_err(|e| LivemdError::Theme(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Read a theme file as JSON with the theme it extends merged underneath. `"extends"` names
+    /// a built-in theme, or another theme file relative to this one (".json" may be omitted).
+    /// Fields are merged at the top level, so an overridden `heading` replaces the whole entry.
+    fn load_value(path: &Path, depth: usize) -> Result<serde_json::Value> {
+        let invalid = |e: serde_json::Error| LivemdError::Theme(format!("{}: {}", path.display(), e));
+        if depth > MAX_EXTENDS_DEPTH {
+            return Err(LivemdError::Theme(format!("theme \"extends\" chain is too deep at {:?} (is there a cycle?)", path)));
+        }
+        if Self::is_base16(path) {
+            return serde_json::to_value(Self::from_base16(path)?).map_err(invalid);
+        }
+        let content = std::fs::read_to_string(path).map_err(|e| LivemdError::Theme(format!("can't read {}: {}", path.display(), e)))?;
+        let value: serde_json::Value = serde_json::from_str(&content).map_err(invalid)?;
+        let serde_json::Value::Object(mut fields) = value else {
+            return Ok(value);
+        };
+        let Some(parent) = fields.remove("extends") else {
+            return Ok(serde_json::Value::Object(fields));
+        };
+        let parent = parent.as_str().ok_or_else(|| LivemdError::Theme("\"extends\" must be a theme name or path".to_string()))?;
+
+        let mut base = match Self::builtin(parent) {
+            Some(theme) => serde_json::to_value(theme).map_err(invalid)?,
+            None => {
+                let mut parent_path = path.parent().unwrap_or(Path::new(".")).join(parent);
+                if parent_path.extension().is_none() {
+                    parent_path.set_extension("json");
+                }
+                Self::load_value(&parent_path, depth + 1)?
+            }
+        };
+        if let serde_json::Value::Object(base_fields) = &mut base {
+            base_fields.extend(fields);
+        }
+        Ok(base)
+    }
+}
+
+/// Alert styles from colors for note, tip, important, warning, and caution
+fn alert_colors(colors: [&str; 5]) -> HashMap<String, Style> {
+    ["note", "tip", "important", "warning", "caution"]
+        .into_iter()
+        .zip(colors)
+        .map(|(kind, color)| (kind.to_string(), Style::from(color)))
+        .collect()
+}
+
+/// Read the `base00`-`base0F` colors from a base16 scheme as lowercase hex without `#`.
+/// Only the flat `key: value` lines matter, so this skips a full YAML parser; it also accepts
+/// the nested `palette:` layout of newer (tinted-theming) schemes.
+fn parse_base16(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.split(" #").next()?.trim();
+            let (key, value) = line.split_once(':')?;
+            let key = key.trim().trim_matches(['"', '\'']);
+            let value = value.trim().trim_matches(['"', '\'']).trim_start_matches('#');
+            let is_slot = key.len() == 6 && key.starts_with("base0");
+            let is_hex = value.len() == 6 && value.chars().all(|c| c.is_ascii_hexdigit());
+            // Slot names are case-insensitive in the wild (base0a vs base0A)
+            (is_slot && is_hex).then(|| (format!("base0{}", key[5..].to_uppercase()), value.to_lowercase()))
+        })
+        .collect()
+}
@@ -0,0 +1,42 @@
+//! Headings of a document, for the table of contents
+
+use crate::math;
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+
+/// A heading's level (1-6) and plain text
+#[derive(Debug, Clone)]
+pub struct Heading {
+    pub level: usize,
+    pub text: String,
+}
+
+/// Every heading in a markdown document, in order, with inline formatting removed
+pub fn headings(markdown: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut current: Option<Heading> = None;
+    for event in Parser::new_ext(markdown, Options::all()) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                current = Some(Heading { level: level as usize, text: String::new() });
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(mut heading) = current.take() {
+                    heading.text = heading.text.trim().to_string();
+                    headings.push(heading);
+                }
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(heading) = current.as_mut() {
+                    heading.text.push_str(&text);
+                }
+            }
+            Event::InlineMath(latex) => {
+                if let Some(heading) = current.as_mut() {
+                    heading.text.push_str(&math::to_unicode(&latex));
+                }
+            }
+            _ => {}
+        }
+    }
+    headings
+}
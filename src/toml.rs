@@ -0,0 +1,450 @@
+//! A small TOML reader for config files. It reads the parts of TOML a config file uses
+//! (tables, dotted keys, strings, numbers, booleans, arrays, inline tables) into JSON values,
+//! so TOML and JSON configs deserialize the same way. Dates and arrays of tables aren't supported.
+
+use serde_json::{Map, Value};
+
+/// A syntax error and the line it's on
+#[derive(Debug)]
+pub struct Error {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Parse a TOML document into a JSON object
+pub fn parse(text: &str) -> Result<Value, Error> {
+    let mut parser = Parser { chars: text.chars().collect(), pos: 0, line: 1 };
+    parser.document().map_err(|message| Error { line: parser.line, message })
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.bump() {
+            Some(found) if found == c => Ok(()),
+            Some(found) => Err(format!("expected `{}`, found `{}`", c, found)),
+            None => Err(format!("expected `{}` at end of file", c)),
+        }
+    }
+
+    /// Skip spaces and tabs on the current line
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.bump();
+        }
+    }
+
+    /// Skip whitespace, newlines, and comments
+    fn skip_blank(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r' | '\n') => {
+                    self.bump();
+                }
+                Some('#') => self.skip_comment(),
+                _ => break,
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while !matches!(self.peek(), None | Some('\n')) {
+            self.bump();
+        }
+    }
+
+    /// Nothing but a comment may follow a key/value pair or a table header on its line
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('#') => self.skip_comment(),
+            None | Some('\n') => {}
+            Some('\r') if self.chars.get(self.pos + 1) == Some(&'\n') => {}
+            Some(c) => return Err(format!("unexpected `{}` after value", c)),
+        }
+        Ok(())
+    }
+
+    fn document(&mut self) -> Result<Value, String> {
+        let mut root = Map::new();
+        let mut table: Vec<String> = Vec::new();
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                None => break,
+                Some('[') => {
+                    if self.starts_with("[[") {
+                        return Err("arrays of tables aren't supported".to_string());
+                    }
+                    self.bump();
+                    self.skip_spaces();
+                    table = self.key()?;
+                    self.skip_spaces();
+                    self.expect(']')?;
+                    self.end_of_line()?;
+                    table_at(&mut root, &table)?;
+                }
+                Some(_) => {
+                    let key = self.key()?;
+                    self.skip_spaces();
+                    self.expect('=')?;
+                    self.skip_spaces();
+                    let value = self.value()?;
+                    self.end_of_line()?;
+                    insert(table_at(&mut root, &table)?, &key, value)?;
+                }
+            }
+        }
+        Ok(Value::Object(root))
+    }
+
+    /// A key, possibly dotted: `a`, `"a b"`, `a.b`
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_spaces();
+            let part = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let mut part = String::new();
+                    while let Some(c) = self.peek().filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_')) {
+                        part.push(c);
+                        self.bump();
+                    }
+                    if part.is_empty() {
+                        return Err(match self.peek() {
+                            Some(c) => format!("expected a key, found `{}`", c),
+                            None => "expected a key at end of file".to_string(),
+                        });
+                    }
+                    part
+                }
+            };
+            parts.push(part);
+            self.skip_spaces();
+            if self.peek() != Some('.') {
+                return Ok(parts);
+            }
+            self.bump();
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') if self.starts_with("\"\"\"") => self.multiline_string('"').map(Value::String),
+            Some('\'') if self.starts_with("'''") => self.multiline_string('\'').map(Value::String),
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some(_) if self.starts_with("true") => {
+                self.pos += 4;
+                Ok(Value::Bool(true))
+            }
+            Some(_) if self.starts_with("false") => {
+                self.pos += 5;
+                Ok(Value::Bool(false))
+            }
+            Some(c) if c.is_ascii_digit() || matches!(c, '+' | '-') => self.number(),
+            Some('\r' | '\n' | '#') | None => Err("expected a value".to_string()),
+            Some(c) => Err(format!("unsupported value starting with `{}`", c)),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let mut text = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_')) {
+            text.push(c);
+            self.bump();
+        }
+        let digits = text.replace('_', "");
+        let is_float = digits.contains(['.', 'e', 'E']) && !digits.starts_with("0x");
+        let number = if is_float {
+            digits.parse::<f64>().ok().and_then(serde_json::Number::from_f64)
+        } else if let Some(hex) = digits.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok().map(Into::into)
+        } else {
+            digits.parse::<i64>().ok().map(Into::into)
+        };
+        number.map(Value::Number).ok_or_else(|| format!("invalid number `{}`", text))
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(out),
+                Some('\\') => out.push(self.escape()?),
+                Some('\n') | None => return Err("unterminated string".to_string()),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        self.expect('\'')?;
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                Some('\'') => return Ok(out),
+                Some('\n') | None => return Err("unterminated string".to_string()),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    /// A `"""` or `'''` string. A newline right after the opening quotes is dropped, and in
+    /// `"""` strings a backslash at the end of a line joins it to the next non-blank text.
+    fn multiline_string(&mut self, quote: char) -> Result<String, String> {
+        let delimiter: String = std::iter::repeat_n(quote, 3).collect();
+        self.pos += 3;
+        if self.starts_with("\r\n") {
+            self.pos += 1;
+        }
+        if self.peek() == Some('\n') {
+            self.bump();
+        }
+        let mut out = String::new();
+        loop {
+            if self.starts_with(&delimiter) {
+                self.pos += 3;
+                return Ok(out);
+            }
+            match self.bump() {
+                Some('\\') if quote == '"' => {
+                    if matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) {
+                        while matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) {
+                            self.bump();
+                        }
+                    } else {
+                        out.push(self.escape()?);
+                    }
+                }
+                Some(c) => out.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    /// The character for a backslash escape, after the backslash
+    fn escape(&mut self) -> Result<char, String> {
+        let c = self.bump().ok_or("unterminated string")?;
+        Ok(match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'e' => '\u{1b}',
+            '"' => '"',
+            '\\' => '\\',
+            'u' | 'U' => {
+                let len = if c == 'u' { 4 } else { 8 };
+                let hex: String = (0..len).filter_map(|_| self.bump()).collect();
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("invalid unicode escape `\\{}{}`", c, hex))?
+            }
+            c => return Err(format!("invalid escape `\\{}`", c)),
+        })
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.peek() == Some(']') {
+                self.bump();
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank();
+            match self.bump() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err("expected `,` or `]` in array".to_string()),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut table = Map::new();
+        self.skip_spaces();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(Value::Object(table));
+        }
+        loop {
+            let key = self.key()?;
+            self.skip_spaces();
+            self.expect('=')?;
+            self.skip_spaces();
+            let value = self.value()?;
+            insert(&mut table, &key, value)?;
+            self.skip_spaces();
+            match self.bump() {
+                Some(',') => self.skip_spaces(),
+                Some('}') => return Ok(Value::Object(table)),
+                _ => return Err("expected `,` or `}` in inline table".to_string()),
+            }
+        }
+    }
+}
+
+/// The table at a dotted path, created if missing
+fn table_at<'a>(root: &'a mut Map<String, Value>, path: &[String]) -> Result<&'a mut Map<String, Value>, String> {
+    let mut table = root;
+    for part in path {
+        table = match table.entry(part.clone()).or_insert_with(|| Value::Object(Map::new())) {
+            Value::Object(inner) => inner,
+            _ => return Err(format!("`{}` is already a value, not a table", part)),
+        };
+    }
+    Ok(table)
+}
+
+/// Set a dotted key in a table
+fn insert(table: &mut Map<String, Value>, key: &[String], value: Value) -> Result<(), String> {
+    let (last, parents) = key.split_last().expect("keys have at least one part");
+    let table = table_at(table, parents)?;
+    if table.contains_key(last) {
+        return Err(format!("duplicate key `{}`", key.join(".")));
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// The line and message of a parse error
+    fn error(text: &str) -> (usize, String) {
+        let e = parse(text).unwrap_err();
+        (e.line, e.message)
+    }
+
+    #[test]
+    fn basic_strings_unescape() {
+        let value = parse(r#"a = "tab\there \"quoted\" \\ \u00e9 \U0001F600 \e[1m""#).unwrap();
+        assert_eq!(value, json!({"a": "tab\there \"quoted\" \\ é 😀 \u{1b}[1m"}));
+    }
+
+    #[test]
+    fn literal_strings_keep_backslashes() {
+        let value = parse(r"path = 'C:\Users\n'").unwrap();
+        assert_eq!(value, json!({"path": r"C:\Users\n"}));
+    }
+
+    #[test]
+    fn multiline_strings_drop_the_first_newline_and_join_escaped_line_ends() {
+        let value = parse("a = \"\"\"\none \\\n    two\nthree\"\"\"\nb = '''\nraw \\n'''").unwrap();
+        assert_eq!(value, json!({"a": "one two\nthree", "b": "raw \\n"}));
+    }
+
+    #[test]
+    fn invalid_escapes_are_errors() {
+        assert_eq!(error(r#"a = "\q""#), (1, "invalid escape `\\q`".to_string()));
+        assert_eq!(error("a = \"open\nb = 1").1, "unterminated string");
+    }
+
+    #[test]
+    fn scalars() {
+        let value = parse("int = -1_000\nhex = 0xff\nfloat = 1.5e2\nyes = true\nno = false # comment").unwrap();
+        assert_eq!(value, json!({"int": -1000, "hex": 255, "float": 150.0, "yes": true, "no": false}));
+    }
+
+    #[test]
+    fn arrays_span_lines_with_trailing_commas_and_comments() {
+        let value = parse("list = [\n  1, # one\n  \"two\",\n  [3],\n]\nempty = []").unwrap();
+        assert_eq!(value, json!({"list": [1, "two", [3]], "empty": []}));
+    }
+
+    #[test]
+    fn inline_tables_with_dotted_keys() {
+        let value = parse(r#"theme = { heading = "red", code.fg = "blue" }"#).unwrap();
+        assert_eq!(value, json!({"theme": {"heading": "red", "code": {"fg": "blue"}}}));
+        assert_eq!(parse("empty = {}").unwrap(), json!({"empty": {}}));
+    }
+
+    #[test]
+    fn dotted_table_headers_nest() {
+        let text = "speed = 0.01\n\n[profiles.fast]\nspeed = 0\n\n[profiles.\"dark mode\"]\ntheme = 'dark'\n[llm-cmd]\ndefault = \"aichat\"";
+        let value = parse(text).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "speed": 0.01,
+                "profiles": {"fast": {"speed": 0}, "dark mode": {"theme": "dark"}},
+                "llm-cmd": {"default": "aichat"},
+            })
+        );
+    }
+
+    #[test]
+    fn headers_and_dotted_keys_share_tables() {
+        let value = parse("profiles.fast.speed = 0\n[profiles.fast]\nstream = false").unwrap();
+        assert_eq!(value, json!({"profiles": {"fast": {"speed": 0, "stream": false}}}));
+    }
+
+    #[test]
+    fn duplicate_keys_are_errors() {
+        assert_eq!(error("a = 1\nb = 2\na = 3"), (3, "duplicate key `a`".to_string()));
+        assert_eq!(error("[t]\nx.y = 1\nx.y = 2"), (3, "duplicate key `x.y`".to_string()));
+        assert_eq!(error("t = { a = 1, a = 2 }").1, "duplicate key `a`");
+    }
+
+    #[test]
+    fn a_value_is_not_a_table() {
+        assert_eq!(error("a = 1\n[a.b]"), (2, "`a` is already a value, not a table".to_string()));
+    }
+
+    #[test]
+    fn errors_report_their_line() {
+        assert_eq!(error("a = 1\n\n# comment\nb = \n"), (4, "expected a value".to_string()));
+        assert_eq!(error("a = 1 2"), (1, "unexpected `2` after value".to_string()));
+        assert_eq!(error("a = [\n1,\n2\n3]"), (4, "expected `,` or `]` in array".to_string()));
+        assert_eq!(error("x = 1\n[[servers]]").0, 2);
+        assert_eq!(error("when = 1979-05-27T07:32:00Z").1, "invalid number `1979-05-27T07`");
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let value = parse("a = 1\r\n[t]\r\nb = 'x'\r\n").unwrap();
+        assert_eq!(value, json!({"a": 1, "t": {"b": "x"}}));
+    }
+}
@@ -0,0 +1,97 @@
+//! Width-aware soft wrapping
+
+use unicode_width::UnicodeWidthStr;
+
+/// Wrap `text` for output starting at `column`, breaking between words so no line exceeds `width`.
+/// Returns the text with line breaks inserted and the column after printing it.
+pub fn wrap_text(text: &str, column: usize, width: usize) -> (String, usize) {
+    let width = width.max(1);
+    let mut out = String::with_capacity(text.len());
+    let mut column = column;
+
+    for segment in split_segments(text) {
+        if segment == "\n" {
+            out.push('\n');
+            column = 0;
+        } else if segment.starts_with(char::is_whitespace) {
+            // Drop whitespace at the start of a wrapped line
+            if column > 0 && column < width {
+                out.push(' ');
+                column += 1;
+            }
+        } else {
+            let mut word = segment;
+            let mut word_width = word.width();
+            if column > 0 && column + word_width > width {
+                // Trim the trailing space we may have emitted before breaking
+                if out.ends_with(' ') {
+                    out.pop();
+                }
+                out.push('\n');
+                column = 0;
+            }
+            // Hard-split words longer than a full line
+            while word_width > width {
+                let split = split_at_width(word, width);
+                out.push_str(&word[..split]);
+                out.push('\n');
+                column = 0;
+                word = &word[split..];
+                word_width = word.width();
+            }
+            out.push_str(word);
+            column += word_width;
+        }
+    }
+
+    (out, column)
+}
+
+/// Like `wrap_text`, with every line after a break starting `indent` columns in, for hanging
+/// indents. `column` and the returned column include the indent.
+pub fn wrap_text_indented(text: &str, column: usize, width: usize, indent: usize) -> (String, usize) {
+    let (wrapped, column) = wrap_text(text, column.saturating_sub(indent), width.saturating_sub(indent));
+    (wrapped.replace('\n', &format!("\n{}", " ".repeat(indent))), column + indent)
+}
+
+/// Split text into runs of words, runs of whitespace, and individual newlines
+fn split_segments(text: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut prev_space: Option<bool> = None;
+
+    for (i, ch) in text.char_indices() {
+        if ch == '\n' {
+            if start < i {
+                segments.push(&text[start..i]);
+            }
+            segments.push(&text[i..i + 1]);
+            start = i + 1;
+            prev_space = None;
+            continue;
+        }
+        let is_space = ch.is_whitespace();
+        if prev_space.is_some_and(|p| p != is_space) {
+            segments.push(&text[start..i]);
+            start = i;
+        }
+        prev_space = Some(is_space);
+    }
+    if start < text.len() {
+        segments.push(&text[start..]);
+    }
+    segments
+}
+
+/// Byte index of the longest prefix of `word` that fits in `width` columns (at least one char)
+pub fn split_at_width(word: &str, width: usize) -> usize {
+    let mut used = 0;
+    for (i, ch) in word.char_indices() {
+        let w = unicode_width::UnicodeWidthChar::width(ch).unwrap_or(0);
+        if used + w > width && i > 0 {
+            return i;
+        }
+        used += w;
+    }
+    word.len()
+}
@@ -0,0 +1,282 @@
+//! Just enough of a WebSocket client for `--ws`: the opening handshake, then text messages
+//! as they arrive, answering pings and the closing handshake. Only `ws://` URLs; there's no
+//! TLS for `wss://`.
+
+use crate::clipboard::base64;
+use crate::error::{LivemdError, Result};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// Largest frame accepted, which keeps a bad length from allocating without bound
+const MAX_FRAME: u64 = 64 * 1024 * 1024;
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+pub struct WebSocket {
+    stream: BufReader<TcpStream>,
+    /// Bytes of a character split across frames, held until the rest arrives
+    pending: Vec<u8>,
+    /// Whether continuation frames belong to a binary message, which is skipped
+    in_binary: bool,
+}
+
+impl WebSocket {
+    /// Connect to a `ws://host[:port]/path` URL and complete the opening handshake
+    pub async fn connect(url: &str) -> Result<Self> {
+        let error = |message: String| LivemdError::Input(format!("--ws {}: {}", url, message));
+        if url.starts_with("wss://") {
+            return Err(error("wss:// isn't supported; use ws:// (a TLS proxy such as stunnel can sit in front)".to_string()));
+        }
+        let rest = url.strip_prefix("ws://").ok_or_else(|| error("expected a ws:// URL".to_string()))?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        };
+        // An IPv6 host is in brackets, and a port follows the last colon after it
+        let (host, port) = match authority.rfind(':').filter(|&colon| !authority[colon..].contains(']')) {
+            Some(colon) => {
+                let port = authority[colon + 1..].parse::<u16>().map_err(|_| error(format!("bad port in {}", authority)))?;
+                (&authority[..colon], port)
+            }
+            None => (authority, 80),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        let stream = TcpStream::connect((host, port)).await.map_err(|e| error(e.to_string()))?;
+        let mut stream = BufReader::new(stream);
+        let key = base64(&random_bytes::<16>());
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path, authority, key
+        );
+        stream.get_mut().write_all(request.as_bytes()).await?;
+
+        let mut status = String::new();
+        stream.read_line(&mut status).await?;
+        if status.split_whitespace().nth(1) != Some("101") {
+            return Err(error(format!("the server didn't accept the connection ({})", status.trim())));
+        }
+        // The rest of the response is headers, up to a blank line
+        let mut line = String::new();
+        while stream.read_line(&mut line).await? > 0 && !line.trim().is_empty() {
+            line.clear();
+        }
+        Ok(WebSocket { stream, pending: Vec::new(), in_binary: false })
+    }
+
+    /// The text of the next text frame, or None once the connection is closed
+    pub async fn next_text(&mut self) -> Result<Option<String>> {
+        loop {
+            let Some((opcode, payload)) = self.read_frame().await? else {
+                return Ok(None);
+            };
+            match opcode {
+                TEXT => self.in_binary = false,
+                BINARY => {
+                    self.in_binary = true;
+                    continue;
+                }
+                CONTINUATION if self.in_binary => continue,
+                CONTINUATION => {}
+                CLOSE => {
+                    // Echo the status code back, which completes the closing handshake
+                    let _ = self.write_frame(CLOSE, &payload[..payload.len().min(2)]).await;
+                    return Ok(None);
+                }
+                PING => {
+                    self.write_frame(PONG, &payload).await?;
+                    continue;
+                }
+                _ => continue,
+            }
+            self.pending.extend(payload);
+            let complete = match std::str::from_utf8(&self.pending) {
+                // Hold back a character cut off at the end of the frame
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                _ => self.pending.len(),
+            };
+            let text = String::from_utf8_lossy(&self.pending[..complete]).into_owned();
+            self.pending.drain(..complete);
+            if !text.is_empty() {
+                return Ok(Some(text));
+            }
+        }
+    }
+
+    /// Read a frame's opcode and payload, or None at the end of the connection
+    async fn read_frame(&mut self) -> Result<Option<(u8, Vec<u8>)>> {
+        let mut head = [0u8; 2];
+        match self.stream.read_exact(&mut head).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let opcode = head[0] & 0x0f;
+        let masked = head[1] & 0x80 != 0;
+        let length = match head[1] & 0x7f {
+            126 => self.stream.read_u16().await? as u64,
+            127 => self.stream.read_u64().await?,
+            length => length as u64,
+        };
+        if length > MAX_FRAME {
+            return Err(LivemdError::Input(format!("--ws: a {}-byte frame is too large", length)));
+        }
+        let mut mask = [0u8; 4];
+        if masked {
+            self.stream.read_exact(&mut mask).await?;
+        }
+        let mut payload = vec![0; length as usize];
+        self.stream.read_exact(&mut payload).await?;
+        if masked {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+        Ok(Some((opcode, payload)))
+    }
+
+    /// Send a control frame; frames from a client are always masked
+    async fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<()> {
+        let mask = random_bytes::<4>();
+        // Control frame payloads are at most 125 bytes
+        let payload = &payload[..payload.len().min(125)];
+        let mut frame = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+        self.stream.get_mut().write_all(&frame).await?;
+        Ok(())
+    }
+}
+
+/// Bytes for the handshake key and frame masks, which need to be unpredictable but not secret
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    for chunk in bytes.chunks_mut(8) {
+        let random = RandomState::new().build_hasher().finish().to_le_bytes();
+        chunk.copy_from_slice(&random[..chunk.len()]);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// An unmasked frame, the way a server sends them
+    fn frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+        match payload.len() {
+            len @ 0..=125 => frame.push(len as u8),
+            len => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    /// Accept one connection, answer the handshake with `status`, send `frames`, and return
+    /// the client's frames as (opcode, unmasked payload)
+    async fn serve(status: &'static str, frames: Vec<u8>) -> (String, tokio::task::JoinHandle<Vec<(u8, Vec<u8>)>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/stream", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap() > 0 && line != "\r\n" {
+                line.clear();
+            }
+            stream.get_mut().write_all(format!("HTTP/1.1 {}\r\nUpgrade: websocket\r\n\r\n", status).as_bytes()).await.unwrap();
+            stream.get_mut().write_all(&frames).await.unwrap();
+            let mut received = Vec::new();
+            let mut head = [0u8; 2];
+            while stream.read_exact(&mut head).await.is_ok() {
+                let mut mask = [0u8; 4];
+                stream.read_exact(&mut mask).await.unwrap();
+                let mut payload = vec![0; (head[1] & 0x7f) as usize];
+                stream.read_exact(&mut payload).await.unwrap();
+                let payload = payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]).collect();
+                received.push((head[0] & 0x0f, payload));
+            }
+            received
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn text_messages_arrive_in_order() {
+        let long = "x".repeat(300);
+        let frames = [frame(true, TEXT, b"# Title\n"), frame(true, TEXT, long.as_bytes()), frame(true, CLOSE, &[])].concat();
+        let (url, _server) = serve("101 Switching Protocols", frames).await;
+        let mut ws = WebSocket::connect(&url).await.unwrap();
+        assert_eq!(ws.next_text().await.unwrap().as_deref(), Some("# Title\n"));
+        assert_eq!(ws.next_text().await.unwrap(), Some(long));
+        assert_eq!(ws.next_text().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn characters_split_across_frames_are_held_back() {
+        let text = "é!".as_bytes();
+        let frames = [frame(false, TEXT, &text[..1]), frame(true, CONTINUATION, &text[1..])].concat();
+        let (url, _server) = serve("101 Switching Protocols", frames).await;
+        let mut ws = WebSocket::connect(&url).await.unwrap();
+        assert_eq!(ws.next_text().await.unwrap().as_deref(), Some("é!"));
+    }
+
+    #[tokio::test]
+    async fn binary_messages_are_skipped_and_pings_answered() {
+        let frames = [
+            frame(false, BINARY, b"\x00\x01"),
+            frame(true, CONTINUATION, b"\x02"),
+            frame(true, PING, b"are you there"),
+            frame(true, TEXT, b"text"),
+            frame(true, CLOSE, &[0x03, 0xe8, b'b', b'y', b'e']),
+        ]
+        .concat();
+        let (url, server) = serve("101 Switching Protocols", frames).await;
+        let mut ws = WebSocket::connect(&url).await.unwrap();
+        assert_eq!(ws.next_text().await.unwrap().as_deref(), Some("text"));
+        assert_eq!(ws.next_text().await.unwrap(), None);
+        drop(ws);
+        // The pong echoes the ping, and the close echoes just the status code
+        assert_eq!(server.await.unwrap(), vec![(PONG, b"are you there".to_vec()), (CLOSE, vec![0x03, 0xe8])]);
+    }
+
+    #[tokio::test]
+    async fn refused_handshakes_are_errors() {
+        let (url, _server) = serve("404 Not Found", Vec::new()).await;
+        let error = WebSocket::connect(&url).await.err().unwrap();
+        assert!(error.to_string().contains("didn't accept the connection (HTTP/1.1 404 Not Found)"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn oversized_frames_are_errors() {
+        let mut frames = vec![0x80 | TEXT, 127];
+        frames.extend_from_slice(&(MAX_FRAME + 1).to_be_bytes());
+        let (url, _server) = serve("101 Switching Protocols", frames).await;
+        let mut ws = WebSocket::connect(&url).await.unwrap();
+        assert!(ws.next_text().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn only_ws_urls_are_accepted() {
+        for (url, message) in [
+            ("wss://example.com/", "wss:// isn't supported"),
+            ("http://example.com/", "expected a ws:// URL"),
+            ("ws://localhost:port/", "bad port in localhost:port"),
+        ] {
+            let error = WebSocket::connect(url).await.err().unwrap();
+            assert!(error.to_string().contains(message), "{}", error);
+        }
+    }
+}